@@ -0,0 +1,20 @@
+import Controller from '@ember/controller';
+
+import { dropTask } from 'ember-concurrency';
+
+import ajax from '../utils/ajax';
+
+export default class WeeklyDigestUnsubscribeController extends Controller {
+  unsubscribed = false;
+  unsubscribeError = false;
+
+  @dropTask *unsubscribeTask() {
+    try {
+      yield ajax(this.model.url, { method: 'POST', body: '{}' });
+      this.setProperties({ unsubscribed: true, unsubscribeError: false });
+    } catch (error) {
+      console.error(error);
+      this.set('unsubscribeError', true);
+    }
+  }
+}
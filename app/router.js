@@ -55,6 +55,7 @@ Router.map(function () {
   this.route('data-access');
   this.route('confirm', { path: '/confirm/:email_token' });
   this.route('accept-invite', { path: '/accept-invite/:token' });
+  this.route('weekly-digest-unsubscribe', { path: '/weekly_digest/unsubscribe/:token' });
 
   this.route('catch-all', { path: '*path' });
 });
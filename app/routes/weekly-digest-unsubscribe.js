@@ -0,0 +1,17 @@
+import Route from '@ember/routing/route';
+
+import ajax from '../utils/ajax';
+
+export default class WeeklyDigestUnsubscribeRoute extends Route {
+  async model(params) {
+    try {
+      let url = `/api/v1/weekly_digest/unsubscribe/${params.token}`;
+      let { login, weekly_digest } = await ajax(url);
+      return { ok: true, url, login, subscribed: weekly_digest };
+    } catch (error) {
+      let json = await error.json?.();
+      let errorText = json?.errors?.[0]?.detail;
+      return { ok: false, errorText };
+    }
+  }
+}
@@ -6,6 +6,7 @@ use reqwest::{
     blocking::{Body, Client, Response},
     header,
 };
+use serde::Deserialize;
 use sha1::Sha1;
 use std::time::Duration;
 
@@ -80,6 +81,60 @@ impl Bucket {
             .map_err(Into::into)
     }
 
+    pub fn get(&self, client: &Client, path: &str) -> Result<Response, Error> {
+        let path = path.strip_prefix('/').unwrap_or(path);
+        let date = Utc::now().to_rfc2822();
+        let auth = self.auth("GET", &date, path, "", "");
+        let url = self.url(path);
+
+        client
+            .get(&url)
+            .header(header::DATE, date)
+            .header(header::AUTHORIZATION, auth)
+            .header(header::USER_AGENT, "crates.io (https://crates.io)")
+            .timeout(Duration::from_secs(60))
+            .send()?
+            .error_for_status()
+            .map_err(Into::into)
+    }
+
+    /// Lists the keys of the objects starting with `prefix` that sort after `start_after`, which
+    /// may be empty.
+    ///
+    /// S3 returns at most 1000 keys per request, so this sends requests until all keys were
+    /// received.
+    pub fn list(
+        &self,
+        client: &Client,
+        prefix: &str,
+        start_after: &str,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut keys: Vec<String> = Vec::new();
+        loop {
+            let date = Utc::now().to_rfc2822();
+            let auth = self.auth("GET", &date, "", "", "");
+            let marker = keys.last().map_or(start_after, String::as_str);
+
+            let body = client
+                .get(&self.url(""))
+                .query(&[("prefix", prefix), ("marker", marker)])
+                .header(header::DATE, date)
+                .header(header::AUTHORIZATION, auth)
+                .header(header::USER_AGENT, "crates.io (https://crates.io)")
+                .timeout(Duration::from_secs(60))
+                .send()?
+                .error_for_status()?
+                .text()?;
+
+            let page: ListBucketResult = quick_xml::de::from_str(&body)?;
+            let done = !page.is_truncated || page.contents.is_empty();
+            keys.extend(page.contents.into_iter().map(|object| object.key));
+            if done {
+                return Ok(keys);
+            }
+        }
+    }
+
     pub fn host(&self) -> String {
         format!(
             "{}.s3{}.amazonaws.com",
@@ -113,3 +168,50 @@ impl Bucket {
         format!("{}://{}/{}", self.proto, self.host(), path)
     }
 }
+
+/// The parts of a `ListObjects` response that are needed by `Bucket::list`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListBucketResult {
+    #[serde(default)]
+    is_truncated: bool,
+    #[serde(default)]
+    contents: Vec<ListedObject>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListedObject {
+    key: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_response() {
+        let body = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">
+    <Name>crates-io</Name>
+    <Prefix>cdn-logs/</Prefix>
+    <IsTruncated>true</IsTruncated>
+    <Contents><Key>cdn-logs/a.gz</Key><Size>10</Size></Contents>
+    <Contents><Key>cdn-logs/b&amp;c.gz</Key><Size>20</Size></Contents>
+</ListBucketResult>"#;
+
+        let page: ListBucketResult = quick_xml::de::from_str(body).unwrap();
+        let keys = page
+            .contents
+            .into_iter()
+            .map(|object| object.key)
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec!["cdn-logs/a.gz", "cdn-logs/b&c.gz"]);
+        assert!(page.is_truncated);
+
+        let body = r#"<ListBucketResult><IsTruncated>false</IsTruncated></ListBucketResult>"#;
+        let page: ListBucketResult = quick_xml::de::from_str(body).unwrap();
+        assert!(page.contents.is_empty());
+        assert!(!page.is_truncated);
+    }
+}
@@ -0,0 +1,5 @@
+-- 1 = yank, 2 = publish, 3 = full (see `models::Rights`)
+ALTER TABLE crate_owners ADD COLUMN rights INTEGER NOT NULL DEFAULT 3;
+UPDATE crate_owners SET rights = 2 WHERE owner_kind = 1;
+
+ALTER TABLE crate_owner_invitations ADD COLUMN rights INTEGER NOT NULL DEFAULT 3;
@@ -0,0 +1,10 @@
+CREATE TABLE team_memberships (
+    team_id INTEGER NOT NULL REFERENCES teams (id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    is_member BOOLEAN NOT NULL,
+    checked_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (team_id, user_id)
+);
+
+CREATE INDEX team_memberships_user_id ON team_memberships (user_id);
+CREATE INDEX team_memberships_checked_at ON team_memberships (checked_at);
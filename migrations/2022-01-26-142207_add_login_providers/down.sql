@@ -0,0 +1,6 @@
+ALTER TABLE teams DROP CONSTRAINT teams_provider_github_id_key;
+DELETE FROM teams WHERE provider <> 0;
+ALTER TABLE teams ADD CONSTRAINT teams_github_id_key UNIQUE (github_id);
+ALTER TABLE teams DROP COLUMN provider;
+
+DROP TABLE linked_accounts;
@@ -0,0 +1,20 @@
+CREATE TABLE linked_accounts (
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    -- 1 = gitlab, 2 = oidc (see `models::AccountProvider`)
+    provider INTEGER NOT NULL,
+    account_id VARCHAR NOT NULL,
+    login VARCHAR NOT NULL,
+    access_token VARCHAR NOT NULL,
+    avatar VARCHAR,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (provider, account_id)
+);
+
+CREATE INDEX linked_accounts_user_id ON linked_accounts (user_id);
+
+-- 0 = github, 1 = gitlab (see `models::AccountProvider`)
+ALTER TABLE teams ADD COLUMN provider INTEGER NOT NULL DEFAULT 0;
+
+-- Team IDs are only unique within a provider
+ALTER TABLE teams DROP CONSTRAINT teams_github_id_key;
+ALTER TABLE teams ADD CONSTRAINT teams_provider_github_id_key UNIQUE (provider, github_id);
@@ -0,0 +1 @@
+DROP TABLE pending_crate_owner_invitations;
@@ -0,0 +1,12 @@
+-- Invitations for GitHub logins without a crates.io account. They are turned into
+-- `crate_owner_invitations` the first time the user logs in.
+CREATE TABLE pending_crate_owner_invitations (
+    -- always lowercase, GitHub logins are case insensitive
+    invited_login VARCHAR NOT NULL,
+    invited_by_user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    crate_id INTEGER NOT NULL REFERENCES crates (id) ON DELETE CASCADE,
+    -- 1 = yank, 2 = publish, 3 = full (see `models::Rights`)
+    rights INTEGER NOT NULL DEFAULT 3,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (invited_login, crate_id)
+);
@@ -0,0 +1 @@
+ALTER TABLE crate_owner_invitations ADD COLUMN reminder_sent_at TIMESTAMP;
@@ -0,0 +1,3 @@
+ALTER TABLE users
+    DROP COLUMN weekly_digest,
+    DROP COLUMN digest_sent_at;
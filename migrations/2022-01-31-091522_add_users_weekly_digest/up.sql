@@ -0,0 +1,3 @@
+ALTER TABLE users
+    ADD COLUMN weekly_digest BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN digest_sent_at TIMESTAMP;
@@ -0,0 +1,11 @@
+DELETE FROM emails WHERE NOT is_primary;
+
+DROP INDEX index_emails_user_id_send_notifications;
+DROP INDEX index_emails_user_id_primary;
+DROP INDEX index_emails_user_id_lower_email;
+DROP INDEX index_emails_user_id;
+ALTER TABLE emails ADD CONSTRAINT emails_user_id_key UNIQUE (user_id);
+
+ALTER TABLE emails
+    DROP COLUMN is_primary,
+    DROP COLUMN send_notifications;
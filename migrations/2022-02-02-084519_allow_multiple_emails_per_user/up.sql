@@ -0,0 +1,12 @@
+ALTER TABLE emails
+    ADD COLUMN is_primary BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN send_notifications BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Every user had a single address so far, which becomes their primary one
+UPDATE emails SET is_primary = TRUE;
+
+ALTER TABLE emails DROP CONSTRAINT emails_user_id_key;
+CREATE INDEX index_emails_user_id ON emails (user_id);
+CREATE UNIQUE INDEX index_emails_user_id_lower_email ON emails (user_id, lower(email));
+CREATE UNIQUE INDEX index_emails_user_id_primary ON emails (user_id) WHERE is_primary;
+CREATE UNIQUE INDEX index_emails_user_id_send_notifications ON emails (user_id) WHERE send_notifications;
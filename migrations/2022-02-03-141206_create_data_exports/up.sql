@@ -0,0 +1,10 @@
+CREATE TABLE data_exports (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    -- Part of the path of the uploaded archive, so that it can't be guessed from the user ID
+    token TEXT NOT NULL DEFAULT random_string(26),
+    requested_at TIMESTAMP NOT NULL DEFAULT now(),
+    completed_at TIMESTAMP
+);
+
+CREATE INDEX index_data_exports_user_id ON data_exports (user_id);
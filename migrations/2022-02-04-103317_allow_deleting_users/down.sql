@@ -0,0 +1,2 @@
+DELETE FROM version_owner_actions WHERE user_id IS NULL;
+ALTER TABLE version_owner_actions ALTER COLUMN user_id SET NOT NULL;
@@ -0,0 +1,2 @@
+-- Actions of deleted users are kept, but no longer point to the user
+ALTER TABLE version_owner_actions ALTER COLUMN user_id DROP NOT NULL;
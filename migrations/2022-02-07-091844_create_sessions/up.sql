@@ -0,0 +1,10 @@
+CREATE TABLE sessions (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    last_used_at TIMESTAMP NOT NULL DEFAULT now(),
+    user_agent VARCHAR NOT NULL,
+    ip_address VARCHAR NOT NULL
+);
+
+CREATE INDEX index_sessions_user_id ON sessions (user_id);
@@ -0,0 +1,16 @@
+CREATE TABLE totp_credentials (
+    user_id INTEGER PRIMARY KEY REFERENCES users (id) ON DELETE CASCADE,
+    secret BYTEA NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT now(),
+    confirmed_at TIMESTAMP,
+    last_used_step BIGINT
+);
+
+CREATE TABLE totp_recovery_codes (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    code BYTEA NOT NULL,
+    used_at TIMESTAMP
+);
+
+CREATE INDEX index_totp_recovery_codes_user_id ON totp_recovery_codes (user_id);
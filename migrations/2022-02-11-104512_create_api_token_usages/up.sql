@@ -0,0 +1,12 @@
+CREATE TABLE api_token_usages (
+    id BIGSERIAL PRIMARY KEY,
+    api_token_id INTEGER NOT NULL REFERENCES api_tokens (id) ON DELETE CASCADE,
+    used_at TIMESTAMP NOT NULL DEFAULT now(),
+    endpoint VARCHAR NOT NULL,
+    crate_name VARCHAR,
+    ip_address VARCHAR NOT NULL,
+    user_agent VARCHAR NOT NULL
+);
+
+CREATE INDEX index_api_token_usages_api_token_id_used_at ON api_token_usages (api_token_id, used_at);
+CREATE INDEX index_api_token_usages_used_at ON api_token_usages (used_at);
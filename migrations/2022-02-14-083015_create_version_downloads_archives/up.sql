@@ -0,0 +1,6 @@
+CREATE TABLE version_downloads_archives (
+    date DATE PRIMARY KEY,
+    rows INTEGER NOT NULL,
+    downloads BIGINT NOT NULL,
+    archived_at TIMESTAMP NOT NULL DEFAULT now()
+);
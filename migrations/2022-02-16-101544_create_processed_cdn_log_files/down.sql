@@ -0,0 +1 @@
+DROP TABLE processed_cdn_log_files;
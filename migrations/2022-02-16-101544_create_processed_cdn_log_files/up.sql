@@ -0,0 +1,5 @@
+CREATE TABLE processed_cdn_log_files (
+    path VARCHAR PRIMARY KEY,
+    downloads INTEGER NOT NULL,
+    processed_at TIMESTAMP NOT NULL DEFAULT now()
+);
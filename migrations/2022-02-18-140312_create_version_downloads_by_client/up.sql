@@ -0,0 +1,10 @@
+CREATE TABLE version_downloads_by_client (
+    version_id INTEGER NOT NULL REFERENCES versions (id) ON DELETE CASCADE,
+    date DATE NOT NULL DEFAULT CURRENT_DATE,
+    client VARCHAR NOT NULL,
+    cargo_version VARCHAR NOT NULL DEFAULT '',
+    downloads INTEGER NOT NULL,
+    PRIMARY KEY (version_id, date, client, cargo_version)
+);
+
+CREATE INDEX version_downloads_by_client_date ON version_downloads_by_client (date);
@@ -0,0 +1,9 @@
+CREATE TABLE crate_scores (
+    crate_id INTEGER PRIMARY KEY REFERENCES crates (id) ON DELETE CASCADE,
+    trending_score DOUBLE PRECISION NOT NULL DEFAULT 0,
+    popularity_score DOUBLE PRECISION NOT NULL DEFAULT 0,
+    updated_at TIMESTAMP NOT NULL DEFAULT now()
+);
+
+CREATE INDEX crate_scores_trending_score ON crate_scores (trending_score DESC);
+CREATE INDEX crate_scores_popularity_score ON crate_scores (popularity_score DESC);
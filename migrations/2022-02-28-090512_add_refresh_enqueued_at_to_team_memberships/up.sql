@@ -0,0 +1 @@
+ALTER TABLE team_memberships ADD COLUMN refresh_enqueued_at TIMESTAMP;
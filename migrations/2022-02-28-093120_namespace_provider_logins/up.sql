@@ -0,0 +1,13 @@
+-- Users that signed up with another provider than GitHub get logins like `foo@gitlab`, so that
+-- they can't collide with GitHub logins. Users with several linked accounts are named after the
+-- one they signed up with.
+UPDATE users
+SET gh_login = accounts.login || '@' || CASE accounts.provider WHEN 1 THEN 'gitlab' ELSE 'oidc' END
+FROM (
+    SELECT DISTINCT ON (user_id) user_id, provider, login
+    FROM linked_accounts
+    ORDER BY user_id, created_at
+) accounts
+WHERE accounts.user_id = users.id
+    AND users.gh_id = 0
+    AND users.gh_login NOT LIKE '%@%';
@@ -0,0 +1,3 @@
+ALTER TABLE totp_credentials
+    DROP COLUMN failed_attempts,
+    DROP COLUMN locked_until;
@@ -0,0 +1 @@
+DROP INDEX versions_crate_id_semver_order;
@@ -0,0 +1,3 @@
+-- Matches the order used to find the latest version of a crate, so that it's a single index
+-- probe per crate instead of sorting all versions of the crate
+CREATE INDEX versions_crate_id_semver_order ON versions (crate_id, to_semver_no_prerelease(num) DESC NULLS LAST, id DESC);
@@ -7,7 +7,10 @@ use std::{sync::Arc, time::Duration};
 use crate::downloads_counter::DownloadsCounter;
 use crate::email::Emails;
 use crate::github::GitHubClient;
+use crate::gitlab::GitLabClient;
+use crate::login_providers::{self, LoginProvider};
 use crate::metrics::{InstanceMetrics, ServiceMetrics};
+use crate::models::AccountProvider;
 use diesel::r2d2;
 use moka::sync::{Cache, CacheBuilder};
 use oauth2::basic::BasicClient;
@@ -29,6 +32,12 @@ pub struct App {
     /// The GitHub OAuth2 configuration
     pub github_oauth: BasicClient,
 
+    /// GitLab API client, used for `gitlab:` teams
+    pub gitlab: Option<GitLabClient>,
+
+    /// The enabled login providers other than GitHub
+    pub login_providers: Vec<Box<dyn LoginProvider>>,
+
     /// The server configuration
     pub config: config::Server,
 
@@ -83,6 +92,12 @@ impl App {
             ),
         );
 
+        let gitlab = config
+            .gitlab
+            .as_ref()
+            .map(|gitlab| GitLabClient::new(http_client.clone(), gitlab.base_url.clone()));
+        let login_providers = login_providers::from_config(&config, http_client.clone());
+
         let db_helper_threads = match (dotenv::var("DB_HELPER_THREADS"), config.env()) {
             (Ok(num), _) => num.parse().expect("couldn't parse DB_HELPER_THREADS"),
             (_, Env::Production) => 3,
@@ -159,14 +174,28 @@ impl App {
             .time_to_live(config.version_id_cache_ttl)
             .build();
 
+        let downloads_counter = match &config.downloads_journal_path {
+            Some(path) => DownloadsCounter::with_journal(path).unwrap_or_else(|err| {
+                // Downloads can still be counted, they're just not safe from crashes
+                println!(
+                    "Failed to open the downloads journal at {}: {err}",
+                    path.display()
+                );
+                DownloadsCounter::new()
+            }),
+            None => DownloadsCounter::new(),
+        };
+
         App {
             primary_database,
             read_only_replica_database: replica_database,
             github,
             github_oauth,
+            gitlab,
+            login_providers,
             config,
             version_id_cacher,
-            downloads_counter: DownloadsCounter::new(),
+            downloads_counter,
             emails: Emails::from_environment(),
             service_metrics: ServiceMetrics::new().expect("could not initialize service metrics"),
             instance_metrics,
@@ -189,8 +218,21 @@ impl App {
             .expect("No HTTP client is configured.  In tests, use `TestApp::with_proxy()`.")
     }
 
+    /// Returns the login provider, if it is enabled
+    pub fn login_provider(&self, provider: AccountProvider) -> Option<&dyn LoginProvider> {
+        self.login_providers
+            .iter()
+            .find(|login_provider| login_provider.provider() == provider)
+            .map(|login_provider| login_provider.as_ref())
+    }
+
     /// A unique key used with conduit_cookie to generate signed/encrypted cookies
     pub fn session_key(&self) -> &str {
         &self.config.session_key
     }
+
+    /// A unique key used to sign the tokens in links of emails, like the unsubscribe links
+    pub fn email_token_key(&self) -> &str {
+        &self.config.email_token_key
+    }
 }
@@ -5,7 +5,10 @@ use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 use swirl::PerformError;
 
 use crate::db::{DieselPool, DieselPooledConn, PoolError};
+use crate::email::Emails;
 use crate::git::Repository;
+use crate::github::GitHubClient;
+use crate::gitlab::GitLabClient;
 use crate::uploaders::Uploader;
 
 impl<'a> swirl::db::BorrowedConnection<'a> for DieselPool {
@@ -21,9 +24,13 @@ impl swirl::db::DieselPool for DieselPool {
 }
 
 pub struct Environment {
-    index: Arc<Mutex<Repository>>,
+    index: Option<Arc<Mutex<Repository>>>,
     pub uploader: Uploader,
     http_client: AssertUnwindSafe<Client>,
+    github: AssertUnwindSafe<GitHubClient>,
+    gitlab: AssertUnwindSafe<Option<GitLabClient>>,
+    emails: Emails,
+    email_token_key: String,
 }
 
 impl Clone for Environment {
@@ -32,29 +39,82 @@ impl Clone for Environment {
             index: self.index.clone(),
             uploader: self.uploader.clone(),
             http_client: AssertUnwindSafe(self.http_client.0.clone()),
+            github: AssertUnwindSafe(self.github.0.clone()),
+            gitlab: AssertUnwindSafe(self.gitlab.0.clone()),
+            emails: self.emails.clone(),
+            email_token_key: self.email_token_key.clone(),
         }
     }
 }
 
 impl Environment {
-    pub fn new(index: Repository, uploader: Uploader, http_client: Client) -> Self {
-        Self::new_shared(Arc::new(Mutex::new(index)), uploader, http_client)
+    pub fn new(
+        index: Repository,
+        uploader: Uploader,
+        http_client: Client,
+        github: GitHubClient,
+        gitlab: Option<GitLabClient>,
+        emails: Emails,
+        email_token_key: String,
+    ) -> Self {
+        Self::new_shared(
+            Arc::new(Mutex::new(index)),
+            uploader,
+            http_client,
+            github,
+            gitlab,
+            emails,
+            email_token_key,
+        )
     }
 
     pub fn new_shared(
         index: Arc<Mutex<Repository>>,
         uploader: Uploader,
         http_client: Client,
+        github: GitHubClient,
+        gitlab: Option<GitLabClient>,
+        emails: Emails,
+        email_token_key: String,
     ) -> Self {
         Self {
-            index,
+            index: Some(index),
             uploader,
             http_client: AssertUnwindSafe(http_client),
+            github: AssertUnwindSafe(github),
+            gitlab: AssertUnwindSafe(gitlab),
+            emails,
+            email_token_key,
+        }
+    }
+
+    /// Creates an environment without access to the index. Jobs that need the index will fail,
+    /// this is used in tests that only need to run other jobs, like sending emails.
+    pub fn without_index(
+        uploader: Uploader,
+        http_client: Client,
+        github: GitHubClient,
+        gitlab: Option<GitLabClient>,
+        emails: Emails,
+        email_token_key: String,
+    ) -> Self {
+        Self {
+            index: None,
+            uploader,
+            http_client: AssertUnwindSafe(http_client),
+            github: AssertUnwindSafe(github),
+            gitlab: AssertUnwindSafe(gitlab),
+            emails,
+            email_token_key,
         }
     }
 
     pub fn lock_index(&self) -> Result<MutexGuard<'_, Repository>, PerformError> {
-        let repo = self.index.lock().unwrap_or_else(PoisonError::into_inner);
+        let index = self
+            .index
+            .as_ref()
+            .ok_or("the index is not available in this environment")?;
+        let repo = index.lock().unwrap_or_else(PoisonError::into_inner);
         repo.reset_head()?;
         Ok(repo)
     }
@@ -63,4 +123,24 @@ impl Environment {
     pub(crate) fn http_client(&self) -> &Client {
         &self.http_client
     }
+
+    /// Returns the client used to query team memberships from GitHub.
+    pub(crate) fn github(&self) -> &GitHubClient {
+        &self.github
+    }
+
+    /// Returns the client used to query group memberships from GitLab, if it is configured.
+    pub(crate) fn gitlab(&self) -> Option<&GitLabClient> {
+        self.gitlab.as_ref()
+    }
+
+    /// Returns the backend used to send notification emails.
+    pub(crate) fn emails(&self) -> &Emails {
+        &self.emails
+    }
+
+    /// Returns the key used to sign tokens in emails.
+    pub(crate) fn email_token_key(&self) -> &str {
+        &self.email_token_key
+    }
 }
@@ -14,7 +14,9 @@
 
 use cargo_registry::config;
 use cargo_registry::git::{Repository, RepositoryConfig};
-use cargo_registry::{background_jobs::*, db};
+use cargo_registry::github::GitHubClient;
+use cargo_registry::gitlab::GitLabClient;
+use cargo_registry::{background_jobs::*, db, env, Emails};
 use diesel::r2d2;
 use reqwest::blocking::Client;
 use std::sync::{Arc, Mutex};
@@ -27,6 +29,8 @@ fn main() {
     let db_config = config::DatabasePools::full_from_environment();
     let base_config = config::Base::from_environment();
     let uploader = base_config.uploader();
+    let emails = Emails::from_environment();
+    let email_token_key = env("EMAIL_TOKEN_KEY");
 
     if db_config.are_all_read_only() {
         loop {
@@ -58,7 +62,17 @@ fn main() {
             .timeout(Duration::from_secs(45))
             .build()
             .expect("Couldn't build client");
-        let environment = Environment::new_shared(repository.clone(), uploader.clone(), client);
+        let github = GitHubClient::new(Some(client.clone()), "https://api.github.com".into());
+        let gitlab = GitLabClient::new(Some(client.clone()), config::gitlab_base_url());
+        let environment = Environment::new_shared(
+            repository.clone(),
+            uploader.clone(),
+            client,
+            github,
+            Some(gitlab),
+            emails.clone(),
+            email_token_key.clone(),
+        );
         let db_config = r2d2::Pool::builder().min_idle(Some(0));
         swirl::Runner::builder(environment)
             .connection_pool_builder(&db_url, db_config)
@@ -1,7 +1,7 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
 use anyhow::{anyhow, Result};
-use cargo_registry::{db, env, worker};
+use cargo_registry::{config, db, env, env_optional, worker};
 use diesel::prelude::*;
 use swirl::schema::background_jobs::dsl::*;
 use swirl::Job;
@@ -36,7 +36,42 @@ fn main() -> Result<()> {
             Ok(worker::dump_db(database_url, target_name).enqueue(&conn)?)
         }
         "daily_db_maintenance" => Ok(worker::daily_db_maintenance().enqueue(&conn)?),
+        "archive_version_downloads" => {
+            let retention_days = env_optional("VERSION_DOWNLOADS_RETENTION_DAYS")
+                .unwrap_or(config::DEFAULT_VERSION_DOWNLOADS_RETENTION_DAYS);
+            Ok(worker::archive_version_downloads(retention_days as i64).enqueue(&conn)?)
+        }
+        "ingest_cdn_logs" => {
+            // The `download` endpoint counts the downloads unless the CDN logs are the source
+            let since = config::DownloadsSource::from_environment()
+                .cdn_logs_since()
+                .ok_or_else(|| {
+                    anyhow!("CDN logs are only ingested with DOWNLOADS_SOURCE=cdn-logs")
+                })?;
+            let prefix = env_optional("CDN_LOGS_PREFIX")
+                .unwrap_or_else(|| config::DEFAULT_CDN_LOGS_PREFIX.into());
+            Ok(worker::ingest_cdn_logs(prefix, since).enqueue(&conn)?)
+        }
+        "update_crate_scores" => Ok(worker::update_crate_scores().enqueue(&conn)?),
         "squash_index" => Ok(worker::squash_index().enqueue(&conn)?),
+        "sync_team_memberships" => {
+            let max_age = env_optional("TEAM_MEMBERSHIP_CACHE_TTL")
+                .unwrap_or(config::DEFAULT_TEAM_MEMBERSHIP_CACHE_TTL);
+            Ok(worker::sync_team_memberships(max_age as i64).enqueue(&conn)?)
+        }
+        "expire_ownership_invitations" => Ok(worker::expire_ownership_invitations(
+            config::DEFAULT_OWNERSHIP_INVITATIONS_EXPIRATION_DAYS as i64,
+            config::DEFAULT_OWNERSHIP_INVITATIONS_REMINDER_DAYS as i64,
+        )
+        .enqueue(&conn)?),
+        "send_weekly_digests" => Ok(worker::send_weekly_digests().enqueue(&conn)?),
+        "prune_api_token_usages" => {
+            let retention_days = env_optional("API_TOKEN_USAGE_RETENTION_DAYS")
+                .unwrap_or(config::DEFAULT_API_TOKEN_USAGE_RETENTION_DAYS);
+            Ok(worker::prune_api_token_usages(retention_days as i64).enqueue(&conn)?)
+        }
+        "delete_expired_data_exports" => Ok(worker::delete_expired_data_exports().enqueue(&conn)?),
+        "delete_expired_sessions" => Ok(worker::delete_expired_sessions().enqueue(&conn)?),
         other => Err(anyhow!("Unrecognized job type `{}`", other)),
     }
 }
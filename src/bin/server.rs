@@ -37,8 +37,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let client = Client::new();
     let app = Arc::new(App::new(config, Some(client)));
 
+    let replayed = app.downloads_counter.replayed_count();
+    if replayed != 0 {
+        println!("Recovered {replayed} downloads from the downloads journal");
+    }
+
     // Start the background thread periodically persisting download counts to the database.
     downloads_counter_thread(app.clone());
+    downloads_journal_thread(app.clone());
 
     // Start the background thread periodically logging instance metrics.
     log_instance_metrics_thread(app.clone());
@@ -134,6 +140,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Block the main thread until the server has shutdown
     rt.block_on(async { server.await.unwrap() });
 
+    // Download counts are otherwise lost, unless they're recorded in the downloads journal
     println!("Persisting remaining downloads counters");
     match app.downloads_counter.persist_all_shards(&app) {
         Ok(stats) => stats.log(),
@@ -159,6 +166,15 @@ fn downloads_counter_thread(app: Arc<App>) {
     });
 }
 
+fn downloads_journal_thread(app: Arc<App>) {
+    let interval = Duration::from_millis(app.config.downloads_journal_flush_interval_ms);
+
+    std::thread::spawn(move || loop {
+        std::thread::sleep(interval);
+        app.downloads_counter.flush_journal();
+    });
+}
+
 fn log_instance_metrics_thread(app: Arc<App>) {
     // Only run the thread if the configuration is provided
     let interval = if let Some(secs) = app.config.instance_metrics_log_every_seconds {
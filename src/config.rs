@@ -3,22 +3,37 @@ use crate::{env, env_optional, uploaders::Uploader, Env};
 
 mod base;
 mod database_pools;
+mod downloads_source;
+mod login_providers;
 
 pub use self::base::Base;
 pub use self::database_pools::DatabasePools;
+pub use self::downloads_source::DownloadsSource;
+pub use self::login_providers::{gitlab_base_url, LoginProvider};
 use std::collections::HashSet;
+use std::path::PathBuf;
 use std::time::Duration;
 
 const DEFAULT_VERSION_ID_CACHE_SIZE: u64 = 10_000;
 const DEFAULT_VERSION_ID_CACHE_TTL: u64 = 5 * 60; // 5 minutes
+pub const DEFAULT_TEAM_MEMBERSHIP_CACHE_TTL: u64 = 60 * 60; // 1 hour
+const DEFAULT_TEAM_MEMBERSHIP_MAX_STALENESS: u64 = 7 * 24 * 60 * 60; // 7 days
+pub const DEFAULT_OWNERSHIP_INVITATIONS_EXPIRATION_DAYS: u64 = 30;
+pub const DEFAULT_OWNERSHIP_INVITATIONS_REMINDER_DAYS: u64 = 3;
+pub const DEFAULT_API_TOKEN_USAGE_RETENTION_DAYS: u64 = 90;
+pub const DEFAULT_VERSION_DOWNLOADS_RETENTION_DAYS: u64 = 90;
+pub const DEFAULT_CDN_LOGS_PREFIX: &str = "cdn-logs";
 
 pub struct Server {
     pub base: Base,
     pub db: DatabasePools,
     pub session_key: String,
+    pub email_token_key: String,
     pub gh_client_id: String,
     pub gh_client_secret: String,
     pub gh_base_url: String,
+    pub gitlab: Option<LoginProvider>,
+    pub oidc: Option<LoginProvider>,
     pub max_upload_size: u64,
     pub max_unpack_size: u64,
     pub publish_rate_limit: PublishRateLimit,
@@ -28,7 +43,11 @@ pub struct Server {
     pub domain_name: String,
     pub allowed_origins: Vec<String>,
     pub downloads_persist_interval_ms: usize,
+    pub downloads_journal_path: Option<PathBuf>,
+    pub downloads_journal_flush_interval_ms: u64,
+    pub downloads_source: DownloadsSource,
     pub ownership_invitations_expiration_days: u64,
+    pub api_token_usage_retention_days: u64,
     pub metrics_authorization_token: Option<String>,
     pub use_test_database_pool: bool,
     pub instance_metrics_log_every_seconds: Option<u64>,
@@ -36,6 +55,8 @@ pub struct Server {
     pub blocked_routes: HashSet<String>,
     pub version_id_cache_size: u64,
     pub version_id_cache_ttl: Duration,
+    pub team_membership_cache_ttl: Duration,
+    pub team_membership_max_staleness: Duration,
 }
 
 impl Default for Server {
@@ -51,9 +72,17 @@ impl Default for Server {
     /// - `SESSION_KEY`: The key used to sign and encrypt session cookies.
     /// - `GH_CLIENT_ID`: The client ID of the associated GitHub application.
     /// - `GH_CLIENT_SECRET`: The client secret of the associated GitHub application.
+    /// - `GITLAB_*` and `OIDC_*`: The configuration of the other login providers. See the
+    ///   `login_providers` module for more documentation.
     /// - `BLOCKED_TRAFFIC`: A list of headers and environment variables to use for blocking
     ///   traffic. See the `block_traffic` module for more documentation.
     /// - `DOWNLOADS_PERSIST_INTERVAL_MS`: how frequent to persist download counts (in ms).
+    /// - `DOWNLOADS_JOURNAL_PATH`: a local file where download counts are recorded until they're
+    ///   persisted, so that they survive a crash of the process. If missing, download counts are
+    ///   only kept in memory.
+    /// - `DOWNLOADS_SOURCE` and `CDN_LOGS_SINCE`: see the `downloads_source` module.
+    /// - `DOWNLOADS_JOURNAL_FLUSH_INTERVAL_MS`: how frequent to write the buffered downloads to the
+    ///   journal (in ms). This is how many downloads a crash of the process can lose.
     /// - `METRICS_AUTHORIZATION_TOKEN`: authorization token needed to query metrics. If missing,
     ///   querying metrics will be completely disabled.
     /// - `WEB_MAX_ALLOWED_PAGE_OFFSET`: Page offsets larger than this value are rejected. Defaults
@@ -68,6 +97,14 @@ impl Default for Server {
     ///   endpoint even with a healthy database pool.
     /// - `BLOCKED_ROUTES`: A comma separated list of HTTP route patterns that are manually blocked
     ///   by an operator (e.g. `/crates/:crate_id/:version/download`).
+    /// - `TEAM_MEMBERSHIP_CACHE_TTL`: How long (in seconds) a cached GitHub team membership is
+    ///   used without asking GitHub again. Defaults to 1 hour.
+    /// - `TEAM_MEMBERSHIP_MAX_STALENESS`: How long (in seconds) an expired cached team membership
+    ///   is still used while it is refreshed in the background. Older entries are only used if
+    ///   GitHub can't be reached. Defaults to 7 days.
+    /// - `API_TOKEN_USAGE_RETENTION_DAYS`: How long (in days) the recorded usages of API tokens
+    ///   are kept. Older usages are no longer listed, and are deleted by the
+    ///   `prune_api_token_usages` job, which reads the same variable. Defaults to 90.
     ///
     /// # Panics
     ///
@@ -83,20 +120,24 @@ impl Default for Server {
             Some(s) if s.is_empty() => vec![],
             Some(s) => s.split(',').map(String::from).collect(),
         };
+        let domain_name = domain_name();
         Server {
             db: DatabasePools::full_from_environment(),
             base: Base::from_environment(),
             session_key: env("SESSION_KEY"),
+            email_token_key: env("EMAIL_TOKEN_KEY"),
             gh_client_id: env("GH_CLIENT_ID"),
             gh_client_secret: env("GH_CLIENT_SECRET"),
             gh_base_url: "https://api.github.com".to_string(),
+            gitlab: LoginProvider::gitlab_from_environment(&domain_name),
+            oidc: LoginProvider::oidc_from_environment(&domain_name),
             max_upload_size: 10 * 1024 * 1024, // 10 MB default file upload size limit
             max_unpack_size: 512 * 1024 * 1024, // 512 MB max when decompressed
             publish_rate_limit: Default::default(),
             blocked_traffic: blocked_traffic(),
             max_allowed_page_offset: env_optional("WEB_MAX_ALLOWED_PAGE_OFFSET").unwrap_or(200),
             page_offset_ua_blocklist,
-            domain_name: domain_name(),
+            domain_name,
             allowed_origins,
             downloads_persist_interval_ms: dotenv::var("DOWNLOADS_PERSIST_INTERVAL_MS")
                 .map(|interval| {
@@ -105,7 +146,15 @@ impl Default for Server {
                         .expect("invalid DOWNLOADS_PERSIST_INTERVAL_MS")
                 })
                 .unwrap_or(60_000), // 1 minute
-            ownership_invitations_expiration_days: 30,
+            downloads_journal_path: env_optional("DOWNLOADS_JOURNAL_PATH"),
+            downloads_journal_flush_interval_ms: env_optional(
+                "DOWNLOADS_JOURNAL_FLUSH_INTERVAL_MS",
+            )
+            .unwrap_or(1000), // 1 second
+            downloads_source: DownloadsSource::from_environment(),
+            ownership_invitations_expiration_days: DEFAULT_OWNERSHIP_INVITATIONS_EXPIRATION_DAYS,
+            api_token_usage_retention_days: env_optional("API_TOKEN_USAGE_RETENTION_DAYS")
+                .unwrap_or(DEFAULT_API_TOKEN_USAGE_RETENTION_DAYS),
             metrics_authorization_token: dotenv::var("METRICS_AUTHORIZATION_TOKEN").ok(),
             use_test_database_pool: false,
             instance_metrics_log_every_seconds: env_optional("INSTANCE_METRICS_LOG_EVERY_SECONDS"),
@@ -118,6 +167,14 @@ impl Default for Server {
             version_id_cache_ttl: Duration::from_secs(
                 env_optional("VERSION_ID_CACHE_TTL").unwrap_or(DEFAULT_VERSION_ID_CACHE_TTL),
             ),
+            team_membership_cache_ttl: Duration::from_secs(
+                env_optional("TEAM_MEMBERSHIP_CACHE_TTL")
+                    .unwrap_or(DEFAULT_TEAM_MEMBERSHIP_CACHE_TTL),
+            ),
+            team_membership_max_staleness: Duration::from_secs(
+                env_optional("TEAM_MEMBERSHIP_MAX_STALENESS")
+                    .unwrap_or(DEFAULT_TEAM_MEMBERSHIP_MAX_STALENESS),
+            ),
         }
     }
 }
@@ -0,0 +1,65 @@
+//! Where the download counts come from
+//!
+//! - `DOWNLOADS_SOURCE`: `endpoint` (the default) to count downloads in the `download` endpoint,
+//!   or `cdn-logs` to count them from the access logs of the CDNs with the `ingest_cdn_logs`
+//!   background job.
+//! - `CDN_LOGS_SINCE`: the first day (`YYYY-MM-DD`, UTC) counted from the CDN logs. Required
+//!   with `DOWNLOADS_SOURCE=cdn-logs`.
+//!
+//! The `download` endpoint redirects to the CDN, so the CDN logs contain the downloads that the
+//! endpoint counted as well. Each day is therefore counted by only one of them: the endpoint
+//! keeps counting until the day before `CDN_LOGS_SINCE`, and the CDN logs are only counted
+//! starting with that day.
+//!
+//! To switch to the CDN logs, set both variables for the server and for `enqueue-job`, with
+//! `CDN_LOGS_SINCE` being a day that didn't start yet, and deploy before that day. The
+//! `ingest_cdn_logs` job can be scheduled right away, requests of earlier days in the logs are
+//! skipped.
+
+use chrono::NaiveDate;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadsSource {
+    /// Downloads are counted by the `download` endpoint.
+    Endpoint,
+    /// Downloads are counted by the `download` endpoint before `since`, and from the CDN logs
+    /// starting with `since`.
+    CdnLogs { since: NaiveDate },
+}
+
+impl DownloadsSource {
+    /// Reads the source from the environment variables above.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the variables are invalid.
+    pub fn from_environment() -> Self {
+        match dotenv::var("DOWNLOADS_SOURCE").as_deref() {
+            Err(_) | Ok("endpoint") => DownloadsSource::Endpoint,
+            Ok("cdn-logs") => {
+                let since = dotenv::var("CDN_LOGS_SINCE")
+                    .expect("CDN_LOGS_SINCE is required with DOWNLOADS_SOURCE=cdn-logs");
+                let since = NaiveDate::parse_from_str(&since, "%Y-%m-%d")
+                    .expect("invalid CDN_LOGS_SINCE, expected YYYY-MM-DD");
+                DownloadsSource::CdnLogs { since }
+            }
+            Ok(source) => panic!("invalid DOWNLOADS_SOURCE `{source}`"),
+        }
+    }
+
+    /// Returns whether the `download` endpoint counts the downloads of `date`.
+    pub fn counted_by_endpoint(self, date: NaiveDate) -> bool {
+        match self {
+            DownloadsSource::Endpoint => true,
+            DownloadsSource::CdnLogs { since } => date < since,
+        }
+    }
+
+    /// Returns the first day that is counted from the CDN logs, if they are used at all.
+    pub fn cdn_logs_since(self) -> Option<NaiveDate> {
+        match self {
+            DownloadsSource::Endpoint => None,
+            DownloadsSource::CdnLogs { since } => Some(since),
+        }
+    }
+}
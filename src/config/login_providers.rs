@@ -0,0 +1,51 @@
+//! Configuration of the login providers other than GitHub
+//!
+//! A provider is only enabled if its client ID is configured.
+//!
+//! - `GITLAB_CLIENT_ID`: The client ID of the associated GitLab application.
+//! - `GITLAB_CLIENT_SECRET`: The client secret of the associated GitLab application.
+//! - `GITLAB_BASE_URL`: The GitLab instance to use. Defaults to `https://gitlab.com`.
+//! - `OIDC_CLIENT_ID`: The client ID registered with the OpenID Connect issuer.
+//! - `OIDC_CLIENT_SECRET`: The client secret registered with the OpenID Connect issuer.
+//! - `OIDC_ISSUER_URL`: The URL of the OpenID Connect issuer. Its endpoints are discovered
+//!   through `/.well-known/openid-configuration`.
+//!
+//! Users are sent back to `https://{DOMAIN_NAME}/{provider}-redirect.html` after authorizing
+//! crates.io with the provider.
+
+use crate::env;
+
+#[derive(Debug, Clone)]
+pub struct LoginProvider {
+    pub base_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub redirect_url: String,
+}
+
+impl LoginProvider {
+    pub fn gitlab_from_environment(domain_name: &str) -> Option<Self> {
+        let client_id = dotenv::var("GITLAB_CLIENT_ID").ok()?;
+        Some(Self {
+            base_url: gitlab_base_url(),
+            client_id,
+            client_secret: env("GITLAB_CLIENT_SECRET"),
+            redirect_url: format!("https://{domain_name}/gitlab-redirect.html"),
+        })
+    }
+
+    pub fn oidc_from_environment(domain_name: &str) -> Option<Self> {
+        let client_id = dotenv::var("OIDC_CLIENT_ID").ok()?;
+        Some(Self {
+            base_url: env("OIDC_ISSUER_URL"),
+            client_id,
+            client_secret: env("OIDC_CLIENT_SECRET"),
+            redirect_url: format!("https://{domain_name}/oidc-redirect.html"),
+        })
+    }
+}
+
+/// The GitLab instance used for logins and for `gitlab:` teams
+pub fn gitlab_base_url() -> String {
+    dotenv::var("GITLAB_BASE_URL").unwrap_or_else(|_| "https://gitlab.com".into())
+}
@@ -94,8 +94,8 @@ fn prepare_list(
             ListFilter::CrateName(crate_name) => {
                 // Only allow crate owners to query pending invitations for their crate.
                 let krate: Crate = Crate::by_name(&crate_name).first(&*conn)?;
-                let owners = krate.owners(&*conn)?;
-                if user.rights(req.app(), &owners)? != Rights::Full {
+                let owners = krate.owners_with_rights(&*conn)?;
+                if user.rights(req.app(), &conn, &owners)? != Rights::Full {
                     return Err(forbidden());
                 }
 
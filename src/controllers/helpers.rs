@@ -1,5 +1,6 @@
 use crate::util::{json_response, EndpointResult};
 
+pub(crate) mod downloads;
 pub(crate) mod pagination;
 
 pub(crate) use self::pagination::Paginate;
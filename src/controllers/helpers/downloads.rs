@@ -0,0 +1,317 @@
+//! Query parameters and responses shared by the download statistics endpoints of crates and
+//! versions.
+//!
+//! Without any of the parameters below, both endpoints keep returning their original responses
+//! covering the last 90 days. Otherwise they return the downloads of the `from`..=`to` range,
+//! summed up per `interval` and `group_by`:
+//!
+//! - `from` and `to`: the first and last days of the range, formatted as `YYYY-MM-DD`. `to`
+//!   defaults to today and `from` to 89 days before `to`.
+//! - `interval`: one of `day` (the default), `week`, `month` or `year`. Periods are named after
+//!   their first day, so the first and last periods may cover only part of the range. With
+//!   `month` and `year`, `from` is moved to the first day of its month, so that only the last
+//!   period may be partial.
+//! - `group_by`: one of `version` (the default), `major` or `minor`, e.g. `1` or `1.2` for
+//!   version `1.2.3`. Following semver, `0.x` versions are grouped by their minor version when
+//!   grouping by `major` too.
+//! - `format`: `json` (the default) or `csv`.
+//!
+//! Daily counts older than 90 days are moved to the archives listed by `/downloads/archives` and
+//! only kept as monthly sums in the database, which are included with an interval of `month` or
+//! `year`. With `day` and `week`, ranges reaching further back start on the day after the last
+//! archived day instead, which is returned as `archived_until` in `meta`. Ranges that were
+//! archived completely are rejected.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+
+use crate::controllers::prelude::*;
+use crate::models::{Version, VersionDownload, VersionDownloadsArchive};
+use crate::util::errors::bad_request;
+use crate::util::{csv_response, json_response, AppResponse};
+
+/// The longest range that can be requested at daily granularity, in days.
+const MAX_DAILY_RANGE_DAYS: i64 = 366;
+/// The longest range that can be requested when aggregating by week, month or year, in days.
+const MAX_AGGREGATED_RANGE_DAYS: i64 = 5 * 366;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Interval {
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl Interval {
+    /// The name of the interval, as accepted by the `interval` parameter and by the
+    /// `date_trunc` function of PostgreSQL.
+    fn as_str(self) -> &'static str {
+        match self {
+            Interval::Day => "day",
+            Interval::Week => "week",
+            Interval::Month => "month",
+            Interval::Year => "year",
+        }
+    }
+
+    fn max_range_days(self) -> i64 {
+        match self {
+            Interval::Day => MAX_DAILY_RANGE_DAYS,
+            _ => MAX_AGGREGATED_RANGE_DAYS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Grouping {
+    Version,
+    Major,
+    Minor,
+}
+
+impl Grouping {
+    fn as_str(self) -> &'static str {
+        match self {
+            Grouping::Version => "version",
+            Grouping::Major => "major",
+            Grouping::Minor => "minor",
+        }
+    }
+
+    fn key(self, version: semver::Version) -> GroupKey {
+        match self {
+            Grouping::Version => GroupKey::Version(version),
+            Grouping::Major if version.major > 0 => GroupKey::Major(version.major),
+            Grouping::Major | Grouping::Minor => GroupKey::Minor(version.major, version.minor),
+        }
+    }
+}
+
+/// The group a version is counted in. Groups are ordered by semver precedence.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum GroupKey {
+    Major(u64),
+    Minor(u64, u64),
+    Version(semver::Version),
+}
+
+impl fmt::Display for GroupKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GroupKey::Major(major) => write!(f, "{major}"),
+            GroupKey::Minor(major, minor) => write!(f, "{major}.{minor}"),
+            GroupKey::Version(version) => write!(f, "{version}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct DownloadsOptions {
+    pub(crate) from: NaiveDate,
+    pub(crate) to: NaiveDate,
+    pub(crate) interval: Interval,
+    pub(crate) group_by: Grouping,
+    pub(crate) format: Format,
+    /// Whether any of the parameters above was passed, asking for the aggregated response.
+    pub(crate) aggregated: bool,
+    /// The last archived day, if `from` was moved after it by `skip_archived_days`.
+    pub(crate) archived_until: Option<NaiveDate>,
+}
+
+impl DownloadsOptions {
+    /// Parses the options from the query string. `to_aliases` lists older names of the `to`
+    /// parameter that are still accepted, like `before_date`. Invalid dates in them are ignored,
+    /// like they were before `to` was added.
+    pub(crate) fn gather(req: &dyn RequestExt, to_aliases: &[&str]) -> AppResult<Self> {
+        let params = req.query();
+        let date = |name: &str| {
+            params
+                .get(name)
+                .map(|value| NaiveDate::parse_from_str(value, "%Y-%m-%d"))
+        };
+        let parse_date = |name: &str| {
+            date(name)
+                .transpose()
+                .map_err(|_| bad_request(&format!("invalid date in `{name}`, expected YYYY-MM-DD")))
+        };
+
+        let mut to = parse_date("to")?;
+        for alias in to_aliases {
+            if to.is_none() {
+                to = date(alias).and_then(Result::ok);
+            }
+        }
+        let to = to.unwrap_or_else(|| Utc::today().naive_utc());
+        let from = parse_date("from")?.unwrap_or(to - Duration::days(89));
+
+        let interval = match params.get("interval").map(String::as_str) {
+            None | Some("day") => Interval::Day,
+            Some("week") => Interval::Week,
+            Some("month") => Interval::Month,
+            Some("year") => Interval::Year,
+            Some(_) => {
+                return Err(bad_request(
+                    "invalid `interval`, expected `day`, `week`, `month` or `year`",
+                ))
+            }
+        };
+        let group_by = match params.get("group_by").map(String::as_str) {
+            None | Some("version") => Grouping::Version,
+            Some("major") => Grouping::Major,
+            Some("minor") => Grouping::Minor,
+            Some(_) => {
+                return Err(bad_request(
+                    "invalid `group_by`, expected `version`, `major` or `minor`",
+                ))
+            }
+        };
+        // The archived downloads are summed up per month, so months can't be split
+        let from = match interval {
+            Interval::Month | Interval::Year => from.with_day(1).unwrap(),
+            Interval::Day | Interval::Week => from,
+        };
+        let format = match params.get("format").map(String::as_str) {
+            None | Some("json") => Format::Json,
+            Some("csv") => Format::Csv,
+            Some(_) => return Err(bad_request("invalid `format`, expected `json` or `csv`")),
+        };
+
+        if from > to {
+            return Err(bad_request("`from` must not be after `to`"));
+        }
+        let max_range_days = interval.max_range_days();
+        if (to - from).num_days() >= max_range_days {
+            return Err(bad_request(&format!(
+                "the range can span at most {max_range_days} days with an interval of `{}`",
+                interval.as_str()
+            )));
+        }
+
+        let aggregated = ["from", "to", "interval", "group_by", "format"]
+            .iter()
+            .any(|name| params.contains_key(*name));
+
+        Ok(Self {
+            from,
+            to,
+            interval,
+            group_by,
+            format,
+            aggregated,
+            archived_until: None,
+        })
+    }
+
+    /// Moves `from` after the last archived day, since the daily counts of the archived days
+    /// are only kept as monthly sums. Ranges of months and years are not affected.
+    pub(crate) fn skip_archived_days(&mut self, conn: &PgConnection) -> AppResult<()> {
+        if let Interval::Month | Interval::Year = self.interval {
+            return Ok(());
+        }
+
+        let last_archived = match VersionDownloadsArchive::last_archived_date(conn)? {
+            Some(date) if date >= self.from => date,
+            _ => return Ok(()),
+        };
+        if last_archived >= self.to {
+            return Err(bad_request(&format!(
+                "the downloads until {last_archived} were archived and are only available \
+                 with an interval of `month` or `year`"
+            )));
+        }
+
+        self.from = last_archived + Duration::days(1);
+        self.archived_until = Some(last_archived);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct AggregatedDownload {
+    /// The first day of the period.
+    date: String,
+    /// The version, or the major or minor version the downloads are grouped by.
+    version: String,
+    downloads: i64,
+}
+
+/// Loads the downloads of `versions` within the range of `options`, summed up per period and
+/// group. The results are ordered by period, and then by version.
+pub(crate) fn load_aggregated(
+    conn: &PgConnection,
+    versions: &[Version],
+    options: &DownloadsOptions,
+) -> QueryResult<Vec<AggregatedDownload>> {
+    let groups = versions
+        .iter()
+        .filter_map(|version| {
+            let num = semver::Version::parse(&version.num).ok()?;
+            Some((version.id, options.group_by.key(num)))
+        })
+        .collect::<BTreeMap<_, _>>();
+    let version_ids = groups.keys().copied().collect::<Vec<_>>();
+
+    let rows = VersionDownload::sum_by_period(
+        conn,
+        &version_ids,
+        options.from,
+        options.to,
+        options.interval.as_str(),
+    )?;
+
+    let mut sums: BTreeMap<(NaiveDate, &GroupKey), i64> = BTreeMap::new();
+    for row in &rows {
+        if let Some(group) = groups.get(&row.version_id) {
+            *sums.entry((row.period, group)).or_default() += row.downloads;
+        }
+    }
+
+    Ok(sums
+        .into_iter()
+        .map(|((period, group), downloads)| AggregatedDownload {
+            date: period.to_string(),
+            version: group.to_string(),
+            downloads,
+        })
+        .collect())
+}
+
+/// Builds the response for aggregated downloads, as JSON or as CSV depending on `format`.
+pub(crate) fn aggregated_response(
+    downloads: &[AggregatedDownload],
+    options: &DownloadsOptions,
+) -> AppResponse {
+    match options.format {
+        Format::Json => json_response(&json!({
+            "downloads": downloads,
+            "meta": {
+                "from": options.from.to_string(),
+                "to": options.to.to_string(),
+                "interval": options.interval.as_str(),
+                "group_by": options.group_by.as_str(),
+                "archived_until": options.archived_until.map(|date| date.to_string()),
+            },
+        })),
+        Format::Csv => {
+            // None of the fields can contain commas or quotes, so they don't need escaping
+            let mut csv = String::from("date,version,downloads\n");
+            for download in downloads {
+                csv.push_str(&format!(
+                    "{},{},{}\n",
+                    download.date, download.version, download.downloads
+                ));
+            }
+
+            csv_response(csv)
+        }
+    }
+}
@@ -1,27 +1,43 @@
-//! Endpoint for exposing crate download counts
+//! Endpoints for exposing crate download counts, and the archives of older counts
 //!
 //! The endpoint for downloading a crate and exposing version specific
 //! download counts are located in `version::downloads`.
 
 use std::cmp;
 
+use chrono::NaiveDate;
+
 use crate::controllers::frontend_prelude::*;
+use crate::controllers::helpers::downloads::{
+    aggregated_response, load_aggregated, DownloadsOptions,
+};
 
-use crate::models::{Crate, CrateVersions, Version, VersionDownload};
-use crate::schema::version_downloads;
+use crate::models::{Crate, CrateVersions, Version, VersionDownload, VersionDownloadsArchive};
+use crate::schema::{version_downloads, version_downloads_archives, version_downloads_by_client};
 use crate::sql::to_char;
-use crate::views::EncodableVersionDownload;
+use crate::views::{EncodableVersionDownload, EncodableVersionDownloadsArchive};
 
 /// Handles the `GET /crates/:crate_id/downloads` route.
+///
+/// See `helpers::downloads` for the parameters selecting the range and the aggregation of the
+/// downloads of all versions.
 pub fn downloads(req: &mut dyn RequestExt) -> EndpointResult {
     use diesel::dsl::*;
     use diesel::sql_types::BigInt;
 
+    let mut options = DownloadsOptions::gather(req, &[])?;
+
     let crate_name = &req.params()["crate_id"];
     let conn = req.db_read_only()?;
     let krate: Crate = Crate::by_name(crate_name).first(&*conn)?;
 
     let mut versions: Vec<Version> = krate.all_versions().load(&*conn)?;
+    if options.aggregated {
+        options.skip_archived_days(&conn)?;
+        let downloads = load_aggregated(&conn, &versions, &options)?;
+        return Ok(aggregated_response(&downloads, &options));
+    }
+
     versions.sort_by_cached_key(|version| cmp::Reverse(semver::Version::parse(&version.num).ok()));
     let (latest_five, rest) = versions.split_at(cmp::min(5, versions.len()));
 
@@ -50,10 +66,87 @@ pub fn downloads(req: &mut dyn RequestExt) -> EndpointResult {
         downloads: i64,
     }
 
+    // The daily downloads of all versions by the kind of client, e.g. `cargo` or `browser`, and
+    // by the minor version of cargo
+    let version_ids = versions
+        .iter()
+        .map(|version| version.id)
+        .collect::<Vec<_>>();
+    let sum_downloads = sql::<BigInt>("SUM(version_downloads_by_client.downloads)");
+    let by_client: Vec<ClientDownload> = version_downloads_by_client::table
+        .select((
+            to_char(version_downloads_by_client::date, "YYYY-MM-DD"),
+            version_downloads_by_client::client,
+            version_downloads_by_client::cargo_version,
+            sum_downloads,
+        ))
+        .filter(version_downloads_by_client::version_id.eq_any(version_ids))
+        .filter(version_downloads_by_client::date.gt(date(now - 90.days())))
+        .group_by((
+            version_downloads_by_client::date,
+            version_downloads_by_client::client,
+            version_downloads_by_client::cargo_version,
+        ))
+        .order((
+            version_downloads_by_client::date.asc(),
+            version_downloads_by_client::client.asc(),
+            version_downloads_by_client::cargo_version.asc(),
+        ))
+        .load(&*conn)?;
+
+    #[derive(Serialize, Queryable)]
+    struct ClientDownload {
+        date: String,
+        client: String,
+        #[serde(skip_serializing_if = "String::is_empty")]
+        cargo_version: String,
+        downloads: i64,
+    }
+
     Ok(req.json(&json!({
         "version_downloads": downloads,
         "meta": {
             "extra_downloads": extra,
+            "client_downloads": by_client,
         },
     })))
 }
+
+/// Handles the `GET /downloads/archives` route.
+///
+/// Daily download counts older than 90 days are moved to CSV files, one per day and covering
+/// all crates. This lists the files of the days between the optional `start` and `end` query
+/// parameters, formatted as `YYYY-MM-DD`. The download statistics endpoints only keep monthly
+/// sums of the archived days, see `helpers::downloads`.
+pub fn archives(req: &mut dyn RequestExt) -> EndpointResult {
+    let params = req.query();
+    let parse_date = |name: &str| {
+        params
+            .get(name)
+            .map(|value| NaiveDate::parse_from_str(value, "%Y-%m-%d"))
+            .transpose()
+            .map_err(|_| bad_request(&format!("invalid date in `{name}`, expected YYYY-MM-DD")))
+    };
+    let start = parse_date("start")?;
+    let end = parse_date("end")?;
+
+    let conn = req.db_read_only()?;
+    let mut query = version_downloads_archives::table
+        .order(version_downloads_archives::date)
+        .into_boxed();
+    if let Some(start) = start {
+        query = query.filter(version_downloads_archives::date.ge(start));
+    }
+    if let Some(end) = end {
+        query = query.filter(version_downloads_archives::date.le(end));
+    }
+
+    let uploader = req.app().config.uploader();
+    let archives = query
+        .load::<VersionDownloadsArchive>(&*conn)?
+        .into_iter()
+        .map(|archive| EncodableVersionDownloadsArchive::from(archive, uploader))
+        .collect::<Vec<_>>();
+
+    Ok(req.json(&json!({ "archives": archives })))
+}
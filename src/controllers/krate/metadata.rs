@@ -83,6 +83,24 @@ pub fn summary(req: &mut dyn RequestExt) -> EndpointResult {
         .limit(10)
         .load(&*conn)?;
 
+    // The scores are computed daily by the `update_crate_scores` background job
+    let trending = crates
+        .left_join(recent_crate_downloads::table)
+        .inner_join(crate_scores::table)
+        .filter(crate_scores::trending_score.gt(0.0))
+        .order(crate_scores::trending_score.desc())
+        .select(selection)
+        .limit(10)
+        .load(&*conn)?;
+
+    let most_popular = crates
+        .left_join(recent_crate_downloads::table)
+        .inner_join(crate_scores::table)
+        .order(crate_scores::popularity_score.desc())
+        .select(selection)
+        .limit(10)
+        .load(&*conn)?;
+
     let popular_keywords = keywords::table
         .order(keywords::crates_cnt.desc())
         .limit(10)
@@ -103,6 +121,8 @@ pub fn summary(req: &mut dyn RequestExt) -> EndpointResult {
         "most_downloaded": encode_crates(most_downloaded)?,
         "most_recently_downloaded": encode_crates(most_recently_downloaded)?,
         "just_updated": encode_crates(just_updated)?,
+        "trending": encode_crates(trending)?,
+        "most_popular": encode_crates(most_popular)?,
         "popular_keywords": popular_keywords,
         "popular_categories": popular_categories,
     })))
@@ -1,6 +1,7 @@
 //! All routes related to managing owners of a crate
 
 use crate::controllers::prelude::*;
+use crate::email::RequestDetails;
 use crate::models::{Crate, Owner, Rights, Team, User};
 use crate::views::EncodableOwner;
 
@@ -59,9 +60,12 @@ pub fn remove_owners(req: &mut dyn RequestExt) -> EndpointResult {
 /// The format is:
 ///
 /// ```json
-/// {"owners": ["username", "github:org:team", ...]}
+/// {"owners": ["username", "github:org:team", ...], "rights": "publish"}
 /// ```
-fn parse_owners_request(req: &mut dyn RequestExt) -> AppResult<Vec<String>> {
+///
+/// The optional `rights` field is only used when adding owners, and is one of `yank`,
+/// `publish` or `full`.
+fn parse_owners_request(req: &mut dyn RequestExt) -> AppResult<(Vec<String>, Option<Rights>)> {
     let mut body = String::new();
     req.body().read_to_string(&mut body)?;
     #[derive(Deserialize)]
@@ -69,67 +73,128 @@ fn parse_owners_request(req: &mut dyn RequestExt) -> AppResult<Vec<String>> {
         // identical, for back-compat (owners preferred)
         users: Option<Vec<String>>,
         owners: Option<Vec<String>>,
+        rights: Option<Rights>,
     }
     let request: Request =
         serde_json::from_str(&body).map_err(|_| cargo_err("invalid json request"))?;
-    request
+    if request.rights == Some(Rights::None) {
+        return Err(cargo_err("owners must be granted at least `yank` rights"));
+    }
+    let logins = request
         .owners
         .or(request.users)
-        .ok_or_else(|| cargo_err("invalid json request"))
+        .ok_or_else(|| cargo_err("invalid json request"))?;
+    Ok((logins, request.rights))
 }
 
 fn modify_owners(req: &mut dyn RequestExt, add: bool) -> EndpointResult {
     let authenticated_user = req.authenticate()?;
-    let logins = parse_owners_request(req)?;
+    let (logins, rights) = parse_owners_request(req)?;
     let app = req.app();
     let crate_name = &req.params()["crate_id"];
 
     let conn = req.db_conn()?;
+    authenticated_user.require_totp(req, &conn)?;
     let user = authenticated_user.user();
 
-    conn.transaction(|| {
-        let krate: Crate = Crate::by_name(crate_name).first(&*conn)?;
-        let owners = krate.owners(&conn)?;
-
-        match user.rights(app, &owners)? {
-            Rights::Full => {}
-            // Yes!
-            Rights::Publish => {
-                return Err(cargo_err(
-                    "team members don't have permission to modify owners",
-                ));
-            }
-            Rights::None => {
-                return Err(cargo_err("only owners have permission to modify owners"));
-            }
+    let krate: Crate = Crate::by_name(crate_name).first(&*conn)?;
+    let owners = krate.owners_with_rights(&conn)?;
+
+    // Checked before the transaction is opened, so that the team memberships cached while
+    // checking are kept when the request is rejected.
+    match user.rights(app, &conn, &owners)? {
+        Rights::Full => {}
+        // Yes!
+        Rights::Publish | Rights::Yank => {
+            let is_user_owner = owners
+                .iter()
+                .any(|(owner, _)| matches!(owner, Owner::User(u) if u.id == user.id));
+            return Err(cargo_err(if is_user_owner {
+                "only owners with full rights have permission to modify owners"
+            } else {
+                "team members don't have permission to modify owners"
+            }));
         }
+        Rights::None => {
+            return Err(cargo_err("only owners have permission to modify owners"));
+        }
+    }
 
-        let comma_sep_msg = if add {
+    conn.transaction(|| {
+        let changes = if add {
             let mut msgs = Vec::with_capacity(logins.len());
             for login in &logins {
-                let login_test =
-                    |owner: &Owner| owner.login().to_lowercase() == *login.to_lowercase();
-                if owners.iter().any(login_test) {
-                    return Err(cargo_err(&format_args!("`{}` is already an owner", login)));
-                }
-                let msg = krate.owner_add(app, &conn, &user, login)?;
+                let existing_owner = owners
+                    .iter()
+                    .map(|(owner, _)| owner)
+                    .find(|owner| owner.login().to_lowercase() == *login.to_lowercase());
+                let msg = match (existing_owner, rights) {
+                    // Adding an existing owner with explicit rights changes their rights
+                    (Some(owner), Some(rights)) => krate.owner_set_rights(&conn, owner, rights)?,
+                    (Some(_), None) => {
+                        return Err(cargo_err(&format_args!("`{}` is already an owner", login)));
+                    }
+                    (None, rights) => krate.owner_add(app, &conn, &user, login, rights)?,
+                };
                 msgs.push(msg);
             }
-            msgs.join(",")
+            msgs
         } else {
+            let mut msgs = Vec::with_capacity(logins.len());
             for login in &logins {
                 krate.owner_remove(app, &conn, &user, login)?;
+                msgs.push(format!("{login} was removed as an owner"));
             }
-            if User::owning(&krate, &conn)?.is_empty() {
-                return Err(cargo_err(
-                    "cannot remove all individual owners of a crate. \
-                     Team member don't have permission to modify owners, so \
-                     at least one individual owner is required.",
-                ));
+            msgs
+        };
+
+        // Only owners with full rights can modify owners, so at least one individual owner
+        // needs to keep them. Teams can't be relied on, as their membership may change.
+        let has_full_user_owner = krate
+            .owners_with_rights(&conn)?
+            .iter()
+            .any(|(owner, rights)| matches!(owner, Owner::User(_)) && *rights == Rights::Full);
+        if !has_full_user_owner {
+            return Err(cargo_err(if add {
+                "cannot remove full rights from all individual owners of a crate. \
+                 At least one individual owner with full rights is required."
+            } else {
+                "cannot remove all individual owners of a crate. \
+                 Team member don't have permission to modify owners, so \
+                 at least one individual owner is required."
+            }));
+        }
+
+        // Let all individual owners know, including the ones that were just removed, so that
+        // changes with compromised credentials are noticed quickly. Errors are only logged, as
+        // the changes were made whether or not the notifications are sent.
+        let details = RequestDetails::from_request(&*req);
+        for (owner, _) in &owners {
+            if let Owner::User(owner) = owner {
+                if let Some(email) = owner.notification_email(&conn)? {
+                    let result = app.emails.send_owners_changed(
+                        &conn,
+                        &email,
+                        &krate.name,
+                        &user.gh_login,
+                        &changes,
+                        &details,
+                    );
+                    if let Err(error) = result {
+                        warn!(
+                            "Failed to notify user {} of owner changes: {error}",
+                            owner.id
+                        );
+                    }
+                }
             }
+        }
+
+        let comma_sep_msg = if add {
+            changes.join(",")
+        } else {
             "owners successfully removed".to_owned()
         };
-
         Ok(req.json(&json!({ "ok": true, "msg": comma_sep_msg })))
     })
 }
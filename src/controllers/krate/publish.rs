@@ -77,6 +77,16 @@ pub fn publish(req: &mut dyn RequestExt) -> EndpointResult {
         ))
     })?;
 
+    // The rights of existing crates are checked before the transaction is opened, so that the
+    // team memberships cached while checking are kept when the request is rejected.
+    let existing_crate: Option<Crate> = Crate::by_name(&new_crate.name).first(&*conn).optional()?;
+    if let Some(krate) = existing_crate {
+        let owners = krate.owners_with_rights(&conn)?;
+        if user.rights(req.app(), &conn, &owners)? < Rights::Publish {
+            return Err(cargo_err(MISSING_RIGHTS_ERROR_MESSAGE));
+        }
+    }
+
     // Create a transaction on the database, if there are no errors,
     // commit the transactions to record a new or updated crate.
     conn.transaction(|| {
@@ -116,8 +126,8 @@ pub fn publish(req: &mut dyn RequestExt) -> EndpointResult {
         let krate =
             persist.create_or_update(&conn, user.id, Some(&app.config.publish_rate_limit))?;
 
-        let owners = krate.owners(&conn)?;
-        if user.rights(req.app(), &owners)? < Rights::Publish {
+        let owners = krate.owners_with_rights(&conn)?;
+        if user.rights(req.app(), &conn, &owners)? < Rights::Publish {
             return Err(cargo_err(MISSING_RIGHTS_ERROR_MESSAGE));
         }
 
@@ -161,6 +171,7 @@ pub fn publish(req: &mut dyn RequestExt) -> EndpointResult {
             &features,
             license,
             license_file,
+            new_crate.rust_version.clone(),
             // Downcast is okay because the file length must be less than the max upload size
             // to get here, and max upload sizes are way less than i32 max
             file_length as i32,
@@ -243,6 +254,9 @@ pub fn publish(req: &mut dyn RequestExt) -> EndpointResult {
         };
         worker::add_crate(git_crate).enqueue(&conn)?;
 
+        // Let the owners know about the new version, in case it wasn't them
+        worker::send_publish_notifications(version.id).enqueue(&conn)?;
+
         // The `other` field on `PublishWarnings` was introduced to handle a temporary warning
         // that is no longer needed. As such, crates.io currently does not return any `other`
         // warnings at this time, but if we need to, the field is available.
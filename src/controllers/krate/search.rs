@@ -1,22 +1,29 @@
 //! Endpoint for searching and discovery functionality
 
+use chrono::NaiveDate;
 use diesel::dsl::*;
-use diesel::sql_types::Array;
+use diesel::pg::Pg;
+use diesel::sql_types::{Array, Float};
 use diesel_full_text_search::*;
 use indexmap::IndexMap;
+use serde_json::Value;
 
+use self::query::{Field, QueryTerm, Term};
 use crate::controllers::cargo_prelude::*;
 use crate::controllers::helpers::Paginate;
 use crate::models::{
-    Crate, CrateBadge, CrateOwner, CrateVersions, OwnerKind, TopVersions, Version,
+    is_valid_rust_version, Crate, CrateBadge, CrateOwner, CrateVersions, OwnerKind, TopVersions,
+    Version,
 };
 use crate::schema::*;
 use crate::util::errors::bad_request;
 use crate::views::EncodableCrate;
 
 use crate::controllers::helpers::pagination::{Page, Paginated, PaginationOptions};
-use crate::models::krate::ALL_COLUMNS;
-use crate::sql::{array_agg, canon_crate_name, lower};
+use crate::models::krate::{ALL_COLUMNS, MAX_NAME_LENGTH};
+use crate::sql::{array_agg, canon_crate_name, lower, similarity};
+
+mod query;
 
 /// Handles the `GET /crates` route.
 /// Returns a list of crates. Called in a variety of scenarios in the
@@ -27,7 +34,13 @@ use crate::sql::{array_agg, canon_crate_name, lower};
 ///
 /// Notes:
 /// The different use cases this function covers is handled through passing
-/// in parameters in the GET request.
+/// in parameters in the GET request. The filters (see `FilterParams`) can be
+/// combined, and `include_facets=yes` adds the most common categories,
+/// keywords and licenses of all matching crates to `meta.facets`. Besides
+/// words, `q` can contain quoted phrases, qualifiers like `keyword:async`
+/// and negated terms, see the `query` module. If no crate matches the
+/// words of `q`, crates with similar names are returned instead, and
+/// `meta.fuzzy` is set.
 ///
 /// We would like to stop adding functionality in here. It was built like
 /// this to keep the number of database queries low, though given Rust's
@@ -40,43 +53,38 @@ use crate::sql::{array_agg, canon_crate_name, lower};
 /// function out to cover the different use cases, and create unit tests
 /// for them.
 pub fn search(req: &mut dyn RequestExt) -> EndpointResult {
-    use diesel::sql_types::{Bool, Text};
+    use diesel::sql_types::Bool;
 
     let params = req.query();
     let sort = params.get("sort").map(|s| &**s);
-    let include_yanked = params
-        .get("include_yanked")
+    let include_facets = params
+        .get("include_facets")
         .map(|s| s == "yes")
-        .unwrap_or(true);
+        .unwrap_or(false);
+    let mut filter_params = FilterParams::from_request(req, &params)?;
+
+    if filter_params.has_text() {
+        let conn = req.db_read_only()?;
+        let any_matches = diesel::select(exists(filter_params.make_query())).get_result(&*conn)?;
+        filter_params.fuzzy = !any_matches;
+    }
 
     let selection = (
         ALL_COLUMNS,
         false.into_sql::<Bool>(),
         recent_crate_downloads::downloads.nullable(),
     );
-    let mut query = crates::table
-        .left_join(recent_crate_downloads::table)
-        .select(selection)
-        .into_boxed();
+    let mut query = filter_params.make_query().select(selection);
 
+    // Seek-based pagination is supported when sorting by name, and when sorting the results of a
+    // query string by relevance. The other sort orders fall back to offset-based pagination.
     let mut supports_seek = true;
+    // The query string of results sorted by relevance, which seeking needs to compare by it.
+    let mut relevance_query = None;
 
-    if let Some(q_string) = params.get("q") {
-        // Searching with a query string always puts the exact match at the start of the results,
-        // so we can't support seek-based pagination with it.
-        supports_seek = false;
-
+    if let Some(q_string) = &filter_params.text {
         if !q_string.is_empty() {
-            let sort = params.get("sort").map(|s| &**s).unwrap_or("relevance");
-
-            let q = sql::<TsQuery>("plainto_tsquery('english', ")
-                .bind::<Text, _>(q_string)
-                .sql(")");
-            query = query.filter(
-                q.clone()
-                    .matches(crates::textsearchable_index_col)
-                    .or(Crate::loosly_matches_name(q_string)),
-            );
+            let sort = sort.unwrap_or("relevance");
 
             query = query.select((
                 ALL_COLUMNS,
@@ -86,135 +94,16 @@ pub fn search(req: &mut dyn RequestExt) -> EndpointResult {
             query = query.order(Crate::with_name(q_string).desc());
 
             if sort == "relevance" {
-                let rank = ts_rank_cd(crates::textsearchable_index_col, q);
-                query = query.then_order_by(rank.desc())
+                let rank = relevance(q_string, filter_params.fuzzy);
+                query = query.then_order_by(rank.desc());
+                relevance_query = Some(q_string.as_str());
+            } else {
+                // The exact match comes first regardless of the sort order.
+                supports_seek = false;
             }
         }
     }
 
-    if let Some(cat) = params.get("category") {
-        // Calculating the total number of results with filters is not supported yet.
-        supports_seek = false;
-
-        query = query.filter(
-            crates::id.eq_any(
-                crates_categories::table
-                    .select(crates_categories::crate_id)
-                    .inner_join(categories::table)
-                    .filter(
-                        categories::slug
-                            .eq(cat)
-                            .or(categories::slug.like(format!("{cat}::%"))),
-                    ),
-            ),
-        );
-    }
-
-    if let Some(kws) = params.get("all_keywords") {
-        // Calculating the total number of results with filters is not supported yet.
-        supports_seek = false;
-
-        let names: Vec<_> = kws
-            .split_whitespace()
-            .map(|name| name.to_lowercase())
-            .collect();
-
-        query = query.filter(
-            // FIXME: Just use `.contains` in Diesel 2.0
-            // https://github.com/diesel-rs/diesel/issues/2066
-            Contains::new(
-                crates_keywords::table
-                    .inner_join(keywords::table)
-                    .filter(crates_keywords::crate_id.eq(crates::id))
-                    .select(array_agg(keywords::keyword))
-                    .single_value(),
-                names.into_sql::<Array<Text>>(),
-            ),
-        );
-    } else if let Some(kw) = params.get("keyword") {
-        // Calculating the total number of results with filters is not supported yet.
-        supports_seek = false;
-
-        query = query.filter(
-            crates::id.eq_any(
-                crates_keywords::table
-                    .select(crates_keywords::crate_id)
-                    .inner_join(keywords::table)
-                    .filter(lower(keywords::keyword).eq(lower(kw))),
-            ),
-        );
-    } else if let Some(letter) = params.get("letter") {
-        // Calculating the total number of results with filters is not supported yet.
-        supports_seek = false;
-
-        let pattern = format!(
-            "{}%",
-            letter
-                .chars()
-                .next()
-                .ok_or_else(|| bad_request("letter value must contain 1 character"))?
-                .to_lowercase()
-                .collect::<String>()
-        );
-        query = query.filter(canon_crate_name(crates::name).like(pattern));
-    } else if let Some(user_id) = params.get("user_id").and_then(|s| s.parse::<i32>().ok()) {
-        // Calculating the total number of results with filters is not supported yet.
-        supports_seek = false;
-
-        query = query.filter(
-            crates::id.eq_any(
-                CrateOwner::by_owner_kind(OwnerKind::User)
-                    .select(crate_owners::crate_id)
-                    .filter(crate_owners::owner_id.eq(user_id)),
-            ),
-        );
-    } else if let Some(team_id) = params.get("team_id").and_then(|s| s.parse::<i32>().ok()) {
-        // Calculating the total number of results with filters is not supported yet.
-        supports_seek = false;
-
-        query = query.filter(
-            crates::id.eq_any(
-                CrateOwner::by_owner_kind(OwnerKind::Team)
-                    .select(crate_owners::crate_id)
-                    .filter(crate_owners::owner_id.eq(team_id)),
-            ),
-        );
-    } else if params.get("following").is_some() {
-        // Calculating the total number of results with filters is not supported yet.
-        supports_seek = false;
-
-        let user_id = req.authenticate()?.user_id();
-        query = query.filter(
-            crates::id.eq_any(
-                follows::table
-                    .select(follows::crate_id)
-                    .filter(follows::user_id.eq(user_id)),
-            ),
-        );
-    } else if params.get("ids[]").is_some() {
-        // Calculating the total number of results with filters is not supported yet.
-        supports_seek = false;
-
-        let query_bytes = req.query_string().unwrap_or("").as_bytes();
-        let ids: Vec<_> = url::form_urlencoded::parse(query_bytes)
-            .filter(|(key, _)| key == "ids[]")
-            .map(|(_, value)| value.to_string())
-            .collect();
-
-        query = query.filter(crates::name.eq(any(ids)));
-    }
-
-    if !include_yanked {
-        // Calculating the total number of results with filters is not supported yet.
-        supports_seek = false;
-
-        query = query.filter(exists(
-            versions::table
-                .filter(versions::crate_id.eq(crates::id))
-                .filter(versions::yanked.eq(false)),
-        ));
-    }
-
     if sort == Some("downloads") {
         // Custom sorting is not supported yet with seek.
         supports_seek = false;
@@ -225,6 +114,30 @@ pub fn search(req: &mut dyn RequestExt) -> EndpointResult {
         supports_seek = false;
 
         query = query.then_order_by(recent_crate_downloads::downloads.desc().nulls_last())
+    } else if sort == Some("trending") {
+        // Custom sorting is not supported yet with seek.
+        supports_seek = false;
+
+        query = query.then_order_by(
+            crate_scores::table
+                .select(crate_scores::trending_score)
+                .filter(crate_scores::crate_id.eq(crates::id))
+                .single_value()
+                .desc()
+                .nulls_last(),
+        )
+    } else if sort == Some("popularity") {
+        // Custom sorting is not supported yet with seek.
+        supports_seek = false;
+
+        query = query.then_order_by(
+            crate_scores::table
+                .select(crate_scores::popularity_score)
+                .filter(crate_scores::crate_id.eq(crates::id))
+                .single_value()
+                .desc()
+                .nulls_last(),
+        )
     } else if sort == Some("recent-updates") {
         // Custom sorting is not supported yet with seek.
         supports_seek = false;
@@ -255,24 +168,55 @@ pub fn search(req: &mut dyn RequestExt) -> EndpointResult {
     // not been provided. This way clients relying on meta.next_page will use the faster seek-based
     // paginations, while client hardcoding pages handling will use the slower offset-based code.
     let (total, next_page, prev_page, data, conn) = if supports_seek && !explicit_page {
-        // Equivalent of:
-        // `WHERE name > (SELECT name FROM crates WHERE id = $1) LIMIT $2`
         query = query.limit(pagination.per_page as i64);
-        if let Some(seek) = seek {
-            let crate_name: String = crates::table
-                .find(seek)
-                .select(crates::name)
-                .get_result(&*conn)?;
-            query = query.filter(crates::name.gt(crate_name));
+        match (seek, relevance_query) {
+            (Some(seek), Some(q_string)) => {
+                // Continues after the crate of the seek key, in the order of
+                // `(exact match DESC, rank DESC, name ASC)`
+                let fuzzy = filter_params.fuzzy;
+                let (exact_match, rank, crate_name): (bool, f32, String) = crates::table
+                    .find(seek)
+                    .select((
+                        Crate::with_name(q_string),
+                        relevance(q_string, fuzzy),
+                        crates::name,
+                    ))
+                    .get_result(&*conn)?;
+                query = query.filter(
+                    Crate::with_name(q_string)
+                        .lt(exact_match)
+                        .or(Crate::with_name(q_string).eq(exact_match).and(
+                            relevance(q_string, fuzzy)
+                                .lt(rank)
+                                .or(relevance(q_string, fuzzy)
+                                    .eq(rank)
+                                    .and(crates::name.gt(crate_name))),
+                        )),
+                );
+            }
+            (Some(seek), None) => {
+                // Equivalent of:
+                // `WHERE name > (SELECT name FROM crates WHERE id = $1) LIMIT $2`
+                let crate_name: String = crates::table
+                    .find(seek)
+                    .select(crates::name)
+                    .get_result(&*conn)?;
+                query = query.filter(crates::name.gt(crate_name));
+            }
+            (None, _) => {}
         }
 
-        // This does a full index-only scan over the crates table to gather how many crates were
-        // published. Unfortunately on PostgreSQL counting the rows in a table requires scanning
-        // the table, and the `total` field is part of the stable registries API.
+        // Without filters, this does a full index-only scan over the crates table to gather how
+        // many crates were published. Unfortunately on PostgreSQL counting the rows in a table
+        // requires scanning the table, and the `total` field is part of the stable registries API.
         //
         // If this becomes a problem in the future the crates count could be denormalized, at least
         // for the filterless happy path.
-        let total: i64 = crates::table.count().get_result(&*conn)?;
+        let total: i64 = if filter_params.has_filters() {
+            filter_params.make_query().count().get_result(&*conn)?
+        } else {
+            crates::table.count().get_result(&*conn)?
+        };
 
         let results: Vec<(Crate, bool, Option<i64>)> = query.load(&*conn)?;
 
@@ -339,14 +283,656 @@ pub fn search(req: &mut dyn RequestExt) -> EndpointResult {
         )
         .collect::<Vec<_>>();
 
+    let mut meta = json!({
+        "total": total,
+        "next_page": next_page,
+        "prev_page": prev_page,
+    });
+    if filter_params.fuzzy {
+        meta["fuzzy"] = json!(true);
+    }
+    if include_facets {
+        meta["facets"] = load_facets(&conn, &filter_params)?;
+    }
+
     Ok(req.json(&json!({
         "crates": crates,
-        "meta": {
-            "total": total,
-            "next_page": next_page,
-            "prev_page": prev_page,
-        },
+        "meta": meta,
     })))
 }
 
+/// How well a crate matches the query string, which the results are sorted by when sorting by
+/// relevance. With the fuzzy fallback, this is the similarity of the name instead.
+fn relevance<QS>(
+    q_string: &str,
+    fuzzy: bool,
+) -> Box<dyn BoxableExpression<QS, Pg, SqlType = Float> + '_>
+where
+    crates::name: SelectableExpression<QS>,
+    crates::textsearchable_index_col: SelectableExpression<QS>,
+{
+    use diesel::sql_types::Text;
+
+    if fuzzy {
+        Box::new(similarity(
+            canon_crate_name(crates::name),
+            canon_crate_name(q_string),
+        ))
+    } else {
+        let q = sql::<TsQuery>("plainto_tsquery('english', ")
+            .bind::<Text, _>(q_string)
+            .sql(")");
+        Box::new(ts_rank_cd(crates::textsearchable_index_col, q))
+    }
+}
+
+/// The number of suggestions returned by default.
+const DEFAULT_SUGGESTIONS: i64 = 10;
+/// The most suggestions that can be requested.
+const MAX_SUGGESTIONS: i64 = 20;
+
+/// Handles the `GET /crate_suggestions` route, which completes the crate name
+/// typed so far in `q`.
+///
+/// Returns the most downloaded crates whose names start with `q`, treating
+/// `-` and `_` alike. If there are none, the crates with the most similar
+/// names are returned instead, to get past typos. Only the `crates` table is
+/// queried, so that this is cheap enough to call on every keystroke.
+pub fn suggest(req: &mut dyn RequestExt) -> EndpointResult {
+    #[derive(Serialize, Queryable)]
+    struct Suggestion {
+        name: String,
+        description: Option<String>,
+        downloads: i32,
+    }
+
+    let params = req.query();
+    let q = params.get("q").map(|q| q.trim()).unwrap_or("");
+    let limit = params
+        .get("limit")
+        .map(|limit| {
+            limit
+                .parse()
+                .ok()
+                .filter(|limit| (1..=MAX_SUGGESTIONS).contains(limit))
+                .ok_or_else(|| {
+                    bad_request(&format!(
+                        "invalid limit, expected a number from 1 to {MAX_SUGGESTIONS}"
+                    ))
+                })
+        })
+        .transpose()?
+        .unwrap_or(DEFAULT_SUGGESTIONS);
+
+    // Nothing else can start a crate name, which also means the prefix doesn't need escaping
+    // besides the `_` wildcard
+    let is_prefix = q.len() <= MAX_NAME_LENGTH
+        && q.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if q.is_empty() || !is_prefix {
+        return Ok(req.json(&json!({ "crates": [] })));
+    }
+
+    let columns = (crates::name, crates::description, crates::downloads);
+    let conn = req.db_read_only()?;
+    let prefix = q.to_lowercase().replace('-', "_").replace('_', "\\_");
+    let mut suggestions: Vec<Suggestion> = crates::table
+        .filter(canon_crate_name(crates::name).like(format!("{prefix}%")))
+        .order((crates::downloads.desc(), crates::name.asc()))
+        .select(columns)
+        .limit(limit)
+        .load(&*conn)?;
+
+    if suggestions.is_empty() {
+        let name_similarity = similarity(canon_crate_name(crates::name), canon_crate_name(q));
+        suggestions = crates::table
+            .filter(Crate::fuzzy_matches_name(q))
+            .order((name_similarity.desc(), crates::downloads.desc()))
+            .select(columns)
+            .limit(limit)
+            .load(&*conn)?;
+    }
+
+    Ok(req.json(&json!({ "crates": suggestions })))
+}
+
+/// The latest version of the crate by semver, through the `latest` alias. Expressions using it
+/// need to be closed with a parenthesis.
+///
+/// The order matches the `versions_crate_id_semver_order` index, so that finding the latest
+/// version is a single index probe per crate.
+const LATEST_VERSION: &str = "EXISTS (SELECT 1 FROM (\
+    SELECT versions.* FROM versions WHERE versions.crate_id = crates.id \
+    ORDER BY to_semver_no_prerelease(versions.num) DESC NULLS LAST, versions.id DESC LIMIT 1\
+    ) latest WHERE ";
+
+/// Like `LATEST_VERSION`, but skipping yanked versions.
+const LATEST_UNYANKED_VERSION: &str = "EXISTS (SELECT 1 FROM (\
+    SELECT versions.* FROM versions WHERE versions.crate_id = crates.id AND NOT versions.yanked \
+    ORDER BY to_semver_no_prerelease(versions.num) DESC NULLS LAST, versions.id DESC LIMIT 1\
+    ) latest WHERE ";
+
+/// The license of the latest unyanked version of the crate, in the same order as
+/// `LATEST_UNYANKED_VERSION`.
+const LATEST_UNYANKED_LICENSE: &str = "(SELECT versions.license FROM versions \
+    WHERE versions.crate_id = crates.id AND NOT versions.yanked \
+    ORDER BY to_semver_no_prerelease(versions.num) DESC NULLS LAST, versions.id DESC LIMIT 1)";
+
+/// The number of values returned for each facet.
+const FACET_LIMIT: i64 = 20;
+
+type BoxedCrates<'a> = IntoBoxed<'a, LeftJoin<crates::table, recent_crate_downloads::table>, Pg>;
+
+/// The filters of the search, which are all applied together. Most of them can be given both as
+/// parameters and as qualifiers in the query string, see `query`.
+struct FilterParams {
+    /// The words and phrases of the query string, which is present even if it's empty.
+    text: Option<String>,
+    /// Phrases which need to appear in this order.
+    phrases: Vec<String>,
+    /// Whether `text` is matched against the names of crates by similarity instead, which is
+    /// used when nothing matches it otherwise.
+    fuzzy: bool,
+    excluded_terms: Vec<String>,
+    include_yanked: bool,
+    categories: Vec<String>,
+    excluded_categories: Vec<String>,
+    /// Keywords which all need to be present.
+    all_keywords: Vec<String>,
+    keyword: Option<String>,
+    excluded_keywords: Vec<String>,
+    letter: Option<String>,
+    user_id: Option<i32>,
+    team_id: Option<i32>,
+    following_user_id: Option<i32>,
+    ids: Option<Vec<String>>,
+    /// License identifiers, one of which needs to be part of the license expression of the
+    /// latest version.
+    licenses: Vec<String>,
+    excluded_licenses: Vec<String>,
+    /// Only crates whose latest version declares a `rust-version` no newer than this one.
+    ///
+    /// Versions without a `rust-version` are treated as compatible, like cargo does. That
+    /// includes all versions published before the `rust-version` was stored, since it wasn't
+    /// backfilled, so this only excludes crates that are known to need a newer Rust.
+    max_rust_version: Option<String>,
+    updated_since: Option<NaiveDate>,
+    min_downloads: Option<i32>,
+    no_yanked_latest: bool,
+    /// Logins of users, GitHub organizations or teams like `github:rust-lang:core`, which all
+    /// need to own the crate.
+    owners: Vec<String>,
+    excluded_owners: Vec<String>,
+}
+
+impl FilterParams {
+    fn from_request(
+        req: &mut dyn RequestExt,
+        params: &IndexMap<String, String>,
+    ) -> AppResult<Self> {
+        let letter = params
+            .get("letter")
+            .map(|letter| {
+                letter
+                    .chars()
+                    .next()
+                    .ok_or_else(|| bad_request("letter value must contain 1 character"))
+                    .map(|letter| letter.to_lowercase().collect::<String>())
+            })
+            .transpose()?;
+
+        let following_user_id = if params.get("following").is_some() {
+            Some(req.authenticate()?.user_id())
+        } else {
+            None
+        };
+
+        let ids = params.get("ids[]").map(|_| {
+            let query_bytes = req.query_string().unwrap_or("").as_bytes();
+            url::form_urlencoded::parse(query_bytes)
+                .filter(|(key, _)| key == "ids[]")
+                .map(|(_, value)| value.to_string())
+                .collect()
+        });
+
+        let updated_since = params
+            .get("updated_since")
+            .map(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d"))
+            .transpose()
+            .map_err(|_| bad_request("invalid date in updated_since, expected YYYY-MM-DD"))?;
+
+        let min_downloads = params
+            .get("min_downloads")
+            .map(|downloads| downloads.parse())
+            .transpose()
+            .map_err(|_| bad_request("invalid min_downloads, expected a number"))?;
+
+        let mut filter_params = Self {
+            text: None,
+            phrases: Vec::new(),
+            fuzzy: false,
+            excluded_terms: Vec::new(),
+            include_yanked: params
+                .get("include_yanked")
+                .map(|s| s == "yes")
+                .unwrap_or(true),
+            categories: params.get("category").cloned().into_iter().collect(),
+            excluded_categories: Vec::new(),
+            all_keywords: params
+                .get("all_keywords")
+                .map(|kws| {
+                    kws.split_whitespace()
+                        .map(|name| name.to_lowercase())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            keyword: params.get("keyword").cloned(),
+            excluded_keywords: Vec::new(),
+            letter,
+            user_id: params.get("user_id").and_then(|s| s.parse().ok()),
+            team_id: params.get("team_id").and_then(|s| s.parse().ok()),
+            following_user_id,
+            ids,
+            licenses: params
+                .get("license")
+                .map(|licenses| licenses.split(',').map(str::to_string).collect())
+                .unwrap_or_default(),
+            excluded_licenses: Vec::new(),
+            max_rust_version: params.get("max_rust_version").cloned(),
+            updated_since,
+            min_downloads,
+            no_yanked_latest: params
+                .get("no_yanked_latest")
+                .map(|s| s == "yes")
+                .unwrap_or(false),
+            owners: params.get("owner").cloned().into_iter().collect(),
+            excluded_owners: Vec::new(),
+        };
+
+        if let Some(q_string) = params.get("q") {
+            let terms = query::parse(q_string).map_err(|err| bad_request(&err.to_string()))?;
+            filter_params.add_query_terms(terms);
+        }
+
+        filter_params.licenses = normalize_licenses(&filter_params.licenses);
+        filter_params.excluded_licenses = normalize_licenses(&filter_params.excluded_licenses);
+        if let Some(rust_version) = &filter_params.max_rust_version {
+            if !is_valid_rust_version(rust_version) {
+                return Err(bad_request(
+                    "invalid rust-version, expected a version like `1.56` or `1.56.1`",
+                ));
+            }
+        }
+
+        Ok(filter_params)
+    }
+
+    /// Maps the terms of the query string onto the filters.
+    fn add_query_terms(&mut self, terms: Vec<QueryTerm>) {
+        let mut words = Vec::new();
+        for QueryTerm { term, negated } in terms {
+            match (term, negated) {
+                (Term::Word(word), false) => words.push(word),
+                (Term::Phrase(phrase), false) => {
+                    words.push(phrase.clone());
+                    self.phrases.push(phrase);
+                }
+                (Term::Word(term) | Term::Phrase(term), true) => self.excluded_terms.push(term),
+                (Term::Qualifier(field, value), negated) => {
+                    let values = match (field, negated) {
+                        (Field::Keyword, false) => &mut self.all_keywords,
+                        (Field::Keyword, true) => &mut self.excluded_keywords,
+                        (Field::Category, false) => &mut self.categories,
+                        (Field::Category, true) => &mut self.excluded_categories,
+                        (Field::Owner, false) => &mut self.owners,
+                        (Field::Owner, true) => &mut self.excluded_owners,
+                        (Field::License, false) => &mut self.licenses,
+                        (Field::License, true) => &mut self.excluded_licenses,
+                        // Negated `rust-version:` qualifiers are rejected by the parser
+                        (Field::RustVersion, _) => {
+                            self.max_rust_version = Some(value);
+                            continue;
+                        }
+                    };
+                    values.push(if field == Field::Keyword {
+                        value.to_lowercase()
+                    } else {
+                        value
+                    });
+                }
+            }
+        }
+        self.text = Some(words.join(" "));
+    }
+
+    /// Whether the query string contains any words or phrases to search for.
+    fn has_text(&self) -> bool {
+        self.text.as_ref().map_or(false, |text| !text.is_empty())
+    }
+
+    /// Whether any filter (including the query string) narrows down the results.
+    fn has_filters(&self) -> bool {
+        self.text.is_some()
+            || !self.include_yanked
+            || !self.categories.is_empty()
+            || !self.excluded_categories.is_empty()
+            || !self.all_keywords.is_empty()
+            || self.keyword.is_some()
+            || !self.excluded_keywords.is_empty()
+            || self.letter.is_some()
+            || self.user_id.is_some()
+            || self.team_id.is_some()
+            || self.following_user_id.is_some()
+            || self.ids.is_some()
+            || !self.licenses.is_empty()
+            || !self.excluded_licenses.is_empty()
+            || self.max_rust_version.is_some()
+            || self.updated_since.is_some()
+            || self.min_downloads.is_some()
+            || self.no_yanked_latest
+            || !self.owners.is_empty()
+            || !self.excluded_owners.is_empty()
+    }
+
+    /// Builds a query for the crates matching all filters. It's built again for every use, since
+    /// boxed queries can't be cloned.
+    fn make_query(&self) -> BoxedCrates<'_> {
+        use diesel::sql_types::{Bool, Text};
+
+        let mut query = crates::table
+            .left_join(recent_crate_downloads::table)
+            .into_boxed();
+
+        let matches_phrase = |phrase: &str| {
+            sql::<TsQuery>("phraseto_tsquery('english', ")
+                .bind::<Text, _>(phrase.to_string())
+                .sql(")")
+                .matches(crates::textsearchable_index_col)
+        };
+        let in_category = |category: &str| {
+            crates::id.eq_any(
+                crates_categories::table
+                    .select(crates_categories::crate_id)
+                    .inner_join(categories::table)
+                    .filter(
+                        categories::slug
+                            .eq(category.to_string())
+                            .or(categories::slug.like(format!("{category}::%"))),
+                    ),
+            )
+        };
+        let has_keyword = |keyword: &str| {
+            crates::id.eq_any(
+                crates_keywords::table
+                    .select(crates_keywords::crate_id)
+                    .inner_join(keywords::table)
+                    .filter(lower(keywords::keyword).eq(lower(keyword.to_string()))),
+            )
+        };
+        let has_license = |licenses: &[String]| {
+            // Splits expressions like `MIT OR Apache-2.0` and the older `MIT/Apache-2.0` into
+            // their license identifiers
+            let matches_license = format!(
+                "{LATEST_UNYANKED_VERSION}\
+                 regexp_split_to_array(lower(latest.license), '[\\s()/]+') && "
+            );
+            sql::<Bool>(&matches_license)
+                .bind::<Array<Text>, _>(licenses.to_vec())
+                .sql(")")
+        };
+        let owned_by = |owner: &str| {
+            let owner = owner.to_lowercase();
+            // Team logins always contain a colon, like `github:rust-lang:core`, while other
+            // logins match both users and the teams of a GitHub organization
+            let escaped = owner
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            let team_pattern = if owner.contains(':') {
+                escaped
+            } else {
+                format!("github:{escaped}:%")
+            };
+            let users = CrateOwner::by_owner_kind(OwnerKind::User)
+                .select(crate_owners::crate_id)
+                .filter(
+                    crate_owners::owner_id.eq_any(
+                        users::table
+                            .select(users::id)
+                            .filter(lower(users::gh_login).eq(owner)),
+                    ),
+                );
+            let teams = CrateOwner::by_owner_kind(OwnerKind::Team)
+                .select(crate_owners::crate_id)
+                .filter(
+                    crate_owners::owner_id.eq_any(
+                        teams::table
+                            .select(teams::id)
+                            .filter(lower(teams::login).like(team_pattern)),
+                    ),
+                );
+            crates::id.eq_any(users).or(crates::id.eq_any(teams))
+        };
+
+        match &self.text {
+            Some(text) if !text.is_empty() && self.fuzzy => {
+                query = query.filter(Crate::fuzzy_matches_name(text));
+            }
+            Some(text) if !text.is_empty() => {
+                let q = sql::<TsQuery>("plainto_tsquery('english', ")
+                    .bind::<Text, _>(text)
+                    .sql(")");
+                query = query.filter(
+                    q.matches(crates::textsearchable_index_col)
+                        .or(Crate::loosly_matches_name(text)),
+                );
+
+                for phrase in &self.phrases {
+                    query = query.filter(matches_phrase(phrase));
+                }
+            }
+            _ => {}
+        }
+
+        for term in &self.excluded_terms {
+            query = query.filter(not(matches_phrase(term)));
+        }
+
+        for category in &self.categories {
+            query = query.filter(in_category(category));
+        }
+
+        for category in &self.excluded_categories {
+            query = query.filter(not(in_category(category)));
+        }
+
+        if !self.all_keywords.is_empty() {
+            query = query.filter(
+                // FIXME: Just use `.contains` in Diesel 2.0
+                // https://github.com/diesel-rs/diesel/issues/2066
+                Contains::new(
+                    crates_keywords::table
+                        .inner_join(keywords::table)
+                        .filter(crates_keywords::crate_id.eq(crates::id))
+                        .select(array_agg(keywords::keyword))
+                        .single_value(),
+                    self.all_keywords.clone().into_sql::<Array<Text>>(),
+                ),
+            );
+        }
+
+        if let Some(keyword) = &self.keyword {
+            query = query.filter(has_keyword(keyword));
+        }
+
+        for keyword in &self.excluded_keywords {
+            query = query.filter(not(has_keyword(keyword)));
+        }
+
+        if let Some(letter) = &self.letter {
+            let pattern = format!("{letter}%");
+            query = query.filter(canon_crate_name(crates::name).like(pattern));
+        }
+
+        if let Some(user_id) = self.user_id {
+            query = query.filter(
+                crates::id.eq_any(
+                    CrateOwner::by_owner_kind(OwnerKind::User)
+                        .select(crate_owners::crate_id)
+                        .filter(crate_owners::owner_id.eq(user_id)),
+                ),
+            );
+        }
+
+        if let Some(team_id) = self.team_id {
+            query = query.filter(
+                crates::id.eq_any(
+                    CrateOwner::by_owner_kind(OwnerKind::Team)
+                        .select(crate_owners::crate_id)
+                        .filter(crate_owners::owner_id.eq(team_id)),
+                ),
+            );
+        }
+
+        if let Some(user_id) = self.following_user_id {
+            query = query.filter(
+                crates::id.eq_any(
+                    follows::table
+                        .select(follows::crate_id)
+                        .filter(follows::user_id.eq(user_id)),
+                ),
+            );
+        }
+
+        if let Some(ids) = &self.ids {
+            query = query.filter(crates::name.eq(any(ids.clone())));
+        }
+
+        if !self.include_yanked {
+            query = query.filter(exists(
+                versions::table
+                    .filter(versions::crate_id.eq(crates::id))
+                    .filter(versions::yanked.eq(false)),
+            ));
+        }
+
+        if !self.licenses.is_empty() {
+            query = query.filter(has_license(&self.licenses));
+        }
+
+        if !self.excluded_licenses.is_empty() {
+            query = query.filter(not(has_license(&self.excluded_licenses)));
+        }
+
+        if let Some(rust_version) = &self.max_rust_version {
+            let compatible = format!(
+                "{LATEST_UNYANKED_VERSION}latest.rust_version IS NULL OR \
+                 string_to_array(latest.rust_version, '.')::int[] <= string_to_array("
+            );
+            query = query.filter(
+                sql::<Bool>(&compatible)
+                    .bind::<Text, _>(rust_version)
+                    .sql(", '.')::int[])"),
+            );
+        }
+
+        if let Some(date) = self.updated_since {
+            query = query.filter(crates::updated_at.ge(date.and_hms(0, 0, 0)));
+        }
+
+        if let Some(downloads) = self.min_downloads {
+            query = query.filter(crates::downloads.ge(downloads));
+        }
+
+        if self.no_yanked_latest {
+            query = query.filter(sql::<Bool>(&format!("{LATEST_VERSION}NOT latest.yanked)")));
+        }
+
+        for owner in &self.owners {
+            query = query.filter(owned_by(owner));
+        }
+
+        for owner in &self.excluded_owners {
+            query = query.filter(not(owned_by(owner)));
+        }
+
+        query
+    }
+}
+
+/// Lowercases license identifiers and drops empty ones, e.g. from `license=MIT,`.
+fn normalize_licenses(licenses: &[String]) -> Vec<String> {
+    licenses
+        .iter()
+        .map(|license| license.trim().to_lowercase())
+        .filter(|license| !license.is_empty())
+        .collect()
+}
+
+/// Counts the crates matching the filters by category, keyword and license of their latest
+/// version, returning the most common values of each.
+fn load_facets(conn: &PgConnection, filter_params: &FilterParams) -> AppResult<Value> {
+    use diesel::sql_types::{Nullable, Text};
+
+    #[derive(Serialize, Queryable)]
+    struct CategoryFacet {
+        category: String,
+        count: i64,
+    }
+
+    #[derive(Serialize, Queryable)]
+    struct KeywordFacet {
+        keyword: String,
+        count: i64,
+    }
+
+    #[derive(Serialize, Queryable)]
+    struct LicenseFacet {
+        license: Option<String>,
+        count: i64,
+    }
+
+    let crate_ids = || filter_params.make_query().select(crates::id);
+
+    let categories: Vec<CategoryFacet> = crates_categories::table
+        .inner_join(categories::table)
+        .filter(crates_categories::crate_id.eq_any(crate_ids()))
+        .group_by(categories::slug)
+        .select((categories::slug, count_star()))
+        .order((count_star().desc(), categories::slug.asc()))
+        .limit(FACET_LIMIT)
+        .load(conn)?;
+
+    let keywords: Vec<KeywordFacet> = crates_keywords::table
+        .inner_join(keywords::table)
+        .filter(crates_keywords::crate_id.eq_any(crate_ids()))
+        .group_by(keywords::keyword)
+        .select((keywords::keyword, count_star()))
+        .order((count_star().desc(), keywords::keyword.asc()))
+        .limit(FACET_LIMIT)
+        .load(conn)?;
+
+    // The latest version is looked up once per matching crate, instead of once per version.
+    // Crates without a license are grouped together and left out afterwards, which is why one
+    // more group is loaded.
+    let latest_license = || sql::<Nullable<Text>>(LATEST_UNYANKED_LICENSE);
+    let licenses: Vec<LicenseFacet> = crates::table
+        .filter(crates::id.eq_any(crate_ids()))
+        .group_by(latest_license())
+        .select((latest_license(), count_star()))
+        .order((count_star().desc(), latest_license().asc()))
+        .limit(FACET_LIMIT + 1)
+        .load::<LicenseFacet>(conn)?
+        .into_iter()
+        .filter(|facet| facet.license.is_some())
+        .take(FACET_LIMIT as usize)
+        .collect();
+
+    Ok(json!({
+        "categories": categories,
+        "keywords": keywords,
+        "licenses": licenses,
+    }))
+}
+
 diesel_infix_operator!(Contains, "@>");
@@ -0,0 +1,265 @@
+//! Parsing of the query language of the `q` parameter, like
+//! `http "web framework" keyword:async -owner:foo`.
+//!
+//! - Words and `"quoted phrases"` are searched for in the names, descriptions and keywords of
+//!   crates.
+//! - `field:value` and `field:"quoted value"` qualifiers are mapped onto the filters of the
+//!   search, see `Field`.
+//! - A leading `-` excludes the crates matching a word, phrase or qualifier.
+//!
+//! Anything that can't be parsed as a qualifier, like `std::io`, is searched for as a word.
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Field {
+    /// `keyword:async`
+    Keyword,
+    /// `category:network-programming`, including its subcategories
+    Category,
+    /// `owner:tokio-rs`, a user or the teams of a GitHub organization, or a team like
+    /// `owner:github:rust-lang:core`
+    Owner,
+    /// `license:MIT`, a license identifier in the license expression of the latest version
+    License,
+    /// `rust-version:1.56`, the newest `rust-version` the latest version may require
+    RustVersion,
+}
+
+impl Field {
+    const ALL: &'static [(&'static str, Field)] = &[
+        ("keyword", Field::Keyword),
+        ("category", Field::Category),
+        ("owner", Field::Owner),
+        ("license", Field::License),
+        ("rust-version", Field::RustVersion),
+    ];
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|(field_name, _)| name.eq_ignore_ascii_case(field_name))
+            .map(|(_, field)| *field)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum Term {
+    Word(String),
+    Phrase(String),
+    Qualifier(Field, String),
+}
+
+/// A term of the query, which the matching crates either need to match or, if it's `negated`,
+/// must not match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct QueryTerm {
+    pub(super) term: Term,
+    pub(super) negated: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum ParseError {
+    UnknownQualifier(String),
+    EmptyQualifier(String),
+    UnterminatedQuote,
+    NegatedRustVersion,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnknownQualifier(name) => {
+                let known = Field::ALL
+                    .iter()
+                    .map(|(name, _)| format!("`{name}`"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(
+                    f,
+                    "unknown search qualifier `{name}:`, expected one of {known}"
+                )
+            }
+            ParseError::EmptyQualifier(name) => {
+                write!(f, "missing value for the search qualifier `{name}:`")
+            }
+            ParseError::UnterminatedQuote => f.write_str("unterminated quote in the search query"),
+            ParseError::NegatedRustVersion => {
+                f.write_str("the search qualifier `rust-version:` can't be negated")
+            }
+        }
+    }
+}
+
+pub(super) fn parse(q: &str) -> Result<Vec<QueryTerm>, ParseError> {
+    let mut terms = Vec::new();
+    let mut chars = q.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let first = match chars.next() {
+            Some(c) => c,
+            None => break,
+        };
+
+        let negated = first == '-' && chars.peek().map_or(false, |c| !c.is_whitespace());
+        let first = if negated {
+            chars.next().unwrap()
+        } else {
+            first
+        };
+
+        if first == '"' {
+            let phrase = read_quoted(&mut chars)?;
+            if !phrase.trim().is_empty() {
+                terms.push(QueryTerm {
+                    term: Term::Phrase(phrase),
+                    negated,
+                });
+            }
+            continue;
+        }
+
+        let mut word = String::from(first);
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '"') {
+            word.push(c);
+        }
+
+        let term = match qualifier_name(&word) {
+            Some(name) => {
+                let field = Field::from_name(name)
+                    .ok_or_else(|| ParseError::UnknownQualifier(name.into()))?;
+                let mut value = word[name.len() + 1..].to_string();
+                if value.is_empty() && chars.next_if_eq(&'"').is_some() {
+                    value = read_quoted(&mut chars)?;
+                }
+                let value = value.trim();
+                if value.is_empty() {
+                    return Err(ParseError::EmptyQualifier(name.into()));
+                }
+                if negated && field == Field::RustVersion {
+                    return Err(ParseError::NegatedRustVersion);
+                }
+                Term::Qualifier(field, value.into())
+            }
+            None => Term::Word(word),
+        };
+        terms.push(QueryTerm { term, negated });
+    }
+
+    Ok(terms)
+}
+
+/// Returns the name of the qualifier if `word` looks like `name:value`, or like `name:` when
+/// followed by a quoted value. Words like `std::io` or `https://` are not qualifiers.
+fn qualifier_name(word: &str) -> Option<&str> {
+    let (name, value) = word.split_once(':')?;
+    let is_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    let is_value = !value.starts_with(':') && !value.starts_with('/');
+    (is_name && is_value).then(|| name)
+}
+
+/// Reads the rest of a quoted string, after its opening quote.
+fn read_quoted(chars: &mut impl Iterator<Item = char>) -> Result<String, ParseError> {
+    let mut quoted = String::new();
+    for c in chars {
+        if c == '"' {
+            return Ok(quoted);
+        }
+        quoted.push(c);
+    }
+    Err(ParseError::UnterminatedQuote)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(word: &str, negated: bool) -> QueryTerm {
+        QueryTerm {
+            term: Term::Word(word.into()),
+            negated,
+        }
+    }
+
+    fn qualifier(field: Field, value: &str, negated: bool) -> QueryTerm {
+        QueryTerm {
+            term: Term::Qualifier(field, value.into()),
+            negated,
+        }
+    }
+
+    #[test]
+    fn words_and_phrases() {
+        assert_eq!(parse("").unwrap(), []);
+        assert_eq!(parse("  serde ").unwrap(), [word("serde", false)]);
+        assert_eq!(
+            parse(r#"web "http server" -blocking - std::io"#).unwrap(),
+            [
+                word("web", false),
+                QueryTerm {
+                    term: Term::Phrase("http server".into()),
+                    negated: false,
+                },
+                word("blocking", true),
+                word("-", false),
+                word("std::io", false),
+            ]
+        );
+        assert_eq!(
+            parse(r#"-"async std" https://docs.rs"#).unwrap(),
+            [
+                QueryTerm {
+                    term: Term::Phrase("async std".into()),
+                    negated: true,
+                },
+                word("https://docs.rs", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn qualifiers() {
+        assert_eq!(
+            parse("keyword:async owner:tokio-rs category:network-programming").unwrap(),
+            [
+                qualifier(Field::Keyword, "async", false),
+                qualifier(Field::Owner, "tokio-rs", false),
+                qualifier(Field::Category, "network-programming", false),
+            ]
+        );
+        assert_eq!(
+            parse(r#"License:"MIT" -owner:github:rust-lang:core rust-version:1.56"#).unwrap(),
+            [
+                qualifier(Field::License, "MIT", false),
+                qualifier(Field::Owner, "github:rust-lang:core", true),
+                qualifier(Field::RustVersion, "1.56", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            parse("author:foo"),
+            Err(ParseError::UnknownQualifier("author".into()))
+        );
+        assert_eq!(
+            parse("keyword: async"),
+            Err(ParseError::EmptyQualifier("keyword".into()))
+        );
+        assert_eq!(parse(r#"owner:"foo"#), Err(ParseError::UnterminatedQuote));
+        assert_eq!(
+            parse("-rust-version:1.56"),
+            Err(ParseError::NegatedRustVersion)
+        );
+        assert_eq!(
+            ParseError::UnknownQualifier("author".into()).to_string(),
+            "unknown search qualifier `author:`, expected one of `keyword`, `category`, `owner`, \
+             `license`, `rust-version`"
+        );
+    }
+}
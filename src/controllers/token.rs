@@ -1,10 +1,15 @@
 use super::frontend_prelude::*;
 
-use crate::models::ApiToken;
-use crate::schema::api_tokens;
+use crate::controllers::helpers::pagination::{Paginated, PaginationOptions};
+use crate::controllers::helpers::Paginate;
+use crate::email::RequestDetails;
+use crate::models::{ApiToken, ApiTokenUsage, User};
+use crate::schema::{api_token_usages, api_tokens};
+use crate::util::errors::not_found;
 use crate::util::read_fill;
-use crate::views::EncodableApiTokenWithToken;
+use crate::views::{EncodableApiTokenUsage, EncodableApiTokenWithToken};
 
+use chrono::{Duration, Utc};
 use conduit::{Body, Response};
 use serde_json as json;
 
@@ -67,6 +72,7 @@ pub fn new(req: &mut dyn RequestExt) -> EndpointResult {
     }
 
     let conn = req.db_conn()?;
+    authenticated_user.require_totp(req, &conn)?;
     let user = authenticated_user.user();
 
     let max_token_per_user = 500;
@@ -81,6 +87,18 @@ pub fn new(req: &mut dyn RequestExt) -> EndpointResult {
     let api_token = ApiToken::insert(&*conn, user.id, name)?;
     let api_token = EncodableApiTokenWithToken::from(api_token);
 
+    if let Some(email) = user.notification_email(&conn)? {
+        // Only log errors, the token was created whether or not the notification is sent
+        let details = RequestDetails::from_request(req);
+        let result =
+            req.app()
+                .emails
+                .send_token_created(&conn, &email, &user.gh_login, name, &details);
+        if let Err(error) = result {
+            warn!("Failed to notify user {} of a new token: {error}", user.id);
+        }
+    }
+
     Ok(req.json(&json!({ "api_token": api_token })))
 }
 
@@ -93,13 +111,65 @@ pub fn revoke(req: &mut dyn RequestExt) -> EndpointResult {
     let authenticated_user = req.authenticate()?;
     let conn = req.db_conn()?;
     let user = authenticated_user.user();
-    diesel::update(ApiToken::belonging_to(&user).find(id))
-        .set(api_tokens::revoked.eq(true))
-        .execute(&*conn)?;
+    let revoked_name: Option<String> = diesel::update(
+        ApiToken::belonging_to(&user)
+            .find(id)
+            .filter(api_tokens::revoked.eq(false)),
+    )
+    .set(api_tokens::revoked.eq(true))
+    .returning(api_tokens::name)
+    .get_result(&*conn)
+    .optional()?;
+
+    if let Some(name) = revoked_name {
+        notify_token_revoked(req, &conn, &user, &name)?;
+    }
 
     Ok(req.json(&json!({})))
 }
 
+/// Handles the `GET /me/tokens/:id/usage` route.
+///
+/// Lists the requests that were authenticated with the token, most recent first. Usages are
+/// only kept for `api_token_usage_retention_days`.
+pub fn usage(req: &mut dyn RequestExt) -> EndpointResult {
+    let id = req.params()["id"]
+        .parse::<i32>()
+        .map_err(|e| bad_request(&format!("invalid token id: {e:?}")))?;
+
+    let authenticated_user = req.authenticate()?.forbid_api_token_auth()?;
+    let pagination = PaginationOptions::builder().gather(req)?;
+    let conn = req.db_conn()?;
+    let user = authenticated_user.user();
+
+    let token: ApiToken = ApiToken::belonging_to(&user)
+        .find(id)
+        .first(&*conn)
+        .optional()?
+        .ok_or_else(not_found)?;
+
+    // Usages are only deleted once a day, so the older ones are skipped until then
+    let retention = Duration::days(req.app().config.api_token_usage_retention_days as i64);
+    let data: Paginated<ApiTokenUsage> = ApiTokenUsage::belonging_to(&token)
+        .filter(api_token_usages::used_at.ge(Utc::now().naive_utc() - retention))
+        .order((
+            api_token_usages::used_at.desc(),
+            api_token_usages::id.desc(),
+        ))
+        .pages_pagination(pagination)
+        .load(&*conn)?;
+    let total = data.total();
+    let usage = data
+        .into_iter()
+        .map(EncodableApiTokenUsage::from)
+        .collect::<Vec<_>>();
+
+    Ok(req.json(&json!({
+        "usage": usage,
+        "meta": { "total": total },
+    })))
+}
+
 /// Handles the `DELETE /tokens/current` route.
 pub fn revoke_current(req: &mut dyn RequestExt) -> EndpointResult {
     let authenticated_user = req.authenticate()?;
@@ -108,9 +178,37 @@ pub fn revoke_current(req: &mut dyn RequestExt) -> EndpointResult {
         .ok_or_else(|| bad_request("token not provided"))?;
 
     let conn = req.db_conn()?;
-    diesel::update(api_tokens::table.filter(api_tokens::id.eq(api_token_id)))
+    let name: String = diesel::update(api_tokens::table.filter(api_tokens::id.eq(api_token_id)))
         .set(api_tokens::revoked.eq(true))
-        .execute(&*conn)?;
+        .returning(api_tokens::name)
+        .get_result(&*conn)?;
+
+    let user = authenticated_user.user();
+    notify_token_revoked(req, &conn, &user, &name)?;
 
     Ok(Response::builder().status(204).body(Body::empty()).unwrap())
 }
+
+fn notify_token_revoked(
+    req: &dyn RequestExt,
+    conn: &PgConnection,
+    user: &User,
+    token_name: &str,
+) -> AppResult<()> {
+    if let Some(email) = user.notification_email(conn)? {
+        // Only log errors, the token was revoked whether or not the notification is sent
+        let details = RequestDetails::from_request(req);
+        let result =
+            req.app()
+                .emails
+                .send_token_revoked(conn, &email, &user.gh_login, token_name, &details);
+        if let Err(error) = result {
+            warn!(
+                "Failed to notify user {} of a revoked token: {error}",
+                user.id
+            );
+        }
+    }
+
+    Ok(())
+}
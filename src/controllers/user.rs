@@ -1,3 +1,6 @@
+pub mod data_export;
+pub mod emails;
 pub mod me;
 pub mod other;
 pub mod session;
+pub mod totp;
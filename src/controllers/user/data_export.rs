@@ -0,0 +1,81 @@
+use chrono::{Duration, Utc};
+use conduit::{Body, Response};
+use swirl::Job;
+
+use crate::controllers::frontend_prelude::*;
+
+use crate::models::DataExport;
+use crate::util::errors::{internal, not_found};
+use crate::views::EncodableDataExport;
+use crate::worker;
+
+/// Handles the `PUT /me/data_export` route.
+///
+/// Building the archive can take a while, so it is done by a background job. The status can be
+/// checked with the `GET /me/data_export` route, and the archive downloaded with the
+/// `GET /me/data_export/download` route once it's built.
+pub fn request(req: &mut dyn RequestExt) -> EndpointResult {
+    let authenticated_user = req.authenticate()?.forbid_api_token_auth()?;
+    let conn = req.db_conn()?;
+    let user = authenticated_user.user();
+
+    if let Some(latest) = DataExport::latest(&conn, &user)? {
+        let next_allowed = latest.requested_at + Duration::days(1);
+        if next_allowed > Utc::now().naive_utc() {
+            return Err(bad_request(
+                "a data export can only be requested once a day, please try again later",
+            ));
+        }
+    }
+
+    let export = conn.transaction::<_, Box<dyn AppError>, _>(|| {
+        let export = DataExport::insert(&conn, user.id)?;
+        worker::export_user_data(export.id).enqueue(&conn)?;
+        Ok(export)
+    })?;
+
+    Ok(req.json(&json!({ "data_export": EncodableDataExport::from(export) })))
+}
+
+/// Handles the `GET /me/data_export` route.
+pub fn show(req: &mut dyn RequestExt) -> EndpointResult {
+    let authenticated_user = req.authenticate()?.forbid_api_token_auth()?;
+    let conn = req.db_conn()?;
+    let user = authenticated_user.user();
+
+    let export = DataExport::latest(&conn, &user)?.map(EncodableDataExport::from);
+
+    Ok(req.json(&json!({ "data_export": export })))
+}
+
+/// Handles the `GET /me/data_export/download` route.
+///
+/// The archive contains private data, so it isn't stored publicly but served through this
+/// route to the user it belongs to, until it expires after `DATA_EXPORT_TTL_DAYS`.
+pub fn download(req: &mut dyn RequestExt) -> EndpointResult {
+    let authenticated_user = req.authenticate()?.forbid_api_token_auth()?;
+    let conn = req.db_conn()?;
+    let user = authenticated_user.user();
+
+    let export = DataExport::latest(&conn, &user)?
+        .filter(DataExport::is_downloadable)
+        .ok_or_else(not_found)?;
+    drop(conn);
+
+    let app = req.app();
+    let archive = app
+        .config
+        .uploader()
+        .get_data_export(app.http_client(), export.user_id, &export.token)
+        .map_err(|e| internal(&format_args!("failed to download data export: {e}")))?;
+
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::CONTENT_LENGTH, archive.len())
+        .header(
+            header::CONTENT_DISPOSITION,
+            "attachment; filename=\"crates-io-data-export.json\"",
+        )
+        .header(header::CACHE_CONTROL, "private, no-store")
+        .body(Body::from_vec(archive))?)
+}
@@ -0,0 +1,235 @@
+use crate::controllers::frontend_prelude::*;
+
+use crate::email::RequestDetails;
+use crate::models::{Email, NewEmail, User};
+use crate::schema::emails;
+use crate::views::EncodableEmail;
+
+/// Handles the `GET /me/emails` route.
+pub fn list(req: &mut dyn RequestExt) -> EndpointResult {
+    let authenticated_user = req.authenticate()?.forbid_api_token_auth()?;
+    let conn = req.db_conn()?;
+    let user = authenticated_user.user();
+
+    let emails: Vec<EncodableEmail> = Email::belonging_to(&user)
+        .order((emails::is_primary.desc(), emails::id))
+        .load::<Email>(&*conn)?
+        .into_iter()
+        .map(EncodableEmail::from)
+        .collect();
+
+    Ok(req.json(&json!({ "emails": emails })))
+}
+
+/// Handles the `PUT /me/emails` route.
+///
+/// The new address has to be confirmed before it can become the primary address or receive
+/// notifications. The first address of a user becomes their primary address.
+pub fn add(req: &mut dyn RequestExt) -> EndpointResult {
+    #[derive(Deserialize)]
+    struct NewEmailRequest {
+        email: String,
+    }
+
+    let mut body = String::new();
+    req.body().read_to_string(&mut body)?;
+    let new: NewEmailRequest =
+        serde_json::from_str(&body).map_err(|_| bad_request("invalid json request"))?;
+
+    let address = new.email.trim();
+    if address.is_empty() {
+        return Err(bad_request("empty email rejected"));
+    }
+
+    let authenticated_user = req.authenticate()?.forbid_api_token_auth()?;
+    let conn = req.db_conn()?;
+    let user = authenticated_user.user();
+
+    let max_emails_per_user = 20;
+    let existing: Vec<Email> = Email::belonging_to(&user).load(&*conn)?;
+    if existing.len() >= max_emails_per_user {
+        return Err(bad_request(&format!(
+            "maximum email addresses per user is: {max_emails_per_user}"
+        )));
+    }
+    if existing
+        .iter()
+        .any(|email| email.email.eq_ignore_ascii_case(address))
+    {
+        return Err(bad_request("email address was already added"));
+    }
+
+    let new_email = NewEmail {
+        user_id: user.id,
+        email: address,
+        is_primary: existing.iter().all(|email| !email.is_primary),
+    };
+
+    let email: Email = diesel::insert_into(emails::table)
+        .values(&new_email)
+        .get_result(&*conn)?;
+
+    // Invalid addresses can't be confirmed anyway, so errors are swallowed like on signup
+    let _ = req
+        .app()
+        .emails
+        .send_user_confirm(&*conn, &email.email, &user.gh_login, &email.token);
+
+    // Let the user know at their current address, in case somebody else added this one
+    if let Some(notification_email) = user.notification_email(&conn)? {
+        let details = RequestDetails::from_request(&*req);
+        let result = req.app().emails.send_email_added(
+            &*conn,
+            &notification_email,
+            &user.gh_login,
+            &email.email,
+            &details,
+        );
+        if let Err(error) = result {
+            warn!(
+                "Failed to notify user {} of the added email: {error}",
+                user.id
+            );
+        }
+    }
+
+    Ok(req.json(&json!({ "email": EncodableEmail::from(email) })))
+}
+
+/// Handles the `DELETE /me/emails/:id` route.
+pub fn remove(req: &mut dyn RequestExt) -> EndpointResult {
+    let id = parse_email_id(req)?;
+    let authenticated_user = req.authenticate()?.forbid_api_token_auth()?;
+    let conn = req.db_conn()?;
+    let user = authenticated_user.user();
+
+    let email = find_email(&conn, &user, id)?;
+    if email.is_primary {
+        return Err(bad_request("the primary email address cannot be removed"));
+    }
+
+    diesel::delete(&email).execute(&*conn)?;
+
+    ok_true()
+}
+
+/// Handles the `PUT /me/emails/:id/primary` route.
+pub fn set_primary(req: &mut dyn RequestExt) -> EndpointResult {
+    let id = parse_email_id(req)?;
+    let authenticated_user = req.authenticate()?.forbid_api_token_auth()?;
+    let conn = req.db_conn()?;
+    let user = authenticated_user.user();
+
+    let email = find_email(&conn, &user, id)?;
+    if !email.verified {
+        return Err(bad_request(
+            "the email address has to be verified to become the primary address",
+        ));
+    }
+
+    let previous_email = user.verified_email(&conn)?;
+    email.make_primary(&conn)?;
+
+    // Let the user know at their previous address, in case somebody else changed it
+    if let Some(previous_email) = previous_email.filter(|previous| *previous != email.email) {
+        notify_email_changed(req, &conn, &user, &previous_email, &email.email);
+    }
+
+    ok_true()
+}
+
+/// Handles the `PUT /me/emails/:id/notifications` route.
+///
+/// Notifications are sent to the primary address if no other address is picked.
+pub fn set_notifications(req: &mut dyn RequestExt) -> EndpointResult {
+    let id = parse_email_id(req)?;
+    let authenticated_user = req.authenticate()?.forbid_api_token_auth()?;
+    let conn = req.db_conn()?;
+    let user = authenticated_user.user();
+
+    let email = find_email(&conn, &user, id)?;
+    if !email.verified {
+        return Err(bad_request(
+            "the email address has to be verified to receive notifications",
+        ));
+    }
+
+    conn.transaction(|| {
+        diesel::update(Email::belonging_to(&user))
+            .set(emails::send_notifications.eq(false))
+            .execute(&*conn)?;
+        diesel::update(&email)
+            .set(emails::send_notifications.eq(true))
+            .execute(&*conn)
+    })?;
+
+    ok_true()
+}
+
+/// Handles the `PUT /me/emails/:id/resend` route.
+pub fn resend(req: &mut dyn RequestExt) -> EndpointResult {
+    use diesel::dsl::sql;
+
+    let id = parse_email_id(req)?;
+    let authenticated_user = req.authenticate()?.forbid_api_token_auth()?;
+    let conn = req.db_conn()?;
+    let user = authenticated_user.user();
+
+    let email = find_email(&conn, &user, id)?;
+    if email.verified {
+        return Err(bad_request("the email address is already verified"));
+    }
+
+    conn.transaction(|| {
+        let email: Email = diesel::update(&email)
+            .set(emails::token.eq(sql("DEFAULT")))
+            .get_result(&*conn)?;
+
+        req.app()
+            .emails
+            .send_user_confirm(&*conn, &email.email, &user.gh_login, &email.token)
+    })?;
+
+    ok_true()
+}
+
+/// Notifies a user at their previous primary address that it was replaced by `new_email`.
+///
+/// Errors are only logged, since the address was changed whether or not the notification is
+/// sent.
+pub(crate) fn notify_email_changed(
+    req: &dyn RequestExt,
+    conn: &PgConnection,
+    user: &User,
+    previous_email: &str,
+    new_email: &str,
+) {
+    let details = RequestDetails::from_request(req);
+    let result = req.app().emails.send_email_changed(
+        conn,
+        previous_email,
+        &user.gh_login,
+        new_email,
+        &details,
+    );
+    if let Err(error) = result {
+        warn!(
+            "Failed to notify user {} of the email change: {error}",
+            user.id
+        );
+    }
+}
+
+fn parse_email_id(req: &dyn RequestExt) -> AppResult<i32> {
+    req.params()["id"]
+        .parse::<i32>()
+        .map_err(|e| bad_request(&format!("invalid email id: {e:?}")))
+}
+
+fn find_email(conn: &PgConnection, user: &User, id: i32) -> AppResult<Email> {
+    Email::belonging_to(user)
+        .find(id)
+        .first(conn)
+        .optional()?
+        .ok_or_else(|| bad_request("email address not found"))
+}
@@ -1,3 +1,4 @@
+use conduit_cookie::RequestSession;
 use std::collections::HashMap;
 
 use crate::controllers::frontend_prelude::*;
@@ -5,10 +6,12 @@ use crate::controllers::frontend_prelude::*;
 use crate::controllers::helpers::*;
 
 use crate::controllers::helpers::pagination::{Paginated, PaginationOptions};
+use crate::controllers::user::emails::notify_email_changed;
 use crate::models::{
     CrateOwner, Email, Follow, NewEmail, OwnerKind, User, Version, VersionOwnerAction,
 };
 use crate::schema::{crate_owners, crates, emails, follows, users, versions};
+use crate::sql::lower;
 use crate::views::{EncodableMe, EncodablePrivateUser, EncodableVersion, OwnedCrate};
 
 /// Handles the `GET /me` route.
@@ -19,7 +22,11 @@ pub fn me(req: &mut dyn RequestExt) -> EndpointResult {
     let (user, verified, email, verification_sent): (User, Option<bool>, Option<String>, bool) =
         users::table
             .find(user_id)
-            .left_join(emails::table)
+            .left_join(
+                emails::table.on(emails::user_id
+                    .eq(users::id)
+                    .and(emails::is_primary.eq(true))),
+            )
             .select((
                 users::all_columns,
                 emails::verified.nullable(),
@@ -50,6 +57,54 @@ pub fn me(req: &mut dyn RequestExt) -> EndpointResult {
     }))
 }
 
+/// Handles the `DELETE /me` route.
+///
+/// Crates that nobody else could manage afterwards have to be transferred to another owner
+/// first, or explicitly abandoned by listing them in `abandon_crates`.
+pub fn delete_account(req: &mut dyn RequestExt) -> EndpointResult {
+    #[derive(Deserialize, Default)]
+    struct DeleteAccount {
+        #[serde(default)]
+        abandon_crates: Vec<String>,
+    }
+
+    let mut body = String::new();
+    req.body().read_to_string(&mut body)?;
+    let request: DeleteAccount = if body.is_empty() {
+        DeleteAccount::default()
+    } else {
+        serde_json::from_str(&body).map_err(|_| bad_request("invalid json request"))?
+    };
+
+    let authenticated_user = req.authenticate()?.forbid_api_token_auth()?;
+    let conn = req.db_conn()?;
+    authenticated_user.require_totp(req, &conn)?;
+    let user = authenticated_user.user();
+
+    // The check and the deletion share a transaction, so that the other owners can't be removed
+    // in between
+    conn.transaction::<_, Box<dyn AppError>, _>(|| {
+        let remaining_crates = user
+            .solely_owned_crates(&conn)?
+            .into_iter()
+            .filter(|name| !request.abandon_crates.contains(name))
+            .collect::<Vec<_>>();
+        if !remaining_crates.is_empty() {
+            return Err(bad_request(&format!(
+                "you are the only owner of the following crates, please add another owner or \
+                 abandon them first: {}",
+                remaining_crates.join(", ")
+            )));
+        }
+
+        user.delete_account(&conn)
+    })?;
+    req.session_mut().remove(&"session_id".to_string());
+    req.session_mut().remove(&"user_id".to_string());
+
+    ok_true()
+}
+
 /// Handles the `GET /me/updates` route.
 pub fn updates(req: &mut dyn RequestExt) -> EndpointResult {
     use diesel::dsl::any;
@@ -92,9 +147,12 @@ pub fn updates(req: &mut dyn RequestExt) -> EndpointResult {
 }
 
 /// Handles the `PUT /users/:user_id` route.
+///
+/// Changing the email address replaces the primary address of the user.
 pub fn update_user(req: &mut dyn RequestExt) -> EndpointResult {
-    use self::emails::user_id;
+    use diesel::dsl::sql;
     use diesel::insert_into;
+    use diesel::sql_types::Integer;
 
     let authenticated_user = req.authenticate()?;
 
@@ -133,28 +191,55 @@ pub fn update_user(req: &mut dyn RequestExt) -> EndpointResult {
     }
 
     conn.transaction::<_, Box<dyn AppError>, _>(|| {
-        let new_email = NewEmail {
-            user_id: user.id,
-            email: user_email,
+        let previous_email = user.verified_email(&conn)?;
+
+        let existing: Option<Email> = Email::belonging_to(&user)
+            .filter(lower(emails::email).eq(user_email.to_lowercase()))
+            .first(&*conn)
+            .optional()?;
+
+        let token = match existing {
+            // The address was added before, so it's promoted instead of replacing the current
+            // primary address, which would conflict with it
+            Some(email) => {
+                email.make_primary(&conn)?;
+                (!email.verified).then(|| email.token)
+            }
+            None => {
+                let new_email = NewEmail {
+                    user_id: user.id,
+                    email: user_email,
+                    is_primary: true,
+                };
+
+                let token: String = insert_into(emails::table)
+                    .values(&new_email)
+                    .on_conflict(sql::<Integer>("(user_id) WHERE is_primary"))
+                    .do_update()
+                    .set(&new_email)
+                    .returning(emails::token)
+                    .get_result(&*conn)
+                    .map_err(|_| server_error("Error in creating token"))?;
+                Some(token)
+            }
         };
 
-        let token: String = insert_into(emails::table)
-            .values(&new_email)
-            .on_conflict(user_id)
-            .do_update()
-            .set(&new_email)
-            .returning(emails::token)
-            .get_result(&*conn)
-            .map_err(|_| server_error("Error in creating token"))?;
-
         // This swallows any errors that occur while attempting to send the email. Some users have
         // an invalid email set in their GitHub profile, and we should let them sign in even though
         // we're trying to silently use their invalid address during signup and can't send them an
         // email. They'll then have to provide a valid email address.
-        let _ = req
-            .app()
-            .emails
-            .send_user_confirm(user_email, &user.gh_login, &token);
+        if let Some(token) = token {
+            let _ = req
+                .app()
+                .emails
+                .send_user_confirm(&*conn, user_email, &user.gh_login, &token);
+        }
+
+        // Let the user know at their previous address, in case somebody else changed it
+        let previous_email = previous_email.filter(|email| !email.eq_ignore_ascii_case(user_email));
+        if let Some(previous_email) = previous_email {
+            notify_email_changed(&*req, &conn, &user, &previous_email, user_email);
+        }
 
         Ok(())
     })?;
@@ -198,14 +283,14 @@ pub fn regenerate_token_and_send(req: &mut dyn RequestExt) -> EndpointResult {
     }
 
     conn.transaction(|| {
-        let email: Email = update(Email::belonging_to(&user))
+        let email: Email = update(Email::belonging_to(&user).filter(emails::is_primary.eq(true)))
             .set(emails::token.eq(sql("DEFAULT")))
             .get_result(&*conn)
             .map_err(|_| bad_request("Email could not be found"))?;
 
         req.app()
             .emails
-            .send_user_confirm(&email.email, &user.gh_login, &email.token)
+            .send_user_confirm(&*conn, &email.email, &user.gh_login, &email.token)
     })?;
 
     ok_true()
@@ -263,3 +348,64 @@ pub fn update_email_notifications(req: &mut dyn RequestExt) -> EndpointResult {
 
     ok_true()
 }
+
+/// Handles the `PUT /me/weekly_digest` route
+pub fn update_weekly_digest(req: &mut dyn RequestExt) -> EndpointResult {
+    #[derive(Deserialize)]
+    struct WeeklyDigest {
+        weekly_digest: bool,
+    }
+
+    let mut body = String::new();
+    req.body().read_to_string(&mut body)?;
+    let update: WeeklyDigest =
+        serde_json::from_str(&body).map_err(|_| bad_request("invalid json request"))?;
+
+    let authenticated_user = req.authenticate()?.forbid_api_token_auth()?;
+    let user = authenticated_user.user();
+    let conn = req.db_conn()?;
+
+    diesel::update(&user)
+        .set(users::weekly_digest.eq(update.weekly_digest))
+        .execute(&*conn)?;
+
+    ok_true()
+}
+
+/// Handles the `GET /weekly_digest/unsubscribe/:token` route
+///
+/// The digest emails link to a confirmation page, which uses this to check the token before
+/// unsubscribing with a `POST` to the same route. This doesn't change anything, since mail
+/// clients and scanners may follow links in emails on their own.
+pub fn show_weekly_digest_unsubscribe(req: &mut dyn RequestExt) -> EndpointResult {
+    let user_id = digest_unsubscribe_user_id(req)?;
+
+    let conn = req.db_conn()?;
+    let (login, weekly_digest): (String, bool) = users::table
+        .find(user_id)
+        .select((users::gh_login, users::weekly_digest))
+        .first(&*conn)?;
+
+    Ok(req.json(&json!({ "login": login, "weekly_digest": weekly_digest })))
+}
+
+/// Handles the `POST /weekly_digest/unsubscribe/:token` route
+///
+/// This works without logging in. It's also the target of the `List-Unsubscribe` header of
+/// the digest emails, for the one-click unsubscribe of RFC 8058.
+pub fn unsubscribe_weekly_digest(req: &mut dyn RequestExt) -> EndpointResult {
+    let user_id = digest_unsubscribe_user_id(req)?;
+
+    let conn = req.db_conn()?;
+    diesel::update(users::table.find(user_id))
+        .set(users::weekly_digest.eq(false))
+        .execute(&*conn)?;
+
+    ok_true()
+}
+
+fn digest_unsubscribe_user_id(req: &dyn RequestExt) -> AppResult<i32> {
+    let token = &req.params()["token"];
+    User::parse_digest_unsubscribe_token(req.app().email_token_key(), token)
+        .ok_or_else(|| bad_request("invalid unsubscribe token"))
+}
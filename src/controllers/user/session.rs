@@ -1,14 +1,19 @@
 use crate::controllers::frontend_prelude::*;
 
+use chrono::Utc;
 use conduit_cookie::RequestSession;
 use oauth2::reqwest::http_client;
 use oauth2::{AuthorizationCode, Scope, TokenResponse};
+use swirl::Job;
 
-use crate::email::Emails;
+use crate::email::{Emails, RequestDetails};
 use crate::github::GithubUser;
-use crate::models::{NewUser, User};
-use crate::schema::users;
-use crate::util::errors::ReadOnlyMode;
+use crate::login_providers::LoginProvider;
+use crate::models::{AccountProvider, CrateOwnerInvitation, LinkedAccount, NewUser, Session, User};
+use crate::schema::{sessions, users};
+use crate::util::errors::{not_found, ReadOnlyMode};
+use crate::views::EncodableSession;
+use crate::{worker, App};
 
 /// Handles the `GET /api/private/session/begin` route.
 ///
@@ -95,15 +100,123 @@ pub fn authorize(req: &mut dyn RequestExt) -> EndpointResult {
 
     // Fetch the user info from GitHub using the access token we just got and create a user record
     let ghuser = req.app().github.current_user(token)?;
-    let user = save_user_to_database(&ghuser, token.secret(), &req.app().emails, &*req.db_conn()?)?;
+    let conn = req.db_conn()?;
+    let user = save_user_to_database(&ghuser, token.secret(), &req.app().emails, &conn)?;
+
+    // Ownership invitations may have been sent before the user signed up. This is allowed
+    // to fail as well, the invitations are picked up again on the next login.
+    let app = req.app();
+    if let Err(error) =
+        CrateOwnerInvitation::create_from_pending(&user, &conn, &app.config, &app.emails)
+    {
+        warn!("Failed to create pending ownership invitations: {error}");
+    }
+
+    // The user might have joined or left teams since we last saw them. This is
+    // allowed to fail, since the memberships are checked again when they are needed.
+    if let Err(error) = worker::sync_user_team_memberships(user.id).enqueue(&conn) {
+        warn!("Failed to enqueue team membership refresh: {error}");
+    }
 
     // Log in by setting a cookie and the middleware authentication
+    start_session(req, &conn, &user)?;
+    drop(conn);
+
+    super::me::me(req)
+}
+
+/// Handles the `GET /api/private/session/:provider/begin` route.
+///
+/// Works like the `begin` route, but for the login providers other than GitHub
+/// (`gitlab` and `oidc`). Providers that are not configured respond with a 404.
+pub fn begin_with_provider(req: &mut dyn RequestExt) -> EndpointResult {
+    let app = req.app().clone();
+    let provider = login_provider(req, &app)?;
+
+    let mut authorize_url = provider
+        .oauth_client()?
+        .authorize_url(oauth2::CsrfToken::new_random);
+    for scope in provider.scopes() {
+        authorize_url = authorize_url.add_scope(Scope::new(scope.to_string()));
+    }
+    let (url, state) = authorize_url.url();
+
+    let state = state.secret().to_string();
     req.session_mut()
-        .insert("user_id".to_string(), user.id.to_string());
+        .insert(oauth_state_key(provider), state.clone());
+
+    Ok(req.json(&json!({ "url": url.to_string(), "state": state })))
+}
+
+/// Handles the `GET /api/private/session/:provider/authorize` route.
+///
+/// Works like the `authorize` route, but for the login providers other than GitHub. The
+/// provider account is linked to an existing crates.io user if both share a verified email
+/// address, otherwise a new user is created.
+pub fn authorize_with_provider(req: &mut dyn RequestExt) -> EndpointResult {
+    let app = req.app().clone();
+    let provider = login_provider(req, &app)?;
+
+    // Parse the url query
+    let mut query = req.query();
+    let code = query.remove("code").unwrap_or_default();
+    let state = query.remove("state").unwrap_or_default();
+
+    // Make sure that the state we just got matches the session state that we
+    // should have issued earlier.
+    {
+        let session_state = req.session_mut().remove(&oauth_state_key(provider));
+        let session_state = session_state.as_deref();
+        if Some(&state[..]) != session_state {
+            return Err(bad_request("invalid state parameter"));
+        }
+    }
+
+    // Fetch the access token from the provider using the code we just got
+    let code = AuthorizationCode::new(code);
+    let token = provider
+        .oauth_client()?
+        .exchange_code(code)
+        .request(http_client)
+        .map_err(|err| err.chain(server_error("Error obtaining token")))?;
+    let token = token.access_token();
+
+    let provider_user = provider.current_user(token)?;
+    let conn = req.db_conn()?;
+    let user = LinkedAccount::find_or_create_user(
+        &conn,
+        &app.emails,
+        provider.provider(),
+        &provider_user,
+        token.secret(),
+    )?;
+
+    if let Err(error) = worker::sync_user_team_memberships(user.id).enqueue(&conn) {
+        warn!("Failed to enqueue team membership refresh: {error}");
+    }
+
+    // Pending ownership invitations aren't materialized here: they are only created for
+    // GitHub logins, while users signing up through other providers get logins like
+    // `foo@gitlab` that can never match one.
+
+    // Log in by setting a cookie and the middleware authentication
+    start_session(req, &conn, &user)?;
+    drop(conn);
 
     super::me::me(req)
 }
 
+fn login_provider<'a>(req: &dyn RequestExt, app: &'a App) -> AppResult<&'a dyn LoginProvider> {
+    AccountProvider::from_name(&req.params()["provider"])
+        .and_then(|provider| app.login_provider(provider))
+        .ok_or_else(not_found)
+}
+
+fn oauth_state_key(provider: &dyn LoginProvider) -> String {
+    let name: &'static str = provider.provider().into();
+    format!("{name}_oauth_state")
+}
+
 fn save_user_to_database(
     user: &GithubUser,
     access_token: &str,
@@ -134,12 +247,105 @@ fn save_user_to_database(
     })
 }
 
+/// Stores a new session for the user in the database and its ID in the session cookie.
+///
+/// In read only mode the session can't be stored, so the session is only held in the cookie
+/// instead. Such sessions can't be listed or revoked, and expire after
+/// `COOKIE_SESSION_MAX_AGE_HOURS`.
+fn start_session(req: &mut dyn RequestExt, conn: &PgConnection, user: &User) -> AppResult<()> {
+    let details = RequestDetails::from_request(req);
+    // Use a new transaction, so that a failure doesn't abort the transaction of the request
+    let session: AppResult<Session> = conn
+        .transaction(|| Session::create(conn, user.id, &details.user_agent, &details.ip_address))
+        .map_err(Into::into);
+
+    let cookie = req.session_mut();
+    match session {
+        Ok(session) => {
+            cookie.insert("session_id".to_string(), session.id.to_string());
+            cookie.remove(&"cookie_session_created_at".to_string());
+        }
+        Err(e) if e.is::<ReadOnlyMode>() => {
+            // A session ID of a previous login would take precedence
+            cookie.remove(&"session_id".to_string());
+            let created_at = Utc::now().timestamp().to_string();
+            cookie.insert("cookie_session_created_at".to_string(), created_at);
+        }
+        Err(e) => return Err(e),
+    }
+    // Authenticates cookie sessions, and attributes errors to users
+    cookie.insert("user_id".to_string(), user.id.to_string());
+    Ok(())
+}
+
 /// Handles the `DELETE /api/private/session` route.
 pub fn logout(req: &mut dyn RequestExt) -> EndpointResult {
+    let session_id = req
+        .session()
+        .get("session_id")
+        .and_then(|s| s.parse::<i32>().ok());
+
+    if let Some(session_id) = session_id {
+        let conn = req.db_conn()?;
+        if let Err(error) = diesel::delete(sessions::table.find(session_id)).execute(&*conn) {
+            warn!("Failed to delete session {session_id}: {error}");
+        }
+    }
+
+    req.session_mut().remove(&"session_id".to_string());
+    req.session_mut()
+        .remove(&"cookie_session_created_at".to_string());
     req.session_mut().remove(&"user_id".to_string());
     Ok(req.json(&true))
 }
 
+/// Handles the `GET /me/sessions` route.
+pub fn list_sessions(req: &mut dyn RequestExt) -> EndpointResult {
+    let authenticated_user = req.authenticate()?.forbid_api_token_auth()?;
+    let current_session_id = authenticated_user.session_id();
+    let conn = req.db_conn()?;
+    let user = authenticated_user.user();
+
+    let sessions: Vec<EncodableSession> = Session::belonging_to(&user)
+        .order(sessions::last_used_at.desc())
+        .load(&*conn)?
+        .into_iter()
+        .map(|session| EncodableSession::from(session, current_session_id))
+        .collect();
+
+    Ok(req.json(&json!({ "sessions": sessions })))
+}
+
+/// Handles the `DELETE /me/sessions/:id` route.
+pub fn revoke_session(req: &mut dyn RequestExt) -> EndpointResult {
+    let id = req.params()["id"]
+        .parse::<i32>()
+        .map_err(|e| bad_request(&format!("invalid session id: {e:?}")))?;
+
+    let authenticated_user = req.authenticate()?.forbid_api_token_auth()?;
+    let conn = req.db_conn()?;
+    let user = authenticated_user.user();
+
+    diesel::delete(Session::belonging_to(&user).find(id)).execute(&*conn)?;
+
+    ok_true()
+}
+
+/// Handles the `DELETE /me/sessions` route.
+///
+/// Logs out everywhere, except for the session making the request.
+pub fn revoke_other_sessions(req: &mut dyn RequestExt) -> EndpointResult {
+    let authenticated_user = req.authenticate()?.forbid_api_token_auth()?;
+    let current_session_id = authenticated_user.session_id().unwrap_or_default();
+    let conn = req.db_conn()?;
+    let user = authenticated_user.user();
+
+    diesel::delete(Session::belonging_to(&user).filter(sessions::id.ne(current_session_id)))
+        .execute(&*conn)?;
+
+    ok_true()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,106 @@
+use crate::controllers::frontend_prelude::*;
+
+use crate::models::TotpCredential;
+use crate::util::totp;
+
+/// Handles the `GET /me/totp` route.
+pub fn show(req: &mut dyn RequestExt) -> EndpointResult {
+    let authenticated_user = req.authenticate()?.forbid_api_token_auth()?;
+    let conn = req.db_conn()?;
+
+    let credential = TotpCredential::find_confirmed(&conn, authenticated_user.user_id())?;
+    let recovery_codes_remaining = match &credential {
+        Some(credential) => credential.remaining_recovery_codes(&conn)?,
+        None => 0,
+    };
+
+    Ok(req.json(&json!({
+        "totp": {
+            "enabled": credential.is_some(),
+            "recovery_codes_remaining": recovery_codes_remaining,
+        }
+    })))
+}
+
+/// Handles the `PUT /me/totp` route.
+///
+/// Generates the secret for the authenticator app. Codes are only required after the
+/// enrollment was confirmed with a first code.
+pub fn enroll(req: &mut dyn RequestExt) -> EndpointResult {
+    let authenticated_user = req.authenticate()?.forbid_api_token_auth()?;
+    let conn = req.db_conn()?;
+    let user = authenticated_user.user();
+
+    if TotpCredential::find_confirmed(&conn, user.id)?.is_some() {
+        return Err(bad_request("two-factor authentication is already enabled"));
+    }
+
+    let credential = TotpCredential::start_enrollment(&conn, user.id)?;
+    let secret = totp::encode_base32(&credential.secret);
+    let uri = totp::provisioning_uri(&credential.secret, &user.gh_login);
+
+    Ok(req.json(&json!({ "totp": { "secret": secret, "uri": uri } })))
+}
+
+/// Handles the `PUT /me/totp/confirm` route.
+///
+/// Returns the recovery codes, which are not shown again.
+pub fn confirm(req: &mut dyn RequestExt) -> EndpointResult {
+    #[derive(Deserialize)]
+    struct ConfirmRequest {
+        code: String,
+    }
+
+    let mut body = String::new();
+    req.body().read_to_string(&mut body)?;
+    let confirm: ConfirmRequest =
+        serde_json::from_str(&body).map_err(|_| bad_request("invalid json request"))?;
+
+    let authenticated_user = req.authenticate()?.forbid_api_token_auth()?;
+    let conn = req.db_conn()?;
+
+    let credential = TotpCredential::find(&conn, authenticated_user.user_id())?
+        .ok_or_else(|| bad_request("two-factor authentication enrollment was not started"))?;
+    if credential.confirmed_at.is_some() {
+        return Err(bad_request("two-factor authentication is already enabled"));
+    }
+
+    if !credential.verify_code(&conn, &confirm.code)? {
+        return Err(bad_request("invalid two-factor authentication code"));
+    }
+
+    let recovery_codes = credential.confirm(&conn)?;
+
+    Ok(req.json(&json!({ "recovery_codes": recovery_codes })))
+}
+
+/// Handles the `PUT /me/totp/recovery_codes` route.
+///
+/// Replaces all recovery codes, e.g. after most of them were used.
+pub fn regenerate_recovery_codes(req: &mut dyn RequestExt) -> EndpointResult {
+    let authenticated_user = req.authenticate()?.forbid_api_token_auth()?;
+    let conn = req.db_conn()?;
+
+    let credential = TotpCredential::find_confirmed(&conn, authenticated_user.user_id())?
+        .ok_or_else(|| bad_request("two-factor authentication is not enabled"))?;
+    authenticated_user.require_totp(req, &conn)?;
+
+    let recovery_codes = credential.regenerate_recovery_codes(&conn)?;
+
+    Ok(req.json(&json!({ "recovery_codes": recovery_codes })))
+}
+
+/// Handles the `DELETE /me/totp` route.
+pub fn disable(req: &mut dyn RequestExt) -> EndpointResult {
+    let authenticated_user = req.authenticate()?.forbid_api_token_auth()?;
+    let conn = req.db_conn()?;
+
+    authenticated_user.require_totp(req, &conn)?;
+
+    // Unconfirmed enrollments are removed as well
+    if let Some(credential) = TotpCredential::find(&conn, authenticated_user.user_id())? {
+        credential.delete(&conn)?;
+    }
+
+    ok_true()
+}
@@ -1,18 +1,24 @@
-use chrono::Utc;
+use chrono::{Duration, Utc};
 use conduit_cookie::RequestSession;
 
 use super::prelude::*;
 
+use crate::email::RequestDetails;
 use crate::middleware::log_request;
-use crate::models::{ApiToken, User};
+use crate::models::{
+    ApiToken, NewApiTokenUsage, Session, TotpCredential, User, COOKIE_SESSION_MAX_AGE_HOURS,
+};
 use crate::util::errors::{
-    account_locked, forbidden, internal, AppError, AppResult, InsecurelyGeneratedTokenRevoked,
+    account_locked, forbidden, internal, two_factor_required, AppError, AppResult,
+    InsecurelyGeneratedTokenRevoked,
 };
+use crate::util::request_header;
 
 #[derive(Debug)]
 pub struct AuthenticatedUser {
     user: User,
     token_id: Option<i32>,
+    session_id: Option<i32>,
 }
 
 impl AuthenticatedUser {
@@ -24,6 +30,10 @@ impl AuthenticatedUser {
         self.token_id
     }
 
+    pub fn session_id(&self) -> Option<i32> {
+        self.session_id
+    }
+
     pub fn user(self) -> User {
         self.user
     }
@@ -39,6 +49,47 @@ impl AuthenticatedUser {
             )
         }
     }
+
+    /// Requires users that enabled two-factor authentication to confirm the request with a
+    /// fresh code in the `X-TOTP-Code` header. A recovery code is accepted as well.
+    ///
+    /// Requests authenticated with an API token are not affected, since cargo can't provide
+    /// a code.
+    pub fn require_totp(&self, req: &dyn RequestExt, conn: &PgConnection) -> AppResult<()> {
+        if self.token_id.is_some() {
+            return Ok(());
+        }
+
+        let credential = match TotpCredential::find_confirmed(conn, self.user.id)? {
+            Some(credential) => credential,
+            None => return Ok(()),
+        };
+
+        let code = request_header(req, "x-totp-code");
+        if code.is_empty() {
+            return Err(two_factor_required(
+                "a two-factor authentication code is required for this action",
+            ));
+        }
+
+        if credential.is_locked() {
+            return Err(two_factor_required(
+                "too many invalid two-factor authentication codes, please try again later",
+            ));
+        }
+
+        if credential.verify_code(conn, code)? || credential.use_recovery_code(conn, code)? {
+            credential.reset_failed_attempts(conn)?;
+            Ok(())
+        } else {
+            // Callers check the code before starting their own transaction, so that the
+            // failed attempt is kept even though the request fails
+            credential.record_failed_attempt(conn)?;
+            Err(two_factor_required(
+                "invalid two-factor authentication code",
+            ))
+        }
+    }
 }
 
 /// The Origin header (https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Origin)
@@ -68,15 +119,49 @@ fn authenticate_user(req: &dyn RequestExt) -> AppResult<AuthenticatedUser> {
     let conn = req.db_conn()?;
 
     let session = req.session();
+    let session_id = session
+        .get("session_id")
+        .and_then(|s| s.parse::<i32>().ok());
+
+    if let Some(id) = session_id {
+        // Sessions that were revoked no longer exist in the database
+        let session = Session::find(&conn, id)?.ok_or_else(|| {
+            internal("session from cookie not found in database").chain(forbidden())
+        })?;
+        if session.is_expired() {
+            return Err(internal("session from cookie expired").chain(forbidden()));
+        }
+        session.record_use(&conn);
+
+        let user = User::find(&conn, session.user_id)
+            .map_err(|err| err.chain(internal("user_id from session not found in database")))?;
+
+        return Ok(AuthenticatedUser {
+            user,
+            token_id: None,
+            session_id: Some(session.id),
+        });
+    }
+
+    // Sessions started in read only mode are only held in the cookie, see `start_session`
+    let cookie_session_created_at = session
+        .get("cookie_session_created_at")
+        .and_then(|s| s.parse::<i64>().ok());
     let user_id_from_session = session.get("user_id").and_then(|s| s.parse::<i32>().ok());
 
-    if let Some(id) = user_id_from_session {
+    if let (Some(created_at), Some(id)) = (cookie_session_created_at, user_id_from_session) {
+        let max_age = Duration::hours(COOKIE_SESSION_MAX_AGE_HOURS).num_seconds();
+        if created_at + max_age < Utc::now().timestamp() {
+            return Err(internal("cookie session expired").chain(forbidden()));
+        }
+
         let user = User::find(&conn, id)
             .map_err(|err| err.chain(internal("user_id from cookie not found in database")))?;
 
         return Ok(AuthenticatedUser {
             user,
             token_id: None,
+            session_id: None,
         });
     }
 
@@ -98,9 +183,12 @@ fn authenticate_user(req: &dyn RequestExt) -> AppResult<AuthenticatedUser> {
         let user = User::find(&conn, token.user_id)
             .map_err(|err| err.chain(internal("user_id from token not found in database")))?;
 
+        record_token_usage(req, &conn, token.id);
+
         return Ok(AuthenticatedUser {
             user,
             token_id: Some(token.id),
+            session_id: None,
         });
     }
 
@@ -108,6 +196,33 @@ fn authenticate_user(req: &dyn RequestExt) -> AppResult<AuthenticatedUser> {
     return Err(internal("no cookie session or auth header found").chain(forbidden()));
 }
 
+/// Records the use of an API token, so that users can find out where their tokens are used.
+///
+/// Failures are only logged, requests should still be served in read only mode.
+fn record_token_usage(req: &dyn RequestExt, conn: &PgConnection, api_token_id: i32) {
+    let details = RequestDetails::from_request(req);
+    let endpoint = format!("{} {}", req.method(), req.path());
+
+    // Publishing doesn't have the crate name in the path, but logs it before authenticating
+    let crate_name = req
+        .params()
+        .find("crate_id")
+        .or_else(|| log_request::get_custom_metadata(req, "crate_name"));
+
+    let usage = NewApiTokenUsage {
+        api_token_id,
+        endpoint: &endpoint,
+        crate_name,
+        ip_address: &details.ip_address,
+        user_agent: &details.user_agent,
+    };
+
+    // Use a new transaction, so that a failure doesn't abort the transaction of the request
+    if let Err(error) = conn.transaction(|| usage.insert(conn)) {
+        warn!("Failed to record usage of API token {api_token_id}: {error}");
+    }
+}
+
 impl<'a> UserAuthenticationExt for dyn RequestExt + 'a {
     /// Obtain `AuthenticatedUser` for the request or return an `Forbidden` error
     fn authenticate(&mut self) -> AppResult<AuthenticatedUser> {
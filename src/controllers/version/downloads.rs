@@ -2,13 +2,19 @@
 //!
 //! Crate level functionality is located in `krate::downloads`.
 
+use chrono::Utc;
+
 use super::{extract_crate_name_and_semver, version_and_crate};
+use crate::controllers::helpers::downloads::{
+    aggregated_response, load_aggregated, DownloadsOptions,
+};
 use crate::controllers::prelude::*;
 use crate::db::PoolError;
+use crate::downloads_counter::DownloadClient;
 use crate::models::{Crate, VersionDownload};
 use crate::schema::*;
+use crate::util::request_header;
 use crate::views::EncodableVersionDownload;
-use chrono::{Duration, NaiveDate, Utc};
 
 /// Handles the `GET /crates/:crate_id/:version/download` route.
 /// This returns a URL to the location where the crate is stored.
@@ -20,13 +26,23 @@ pub fn download(req: &mut dyn RequestExt) -> EndpointResult {
 
     let mut log_metadata = None;
 
+    let client = DownloadClient::from_user_agent(request_header(req, header::USER_AGENT));
+    // Once the downloads are counted from the CDN logs, counting them here as well would count
+    // them twice. See `config::DownloadsSource` for the cutover.
+    let counted = app
+        .config
+        .downloads_source
+        .counted_by_endpoint(Utc::today().naive_utc());
+
     let cache_key = (crate_name.to_string(), version.to_string());
     if let Some(version_id) = app.version_id_cacher.get(&cache_key) {
         app.instance_metrics.version_id_cache_hits.inc();
 
         // The increment does not happen instantly, but it's deferred to be executed in a batch
         // along with other downloads. See crate::downloads_counter for the implementation.
-        app.downloads_counter.increment(version_id);
+        if counted {
+            app.downloads_counter.increment(version_id, client);
+        }
     } else {
         app.instance_metrics.version_id_cache_misses.inc();
 
@@ -76,7 +92,9 @@ pub fn download(req: &mut dyn RequestExt) -> EndpointResult {
 
             // The increment does not happen instantly, but it's deferred to be executed in a batch
             // along with other downloads. See crate::downloads_counter for the implementation.
-            app.downloads_counter.increment(version_id);
+            if counted {
+                app.downloads_counter.increment(version_id, client);
+            }
         } else {
             // The download endpoint is the most critical route in the whole crates.io application,
             // as it's relied upon by users and automations to download crates. Keeping it working
@@ -119,21 +137,24 @@ pub fn download(req: &mut dyn RequestExt) -> EndpointResult {
 }
 
 /// Handles the `GET /crates/:crate_id/:version/downloads` route.
+///
+/// See `helpers::downloads` for the parameters selecting the range and the aggregation of the
+/// downloads. `before_date` is still accepted in place of `to`.
 pub fn downloads(req: &mut dyn RequestExt) -> EndpointResult {
     let (crate_name, semver) = extract_crate_name_and_semver(req)?;
+    let mut options = DownloadsOptions::gather(req, &["before_date"])?;
 
     let conn = req.db_read_only()?;
     let (version, _) = version_and_crate(&conn, crate_name, semver)?;
 
-    let cutoff_end_date = req
-        .query()
-        .get("before_date")
-        .and_then(|d| NaiveDate::parse_from_str(d, "%F").ok())
-        .unwrap_or_else(|| Utc::today().naive_utc());
-    let cutoff_start_date = cutoff_end_date - Duration::days(89);
+    if options.aggregated {
+        options.skip_archived_days(&conn)?;
+        let downloads = load_aggregated(&conn, &[version], &options)?;
+        return Ok(aggregated_response(&downloads, &options));
+    }
 
     let downloads = VersionDownload::belonging_to(&version)
-        .filter(version_downloads::date.between(cutoff_start_date, cutoff_end_date))
+        .filter(version_downloads::date.between(options.from, options.to))
         .order(version_downloads::date)
         .load(&*conn)?
         .into_iter()
@@ -38,12 +38,15 @@ fn modify_yank(req: &mut dyn RequestExt, yanked: bool) -> EndpointResult {
     let (version, krate) = version_and_crate(&conn, crate_name, semver)?;
     let api_token_id = authenticated_user.api_token_id();
     let user = authenticated_user.user();
-    let owners = krate.owners(&conn)?;
+    let owners = krate.owners_with_rights(&conn)?;
 
-    if user.rights(req.app(), &owners)? < Rights::Publish {
+    if user.rights(req.app(), &conn, &owners)? < Rights::Yank {
         return Err(cargo_err("must already be an owner to yank or unyank"));
     }
 
+    // Tokens are used by cargo, which can't ask for a code
+    authenticated_user.require_totp(req, &conn)?;
+
     if version.yanked == yanked {
         // The crate is already in the state requested, nothing to do
         return ok_true();
@@ -3,7 +3,16 @@ use anyhow::Error;
 use dashmap::{DashMap, SharedValue};
 use diesel::{pg::upsert::excluded, prelude::*};
 use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::Path;
 use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+pub use self::client::DownloadClient;
+use self::journal::Journal;
+
+mod client;
+mod journal;
 
 /// crates.io receives a lot of download requests, and we can't execute a write query to the
 /// database during each connection for performance reasons. To reduce the write load, this struct
@@ -14,23 +23,39 @@ use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
 /// stores the items into `num_cpus()*4` individually locked shards. This approach reduces the
 /// likelyhood of a request encountering a locked shard.
 ///
+/// Downloads are counted by version and by the kind of client that downloaded the crate, so that
+/// the daily totals of each client can be persisted as well.
+///
 /// Persisting the download counts in the database also takes advantage of the inner sharding of
 /// DashMaps: to avoid locking all the download requests at the same time each iteration only
 /// persists a single shard at the time.
 ///
 /// The disadvantage of this approach is that download counts are stored in memory until they're
-/// persisted, so it's possible to lose some of them if the process exits ungracefully. While
-/// that's far from ideal, the advantage of batching database updates far outweights potentially
-/// losing some download counts.
+/// persisted, so it's possible to lose some of them if the process exits ungracefully. To avoid
+/// that, the counter can be created with a journal (see the `journal` module): every download is
+/// also appended to a local file, which is replayed when the next process starts. Downloads are
+/// only appended to an in-memory buffer of their shard, and the buffers are written to the file by
+/// `flush_journal` every `downloads_journal_flush_interval_ms`, so that requests neither wait for
+/// the file nor for downloads of other shards. A crash loses the downloads that were buffered
+/// since the last flush.
 #[derive(Debug)]
 pub struct DownloadsCounter {
     /// Inner storage for the download counts.
-    inner: DashMap<i32, AtomicUsize>,
+    inner: DashMap<(i32, DownloadClient), AtomicUsize>,
     /// Index of the next shard that should be persisted by `persist_next_shard`.
     shard_idx: AtomicUsize,
     /// Number of downloads that are not yet persisted on the database. This is just used as a
     /// metric included in log lines, and it's not guaranteed to be accurate.
     pending_count: AtomicI64,
+    /// Number of downloads recovered from the journal when the counter was created.
+    replayed_count: AtomicUsize,
+    /// Number of downloads that were never persisted, because their version was deleted or
+    /// because their line in the journal was corrupted.
+    dropped_count: AtomicUsize,
+    journal: Option<Journal>,
+    /// Held while persisting a shard, so that the journal isn't compacted between a shard being
+    /// removed from memory and its persisted counts being recorded in the journal.
+    persist_lock: Mutex<()>,
 }
 
 impl DownloadsCounter {
@@ -39,28 +64,77 @@ impl DownloadsCounter {
             inner: DashMap::new(),
             shard_idx: AtomicUsize::new(0),
             pending_count: AtomicI64::new(0),
+            replayed_count: AtomicUsize::new(0),
+            dropped_count: AtomicUsize::new(0),
+            journal: None,
+            persist_lock: Mutex::new(()),
         }
     }
 
-    pub(crate) fn increment(&self, version_id: i32) {
+    /// Creates a counter that records downloads in the journal at `path`, starting with the
+    /// downloads that a previous process left in it.
+    pub(crate) fn with_journal(path: &Path) -> io::Result<Self> {
+        let inner = DashMap::new();
+        let (journal, replayed) = Journal::open(path, inner.shards().len())?;
+
+        let counter = Self {
+            inner,
+            journal: Some(journal),
+            ..Self::new()
+        };
+        for (&key, &count) in &replayed.counts {
+            counter.add(key, count);
+            counter
+                .pending_count
+                .fetch_add(count as i64, Ordering::SeqCst);
+            counter.replayed_count.fetch_add(count, Ordering::SeqCst);
+        }
+        counter
+            .dropped_count
+            .fetch_add(replayed.malformed_lines, Ordering::SeqCst);
+
+        // Start with a compact journal instead of the history of the previous process
+        if let Some(journal) = &counter.journal {
+            journal.compact(&mut journal.lock(), replayed.counts.into_iter())?;
+        }
+
+        Ok(counter)
+    }
+
+    pub(crate) fn increment(&self, version_id: i32, client: DownloadClient) {
         self.pending_count.fetch_add(1, Ordering::SeqCst);
+        let key = (version_id, client);
+
+        match &self.journal {
+            Some(journal) => {
+                // The download is added to the map while the buffer is locked, otherwise a
+                // compaction could happen in between and the download would be missing from both
+                // the compacted journal and the persisted shards.
+                let mut buffer = journal.lock_buffer(self.inner.determine_map(&key));
+                journal.append(&mut buffer, &[(key, 1)]);
+                self.add(key, 1);
+            }
+            None => self.add(key, 1),
+        }
+    }
 
-        if let Some(counter) = self.inner.get(&version_id) {
+    fn add(&self, key: (i32, DownloadClient), count: usize) {
+        if let Some(counter) = self.inner.get(&key) {
             // The version is already recorded in the DashMap, so we don't need to lock the whole
             // shard in write mode. The shard is instead locked in read mode, which allows an
             // unbounded number of readers as long as there are no write locks.
-            counter.value().fetch_add(1, Ordering::SeqCst);
+            counter.value().fetch_add(count, Ordering::SeqCst);
         } else {
             // The version is not in the DashMap, so we need to lock the whole shard in write mode
             // and insert the version into it. This has worse performance than the above case.
             self.inner
-                .entry(version_id)
+                .entry(key)
                 .and_modify(|counter| {
                     // Handle the version being inserted by another thread while we were waiting
                     // for the write lock on the shard.
-                    counter.fetch_add(1, Ordering::SeqCst);
+                    counter.fetch_add(count, Ordering::SeqCst);
                 })
-                .or_insert_with(|| AtomicUsize::new(1));
+                .or_insert_with(|| AtomicUsize::new(count));
         }
     }
 
@@ -75,16 +149,30 @@ impl DownloadsCounter {
     }
 
     fn persist_all_shards_with_conn(&self, conn: &PgConnection) -> Result<PersistStats, Error> {
+        let _persisting = self
+            .persist_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
         let mut stats = PersistStats::default();
         for shard in self.inner.shards() {
             let shard = std::mem::take(&mut *shard.write());
             stats = stats.merge(self.persist_shard(conn, shard)?);
         }
 
+        // Almost nothing is left in memory at this point, so this shrinks the journal to a few
+        // lines. This is also what happens on a graceful shutdown.
+        self.compact_journal();
+
         Ok(stats)
     }
 
     fn persist_next_shard_with_conn(&self, conn: &PgConnection) -> Result<PersistStats, Error> {
+        let _persisting = self
+            .persist_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
         // Replace the next shard in the ring with an empty HashMap (clearing it), and return the
         // previous contents for processing. The fetch_add method wraps around on overflow, so it's
         // fine to keep incrementing it without resetting.
@@ -100,89 +188,50 @@ impl DownloadsCounter {
     fn persist_shard(
         &self,
         conn: &PgConnection,
-        shard: HashMap<i32, SharedValue<AtomicUsize>>,
+        shard: HashMap<(i32, DownloadClient), SharedValue<AtomicUsize>>,
     ) -> Result<PersistStats, Error> {
-        use crate::schema::{version_downloads, versions};
-
-        let mut discarded_downloads = 0;
-        let mut counted_downloads = 0;
-        let mut counted_versions = 0;
-
-        let mut to_insert = shard
+        let to_insert = shard
             .iter()
             .map(|(id, atomic)| (*id, atomic.get().load(Ordering::SeqCst)))
             .collect::<Vec<_>>();
 
-        if !to_insert.is_empty() {
-            // The rows we're about to insert need to be sorted to avoid deadlocks when multiple
-            // instances of crates.io are running at the same time.
-            //
-            // In PostgreSQL a transaction modifying a row locks that row until the transaction is
-            // committed. Multiple transactions inserting rows into a table could end up
-            // deadlocking each other though: PostgreSQL will detect that deadlock, abort one of
-            // the transactions and allow the other one to continue. We don't want that to happen,
-            // as we'd lose the downloads from the aborted transaction.
-            //
-            // Ensuring the rows are inserted in a consistent order (in our case by sorting them by
-            // the version ID) will prevent deadlocks from occuring. For more information:
-            //
-            //     https://www.postgresql.org/docs/11/explicit-locking.html#LOCKING-DEADLOCKS
-            //
-            to_insert.sort_by_key(|(key, _)| *key);
-
-            // Our database schema enforces that every row in the `version_downloads` table points
-            // to a valid version in the `versions` table with a foreign key. This doesn't cause
-            // problems most of the times, as the rest of the application checks whether the
-            // version exists before calling the `increment` method.
-            //
-            // On rare occasions crates are deleted from crates.io though, and that would break the
-            // invariant if the crate is deleted after the `increment` method is called but before
-            // the downloads are persisted in the database.
-            //
-            // That happening would cause the whole `INSERT` to fail, also losing the downloads in
-            // the shard we were about to persist. To avoid that from happening this snippet does a
-            // `SELECT` query on the version table before persisting to check whether every version
-            // still exists in the database. Missing versions are removed from the following query.
-            let version_ids = to_insert.iter().map(|(id, _)| *id).collect::<Vec<_>>();
-            let existing_version_ids: HashSet<i32> = versions::table
-                .select(versions::id)
-                // `FOR SHARE` prevents updates or deletions on the selected rows in the `versions`
-                // table until this transaction commits. That prevents a version from being deleted
-                // between this query and the next one.
-                //
-                // `FOR SHARE` is used instead of `FOR UPDATE` to allow rows to be locked by
-                // multiple `SELECT` transactions, to allow for concurrent downloads persisting.
-                .for_share()
-                .filter(versions::id.eq_any(version_ids))
-                .load(conn)?
-                .into_iter()
-                .collect();
-
-            let mut values = Vec::new();
-            for (id, count) in &to_insert {
-                if !existing_version_ids.contains(id) {
-                    discarded_downloads += *count;
-                    continue;
+        let (counted_versions, counted_downloads, discarded_downloads) = if to_insert.is_empty() {
+            (0, 0, 0)
+        } else {
+            match insert_downloads(conn, &to_insert) {
+                Ok(counts) => counts,
+                Err(err) => {
+                    // The shard was already removed from memory, so the downloads are put back
+                    // to be persisted together with the next downloads of the shard.
+                    for (key, count) in to_insert {
+                        self.add(key, count);
+                    }
+                    return Err(err.into());
                 }
-                counted_versions += 1;
-                counted_downloads += *count;
-                values.push((
-                    version_downloads::version_id.eq(*id),
-                    version_downloads::downloads.eq(*count as i32),
-                ));
+            }
+        };
+
+        if let Some(journal) = &self.journal {
+            let deltas = to_insert
+                .iter()
+                .map(|(key, count)| (*key, -(*count as i64)))
+                .collect::<Vec<_>>();
+            // The persisted downloads would be counted twice if they were replayed, so the
+            // deltas are written right away instead of waiting for the next flush
+            let appended = journal.write(&mut journal.lock(), &deltas);
+            if let Err(err) = &appended {
+                error!("Failed to write the downloads journal: {err}");
             }
 
-            diesel::insert_into(version_downloads::table)
-                .values(&values)
-                .on_conflict((version_downloads::version_id, version_downloads::date))
-                .do_update()
-                .set(
-                    version_downloads::downloads
-                        .eq(version_downloads::downloads + excluded(version_downloads::downloads)),
-                )
-                .execute(conn)?;
+            // If the persisted downloads couldn't be recorded they would be replayed and counted
+            // twice, so the journal is rewritten from memory instead.
+            if appended.is_err() || journal.needs_compaction() {
+                self.compact_journal();
+            }
         }
 
+        self.dropped_count
+            .fetch_add(discarded_downloads, Ordering::SeqCst);
         let old_pending = self.pending_count.fetch_sub(
             (counted_downloads + discarded_downloads) as i64,
             Ordering::SeqCst,
@@ -196,6 +245,30 @@ impl DownloadsCounter {
         })
     }
 
+    /// Writes the buffered downloads to the journal.
+    pub fn flush_journal(&self) {
+        if let Some(journal) = &self.journal {
+            if let Err(err) = journal.flush(&mut journal.lock()) {
+                error!("Failed to write the downloads journal: {err}");
+            }
+        }
+    }
+
+    /// Replaces the journal with the downloads currently held in memory.
+    fn compact_journal(&self) {
+        if let Some(journal) = &self.journal {
+            let mut file = journal.lock();
+            let counts = self
+                .inner
+                .iter()
+                .map(|entry| (*entry.key(), entry.value().load(Ordering::SeqCst)))
+                .filter(|(_, count)| *count > 0);
+            if let Err(err) = journal.compact(&mut file, counts) {
+                error!("Failed to compact the downloads journal: {err}");
+            }
+        }
+    }
+
     pub fn shards_count(&self) -> usize {
         self.inner.shards().len()
     }
@@ -203,6 +276,139 @@ impl DownloadsCounter {
     pub(crate) fn pending_count(&self) -> i64 {
         self.pending_count.load(Ordering::SeqCst)
     }
+
+    pub fn replayed_count(&self) -> usize {
+        self.replayed_count.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn dropped_count(&self) -> usize {
+        self.dropped_count.load(Ordering::SeqCst)
+    }
+}
+
+/// Inserts the download counts in the database, returning the number of counted versions,
+/// counted downloads and discarded downloads.
+///
+/// The totals of each version and the downloads by client are inserted in the same transaction,
+/// so that a failure doesn't persist one without the other.
+fn insert_downloads(
+    conn: &PgConnection,
+    by_client: &[((i32, DownloadClient), usize)],
+) -> QueryResult<(usize, usize, usize)> {
+    use crate::schema::{version_downloads, version_downloads_by_client, versions};
+
+    conn.transaction(|| {
+        // The rows we're about to insert need to be sorted to avoid deadlocks when multiple
+        // instances of crates.io are running at the same time.
+        //
+        // In PostgreSQL a transaction modifying a row locks that row until the transaction is
+        // committed. Multiple transactions inserting rows into a table could end up
+        // deadlocking each other though: PostgreSQL will detect that deadlock, abort one of
+        // the transactions and allow the other one to continue. We don't want that to happen,
+        // as we'd lose the downloads from the aborted transaction.
+        //
+        // Ensuring the rows are inserted in a consistent order (in our case by sorting them by
+        // the version ID) will prevent deadlocks from occuring. For more information:
+        //
+        //     https://www.postgresql.org/docs/11/explicit-locking.html#LOCKING-DEADLOCKS
+        //
+        let mut totals: HashMap<i32, usize> = HashMap::new();
+        for ((id, _), count) in by_client {
+            *totals.entry(*id).or_default() += count;
+        }
+        let mut to_insert = totals.into_iter().collect::<Vec<_>>();
+        to_insert.sort_by_key(|(key, _)| *key);
+
+        // Our database schema enforces that every row in the `version_downloads` table points
+        // to a valid version in the `versions` table with a foreign key. This doesn't cause
+        // problems most of the times, as the rest of the application checks whether the
+        // version exists before calling the `increment` method.
+        //
+        // On rare occasions crates are deleted from crates.io though, and that would break the
+        // invariant if the crate is deleted after the `increment` method is called but before
+        // the downloads are persisted in the database.
+        //
+        // That happening would cause the whole `INSERT` to fail, also losing the downloads in
+        // the shard we were about to persist. To avoid that from happening this snippet does a
+        // `SELECT` query on the version table before persisting to check whether every version
+        // still exists in the database. Missing versions are removed from the following query.
+        let version_ids = to_insert.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        let existing_version_ids: HashSet<i32> = versions::table
+            .select(versions::id)
+            // `FOR SHARE` prevents updates or deletions on the selected rows in the `versions`
+            // table until this transaction commits. That prevents a version from being deleted
+            // between this query and the next one.
+            //
+            // `FOR SHARE` is used instead of `FOR UPDATE` to allow rows to be locked by
+            // multiple `SELECT` transactions, to allow for concurrent downloads persisting.
+            .for_share()
+            .filter(versions::id.eq_any(version_ids))
+            .load(conn)?
+            .into_iter()
+            .collect();
+
+        let mut discarded_downloads = 0;
+        let mut counted_downloads = 0;
+        let mut counted_versions = 0;
+        let mut values = Vec::new();
+        for (id, count) in &to_insert {
+            if !existing_version_ids.contains(id) {
+                discarded_downloads += *count;
+                continue;
+            }
+            counted_versions += 1;
+            counted_downloads += *count;
+            values.push((
+                version_downloads::version_id.eq(*id),
+                version_downloads::downloads.eq(*count as i32),
+            ));
+        }
+
+        diesel::insert_into(version_downloads::table)
+            .values(&values)
+            .on_conflict((version_downloads::version_id, version_downloads::date))
+            .do_update()
+            .set(
+                version_downloads::downloads
+                    .eq(version_downloads::downloads + excluded(version_downloads::downloads)),
+            )
+            .execute(conn)?;
+
+        let mut client_values = by_client
+            .iter()
+            .filter(|((id, _), _)| existing_version_ids.contains(id))
+            .map(|((id, client), count)| (*id, client.family(), client.cargo_version(), *count))
+            .collect::<Vec<_>>();
+        client_values.sort();
+        let client_values = client_values
+            .iter()
+            .map(|(id, family, cargo_version, count)| {
+                (
+                    version_downloads_by_client::version_id.eq(id),
+                    version_downloads_by_client::client.eq(*family),
+                    version_downloads_by_client::cargo_version.eq(cargo_version),
+                    version_downloads_by_client::downloads.eq(*count as i32),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        diesel::insert_into(version_downloads_by_client::table)
+            .values(&client_values)
+            .on_conflict((
+                version_downloads_by_client::version_id,
+                version_downloads_by_client::date,
+                version_downloads_by_client::client,
+                version_downloads_by_client::cargo_version,
+            ))
+            .do_update()
+            .set(
+                version_downloads_by_client::downloads.eq(version_downloads_by_client::downloads
+                    + excluded(version_downloads_by_client::downloads)),
+            )
+            .execute(conn)?;
+
+        Ok((counted_versions, counted_downloads, discarded_downloads))
+    })
 }
 
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
@@ -249,6 +455,8 @@ mod tests {
     use diesel::PgConnection;
     use semver::Version;
 
+    const CARGO: DownloadClient = DownloadClient::Cargo(Some((1, 58)));
+
     #[test]
     fn test_increment_and_persist_all() {
         let counter = DownloadsCounter::new();
@@ -261,10 +469,10 @@ mod tests {
 
         // Add 15 downloads between v1 and v2, and no downloads for v3.
         for _ in 0..10 {
-            counter.increment(v1);
+            counter.increment(v1, CARGO);
         }
         for _ in 0..5 {
-            counter.increment(v2);
+            counter.increment(v2, CARGO);
         }
         assert_eq!(15, counter.pending_count.load(Ordering::SeqCst));
 
@@ -297,21 +505,21 @@ mod tests {
         let mut state = State::new(&conn);
 
         let v1 = state.new_version(&conn);
-        let v1_shard = counter.inner.determine_map(&v1);
+        let v1_shard = counter.inner.determine_map(&(v1, CARGO));
 
         // For this test to work we need the two versions to be stored in different shards.
         let mut v2 = state.new_version(&conn);
-        while counter.inner.determine_map(&v2) == v1_shard {
+        while counter.inner.determine_map(&(v2, CARGO)) == v1_shard {
             v2 = state.new_version(&conn);
         }
-        let v2_shard = counter.inner.determine_map(&v2);
+        let v2_shard = counter.inner.determine_map(&(v2, CARGO));
 
         // Add 15 downloads between v1 and v2.
         for _ in 0..10 {
-            counter.increment(v1);
+            counter.increment(v1, CARGO);
         }
         for _ in 0..5 {
-            counter.increment(v2);
+            counter.increment(v2, CARGO);
         }
         assert_eq!(15, counter.pending_count.load(Ordering::SeqCst));
 
@@ -365,23 +573,58 @@ mod tests {
         state.assert_downloads_count(&conn, v2, 5);
     }
 
+    #[test]
+    fn test_persist_downloads_by_client() {
+        use crate::schema::version_downloads_by_client::dsl::*;
+
+        let counter = DownloadsCounter::new();
+        let conn = crate::db::test_conn();
+        let mut state = State::new(&conn);
+
+        let v1 = state.new_version(&conn);
+        counter.increment(v1, CARGO);
+        counter.increment(v1, CARGO);
+        counter.increment(v1, DownloadClient::Cargo(Some((1, 57))));
+        counter.increment(v1, DownloadClient::Browser);
+
+        let stats = counter
+            .persist_all_shards_with_conn(&conn)
+            .expect("failed to persist all shards");
+        assert_eq!(stats.counted_versions, 1);
+        assert_eq!(stats.counted_downloads, 4);
+        state.assert_downloads_count(&conn, v1, 4);
+
+        let rows: Vec<(String, String, i32)> = version_downloads_by_client
+            .select((client, cargo_version, downloads))
+            .filter(version_id.eq(v1))
+            .order((client, cargo_version))
+            .load(&conn)
+            .unwrap();
+        let expected = vec![
+            ("browser".to_string(), String::new(), 1),
+            ("cargo".to_string(), "1.57".to_string(), 1),
+            ("cargo".to_string(), "1.58".to_string(), 2),
+        ];
+        assert_eq!(rows, expected);
+    }
+
     #[test]
     fn test_increment_existing_and_missing_version_same_shard() {
         test_increment_existing_and_missing_version(|map, v1, v2| {
-            map.determine_map(&v1) == map.determine_map(&v2)
+            map.determine_map(&(v1, CARGO)) == map.determine_map(&(v2, CARGO))
         })
     }
 
     #[test]
     fn test_increment_existing_and_missing_version_different_shard() {
         test_increment_existing_and_missing_version(|map, v1, v2| {
-            map.determine_map(&v1) != map.determine_map(&v2)
+            map.determine_map(&(v1, CARGO)) != map.determine_map(&(v2, CARGO))
         })
     }
 
     fn test_increment_existing_and_missing_version<F>(shard_condition: F)
     where
-        F: Fn(&DashMap<i32, AtomicUsize>, i32, i32) -> bool,
+        F: Fn(&DashMap<(i32, DownloadClient), AtomicUsize>, i32, i32) -> bool,
     {
         let counter = DownloadsCounter::new();
         let conn = crate::db::test_conn();
@@ -396,8 +639,8 @@ mod tests {
         }
 
         // No error should happen when calling the increment method on a missing version.
-        counter.increment(v1);
-        counter.increment(v2);
+        counter.increment(v1, CARGO);
+        counter.increment(v2, CARGO);
 
         // No error should happen when persisting. The missing versions should be ignored.
         let stats = counter
@@ -416,6 +659,99 @@ mod tests {
         );
         state.assert_downloads_count(&conn, v1, 1);
         state.assert_downloads_count(&conn, v2, 0);
+        assert_eq!(counter.dropped_count(), 1);
+    }
+
+    #[test]
+    fn test_journal_replays_pending_downloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("downloads.journal");
+        let conn = crate::db::test_conn();
+        let mut state = State::new(&conn);
+
+        let v1 = state.new_version(&conn);
+        let v2 = state.new_version(&conn);
+
+        let counter = DownloadsCounter::with_journal(&path).unwrap();
+        for _ in 0..3 {
+            counter.increment(v1, CARGO);
+        }
+        counter.increment(v2, CARGO);
+        counter.persist_all_shards_with_conn(&conn).unwrap();
+
+        // These downloads are lost when the process exits without persisting them
+        counter.increment(v1, CARGO);
+        counter.increment(v2, CARGO);
+        counter.increment(v2, CARGO);
+        drop(counter);
+
+        let counter = DownloadsCounter::with_journal(&path).unwrap();
+        assert_eq!(counter.pending_count(), 3);
+        assert_eq!(counter.replayed_count(), 3);
+        assert_eq!(counter.dropped_count(), 0);
+
+        counter.persist_all_shards_with_conn(&conn).unwrap();
+        state.assert_downloads_count(&conn, v1, 4);
+        state.assert_downloads_count(&conn, v2, 3);
+        drop(counter);
+
+        // Nothing is replayed after everything was persisted
+        let counter = DownloadsCounter::with_journal(&path).unwrap();
+        assert_eq!(counter.pending_count(), 0);
+        assert_eq!(counter.replayed_count(), 0);
+    }
+
+    #[test]
+    fn test_journal_after_persisting_shards() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("downloads.journal");
+        let conn = crate::db::test_conn();
+        let mut state = State::new(&conn);
+
+        let v1 = state.new_version(&conn);
+        let counter = DownloadsCounter::with_journal(&path).unwrap();
+        let v1_shard = counter.inner.determine_map(&(v1, CARGO));
+
+        let mut v2 = state.new_version(&conn);
+        while counter.inner.determine_map(&(v2, CARGO)) == v1_shard {
+            v2 = state.new_version(&conn);
+        }
+
+        counter.increment(v1, CARGO);
+        counter.increment(v2, CARGO);
+        counter.increment(v2, CARGO);
+
+        // Only persist the shard of the first version
+        let shard = std::mem::take(&mut *counter.inner.shards()[v1_shard].write());
+        counter.persist_shard(&conn, shard).unwrap();
+        state.assert_downloads_count(&conn, v1, 1);
+        drop(counter);
+
+        let counter = DownloadsCounter::with_journal(&path).unwrap();
+        assert_eq!(counter.replayed_count(), 2);
+        counter.persist_all_shards_with_conn(&conn).unwrap();
+        state.assert_downloads_count(&conn, v1, 1);
+        state.assert_downloads_count(&conn, v2, 2);
+    }
+
+    #[test]
+    fn test_journal_loses_unflushed_downloads_on_crash() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("downloads.journal");
+        let conn = crate::db::test_conn();
+        let mut state = State::new(&conn);
+
+        let v1 = state.new_version(&conn);
+        let counter = DownloadsCounter::with_journal(&path).unwrap();
+        counter.increment(v1, CARGO);
+        counter.flush_journal();
+        counter.increment(v1, CARGO);
+
+        // Forgetting the counter skips writing the buffer when it's dropped, like a crash would
+        std::mem::forget(counter);
+
+        let counter = DownloadsCounter::with_journal(&path).unwrap();
+        assert_eq!(counter.replayed_count(), 1);
     }
 
     struct State {
@@ -455,6 +791,7 @@ mod tests {
                 &HashMap::new(),
                 None,
                 None,
+                None,
                 0,
                 self.user.id,
             )
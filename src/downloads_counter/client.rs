@@ -0,0 +1,165 @@
+//! Classification of the clients downloading crates, based on their `User-Agent` header.
+
+use chrono::{NaiveDate, Utc};
+use std::fmt;
+use std::str::FromStr;
+
+/// Substrings of the `User-Agent` headers of registry mirrors and proxies, in lowercase.
+const MIRRORS: &[&str] = &["artifactory", "nexus", "panamax", "romt", "crates-mirror"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum DownloadClient {
+    /// cargo, with its `major.minor` version if it could be parsed and was released already.
+    Cargo(Option<(u16, u16)>),
+    /// Registry mirrors and proxies, like Artifactory or panamax.
+    Mirror,
+    Browser,
+    /// Everything else, like curl or scripts, and requests without a `User-Agent`.
+    Other,
+}
+
+impl DownloadClient {
+    pub fn from_user_agent(user_agent: &str) -> Self {
+        // e.g. `cargo 1.58.0 (f01b232bc 2022-01-19)`
+        if let Some(version) = user_agent.strip_prefix("cargo ") {
+            // Anyone can send any version, and each one would be counted separately
+            let latest = latest_cargo_minor_version(Utc::today().naive_utc());
+            let version = parse_minor_version(version)
+                .filter(|&(major, minor)| major == 1 && minor <= latest);
+            return DownloadClient::Cargo(version);
+        }
+
+        let lowercase = user_agent.to_lowercase();
+        if MIRRORS.iter().any(|mirror| lowercase.contains(mirror)) {
+            DownloadClient::Mirror
+        } else if user_agent.starts_with("Mozilla/") {
+            DownloadClient::Browser
+        } else {
+            DownloadClient::Other
+        }
+    }
+
+    /// The name of the client family, as stored in the `client` column.
+    pub fn family(&self) -> &'static str {
+        match self {
+            DownloadClient::Cargo(_) => "cargo",
+            DownloadClient::Mirror => "mirror",
+            DownloadClient::Browser => "browser",
+            DownloadClient::Other => "other",
+        }
+    }
+
+    /// The `major.minor` version of cargo as stored in the `cargo_version` column, which is empty
+    /// for other clients.
+    pub fn cargo_version(&self) -> String {
+        match self {
+            DownloadClient::Cargo(Some((major, minor))) => format!("{major}.{minor}"),
+            _ => String::new(),
+        }
+    }
+}
+
+/// Formats the client as a single word, e.g. `cargo/1.58` or `browser`.
+impl fmt::Display for DownloadClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DownloadClient::Cargo(Some((major, minor))) => write!(f, "cargo/{major}.{minor}"),
+            _ => f.write_str(self.family()),
+        }
+    }
+}
+
+impl FromStr for DownloadClient {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cargo" => Ok(DownloadClient::Cargo(None)),
+            "mirror" => Ok(DownloadClient::Mirror),
+            "browser" => Ok(DownloadClient::Browser),
+            "other" => Ok(DownloadClient::Other),
+            _ => {
+                let version = s.strip_prefix("cargo/").ok_or(())?;
+                Ok(DownloadClient::Cargo(Some(
+                    parse_minor_version(version).ok_or(())?,
+                )))
+            }
+        }
+    }
+}
+
+/// The greatest minor version of cargo 1.x that could be in use on `today`, including nightly
+/// versions. A new version was released every six weeks since 1.0 was released.
+fn latest_cargo_minor_version(today: NaiveDate) -> u16 {
+    let weeks = (today - NaiveDate::from_ymd(2015, 5, 15)).num_weeks();
+    // Nightly is two versions ahead of stable, plus one for the days the schedule is off by
+    u16::try_from(weeks / 6 + 4).unwrap_or(u16::MAX)
+}
+
+/// Parses the `major.minor` part of versions like `1.58.0` or `1.60.0-nightly`.
+fn parse_minor_version(version: &str) -> Option<(u16, u16)> {
+    let version = version.split(' ').next()?;
+    let mut parts = version.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?;
+    let minor_digits = minor
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(minor.len());
+    Some((major, minor[..minor_digits].parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_user_agents() {
+        let classify = DownloadClient::from_user_agent;
+
+        assert_eq!(
+            classify("cargo 1.58.0 (f01b232bc 2022-01-19)"),
+            DownloadClient::Cargo(Some((1, 58)))
+        );
+        assert_eq!(
+            classify("cargo 1.60.0-nightly (95bb3c92b 2022-01-18)"),
+            DownloadClient::Cargo(Some((1, 60)))
+        );
+        assert_eq!(classify("cargo unknown"), DownloadClient::Cargo(None));
+        assert_eq!(classify("cargo 1.9999.0"), DownloadClient::Cargo(None));
+        assert_eq!(classify("cargo 7.1.0"), DownloadClient::Cargo(None));
+        assert_eq!(
+            classify("Artifactory/7.31.10 72910900"),
+            DownloadClient::Mirror
+        );
+        assert_eq!(classify("panamax/1.0.3"), DownloadClient::Mirror);
+        assert_eq!(
+            classify("Mozilla/5.0 (X11; Linux x86_64; rv:96.0) Gecko/20100101 Firefox/96.0"),
+            DownloadClient::Browser
+        );
+        assert_eq!(classify("curl/7.81.0"), DownloadClient::Other);
+        assert_eq!(classify(""), DownloadClient::Other);
+    }
+
+    #[test]
+    fn latest_cargo_version() {
+        let latest = |y, m, d| latest_cargo_minor_version(NaiveDate::from_ymd(y, m, d));
+        // 1.58 was released on 2022-01-13, when 1.60 was the nightly version
+        assert!((60..=62).contains(&latest(2022, 1, 13)));
+        assert!((60..=62).contains(&latest(2022, 2, 23)));
+    }
+
+    #[test]
+    fn round_trip() {
+        let clients = [
+            DownloadClient::Cargo(Some((1, 58))),
+            DownloadClient::Cargo(None),
+            DownloadClient::Mirror,
+            DownloadClient::Browser,
+            DownloadClient::Other,
+        ];
+        for client in clients {
+            assert_eq!(client.to_string().parse(), Ok(client));
+        }
+        assert_eq!("cargo/x".parse::<DownloadClient>(), Err(()));
+    }
+}
@@ -0,0 +1,279 @@
+//! An append-only file recording the downloads that are not persisted in the database yet, so
+//! that they can be recovered when the process exits ungracefully.
+//!
+//! Every line of the journal contains a version ID, a client (see `DownloadClient`) and a delta:
+//! each download appends a `1`, while persisting a shard appends the negated counts of its
+//! versions. Replaying the journal sums the deltas of each version and client. To keep the file
+//! from growing forever it's periodically compacted, replacing it with the counts that are
+//! currently held in memory.
+//!
+//! Downloads are appended to in-memory buffers, which are written to the file every
+//! `DOWNLOADS_JOURNAL_FLUSH_INTERVAL_MS` (see `DownloadsCounter::flush_journal`). There is a
+//! buffer for each shard of the counter, so that downloads of different shards don't wait for
+//! each other. If the process crashes, the downloads of at most the last interval are lost. The
+//! deltas of persisted shards are written right away instead, since losing them would count the
+//! downloads twice.
+//!
+//! Writes are not synced to disk, so the journal survives the process crashing but not the whole
+//! machine going down.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use super::DownloadClient;
+
+/// Number of lines after which the journal is compacted.
+const COMPACT_AFTER_RECORDS: usize = 100_000;
+
+#[derive(Debug)]
+pub(super) struct Journal {
+    path: PathBuf,
+    file: Mutex<File>,
+    /// Lines that were not written to the file yet, by shard of the counter. The file is always
+    /// locked before the buffers, to avoid deadlocks.
+    buffers: Vec<Mutex<String>>,
+    /// Number of lines appended since the journal was last compacted.
+    records: AtomicUsize,
+}
+
+/// The contents of a journal left behind by a previous process.
+#[derive(Debug, Default)]
+pub(super) struct Replayed {
+    /// Downloads that were not persisted, by version ID and client.
+    pub(super) counts: HashMap<(i32, DownloadClient), usize>,
+    /// Number of lines that couldn't be parsed, usually because the process exited while the
+    /// line was being written. Each of them is most likely a single lost download.
+    pub(super) malformed_lines: usize,
+}
+
+impl Journal {
+    /// Opens the journal at `path` with a buffer for each of the `shards` of the counter,
+    /// returning the downloads recorded in it.
+    pub(super) fn open(path: &Path, shards: usize) -> io::Result<(Self, Replayed)> {
+        let mut replayed = Replayed::default();
+        let mut deltas: HashMap<(i32, DownloadClient), i64> = HashMap::new();
+        match File::open(path) {
+            Ok(file) => {
+                for line in BufReader::new(file).split(b'\n') {
+                    match parse_line(&line?) {
+                        Some((key, delta)) => *deltas.entry(key).or_default() += delta,
+                        None => replayed.malformed_lines += 1,
+                    }
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+
+        replayed.counts = deltas
+            .into_iter()
+            .filter(|(_, count)| *count > 0)
+            .map(|(key, count)| (key, count as usize))
+            .collect();
+
+        let journal = Self {
+            path: path.into(),
+            file: Mutex::new(open_for_append(path)?),
+            buffers: (0..shards).map(|_| Mutex::new(String::new())).collect(),
+            records: AtomicUsize::new(0),
+        };
+        Ok((journal, replayed))
+    }
+
+    /// Locks the journal file. Other downloads can still be buffered while the guard is held.
+    pub(super) fn lock(&self) -> MutexGuard<'_, File> {
+        // A panic while writing can leave at most a partial line behind, which is skipped when
+        // replaying the journal.
+        self.file.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the buffer of a shard. Other downloads of the shard can't be recorded until the
+    /// guard is dropped.
+    pub(super) fn lock_buffer(&self, shard: usize) -> MutexGuard<'_, String> {
+        self.buffers[shard]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Appends a delta for each version and client to a buffer of the journal.
+    pub(super) fn append(&self, buffer: &mut String, deltas: &[((i32, DownloadClient), i64)]) {
+        for ((version_id, client), delta) in deltas {
+            writeln!(buffer, "{version_id} {client} {delta}").unwrap();
+        }
+        self.records.fetch_add(deltas.len(), Ordering::SeqCst);
+    }
+
+    /// Writes the buffered lines to the file. The lines are dropped if the write fails.
+    pub(super) fn flush(&self, file: &mut File) -> io::Result<()> {
+        self.write(file, &[])
+    }
+
+    /// Writes the buffered lines to the file, followed by `deltas`.
+    pub(super) fn write(
+        &self,
+        file: &mut File,
+        deltas: &[((i32, DownloadClient), i64)],
+    ) -> io::Result<()> {
+        let mut lines = String::new();
+        for shard in 0..self.buffers.len() {
+            lines.push_str(&std::mem::take(&mut *self.lock_buffer(shard)));
+        }
+        self.append(&mut lines, deltas);
+        if lines.is_empty() {
+            return Ok(());
+        }
+        // A single write keeps the lines of a batch together in the file.
+        file.write_all(lines.as_bytes())
+    }
+
+    pub(super) fn needs_compaction(&self) -> bool {
+        self.records.load(Ordering::SeqCst) >= COMPACT_AFTER_RECORDS
+    }
+
+    /// Replaces the contents of the journal with `counts`, which are collected while no
+    /// downloads can be recorded.
+    ///
+    /// The new contents are written to a temporary file first and then moved over the journal,
+    /// so that a crash during the compaction leaves either the old or the new journal behind.
+    pub(super) fn compact(
+        &self,
+        file: &mut File,
+        counts: impl Iterator<Item = ((i32, DownloadClient), usize)>,
+    ) -> io::Result<()> {
+        let mut buffers = (0..self.buffers.len())
+            .map(|shard| self.lock_buffer(shard))
+            .collect::<Vec<_>>();
+
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+
+        let mut lines = String::new();
+        for ((version_id, client), count) in counts {
+            writeln!(lines, "{version_id} {client} {count}").unwrap();
+        }
+
+        let mut tmp = File::create(&tmp_path)?;
+        tmp.write_all(lines.as_bytes())?;
+        tmp.sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+
+        // The buffered lines are dropped, since `counts` replaces them
+        *file = open_for_append(&self.path)?;
+        for buffer in &mut buffers {
+            buffer.clear();
+        }
+        self.records.store(0, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// Writes the buffered lines when the journal is dropped, e.g. on a graceful shutdown.
+impl Drop for Journal {
+    fn drop(&mut self) {
+        // Errors are ignored, like dropping a `BufWriter` does
+        let _ = self.flush(&mut self.lock());
+    }
+}
+
+fn open_for_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn parse_line(line: &[u8]) -> Option<((i32, DownloadClient), i64)> {
+    let line = std::str::from_utf8(line).ok()?;
+    let parts = line.split(' ').collect::<Vec<_>>();
+    let (version_id, client, delta) = match parts[..] {
+        [version_id, client, delta] => (version_id, client.parse().ok()?, delta),
+        // Journals written before downloads were counted by client
+        [version_id, delta] => (version_id, DownloadClient::Other, delta),
+        _ => return None,
+    };
+    Some(((version_id.parse().ok()?, client), delta.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CARGO: DownloadClient = DownloadClient::Cargo(Some((1, 58)));
+
+    #[test]
+    fn replay_sums_deltas() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("downloads.journal");
+        let contents = "1 cargo/1.58 1\n2 other 1\n1 cargo/1.58 1\n2 other -1\n1 browser 1\n\
+            3 other 5\n3 other -2\n4 other 1\n4 other -2\n";
+        fs::write(&path, contents).unwrap();
+
+        let (_, replayed) = Journal::open(&path, 1).unwrap();
+        let expected = [
+            ((1, CARGO), 2),
+            ((1, DownloadClient::Browser), 1),
+            ((3, DownloadClient::Other), 3),
+        ];
+        assert_eq!(replayed.counts, expected.into_iter().collect());
+        assert_eq!(replayed.malformed_lines, 0);
+    }
+
+    #[test]
+    fn replay_skips_partial_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("downloads.journal");
+        fs::write(&path, "1 cargo/1.58 1\n1 cargo/1.58 1\n12 car").unwrap();
+
+        let (_, replayed) = Journal::open(&path, 1).unwrap();
+        assert_eq!(replayed.counts.get(&(1, CARGO)), Some(&2));
+        assert_eq!(replayed.malformed_lines, 1);
+    }
+
+    #[test]
+    fn replay_lines_without_client() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("downloads.journal");
+        fs::write(&path, "1 1\n1 1\n").unwrap();
+
+        let (_, replayed) = Journal::open(&path, 1).unwrap();
+        assert_eq!(replayed.counts.get(&(1, DownloadClient::Other)), Some(&2));
+    }
+
+    #[test]
+    fn append_and_compact() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("downloads.journal");
+
+        let (journal, replayed) = Journal::open(&path, 2).unwrap();
+        assert!(replayed.counts.is_empty());
+
+        journal.append(
+            &mut journal.lock_buffer(0),
+            &[((1, CARGO), 1), ((2, CARGO), 1)],
+        );
+        journal.append(&mut journal.lock_buffer(1), &[((3, CARGO), 1)]);
+        // Appended lines are only written to the file when the journal is flushed
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+        journal
+            .write(&mut journal.lock(), &[((1, CARGO), -1)])
+            .unwrap();
+        let expected = "1 cargo/1.58 1\n2 cargo/1.58 1\n3 cargo/1.58 1\n1 cargo/1.58 -1\n";
+        assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+        assert_eq!(journal.records.load(Ordering::SeqCst), 4);
+
+        journal.append(&mut journal.lock_buffer(0), &[((4, CARGO), 1)]);
+        journal
+            .compact(&mut journal.lock(), [((2, CARGO), 1)].into_iter())
+            .unwrap();
+        journal.append(
+            &mut journal.lock_buffer(1),
+            &[((3, DownloadClient::Mirror), 1)],
+        );
+        journal.flush(&mut journal.lock()).unwrap();
+        let expected = "2 cargo/1.58 1\n3 mirror 1\n";
+        assert_eq!(fs::read_to_string(&path).unwrap(), expected);
+        assert_eq!(journal.records.load(Ordering::SeqCst), 1);
+    }
+}
@@ -1,14 +1,250 @@
+use chrono::{NaiveDateTime, Utc};
+use conduit::{header, RequestExt};
+use diesel::{Connection, PgConnection};
+use minijinja::Environment;
+use serde_json::Value;
 use std::path::PathBuf;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
+use swirl::Job;
 
 use crate::util::errors::{server_error, AppResult};
+use crate::util::request_header;
+use crate::worker;
 
+use lettre::message::header::{Header, HeaderName, HeaderValue};
+use lettre::message::MultiPart;
 use lettre::transport::file::FileTransport;
 use lettre::transport::smtp::authentication::{Credentials, Mechanism};
 use lettre::transport::smtp::SmtpTransport;
 use lettre::{Message, Transport};
 
-#[derive(Debug)]
+/// The templates used to render the emails. Every email has a plain text and an HTML version,
+/// the HTML versions extend `base.html`. Templates ending in `.html` are escaped automatically.
+const TEMPLATES: &[(&str, &str)] = &[
+    ("base.html", include_str!("email/base.html.j2")),
+    (
+        "user_confirm.txt",
+        include_str!("email/user_confirm.txt.j2"),
+    ),
+    (
+        "user_confirm.html",
+        include_str!("email/user_confirm.html.j2"),
+    ),
+    (
+        "owner_invite.txt",
+        include_str!("email/owner_invite.txt.j2"),
+    ),
+    (
+        "owner_invite.html",
+        include_str!("email/owner_invite.html.j2"),
+    ),
+    (
+        "owner_invite_reminder.txt",
+        include_str!("email/owner_invite_reminder.txt.j2"),
+    ),
+    (
+        "owner_invite_reminder.html",
+        include_str!("email/owner_invite_reminder.html.j2"),
+    ),
+    (
+        "owner_invite_expired.txt",
+        include_str!("email/owner_invite_expired.txt.j2"),
+    ),
+    (
+        "owner_invite_expired.html",
+        include_str!("email/owner_invite_expired.html.j2"),
+    ),
+    (
+        "version_published.txt",
+        include_str!("email/version_published.txt.j2"),
+    ),
+    (
+        "version_published.html",
+        include_str!("email/version_published.html.j2"),
+    ),
+    (
+        "weekly_digest.txt",
+        include_str!("email/weekly_digest.txt.j2"),
+    ),
+    (
+        "weekly_digest.html",
+        include_str!("email/weekly_digest.html.j2"),
+    ),
+    (
+        "security_details.txt",
+        include_str!("email/security_details.txt.j2"),
+    ),
+    (
+        "security_details.html",
+        include_str!("email/security_details.html.j2"),
+    ),
+    (
+        "token_created.txt",
+        include_str!("email/token_created.txt.j2"),
+    ),
+    (
+        "token_created.html",
+        include_str!("email/token_created.html.j2"),
+    ),
+    (
+        "token_revoked.txt",
+        include_str!("email/token_revoked.txt.j2"),
+    ),
+    (
+        "token_revoked.html",
+        include_str!("email/token_revoked.html.j2"),
+    ),
+    (
+        "owners_changed.txt",
+        include_str!("email/owners_changed.txt.j2"),
+    ),
+    (
+        "owners_changed.html",
+        include_str!("email/owners_changed.html.j2"),
+    ),
+    (
+        "email_changed.txt",
+        include_str!("email/email_changed.txt.j2"),
+    ),
+    (
+        "email_changed.html",
+        include_str!("email/email_changed.html.j2"),
+    ),
+];
+
+fn templates() -> Environment<'static> {
+    let mut env = Environment::new();
+    for &(name, source) in TEMPLATES {
+        env.add_template(name, source)
+            .unwrap_or_else(|e| panic!("invalid email template {name}: {e}"));
+    }
+    env
+}
+
+/// A rendered email, ready to be delivered by the `send_email` background job.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmailMessage {
+    pub to: String,
+    pub subject: String,
+    pub text_body: String,
+    pub html_body: String,
+    /// The URL that unsubscribes the recipient with a `POST` request, see RFC 8058
+    #[serde(default)]
+    pub list_unsubscribe: Option<String>,
+}
+
+impl EmailMessage {
+    /// Renders the text and HTML versions of a template. The `subject` and the `domain` of
+    /// crates.io are available to all templates.
+    fn render(to: &str, subject: &str, template: &str, mut context: Value) -> AppResult<Self> {
+        if let Value::Object(context) = &mut context {
+            context.insert("subject".into(), subject.into());
+            context.insert("domain".into(), crate::config::domain_name().into());
+        }
+
+        let env = templates();
+        let text_body = env
+            .get_template(&format!("{template}.txt"))?
+            .render(&context)?;
+        let html_body = env
+            .get_template(&format!("{template}.html"))?
+            .render(&context)?;
+
+        Ok(Self {
+            to: to.into(),
+            subject: subject.into(),
+            text_body,
+            html_body,
+            list_unsubscribe: None,
+        })
+    }
+}
+
+/// The `List-Unsubscribe` header, which lets mail clients offer an unsubscribe button.
+#[derive(Debug, Clone)]
+struct ListUnsubscribe(String);
+
+impl Header for ListUnsubscribe {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe")
+    }
+
+    fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let url = s.trim().trim_start_matches('<').trim_end_matches('>');
+        Ok(Self(url.into()))
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), format!("<{}>", self.0))
+    }
+}
+
+/// The `List-Unsubscribe-Post` header, which tells mail clients that the `List-Unsubscribe`
+/// URL unsubscribes with a single `POST` request, as described in RFC 8058.
+#[derive(Debug, Clone)]
+struct ListUnsubscribePost;
+
+impl Header for ListUnsubscribePost {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe-Post")
+    }
+
+    fn parse(_s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self)
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), "List-Unsubscribe=One-Click".into())
+    }
+}
+
+/// Details about the request that caused a security notification, to help users recognize
+/// activity that wasn't their own.
+#[derive(Debug, Serialize)]
+pub struct RequestDetails {
+    pub time: String,
+    pub ip_address: String,
+    pub user_agent: String,
+}
+
+impl RequestDetails {
+    pub fn from_request(req: &dyn RequestExt) -> Self {
+        let or_unknown = |value: &str| match value {
+            "" => "unknown".to_string(),
+            value => value.to_string(),
+        };
+
+        Self {
+            time: Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            ip_address: or_unknown(request_header(req, "x-real-ip")),
+            user_agent: or_unknown(request_header(req, header::USER_AGENT)),
+        }
+    }
+}
+
+/// The changes to the crates a user follows that are summarized in their weekly digest.
+#[derive(Debug, Default, Serialize)]
+pub struct WeeklyDigest {
+    pub new_versions: Vec<DigestVersion>,
+    pub yanked_versions: Vec<DigestVersion>,
+    pub deprecated_crates: Vec<String>,
+}
+
+impl WeeklyDigest {
+    pub fn is_empty(&self) -> bool {
+        self.new_versions.is_empty()
+            && self.yanked_versions.is_empty()
+            && self.deprecated_crates.is_empty()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DigestVersion {
+    pub crate_name: String,
+    pub version: String,
+}
+
+#[derive(Debug, Clone)]
 pub struct Emails {
     backend: EmailBackend,
 }
@@ -40,47 +276,229 @@ impl Emails {
     pub fn new_in_memory() -> Self {
         Self {
             backend: EmailBackend::Memory {
-                mails: Mutex::new(Vec::new()),
+                mails: Arc::new(Mutex::new(Vec::new())),
             },
         }
     }
 
-    /// Attempts to send a confirmation email.
-    pub fn send_user_confirm(&self, email: &str, user_name: &str, token: &str) -> AppResult<()> {
+    /// Attempts to enqueue a confirmation email.
+    pub fn send_user_confirm(
+        &self,
+        conn: &PgConnection,
+        email: &str,
+        user_name: &str,
+        token: &str,
+    ) -> AppResult<()> {
         // Create a URL with token string as path to send to user
         // If user clicks on path, look email/user up in database,
         // make sure tokens match
 
         let subject = "Please confirm your email address";
-        let body = format!(
-            "Hello {}! Welcome to Crates.io. Please click the
-link below to verify your email address. Thank you!\n
-https://{}/confirm/{}",
-            user_name,
-            crate::config::domain_name(),
-            token
-        );
+        let context = json!({ "user_name": user_name, "token": token });
 
-        self.send(email, subject, &body)
+        self.enqueue(conn, email, subject, "user_confirm", context)
     }
 
-    /// Attempts to send an ownership invitation.
+    /// Attempts to enqueue an ownership invitation.
     pub fn send_owner_invite(
         &self,
+        conn: &PgConnection,
         email: &str,
         user_name: &str,
         crate_name: &str,
         token: &str,
     ) -> AppResult<()> {
         let subject = "Crate ownership invitation";
-        let body = format!(
-            "{user_name} has invited you to become an owner of the crate {crate_name}!\n
-Visit https://{domain}/accept-invite/{token} to accept this invitation,
-or go to https://{domain}/me/pending-invites to manage all of your crate ownership invitations.",
-            domain = crate::config::domain_name()
-        );
+        let context = json!({
+            "inviter": user_name,
+            "crate_name": crate_name,
+            "token": token,
+        });
+
+        self.enqueue(conn, email, subject, "owner_invite", context)
+    }
+
+    /// Attempts to enqueue a reminder about an ownership invitation that expires soon.
+    pub fn send_owner_invite_reminder(
+        &self,
+        conn: &PgConnection,
+        email: &str,
+        user_name: &str,
+        crate_name: &str,
+        token: &str,
+        days_left: i64,
+    ) -> AppResult<()> {
+        let subject = "Crate ownership invitation expires soon";
+        let context = json!({
+            "inviter": user_name,
+            "crate_name": crate_name,
+            "token": token,
+            "days_left": days_left,
+        });
+
+        self.enqueue(conn, email, subject, "owner_invite_reminder", context)
+    }
 
-        self.send(email, subject, &body)
+    /// Attempts to notify the inviter that an ownership invitation expired without being accepted.
+    pub fn send_owner_invite_expired(
+        &self,
+        conn: &PgConnection,
+        email: &str,
+        invitee_name: &str,
+        crate_name: &str,
+    ) -> AppResult<()> {
+        let subject = "Crate ownership invitation expired";
+        let context = json!({ "invitee": invitee_name, "crate_name": crate_name });
+
+        self.enqueue(conn, email, subject, "owner_invite_expired", context)
+    }
+
+    /// Attempts to notify a crate owner about a newly published version.
+    #[allow(clippy::too_many_arguments)]
+    pub fn send_version_published(
+        &self,
+        conn: &PgConnection,
+        email: &str,
+        crate_name: &str,
+        version: &str,
+        publisher: &str,
+        token_name: Option<&str>,
+        published_at: NaiveDateTime,
+    ) -> AppResult<()> {
+        let subject = format!("Crate {crate_name} version {version} was published");
+        let context = json!({
+            "crate_name": crate_name,
+            "version": version,
+            "publisher": publisher,
+            "token_name": token_name,
+            "published_at": published_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+        });
+
+        self.enqueue(conn, email, &subject, "version_published", context)
+    }
+
+    /// Attempts to enqueue the weekly digest about the crates a user follows.
+    pub fn send_weekly_digest(
+        &self,
+        conn: &PgConnection,
+        email: &str,
+        user_name: &str,
+        digest: &WeeklyDigest,
+        unsubscribe_token: &str,
+    ) -> AppResult<()> {
+        let subject = "Your weekly crates.io digest";
+        let context = json!({
+            "user_name": user_name,
+            "new_versions": digest.new_versions,
+            "yanked_versions": digest.yanked_versions,
+            "deprecated_crates": digest.deprecated_crates,
+            "unsubscribe_token": unsubscribe_token,
+        });
+
+        let mut message = EmailMessage::render(email, subject, "weekly_digest", context)?;
+        message.list_unsubscribe = Some(format!(
+            "https://{}/api/v1/weekly_digest/unsubscribe/{unsubscribe_token}",
+            crate::config::domain_name()
+        ));
+        self.enqueue_message(conn, message)
+    }
+
+    /// Attempts to notify a user that an API token was created for their account.
+    pub fn send_token_created(
+        &self,
+        conn: &PgConnection,
+        email: &str,
+        user_name: &str,
+        token_name: &str,
+        details: &RequestDetails,
+    ) -> AppResult<()> {
+        let subject = "A new API token was created";
+        let context = json!({
+            "user_name": user_name,
+            "token_name": token_name,
+            "details": details,
+        });
+
+        self.enqueue(conn, email, subject, "token_created", context)
+    }
+
+    /// Attempts to notify a user that an API token of their account was revoked.
+    pub fn send_token_revoked(
+        &self,
+        conn: &PgConnection,
+        email: &str,
+        user_name: &str,
+        token_name: &str,
+        details: &RequestDetails,
+    ) -> AppResult<()> {
+        let subject = "An API token was revoked";
+        let context = json!({
+            "user_name": user_name,
+            "token_name": token_name,
+            "details": details,
+        });
+
+        self.enqueue(conn, email, subject, "token_revoked", context)
+    }
+
+    /// Attempts to notify a crate owner that the owners of the crate were changed.
+    pub fn send_owners_changed(
+        &self,
+        conn: &PgConnection,
+        email: &str,
+        crate_name: &str,
+        actor: &str,
+        changes: &[String],
+        details: &RequestDetails,
+    ) -> AppResult<()> {
+        let subject = format!("The owners of the crate {crate_name} were changed");
+        let context = json!({
+            "crate_name": crate_name,
+            "actor": actor,
+            "changes": changes,
+            "details": details,
+        });
+
+        self.enqueue(conn, email, &subject, "owners_changed", context)
+    }
+
+    /// Attempts to notify a user at their previous address that their email address was changed.
+    pub fn send_email_changed(
+        &self,
+        conn: &PgConnection,
+        email: &str,
+        user_name: &str,
+        new_email: &str,
+        details: &RequestDetails,
+    ) -> AppResult<()> {
+        let subject = "The email address of your account was changed";
+        let context = json!({
+            "user_name": user_name,
+            "new_email": new_email,
+            "details": details,
+        });
+
+        self.enqueue(conn, email, subject, "email_changed", context)
+    }
+
+    /// Attempts to notify a user that an email address was added to their account.
+    pub fn send_email_added(
+        &self,
+        conn: &PgConnection,
+        email: &str,
+        user_name: &str,
+        new_email: &str,
+        details: &RequestDetails,
+    ) -> AppResult<()> {
+        let subject = "An email address was added to your account";
+        let context = json!({
+            "user_name": user_name,
+            "new_email": new_email,
+            "added": true,
+            "details": details,
+        });
+
+        self.enqueue(conn, email, subject, "email_changed", context)
     }
 
     /// This is supposed to be used only during tests, to retrieve the messages stored in the
@@ -93,12 +511,51 @@ or go to https://{domain}/me/pending-invites to manage all of your crate ownersh
         }
     }
 
-    fn send(&self, recipient: &str, subject: &str, body: &str) -> AppResult<()> {
-        let email = Message::builder()
-            .to(recipient.parse()?)
+    /// Renders an email and enqueues a `send_email` job to deliver it.
+    ///
+    /// The addresses are validated before the job is enqueued, so that invalid recipients are
+    /// reported to the caller instead of failing the job over and over again.
+    fn enqueue(
+        &self,
+        conn: &PgConnection,
+        recipient: &str,
+        subject: &str,
+        template: &str,
+        context: Value,
+    ) -> AppResult<()> {
+        let message = EmailMessage::render(recipient, subject, template, context)?;
+        self.enqueue_message(conn, message)
+    }
+
+    /// The job is enqueued in a new transaction, so that a failure doesn't abort the
+    /// transaction of the caller, which usually swallows the error.
+    fn enqueue_message(&self, conn: &PgConnection, message: EmailMessage) -> AppResult<()> {
+        self.build(&message)?;
+        conn.transaction(|| worker::send_email(message).enqueue(conn))?;
+        Ok(())
+    }
+
+    fn build(&self, message: &EmailMessage) -> AppResult<Message> {
+        let mut builder = Message::builder()
+            .to(message.to.parse()?)
             .from(self.sender_address().parse()?)
-            .subject(subject)
-            .body(body.to_string())?;
+            .subject(&message.subject);
+        if let Some(url) = &message.list_unsubscribe {
+            builder = builder
+                .header(ListUnsubscribe(url.clone()))
+                .header(ListUnsubscribePost);
+        }
+
+        Ok(builder.multipart(MultiPart::alternative_plain_html(
+            message.text_body.clone(),
+            message.html_body.clone(),
+        ))?)
+    }
+
+    /// Delivers a rendered email using the configured backend. This is called by the
+    /// `send_email` background job.
+    pub fn deliver(&self, message: &EmailMessage) -> AppResult<()> {
+        let email = self.build(message)?;
 
         match &self.backend {
             EmailBackend::Smtp {
@@ -119,9 +576,11 @@ or go to https://{domain}/me/pending-invites to manage all of your crate ownersh
                     .map_err(|_| server_error("Email file could not be generated"))?;
             }
             EmailBackend::Memory { mails } => mails.lock().unwrap().push(StoredEmail {
-                to: recipient.into(),
-                subject: subject.into(),
-                body: body.into(),
+                to: message.to.clone(),
+                subject: message.subject.clone(),
+                body: message.text_body.clone(),
+                html_body: message.html_body.clone(),
+                list_unsubscribe: message.list_unsubscribe.clone(),
             }),
         }
 
@@ -137,6 +596,7 @@ or go to https://{domain}/me/pending-invites to manage all of your crate ownersh
     }
 }
 
+#[derive(Clone)]
 enum EmailBackend {
     /// Backend used in production to send mails using SMTP.
     Smtp {
@@ -147,7 +607,8 @@ enum EmailBackend {
     /// Backend used locally during development, will store the emails in the provided directory.
     FileSystem { path: PathBuf },
     /// Backend used during tests, will keep messages in memory to allow tests to retrieve them.
-    Memory { mails: Mutex<Vec<StoredEmail>> },
+    /// Clones share the messages, so that mails sent by background jobs can be retrieved too.
+    Memory { mails: Arc<Mutex<Vec<StoredEmail>>> },
 }
 
 // Custom Debug implementation to avoid showing the SMTP password.
@@ -174,28 +635,74 @@ impl std::fmt::Debug for EmailBackend {
 pub struct StoredEmail {
     pub to: String,
     pub subject: String,
+    /// The plain text version of the email
     pub body: String,
+    pub html_body: String,
+    pub list_unsubscribe: Option<String>,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn message(to: &str) -> EmailMessage {
+        EmailMessage {
+            to: to.into(),
+            subject: "test".into(),
+            text_body: "test".into(),
+            html_body: "<p>test</p>".into(),
+            list_unsubscribe: None,
+        }
+    }
+
     #[test]
     fn sending_to_invalid_email_fails() {
         let emails = Emails::new_in_memory();
 
-        assert_err!(emails.send(
-            "String.Format(\"{0}.{1}@live.com\", FirstName, LastName)",
-            "test",
-            "test",
-        ));
+        assert_err!(emails.deliver(&message(
+            "String.Format(\"{0}.{1}@live.com\", FirstName, LastName)"
+        )));
     }
 
     #[test]
     fn sending_to_valid_email_succeeds() {
         let emails = Emails::new_in_memory();
 
-        assert_ok!(emails.send("someone@example.com", "test", "test"));
+        assert_ok!(emails.deliver(&message("someone@example.com")));
+
+        let mails = emails.mails_in_memory().unwrap();
+        assert_eq!(mails.len(), 1);
+        assert_eq!(mails[0].body, "test");
+        assert_eq!(mails[0].html_body, "<p>test</p>");
+    }
+
+    #[test]
+    fn all_templates_are_valid() {
+        let env = templates();
+        for (name, _) in TEMPLATES {
+            assert_ok!(env.get_template(name));
+        }
+    }
+
+    #[test]
+    fn templates_render_text_and_html() {
+        let context = json!({ "inviter": "foo", "crate_name": "<bar>", "token": "secret" });
+        let message = assert_ok!(EmailMessage::render(
+            "someone@example.com",
+            "Crate ownership invitation",
+            "owner_invite",
+            context,
+        ));
+
+        assert_eq!(message.subject, "Crate ownership invitation");
+        assert!(message
+            .text_body
+            .starts_with("foo has invited you to become an owner of the crate <bar>!"));
+        assert!(message.text_body.contains("/accept-invite/secret"));
+        assert!(message
+            .html_body
+            .contains("<title>Crate ownership invitation</title>"));
+        assert!(message.html_body.contains("&lt;bar&gt;"));
+        assert!(!message.html_body.contains("<bar>"));
     }
 }
@@ -10,7 +10,7 @@ use std::str;
 use crate::util::errors::{cargo_err, internal, not_found, AppError, AppResult};
 use reqwest::blocking::Client;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct GitHubClient {
     base_url: String,
     client: Option<Client>,
@@ -0,0 +1,132 @@
+//! This module implements functionality for interacting with GitLab.
+
+use oauth2::AccessToken;
+use reqwest::{self, header};
+
+use serde::de::DeserializeOwned;
+
+use crate::util::errors::{cargo_err, internal, not_found, AppError, AppResult};
+use reqwest::blocking::Client;
+
+#[derive(Debug, Clone)]
+pub struct GitLabClient {
+    base_url: String,
+    client: Option<Client>,
+}
+
+impl GitLabClient {
+    pub fn new(client: Option<Client>, base_url: String) -> Self {
+        Self { base_url, client }
+    }
+
+    /// The URL of the GitLab instance, e.g. `https://gitlab.com`
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn current_user(&self, auth: &AccessToken) -> AppResult<GitLabUser> {
+        self.request("/user", auth)
+    }
+
+    /// Looks up a group by its full path, e.g. `group/subgroup`
+    pub fn group_by_path(&self, path: &str, auth: &AccessToken) -> AppResult<GitLabGroup> {
+        let path = url::form_urlencoded::byte_serialize(path.as_bytes()).collect::<String>();
+        let url = format!("/groups/{path}");
+        self.request(&url, auth)
+    }
+
+    /// Returns the membership of a user in a group, including memberships that were
+    /// inherited from a parent group
+    pub fn group_membership(
+        &self,
+        group_id: i32,
+        user_id: i32,
+        auth: &AccessToken,
+    ) -> AppResult<GitLabGroupMember> {
+        let url = format!("/groups/{group_id}/members/all/{user_id}");
+        self.request(&url, auth)
+    }
+
+    /// Sends a GET request to the GitLab REST API.
+    pub fn request<T>(&self, url: &str, auth: &AccessToken) -> AppResult<T>
+    where
+        T: DeserializeOwned,
+    {
+        let url = format!("{}/api/v4{}", self.base_url, url);
+        info!("GITLAB HTTP: {url}");
+
+        self.client()
+            .get(&url)
+            .header(header::ACCEPT, "application/json")
+            .header(header::AUTHORIZATION, format!("Bearer {}", auth.secret()))
+            .header(header::USER_AGENT, "crates.io (https://crates.io)")
+            .send()?
+            .error_for_status()
+            .map_err(|e| handle_error_response(&e))?
+            .json()
+            .map_err(Into::into)
+    }
+
+    /// Returns a client for making HTTP requests to GitLab.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the application was not initialized with a client.  This should only occur in
+    /// tests that were not properly initialized.
+    fn client(&self) -> &Client {
+        self.client
+            .as_ref()
+            .expect("No HTTP client is configured.  In tests, use `TestApp::with_proxy()`.")
+    }
+}
+
+fn handle_error_response(error: &reqwest::Error) -> Box<dyn AppError> {
+    use reqwest::StatusCode as Status;
+
+    match error.status() {
+        Some(Status::UNAUTHORIZED) | Some(Status::FORBIDDEN) => cargo_err(
+            "It looks like you don't have permission \
+             to query a necessary property from GitLab \
+             to complete this request. \
+             You may need to log in to crates.io with \
+             GitLab again to grant permission to read \
+             GitLab group memberships.",
+        ),
+        Some(Status::NOT_FOUND) => not_found(),
+        _ => internal(&format_args!(
+            "didn't get a 200 result from gitlab: {}",
+            error
+        )),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GitLabUser {
+    pub id: i32,
+    pub username: String,
+    pub name: Option<String>,
+    pub avatar_url: Option<String>,
+    /// The primary email address
+    pub email: Option<String>,
+    /// When the primary email address was confirmed. Self-managed instances can allow
+    /// unconfirmed addresses, so the address can't be trusted without this.
+    pub confirmed_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GitLabGroup {
+    pub id: i32,
+    pub full_path: String,
+    pub name: Option<String>,
+    pub avatar_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GitLabGroupMember {
+    pub state: String,
+}
+
+pub fn group_url(base_url: &str, login: &str) -> String {
+    let (_, path) = login.split_once(':').expect("group failed");
+    format!("{}/{}", base_url, path.replace(':', "/"))
+}
@@ -0,0 +1,156 @@
+//! This module implements the login providers that can be used instead of GitHub.
+//!
+//! Accounts with these providers are stored in the `linked_accounts` table, see
+//! `LinkedAccount::find_or_create_user` for how they are matched to crates.io users.
+
+use oauth2::basic::BasicClient;
+use oauth2::{AccessToken, AuthUrl, ClientId, ClientSecret, RedirectUrl, TokenUrl};
+use reqwest::blocking::Client;
+
+use crate::config;
+use crate::gitlab::GitLabClient;
+use crate::models::AccountProvider;
+use crate::oidc::OidcClient;
+use crate::util::errors::{bad_request, internal, AppResult};
+
+/// The information about an account that is needed to log its owner in.
+#[derive(Debug)]
+pub struct ProviderUser {
+    /// The unique ID of the account with the provider
+    pub id: String,
+    pub login: String,
+    pub name: Option<String>,
+    pub avatar: Option<String>,
+    pub email: Option<String>,
+    /// Whether the provider verified that the email address belongs to the account
+    pub email_verified: bool,
+}
+
+pub trait LoginProvider: Send + Sync {
+    fn provider(&self) -> AccountProvider;
+
+    /// Returns the OAuth client used to authorize crates.io with the provider.
+    fn oauth_client(&self) -> AppResult<BasicClient>;
+
+    /// The scopes requested when authorizing crates.io.
+    fn scopes(&self) -> &'static [&'static str];
+
+    /// Fetches the account the access token belongs to.
+    fn current_user(&self, token: &AccessToken) -> AppResult<ProviderUser>;
+}
+
+/// Creates the login providers enabled in the configuration.
+pub fn from_config(config: &config::Server, client: Option<Client>) -> Vec<Box<dyn LoginProvider>> {
+    let mut providers: Vec<Box<dyn LoginProvider>> = Vec::new();
+    if let Some(gitlab) = &config.gitlab {
+        providers.push(Box::new(GitLabLogin {
+            api: GitLabClient::new(client.clone(), gitlab.base_url.clone()),
+            config: gitlab.clone(),
+        }));
+    }
+    if let Some(oidc) = &config.oidc {
+        providers.push(Box::new(OidcLogin {
+            api: OidcClient::new(client, oidc.base_url.clone()),
+            config: oidc.clone(),
+        }));
+    }
+    providers
+}
+
+fn oauth_client(
+    config: &config::LoginProvider,
+    auth_url: String,
+    token_url: String,
+) -> AppResult<BasicClient> {
+    let auth_url = AuthUrl::new(auth_url).map_err(|e| internal(&e))?;
+    let token_url = TokenUrl::new(token_url).map_err(|e| internal(&e))?;
+    let redirect_url = RedirectUrl::new(config.redirect_url.clone()).map_err(|e| internal(&e))?;
+
+    let client = BasicClient::new(
+        ClientId::new(config.client_id.clone()),
+        Some(ClientSecret::new(config.client_secret.clone())),
+        auth_url,
+        Some(token_url),
+    )
+    .set_redirect_uri(redirect_url);
+
+    Ok(client)
+}
+
+struct GitLabLogin {
+    api: GitLabClient,
+    config: config::LoginProvider,
+}
+
+impl LoginProvider for GitLabLogin {
+    fn provider(&self) -> AccountProvider {
+        AccountProvider::GitLab
+    }
+
+    fn oauth_client(&self) -> AppResult<BasicClient> {
+        let base_url = self.api.base_url();
+        oauth_client(
+            &self.config,
+            format!("{base_url}/oauth/authorize"),
+            format!("{base_url}/oauth/token"),
+        )
+    }
+
+    fn scopes(&self) -> &'static [&'static str] {
+        // `read_api` is needed to check group memberships
+        &["read_user", "read_api"]
+    }
+
+    fn current_user(&self, token: &AccessToken) -> AppResult<ProviderUser> {
+        let user = self.api.current_user(token)?;
+        Ok(ProviderUser {
+            id: user.id.to_string(),
+            login: user.username,
+            name: user.name,
+            avatar: user.avatar_url,
+            email_verified: user.email.is_some() && user.confirmed_at.is_some(),
+            email: user.email,
+        })
+    }
+}
+
+struct OidcLogin {
+    api: OidcClient,
+    config: config::LoginProvider,
+}
+
+impl LoginProvider for OidcLogin {
+    fn provider(&self) -> AccountProvider {
+        AccountProvider::Oidc
+    }
+
+    fn oauth_client(&self) -> AppResult<BasicClient> {
+        let metadata = self.api.discover()?;
+        oauth_client(
+            &self.config,
+            metadata.authorization_endpoint,
+            metadata.token_endpoint,
+        )
+    }
+
+    fn scopes(&self) -> &'static [&'static str] {
+        &["openid", "profile", "email"]
+    }
+
+    fn current_user(&self, token: &AccessToken) -> AppResult<ProviderUser> {
+        let metadata = self.api.discover()?;
+        let info = self.api.user_info(&metadata, token)?;
+        let login = info.preferred_username.ok_or_else(|| {
+            bad_request("the OpenID Connect provider did not report a username for your account")
+        })?;
+
+        Ok(ProviderUser {
+            id: info.sub,
+            login,
+            name: info.name,
+            avatar: info.picture,
+            email: info.email,
+            email_verified: info.email_verified,
+        })
+    }
+}
@@ -50,6 +50,10 @@ metrics! {
         pub downloads_select_query_execution_time: Histogram,
         /// Number of download requests that are not counted yet.
         downloads_not_counted_total: IntGauge,
+        /// Number of downloads recovered from the journal when the instance started.
+        downloads_replayed_total: IntGauge,
+        /// Number of downloads that could not be counted.
+        downloads_dropped_total: IntGauge,
 
         /// Number of version ID cache hits on the download endpoint.
         pub version_id_cache_hits: IntCounter,
@@ -71,6 +75,10 @@ impl InstanceMetrics {
 
         self.downloads_not_counted_total
             .set(app.downloads_counter.pending_count());
+        self.downloads_replayed_total
+            .set(app.downloads_counter.replayed_count() as i64);
+        self.downloads_dropped_total
+            .set(app.downloads_counter.dropped_count() as i64);
 
         Ok(self.registry.gather())
     }
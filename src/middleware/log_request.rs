@@ -41,6 +41,16 @@ pub fn add_custom_metadata<V: Display>(req: &mut dyn RequestExt, key: &'static s
     sentry::configure_scope(|scope| scope.set_extra(key, value.to_string().into()));
 }
 
+/// Returns the value of a metadata entry that was added earlier while handling the request.
+pub(crate) fn get_custom_metadata<'a>(req: &'a dyn RequestExt, key: &str) -> Option<&'a str> {
+    let metadata = req.extensions().get::<CustomMetadata>()?;
+    metadata
+        .entries
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| v.as_str())
+}
+
 #[cfg(test)]
 pub(crate) fn get_log_message(req: &dyn RequestExt, key: &'static str) -> String {
     // Unwrap shouldn't panic as no other code has access to the private struct to remove it
@@ -1,35 +1,53 @@
 pub use self::action::{insert_version_owner_action, VersionAction, VersionOwnerAction};
 pub use self::badge::{Badge, CrateBadge, MaintenanceStatus};
 pub use self::category::{Category, CrateCategory, NewCategory};
-pub use self::crate_owner_invitation::{CrateOwnerInvitation, NewCrateOwnerInvitationOutcome};
+pub use self::crate_owner_invitation::{
+    CrateOwnerInvitation, NewCrateOwnerInvitationOutcome, PendingCrateOwnerInvitation,
+};
+pub use self::data_export::{DataExport, DATA_EXPORT_TTL_DAYS};
 pub use self::dependency::{Dependency, DependencyKind, ReverseDependency};
-pub use self::download::VersionDownload;
+pub use self::download::{PeriodDownloads, VersionDownload, VersionDownloadsArchive};
 pub use self::email::{Email, NewEmail};
 pub use self::follow::Follow;
 pub use self::keyword::{CrateKeyword, Keyword};
 pub use self::krate::{Crate, CrateVersions, NewCrate, RecentCrateDownloads};
+pub use self::linked_account::{AccountProvider, LinkedAccount};
 pub use self::owner::{CrateOwner, Owner, OwnerKind};
 pub use self::rights::Rights;
+pub use self::session::{
+    Session, COOKIE_SESSION_MAX_AGE_HOURS, SESSION_IDLE_TIMEOUT_DAYS, SESSION_MAX_AGE_DAYS,
+};
 pub use self::team::{NewTeam, Team};
+pub use self::team_membership::TeamMembership;
 pub use self::token::{ApiToken, CreatedApiToken};
+pub use self::token_usage::{ApiTokenUsage, NewApiTokenUsage};
+pub use self::totp::{TotpCredential, LOCKOUT_MINUTES, MAX_FAILED_ATTEMPTS};
 pub use self::user::{NewUser, User};
 pub use self::version::{NewVersion, TopVersions, Version};
 
+pub(crate) use self::version::is_valid_rust_version;
+
 pub mod helpers;
 
 mod action;
 mod badge;
 pub mod category;
 mod crate_owner_invitation;
+mod data_export;
 pub mod dependency;
 mod download;
 mod email;
 mod follow;
 mod keyword;
 pub mod krate;
+mod linked_account;
 mod owner;
 mod rights;
+mod session;
 mod team;
+mod team_membership;
 mod token;
+mod token_usage;
+mod totp;
 pub mod user;
 mod version;
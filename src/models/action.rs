@@ -63,7 +63,8 @@ impl ToSql<Integer, Pg> for VersionAction {
 pub struct VersionOwnerAction {
     pub id: i32,
     pub version_id: i32,
-    pub user_id: i32,
+    /// `None` if the user deleted their account
+    pub user_id: Option<i32>,
     pub api_token_id: Option<i32>,
     pub action: VersionAction,
     pub time: NaiveDateTime,
@@ -74,12 +75,15 @@ impl VersionOwnerAction {
         version_owner_actions::table.load(conn)
     }
 
-    pub fn by_version(conn: &PgConnection, version: &Version) -> QueryResult<Vec<(Self, User)>> {
+    pub fn by_version(
+        conn: &PgConnection,
+        version: &Version,
+    ) -> QueryResult<Vec<(Self, Option<User>)>> {
         use version_owner_actions::dsl::version_id;
 
         version_owner_actions::table
             .filter(version_id.eq(version.id))
-            .inner_join(users::table)
+            .left_outer_join(users::table)
             .order(version_owner_actions::dsl::id)
             .load(conn)
     }
@@ -87,16 +91,15 @@ impl VersionOwnerAction {
     pub fn for_versions(
         conn: &PgConnection,
         versions: &[Version],
-    ) -> QueryResult<Vec<Vec<(Self, User)>>> {
+    ) -> QueryResult<Vec<Vec<(Self, Option<User>)>>> {
         Ok(Self::belonging_to(versions)
-            .inner_join(users::table)
+            .left_outer_join(users::table)
             .order(version_owner_actions::dsl::id)
             .load(conn)?
             .grouped_by(versions))
     }
 }
 
-
 pub fn insert_version_owner_action(
     conn: &PgConnection,
     version_id_: i32,
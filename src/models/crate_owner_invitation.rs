@@ -2,8 +2,11 @@ use chrono::{NaiveDateTime, Utc};
 use diesel::prelude::*;
 
 use crate::config;
-use crate::models::{CrateOwner, OwnerKind};
-use crate::schema::{crate_owner_invitations, crate_owners, crates};
+use crate::email::Emails;
+use crate::models::{CrateOwner, OwnerKind, Rights, User};
+use crate::schema::{
+    crate_owner_invitations, crate_owners, crates, pending_crate_owner_invitations, users,
+};
 use crate::util::errors::{AppResult, OwnershipInvitationExpired};
 
 #[derive(Debug)]
@@ -22,6 +25,9 @@ pub struct CrateOwnerInvitation {
     pub created_at: NaiveDateTime,
     pub token: String,
     pub token_created_at: Option<NaiveDateTime>,
+    pub rights: Rights,
+    /// When the invitee was reminded that the invitation is about to expire
+    pub reminder_sent_at: Option<NaiveDateTime>,
 }
 
 impl CrateOwnerInvitation {
@@ -29,6 +35,7 @@ impl CrateOwnerInvitation {
         invited_user_id: i32,
         invited_by_user_id: i32,
         crate_id: i32,
+        rights: Rights,
         conn: &PgConnection,
         config: &config::Server,
     ) -> AppResult<NewCrateOwnerInvitationOutcome> {
@@ -38,6 +45,7 @@ impl CrateOwnerInvitation {
             invited_user_id: i32,
             invited_by_user_id: i32,
             crate_id: i32,
+            rights: Rights,
         }
 
         // Before actually creating the invite, check if an expired invitation already exists
@@ -65,6 +73,7 @@ impl CrateOwnerInvitation {
                 invited_user_id,
                 invited_by_user_id,
                 crate_id,
+                rights,
             })
             // The ON CONFLICT DO NOTHING clause results in not creating the invite if another one
             // already exists. This does not cause problems with expired invitation as those are
@@ -110,10 +119,14 @@ impl CrateOwnerInvitation {
                     created_by: self.invited_by_user_id,
                     owner_kind: OwnerKind::User as i32,
                     email_notifications: true,
+                    rights: self.rights,
                 })
                 .on_conflict(crate_owners::table.primary_key())
                 .do_update()
-                .set(crate_owners::deleted.eq(false))
+                .set((
+                    crate_owners::deleted.eq(false),
+                    crate_owners::rights.eq(self.rights),
+                ))
                 .execute(conn)?;
 
             diesel::delete(&self).execute(conn)?;
@@ -131,12 +144,167 @@ impl CrateOwnerInvitation {
         Ok(())
     }
 
+    /// Turns the pending invitations for the login of a user that just signed up into
+    /// regular invitations, and emails them like any other invitation. Pending invitations
+    /// that expired are discarded.
+    ///
+    /// Pending invitations are only created for GitHub logins, so this is only called when
+    /// logging in with GitHub. Users of the other login providers have logins like
+    /// `foo@gitlab`, which never match a pending invitation.
+    ///
+    /// Returns the number of invitations that were created.
+    pub fn create_from_pending(
+        user: &User,
+        conn: &PgConnection,
+        config: &config::Server,
+        emails: &Emails,
+    ) -> AppResult<usize> {
+        conn.transaction(|| {
+            let pending: Vec<PendingCrateOwnerInvitation> =
+                diesel::delete(pending_crate_owner_invitations::table.filter(
+                    pending_crate_owner_invitations::invited_login.eq(user.gh_login.to_lowercase()),
+                ))
+                .get_results(conn)?;
+
+            let email = user.notification_email(conn)?;
+            let mut created = 0;
+            for invitation in pending.iter().filter(|i| !i.is_expired(config)) {
+                let outcome = CrateOwnerInvitation::create(
+                    user.id,
+                    invitation.invited_by_user_id,
+                    invitation.crate_id,
+                    invitation.rights,
+                    conn,
+                    config,
+                )?;
+                if let NewCrateOwnerInvitationOutcome::InviteCreated { plaintext_token } = outcome {
+                    created += 1;
+                    if let Some(email) = &email {
+                        invitation.send_email(conn, emails, email, &plaintext_token);
+                    }
+                }
+            }
+            Ok(created)
+        })
+    }
+
     pub fn is_expired(&self, config: &config::Server) -> bool {
         self.expires_at(config) <= Utc::now().naive_utc()
     }
 
     pub fn expires_at(&self, config: &config::Server) -> NaiveDateTime {
-        let days = chrono::Duration::days(config.ownership_invitations_expiration_days as i64);
-        self.created_at + days
+        expires_at(self.created_at, config)
+    }
+}
+
+/// The model representing a row in the `pending_crate_owner_invitations` database table.
+///
+/// These are invitations for GitHub logins that don't have a crates.io account yet. They are
+/// turned into `CrateOwnerInvitation`s when the user logs in for the first time.
+#[derive(Clone, Debug, PartialEq, Eq, Identifiable, Queryable)]
+#[primary_key(invited_login, crate_id)]
+pub struct PendingCrateOwnerInvitation {
+    /// The invited GitHub login, always lowercase
+    pub invited_login: String,
+    pub invited_by_user_id: i32,
+    pub crate_id: i32,
+    pub rights: Rights,
+    pub created_at: NaiveDateTime,
+}
+
+impl PendingCrateOwnerInvitation {
+    /// Emails the invitation that was created from this pending one.
+    ///
+    /// Failures are only logged, since the invitee still sees the invitation on their pending
+    /// invitations page.
+    fn send_email(&self, conn: &PgConnection, emails: &Emails, email: &str, token: &str) {
+        // Use a new transaction, so that a failure doesn't abort the one creating the invitations
+        let sent = conn.transaction(|| {
+            let inviter: String = users::table
+                .find(self.invited_by_user_id)
+                .select(users::gh_login)
+                .first(conn)?;
+            let crate_name: String = crates::table
+                .find(self.crate_id)
+                .select(crates::name)
+                .first(conn)?;
+            emails.send_owner_invite(conn, email, &inviter, &crate_name, token)
+        });
+        if let Err(error) = sent {
+            warn!("Failed to send ownership invitation: {error}");
+        }
     }
+
+    /// Creates a pending invitation, unless an invitation for the same login and crate
+    /// already exists. Returns whether an invitation was created.
+    pub fn create(
+        invited_login: &str,
+        invited_by_user_id: i32,
+        crate_id: i32,
+        rights: Rights,
+        conn: &PgConnection,
+        config: &config::Server,
+    ) -> AppResult<bool> {
+        #[derive(Insertable, Clone, Debug)]
+        #[table_name = "pending_crate_owner_invitations"]
+        struct NewRecord {
+            invited_login: String,
+            invited_by_user_id: i32,
+            crate_id: i32,
+            rights: Rights,
+        }
+
+        let invited_login = invited_login.to_lowercase();
+
+        // Same as for regular invitations, expired invitations are replaced by new ones.
+        conn.transaction(|| -> AppResult<()> {
+            let existing: Option<PendingCrateOwnerInvitation> =
+                pending_crate_owner_invitations::table
+                    .find((&invited_login, crate_id))
+                    .for_update()
+                    .first(conn)
+                    .optional()?;
+
+            if let Some(existing) = existing {
+                if existing.is_expired(config) {
+                    diesel::delete(&existing).execute(conn)?;
+                }
+            }
+            Ok(())
+        })?;
+
+        let inserted = diesel::insert_into(pending_crate_owner_invitations::table)
+            .values(&NewRecord {
+                invited_login,
+                invited_by_user_id,
+                crate_id,
+                rights,
+            })
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+
+        Ok(inserted > 0)
+    }
+
+    /// Deletes the pending invitation of a login. Returns whether an invitation existed.
+    pub fn delete(invited_login: &str, crate_id: i32, conn: &PgConnection) -> QueryResult<bool> {
+        let target =
+            pending_crate_owner_invitations::table.find((invited_login.to_lowercase(), crate_id));
+        diesel::delete(target)
+            .execute(conn)
+            .map(|deleted| deleted > 0)
+    }
+
+    pub fn is_expired(&self, config: &config::Server) -> bool {
+        self.expires_at(config) <= Utc::now().naive_utc()
+    }
+
+    pub fn expires_at(&self, config: &config::Server) -> NaiveDateTime {
+        expires_at(self.created_at, config)
+    }
+}
+
+fn expires_at(created_at: NaiveDateTime, config: &config::Server) -> NaiveDateTime {
+    let days = chrono::Duration::days(config.ownership_invitations_expiration_days as i64);
+    created_at + days
 }
@@ -0,0 +1,50 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+
+use crate::models::User;
+use crate::schema::data_exports;
+
+/// How long a completed export can be downloaded, before the `delete_expired_data_exports`
+/// background job deletes it.
+pub const DATA_EXPORT_TTL_DAYS: i64 = 7;
+
+/// A request of a user to download all the data the registry holds about them.
+///
+/// The archive is built by the `export_user_data` background job, which sets `completed_at`
+/// once the archive was uploaded. Only the latest export of a user is kept.
+#[derive(Clone, Debug, Queryable, Identifiable, Associations)]
+#[belongs_to(User)]
+pub struct DataExport {
+    pub id: i32,
+    pub user_id: i32,
+    pub token: String,
+    pub requested_at: NaiveDateTime,
+    pub completed_at: Option<NaiveDateTime>,
+}
+
+impl DataExport {
+    pub fn insert(conn: &PgConnection, user_id: i32) -> QueryResult<DataExport> {
+        diesel::insert_into(data_exports::table)
+            .values(data_exports::user_id.eq(user_id))
+            .get_result(conn)
+    }
+
+    /// Returns when the archive stops being downloadable, if it was built already.
+    pub fn expires_at(&self) -> Option<NaiveDateTime> {
+        self.completed_at
+            .map(|completed_at| completed_at + Duration::days(DATA_EXPORT_TTL_DAYS))
+    }
+
+    pub fn is_downloadable(&self) -> bool {
+        self.expires_at()
+            .map_or(false, |expires_at| expires_at > Utc::now().naive_utc())
+    }
+
+    /// Returns the most recently requested export of a user.
+    pub fn latest(conn: &PgConnection, user: &User) -> QueryResult<Option<DataExport>> {
+        DataExport::belonging_to(user)
+            .order(data_exports::requested_at.desc())
+            .first(conn)
+            .optional()
+    }
+}
@@ -1,6 +1,8 @@
 use crate::models::Version;
-use crate::schema::version_downloads;
-use chrono::NaiveDate;
+use crate::schema::{version_downloads, version_downloads_archives};
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Date, Integer};
 
 #[derive(Queryable, Identifiable, Associations, Debug, Clone, Copy)]
 #[belongs_to(Version)]
@@ -12,3 +14,64 @@ pub struct VersionDownload {
     pub date: NaiveDate,
     pub processed: bool,
 }
+
+/// The downloads of a version summed up over a period, see `VersionDownload::sum_by_period`.
+#[derive(QueryableByName, Debug, Clone, Copy)]
+pub struct PeriodDownloads {
+    #[sql_type = "Integer"]
+    pub version_id: i32,
+    /// The first day of the period.
+    #[sql_type = "Date"]
+    pub period: NaiveDate,
+    #[sql_type = "BigInt"]
+    pub downloads: i64,
+}
+
+impl VersionDownload {
+    /// Sums up the downloads of each version between `from` and `to` (inclusive) per `interval`,
+    /// which is one of the fields accepted by the `date_trunc` function of PostgreSQL, like
+    /// `week` or `month`.
+    ///
+    /// With `month` and `year`, the monthly sums of the archived days are included as well.
+    /// `from` should be the first day of a month then, since the sums can't be split.
+    pub fn sum_by_period(
+        conn: &PgConnection,
+        version_ids: &[i32],
+        from: NaiveDate,
+        to: NaiveDate,
+        interval: &str,
+    ) -> QueryResult<Vec<PeriodDownloads>> {
+        use diesel::sql_query;
+        use diesel::sql_types::{Array, Text};
+
+        sql_query(include_str!("version_downloads_by_period.sql"))
+            .bind::<Text, _>(interval)
+            .bind::<Array<Integer>, _>(version_ids)
+            .bind::<Date, _>(from)
+            .bind::<Date, _>(to)
+            .load(conn)
+    }
+}
+
+/// A day of `version_downloads` rows that was moved to a CSV file in the uploader, see the
+/// `archive_version_downloads` background job.
+#[derive(Queryable, Identifiable, Debug, Clone, Copy)]
+#[primary_key(date)]
+pub struct VersionDownloadsArchive {
+    pub date: NaiveDate,
+    pub rows: i32,
+    pub downloads: i64,
+    pub archived_at: NaiveDateTime,
+}
+
+impl VersionDownloadsArchive {
+    /// Returns the last day that was archived. Days are archived oldest first, so the daily
+    /// counts up to this day are only available in the archives.
+    pub fn last_archived_date(conn: &PgConnection) -> QueryResult<Option<NaiveDate>> {
+        use diesel::dsl::max;
+
+        version_downloads_archives::table
+            .select(max(version_downloads_archives::date))
+            .get_result(conn)
+    }
+}
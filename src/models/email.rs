@@ -1,8 +1,14 @@
 use chrono::NaiveDateTime;
+use diesel::prelude::*;
 
 use crate::models::User;
 use crate::schema::emails;
 
+/// An email address of a user.
+///
+/// Users can have several addresses, but at most one of them is their primary address, which
+/// is used by `User::verified_email`. Another verified address can be picked to receive the
+/// notification emails instead of the primary one.
 #[derive(Debug, Queryable, AsChangeset, Identifiable, Associations)]
 #[belongs_to(User)]
 pub struct Email {
@@ -12,6 +18,25 @@ pub struct Email {
     pub verified: bool,
     pub token: String,
     pub token_generated_at: Option<NaiveDateTime>,
+    pub is_primary: bool,
+    pub send_notifications: bool,
+}
+
+impl Email {
+    /// Makes this address the primary address of its user.
+    pub fn make_primary(&self, conn: &PgConnection) -> QueryResult<()> {
+        // Two statements are needed, since the unique index on the primary address is checked
+        // for every updated row
+        conn.transaction(|| {
+            diesel::update(emails::table.filter(emails::user_id.eq(self.user_id)))
+                .set(emails::is_primary.eq(false))
+                .execute(conn)?;
+            diesel::update(self)
+                .set(emails::is_primary.eq(true))
+                .execute(conn)?;
+            Ok(())
+        })
+    }
 }
 
 #[derive(Debug, Insertable, AsChangeset)]
@@ -19,4 +44,5 @@ pub struct Email {
 pub struct NewEmail<'a> {
     pub user_id: i32,
     pub email: &'a str,
+    pub is_primary: bool,
 }
@@ -10,7 +10,7 @@ use crate::controllers::helpers::pagination::*;
 use crate::models::version::TopVersions;
 use crate::models::{
     Badge, CrateOwner, CrateOwnerInvitation, NewCrateOwnerInvitationOutcome, Owner, OwnerKind,
-    ReverseDependency, User, Version,
+    PendingCrateOwnerInvitation, ReverseDependency, Rights, Team, User, Version,
 };
 use crate::util::errors::{cargo_err, AppResult};
 
@@ -187,6 +187,7 @@ impl<'a> NewCrate<'a> {
                     created_by: user_id,
                     owner_kind: OwnerKind::User as i32,
                     email_notifications: true,
+                    rights: Rights::Full,
                 };
                 diesel::insert_into(crate_owners::table)
                     .values(&owner)
@@ -221,6 +222,24 @@ impl Crate {
         }
     }
 
+    /// SQL filter based on whether the crate's name is similar to the given
+    /// string, which also matches names with typos like `tokoi`.
+    ///
+    /// Names are similar if they share enough trigrams, as configured by the
+    /// `pg_trgm.similarity_threshold` setting.
+    pub fn fuzzy_matches_name<QS>(
+        name: &str,
+    ) -> Box<dyn BoxableExpression<QS, Pg, SqlType = Bool> + '_>
+    where
+        crates::name: SelectableExpression<QS>,
+    {
+        diesel_infix_operator!(IsSimilar, " % ");
+        Box::new(IsSimilar::new(
+            canon_crate_name(crates::name),
+            canon_crate_name(name),
+        ))
+    }
+
     /// SQL filter with the = binary operator
     pub fn with_name(name: &str) -> WithName<'_> {
         canon_crate_name(crates::name).eq(canon_crate_name(name))
@@ -317,46 +336,74 @@ impl Crate {
     }
 
     pub fn owners(&self, conn: &PgConnection) -> QueryResult<Vec<Owner>> {
+        Ok(self
+            .owners_with_rights(conn)?
+            .into_iter()
+            .map(|(owner, _)| owner)
+            .collect())
+    }
+
+    /// Returns all owners of this crate together with the rights each of them was granted.
+    pub fn owners_with_rights(&self, conn: &PgConnection) -> QueryResult<Vec<(Owner, Rights)>> {
         let users = CrateOwner::by_owner_kind(OwnerKind::User)
             .filter(crate_owners::crate_id.eq(self.id))
             .inner_join(users::table)
-            .select(users::all_columns)
-            .load(conn)?
+            .select((users::all_columns, crate_owners::rights))
+            .load::<(User, Rights)>(conn)?
             .into_iter()
-            .map(Owner::User);
+            .map(|(user, rights)| (Owner::User(user), rights));
         let teams = CrateOwner::by_owner_kind(OwnerKind::Team)
             .filter(crate_owners::crate_id.eq(self.id))
             .inner_join(teams::table)
-            .select(teams::all_columns)
-            .load(conn)?
+            .select((teams::all_columns, crate_owners::rights))
+            .load::<(Team, Rights)>(conn)?
             .into_iter()
-            .map(Owner::Team);
+            .map(|(team, rights)| (Owner::Team(team), rights));
 
         Ok(users.chain(teams).collect())
     }
 
+    /// Invites a user or adds a team as an owner of this crate.
+    ///
+    /// If `rights` is `None`, the owner is granted the default rights for its kind
+    /// (see `Owner::default_rights`). GitHub logins that don't have a crates.io account yet
+    /// receive a pending invitation, which is turned into a regular one when they sign up.
     pub fn owner_add(
         &self,
         app: &App,
         conn: &PgConnection,
         req_user: &User,
         login: &str,
+        rights: Option<Rights>,
     ) -> AppResult<String> {
         use diesel::insert_into;
 
+        if !login.contains(':') && Owner::find_user_by_login(conn, login)?.is_none() {
+            return self.pending_owner_add(app, conn, req_user, login, rights);
+        }
+
         let owner = Owner::find_or_create_by_login(app, conn, req_user, login)?;
+        let rights = rights.unwrap_or_else(|| owner.default_rights());
 
         match owner {
             // Users are invited and must accept before being added
             Owner::User(user) => {
                 let config = &app.config;
-                match CrateOwnerInvitation::create(user.id, req_user.id, self.id, conn, config)? {
+                match CrateOwnerInvitation::create(
+                    user.id,
+                    req_user.id,
+                    self.id,
+                    rights,
+                    conn,
+                    config,
+                )? {
                     NewCrateOwnerInvitationOutcome::InviteCreated { plaintext_token } => {
-                        if let Ok(Some(email)) = user.verified_email(conn) {
+                        if let Ok(Some(email)) = user.notification_email(conn) {
                             // Swallow any error. Whether or not the email is sent, the invitation
                             // entry will be created in the database and the user will see the
                             // invitation when they visit https://crates.io/me/pending-invites/.
                             let _ = app.emails.send_owner_invite(
+                                conn,
                                 &email,
                                 &req_user.gh_login,
                                 &self.name,
@@ -384,10 +431,14 @@ impl Crate {
                         created_by: req_user.id,
                         owner_kind: OwnerKind::Team as i32,
                         email_notifications: true,
+                        rights,
                     })
                     .on_conflict(crate_owners::table.primary_key())
                     .do_update()
-                    .set(crate_owners::deleted.eq(false))
+                    .set((
+                        crate_owners::deleted.eq(false),
+                        crate_owners::rights.eq(rights),
+                    ))
                     .execute(conn)?;
 
                 Ok(format!(
@@ -399,6 +450,66 @@ impl Crate {
         }
     }
 
+    fn pending_owner_add(
+        &self,
+        app: &App,
+        conn: &PgConnection,
+        req_user: &User,
+        login: &str,
+        rights: Option<Rights>,
+    ) -> AppResult<String> {
+        if !is_valid_github_login(login) {
+            return Err(cargo_err(&format_args!(
+                "could not find user with login `{}`",
+                login
+            )));
+        }
+
+        let rights = rights.unwrap_or(Rights::Full);
+        let created = PendingCrateOwnerInvitation::create(
+            login,
+            req_user.id,
+            self.id,
+            rights,
+            conn,
+            &app.config,
+        )?;
+
+        Ok(if created {
+            format!(
+                "user {} does not have a crates.io account yet, \
+                 they will be invited to be an owner of crate {} when they sign up",
+                login, self.name
+            )
+        } else {
+            format!(
+                "user {} already has a pending invitation to be an owner of crate {}",
+                login, self.name
+            )
+        })
+    }
+
+    /// Changes the rights granted to an existing owner of this crate.
+    pub fn owner_set_rights(
+        &self,
+        conn: &PgConnection,
+        owner: &Owner,
+        rights: Rights,
+    ) -> AppResult<String> {
+        let target = crate_owners::table.find((self.id(), owner.id(), owner.kind()));
+        diesel::update(target)
+            .set(crate_owners::rights.eq(rights))
+            .execute(conn)?;
+
+        let rights: &'static str = rights.into();
+        Ok(format!(
+            "{} now has {} rights on crate {}",
+            owner.login(),
+            rights,
+            self.name
+        ))
+    }
+
     pub fn owner_remove(
         &self,
         app: &App,
@@ -406,6 +517,14 @@ impl Crate {
         req_user: &User,
         login: &str,
     ) -> AppResult<()> {
+        // Removing a login without an account withdraws its pending invitation
+        if !login.contains(':')
+            && Owner::find_user_by_login(conn, login)?.is_none()
+            && PendingCrateOwnerInvitation::delete(login, self.id, conn)?
+        {
+            return Ok(());
+        }
+
         let owner = Owner::find_or_create_by_login(app, conn, req_user, login)?;
 
         let target = crate_owners::table.find((self.id(), owner.id(), owner.kind() as i32));
@@ -442,8 +561,20 @@ impl Crate {
     }
 }
 
+/// GitHub logins consist of alphanumeric characters and single hyphens, which
+/// can't be at the start or the end, and have at most 39 characters.
+fn is_valid_github_login(login: &str) -> bool {
+    !login.is_empty()
+        && login.len() <= 39
+        && !login.starts_with('-')
+        && !login.ends_with('-')
+        && !login.contains("--")
+        && login.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
 #[cfg(test)]
 mod tests {
+    use super::is_valid_github_login;
     use crate::models::{Crate, NewCrate};
 
     #[test]
@@ -505,6 +636,18 @@ mod tests {
         assert!(!Crate::valid_feature("foo/?bar"));
         assert!(!Crate::valid_feature("foo?bar"));
     }
+
+    #[test]
+    fn valid_github_login() {
+        assert!(is_valid_github_login("foo"));
+        assert!(is_valid_github_login("Foo-Bar42"));
+        assert!(!is_valid_github_login(""));
+        assert!(!is_valid_github_login("-foo"));
+        assert!(!is_valid_github_login("foo-"));
+        assert!(!is_valid_github_login("foo--bar"));
+        assert!(!is_valid_github_login("foo_bar"));
+        assert!(!is_valid_github_login(&"a".repeat(40)));
+    }
 }
 
 pub trait CrateVersions {
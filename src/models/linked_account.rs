@@ -0,0 +1,209 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use diesel::{
+    deserialize::{self, FromSql},
+    pg::Pg,
+    serialize::{self, Output, ToSql},
+    sql_types::Integer,
+};
+use std::io::Write;
+
+use crate::email::Emails;
+use crate::login_providers::ProviderUser;
+use crate::models::{NewUser, User};
+use crate::schema::{emails, linked_accounts, users};
+use crate::sql::lower;
+use crate::util::errors::{bad_request, AppResult};
+
+/// The services users can log in with, and teams can be hosted on.
+///
+/// GitHub accounts are stored directly in the `users` table, all other accounts are stored in
+/// the `linked_accounts` table.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, FromSqlRow, AsExpression, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+#[repr(i32)]
+#[sql_type = "Integer"]
+pub enum AccountProvider {
+    GitHub = 0,
+    GitLab = 1,
+    Oidc = 2,
+}
+
+impl AccountProvider {
+    /// Parses the name used in URLs and team logins.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "github" => Some(AccountProvider::GitHub),
+            "gitlab" => Some(AccountProvider::GitLab),
+            "oidc" => Some(AccountProvider::Oidc),
+            _ => None,
+        }
+    }
+}
+
+impl From<AccountProvider> for &'static str {
+    fn from(provider: AccountProvider) -> Self {
+        match provider {
+            AccountProvider::GitHub => "github",
+            AccountProvider::GitLab => "gitlab",
+            AccountProvider::Oidc => "oidc",
+        }
+    }
+}
+
+impl FromSql<Integer, Pg> for AccountProvider {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match <i32 as FromSql<Integer, Pg>>::from_sql(bytes)? {
+            0 => Ok(AccountProvider::GitHub),
+            1 => Ok(AccountProvider::GitLab),
+            2 => Ok(AccountProvider::Oidc),
+            n => Err(format!("unknown account provider: {n}").into()),
+        }
+    }
+}
+
+impl ToSql<Integer, Pg> for AccountProvider {
+    fn to_sql<W: Write>(&self, out: &mut Output<'_, W, Pg>) -> serialize::Result {
+        ToSql::<Integer, Pg>::to_sql(&(*self as i32), out)
+    }
+}
+
+/// An account of a user with a login provider other than GitHub.
+#[derive(Clone, Debug, Queryable, Identifiable, Associations)]
+#[belongs_to(User)]
+#[primary_key(provider, account_id)]
+#[table_name = "linked_accounts"]
+pub struct LinkedAccount {
+    pub user_id: i32,
+    pub provider: AccountProvider,
+    /// The ID of the account with the provider (the `sub` claim for OpenID Connect)
+    pub account_id: String,
+    pub login: String,
+    pub access_token: String,
+    pub avatar: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, AsChangeset, Debug)]
+#[table_name = "linked_accounts"]
+struct NewLinkedAccount<'a> {
+    user_id: i32,
+    provider: AccountProvider,
+    account_id: &'a str,
+    login: &'a str,
+    access_token: &'a str,
+    avatar: Option<&'a str>,
+}
+
+impl LinkedAccount {
+    /// Returns the account of the user with the given provider, if they linked one.
+    pub fn find_by_user(
+        conn: &PgConnection,
+        user: &User,
+        provider: AccountProvider,
+    ) -> QueryResult<Option<Self>> {
+        LinkedAccount::belonging_to(user)
+            .filter(linked_accounts::provider.eq(provider))
+            .first(conn)
+            .optional()
+    }
+
+    /// Finds the crates.io user a provider account belongs to, creating one if necessary.
+    ///
+    /// Accounts are linked to an existing user if the provider reports a verified email
+    /// address that the user has verified on crates.io as well. Users that signed up with
+    /// another provider than GitHub have a `gh_id` of 0.
+    pub fn find_or_create_user(
+        conn: &PgConnection,
+        emails: &Emails,
+        provider: AccountProvider,
+        provider_user: &ProviderUser,
+        access_token: &str,
+    ) -> AppResult<User> {
+        conn.transaction(|| {
+            let account = linked_accounts::table
+                .find((provider, &provider_user.id))
+                .first::<LinkedAccount>(conn)
+                .optional()?;
+
+            let user = match account {
+                Some(account) => User::find(conn, account.user_id)?,
+                None => match find_user_by_verified_email(conn, provider_user)? {
+                    Some(user) => user,
+                    None => create_user(conn, emails, provider, provider_user)?,
+                },
+            };
+
+            let new_account = NewLinkedAccount {
+                user_id: user.id,
+                provider,
+                account_id: &provider_user.id,
+                login: &provider_user.login,
+                access_token,
+                avatar: provider_user.avatar.as_deref(),
+            };
+            diesel::insert_into(linked_accounts::table)
+                .values(&new_account)
+                .on_conflict((linked_accounts::provider, linked_accounts::account_id))
+                .do_update()
+                .set(&new_account)
+                .execute(conn)?;
+
+            Ok(user)
+        })
+    }
+}
+
+fn find_user_by_verified_email(
+    conn: &PgConnection,
+    provider_user: &ProviderUser,
+) -> QueryResult<Option<User>> {
+    let email = match &provider_user.email {
+        Some(email) if provider_user.email_verified => email,
+        _ => return Ok(None),
+    };
+
+    users::table
+        .inner_join(emails::table)
+        .filter(lower(emails::email).eq(email.to_lowercase()))
+        .filter(emails::verified.eq(true))
+        .select(users::all_columns)
+        .first(conn)
+        .optional()
+}
+
+/// Creates a user for an account that isn't linked to any crates.io user yet.
+///
+/// Its login is namespaced by the provider, like `foo@gitlab`, since the `gh_login` of users
+/// is shared with GitHub, whose logins can't contain an `@`. This keeps a later GitHub signup
+/// from taking the login and owner lookups by login from resolving to the wrong user.
+fn create_user(
+    conn: &PgConnection,
+    emails: &Emails,
+    provider: AccountProvider,
+    provider_user: &ProviderUser,
+) -> AppResult<User> {
+    let provider_name: &'static str = provider.into();
+    let login = format!("{}@{provider_name}", provider_user.login);
+    let login_taken = diesel::select(diesel::dsl::exists(
+        users::table.filter(lower(users::gh_login).eq(login.to_lowercase())),
+    ))
+    .get_result::<bool>(conn)?;
+    if login_taken {
+        return Err(bad_request(&format_args!(
+            "the login `{login}` is already used by another crates.io account. To link \
+             your accounts, verify the same email address on both services and log in again."
+        )));
+    }
+
+    let new_user = NewUser::new(
+        0,
+        &login,
+        provider_user.name.as_deref(),
+        provider_user.avatar.as_deref(),
+        "",
+    );
+    Ok(new_user.create_or_update(provider_user.email.as_deref(), emails, conn)?)
+}
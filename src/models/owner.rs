@@ -4,7 +4,7 @@ use diesel::prelude::*;
 use crate::app::App;
 use crate::util::errors::{cargo_err, AppResult};
 
-use crate::models::{Crate, Team, User};
+use crate::models::{Crate, Rights, Team, User};
 use crate::schema::{crate_owners, users};
 use crate::sql::lower;
 
@@ -20,6 +20,7 @@ pub struct CrateOwner {
     pub created_by: i32,
     pub owner_kind: i32,
     pub email_notifications: bool,
+    pub rights: Rights,
 }
 
 type BoxedQuery<'a> = crate_owners::BoxedQuery<'a, Pg, crate_owners::SqlType>;
@@ -69,16 +70,27 @@ impl Owner {
                 app, conn, name, req_user,
             )?))
         } else {
-            users::table
-                .filter(lower(users::gh_login).eq(name.to_lowercase()))
-                .filter(users::gh_id.ne(-1))
-                .order(users::gh_id.desc())
-                .first(conn)
+            Owner::find_user_by_login(conn, name)
+                .ok()
+                .flatten()
                 .map(Owner::User)
-                .map_err(|_| cargo_err(&format_args!("could not find user with login `{}`", name)))
+                .ok_or_else(|| {
+                    cargo_err(&format_args!("could not find user with login `{}`", name))
+                })
         }
     }
 
+    /// Finds the user with the given login, ignoring the case. Users whose GitHub account
+    /// was deleted are skipped.
+    pub fn find_user_by_login(conn: &PgConnection, name: &str) -> QueryResult<Option<User>> {
+        users::table
+            .filter(lower(users::gh_login).eq(name.to_lowercase()))
+            .filter(users::gh_id.ne(-1))
+            .order(users::gh_id.desc())
+            .first(conn)
+            .optional()
+    }
+
     pub fn kind(&self) -> i32 {
         match *self {
             Owner::User(_) => OwnerKind::User as i32,
@@ -99,4 +111,15 @@ impl Owner {
             Owner::Team(ref team) => team.id,
         }
     }
+
+    /// The rights granted to a new owner if none were explicitly requested.
+    ///
+    /// Teams could only ever publish before rights became configurable, so that remains their
+    /// default.
+    pub fn default_rights(&self) -> Rights {
+        match *self {
+            Owner::User(_) => Rights::Full,
+            Owner::Team(_) => Rights::Publish,
+        }
+    }
 }
@@ -1,8 +1,56 @@
+use diesel::{
+    deserialize::{self, FromSql},
+    pg::Pg,
+    serialize::{self, Output, ToSql},
+    sql_types::Integer,
+};
+use std::io::Write;
+
 /// Access rights to the crate (publishing and ownership management)
 /// NOTE: The order of these variants matters!
-#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
+///
+/// The non-`None` variants are stored in the `rights` column of the `crate_owners` and
+/// `crate_owner_invitations` tables.
+#[derive(
+    PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy, FromSqlRow, AsExpression, Deserialize,
+)]
+#[repr(i32)]
+#[sql_type = "Integer"]
+#[serde(rename_all = "lowercase")]
 pub enum Rights {
-    None,
-    Publish,
-    Full,
+    None = 0,
+    /// Allows yanking and unyanking existing versions
+    Yank = 1,
+    /// Allows publishing new versions, in addition to yanking
+    Publish = 2,
+    /// Allows managing the owners of the crate, in addition to publishing
+    Full = 3,
+}
+
+impl From<Rights> for &'static str {
+    fn from(rights: Rights) -> Self {
+        match rights {
+            Rights::None => "none",
+            Rights::Yank => "yank",
+            Rights::Publish => "publish",
+            Rights::Full => "full",
+        }
+    }
+}
+
+impl FromSql<Integer, Pg> for Rights {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match <i32 as FromSql<Integer, Pg>>::from_sql(bytes)? {
+            1 => Ok(Rights::Yank),
+            2 => Ok(Rights::Publish),
+            3 => Ok(Rights::Full),
+            n => Err(format!("unknown rights: {n}").into()),
+        }
+    }
+}
+
+impl ToSql<Integer, Pg> for Rights {
+    fn to_sql<W: Write>(&self, out: &mut Output<'_, W, Pg>) -> serialize::Result {
+        ToSql::<Integer, Pg>::to_sql(&(*self as i32), out)
+    }
 }
@@ -0,0 +1,81 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+
+use crate::models::User;
+use crate::schema::sessions;
+
+/// How long a session can be used after logging in.
+pub const SESSION_MAX_AGE_DAYS: i64 = 90;
+
+/// How long a session can be used after it was last used.
+pub const SESSION_IDLE_TIMEOUT_DAYS: i64 = 30;
+
+/// How long a session that is only held in the cookie can be used. These sessions are started
+/// in read only mode, when sessions can't be stored, so they can't be revoked either.
+pub const COOKIE_SESSION_MAX_AGE_HOURS: i64 = 24;
+
+/// How outdated `last_used_at` may be, so that it isn't written on every request.
+const LAST_USED_UPDATE_MINUTES: i64 = 5;
+
+/// A cookie session of a user.
+///
+/// The cookie only holds the ID of the session, so deleting the row logs out the browser that
+/// holds the cookie. Sessions expire `SESSION_MAX_AGE_DAYS` after logging in, or after not
+/// being used for `SESSION_IDLE_TIMEOUT_DAYS`. Expired sessions are deleted by the
+/// `delete_expired_sessions` background job.
+#[derive(Clone, Debug, PartialEq, Eq, Identifiable, Queryable, Associations)]
+#[belongs_to(User)]
+pub struct Session {
+    pub id: i32,
+    pub user_id: i32,
+    pub created_at: NaiveDateTime,
+    pub last_used_at: NaiveDateTime,
+    pub user_agent: String,
+    pub ip_address: String,
+}
+
+impl Session {
+    pub fn create(
+        conn: &PgConnection,
+        user_id: i32,
+        user_agent: &str,
+        ip_address: &str,
+    ) -> QueryResult<Session> {
+        diesel::insert_into(sessions::table)
+            .values((
+                sessions::user_id.eq(user_id),
+                sessions::user_agent.eq(user_agent),
+                sessions::ip_address.eq(ip_address),
+            ))
+            .get_result(conn)
+    }
+
+    /// Looks up the session of a cookie. The caller has to check whether it expired.
+    pub fn find(conn: &PgConnection, id: i32) -> QueryResult<Option<Session>> {
+        sessions::table.find(id).first(conn).optional()
+    }
+
+    pub fn is_expired(&self) -> bool {
+        let now = Utc::now().naive_utc();
+        self.created_at + Duration::days(SESSION_MAX_AGE_DAYS) < now
+            || self.last_used_at + Duration::days(SESSION_IDLE_TIMEOUT_DAYS) < now
+    }
+
+    /// Records that the session was used, unless that was done in the last few minutes.
+    pub fn record_use(&self, conn: &PgConnection) {
+        use diesel::dsl::now;
+
+        let threshold = Utc::now().naive_utc() - Duration::minutes(LAST_USED_UPDATE_MINUTES);
+        if self.last_used_at > threshold {
+            return;
+        }
+
+        // If the database is in read only mode, we can't update last_used_at, so errors are
+        // swallowed. Update in a new transaction, so that a failure doesn't affect the request.
+        let _ = conn.transaction(|| {
+            diesel::update(self)
+                .set(sessions::last_used_at.eq(now))
+                .execute(conn)
+        });
+    }
+}
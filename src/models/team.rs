@@ -1,32 +1,41 @@
 use diesel::prelude::*;
+use swirl::Job;
 
 use crate::app::App;
-use crate::util::errors::{cargo_err, AppResult, NotFound};
+use crate::github::{self, GitHubClient};
+use crate::gitlab::{self, GitLabClient};
+use crate::util::errors::{cargo_err, internal, AppError, AppResult, NotFound};
+use crate::{config, worker};
 
 use oauth2::AccessToken;
 
-use crate::models::{Crate, CrateOwner, Owner, OwnerKind, User};
+use crate::models::{
+    AccountProvider, Crate, CrateOwner, LinkedAccount, Owner, OwnerKind, TeamMembership, User,
+};
 use crate::schema::{crate_owners, teams};
 
-/// For now, just a Github Team. Can be upgraded to other teams
-/// later if desirable.
+/// A GitHub Team or a GitLab group.
 #[derive(Queryable, Identifiable, Serialize, Deserialize, Debug)]
 pub struct Team {
     /// Unique table id
     pub id: i32,
-    /// "github:org:team"
-    /// An opaque unique ID, that was at one point parsed out to query Github.
-    /// We only query membership with github using the github_id, though.
+    /// "github:org:team" or "gitlab:group:subgroup"
+    /// An opaque unique ID, that was at one point parsed out to query the provider.
+    /// We only query membership with the provider using the github_id, though.
     /// This is the only name we should ever talk to Cargo about.
     pub login: String,
     /// The GitHub API works on team ID numbers. This can change, if a team
     /// is deleted and then recreated with the same name!!!
+    /// For GitLab groups, this is the group ID.
     pub github_id: i32,
     /// Sugary goodness
     pub name: Option<String>,
     pub avatar: Option<String>,
-    /// The GitHub Organization ID this team sits under
+    /// The GitHub Organization ID this team sits under. For GitLab groups,
+    /// this is the group ID.
     pub org_id: Option<i32>,
+    /// The service hosting the team
+    pub provider: AccountProvider,
 }
 
 #[derive(Insertable, AsChangeset, Debug)]
@@ -37,6 +46,7 @@ pub struct NewTeam<'a> {
     pub name: Option<String>,
     pub avatar: Option<String>,
     pub org_id: i32,
+    pub provider: AccountProvider,
 }
 
 impl<'a> NewTeam<'a> {
@@ -53,6 +63,7 @@ impl<'a> NewTeam<'a> {
             name,
             avatar,
             org_id,
+            provider: AccountProvider::GitHub,
         }
     }
 
@@ -62,7 +73,7 @@ impl<'a> NewTeam<'a> {
 
         insert_into(teams)
             .values(self)
-            .on_conflict(github_id)
+            .on_conflict((provider, github_id))
             .do_update()
             .set(self)
             .get_result(conn)
@@ -74,7 +85,7 @@ impl Team {
     ///
     /// # Panics
     ///
-    /// This function will panic if login contains less than 2 `:` characters.
+    /// This function will panic if a `github:` login contains less than 2 `:` characters.
     pub fn create_or_update(
         app: &App,
         conn: &PgConnection,
@@ -104,6 +115,27 @@ impl Team {
                     req_user,
                 )
             }
+            // gitlab:group:subgroup
+            "gitlab" if app.gitlab.is_some() => {
+                let path = chunks.collect::<Vec<_>>();
+                if path.is_empty() || path.iter().any(|chunk| chunk.is_empty()) {
+                    return Err(cargo_err(
+                        "missing gitlab group argument; \
+                         format is gitlab:group:subgroup",
+                    ));
+                }
+                Team::create_or_update_gitlab_group(
+                    app,
+                    conn,
+                    &login.to_lowercase(),
+                    &path.join("/"),
+                    req_user,
+                )
+            }
+            _ if app.gitlab.is_some() => Err(cargo_err(
+                "unknown organization handler, \
+                 only 'github:org:team' and 'gitlab:group:subgroup' are supported",
+            )),
             _ => Err(cargo_err(
                 "unknown organization handler, \
                  only 'github:org:team' is supported",
@@ -151,7 +183,7 @@ impl Team {
 
         let org_id = team.organization.id;
 
-        if !team_with_gh_id_contains_user(app, org_id, team.id, req_user)? {
+        if !team_with_gh_id_contains_user(&app.github, org_id, team.id, req_user)? {
             return Err(cargo_err("only members of a team can add it as an owner"));
         }
 
@@ -168,19 +200,158 @@ impl Team {
         .map_err(Into::into)
     }
 
-    /// Phones home to Github to ask if this User is a member of the given team.
+    /// Tries to create or update a GitLab group. `path` is the full path of the
+    /// group, e.g. `group/subgroup`.
+    fn create_or_update_gitlab_group(
+        app: &App,
+        conn: &PgConnection,
+        login: &str,
+        path: &str,
+        req_user: &User,
+    ) -> AppResult<Self> {
+        // "sanitization"
+        fn is_allowed_char(c: char) -> bool {
+            matches!(c, 'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' | '/')
+        }
+
+        if let Some(c) = path.chars().find(|c| !is_allowed_char(*c)) {
+            return Err(cargo_err(&format_args!(
+                "group cannot contain special characters like {c}"
+            )));
+        }
+
+        let gitlab = app.gitlab.as_ref().expect("GitLab is not configured");
+        let account = LinkedAccount::find_by_user(conn, req_user, AccountProvider::GitLab)?
+            .ok_or_else(|| {
+                cargo_err(
+                    "you need to log in to crates.io with GitLab \
+                     before adding a GitLab group as an owner",
+                )
+            })?;
+
+        let token = AccessToken::new(account.access_token.clone());
+        let group = gitlab
+            .group_by_path(path, &token)
+            .map_err(|_| cargo_err(&format_args!("could not find the gitlab group {path}")))?;
+
+        if !gitlab_group_contains_account(gitlab, group.id, &account)? {
+            return Err(cargo_err("only members of a group can add it as an owner"));
+        }
+
+        NewTeam {
+            login,
+            github_id: group.id,
+            name: group.name,
+            avatar: group.avatar_url,
+            org_id: group.id,
+            provider: AccountProvider::GitLab,
+        }
+        .create_or_update(conn)
+        .map_err(Into::into)
+    }
+
+    /// The URL of the team's page on its provider
+    pub fn url(&self) -> String {
+        match self.provider {
+            AccountProvider::GitLab => gitlab::group_url(&config::gitlab_base_url(), &self.login),
+            _ => github::team_url(&self.login),
+        }
+    }
+
+    /// Determines whether this User is a member of the team.
+    ///
+    /// Memberships are cached in the `team_memberships` table. A cached result younger than
+    /// `team_membership_cache_ttl` is used as is. An older one is still used while a background
+    /// job asks the provider again, unless it is older than `team_membership_max_staleness`, in
+    /// which case we phone home right away. If the provider can't be reached, the cached result
+    /// is used no matter how old it is.
+    ///
     /// Note that we're assuming that the given user is the one interested in
     /// the answer. If this is not the case, then we could accidentally leak
     /// private membership information here.
-    pub fn contains_user(&self, app: &App, user: &User) -> AppResult<bool> {
-        match self.org_id {
-            Some(org_id) => team_with_gh_id_contains_user(app, org_id, self.github_id, user),
-            // This means we don't have an org_id on file for the `self` team. It much
-            // probably was deleted from github by the time we backfilled the database.
-            // Short-circuiting to false since a non-existent team cannot contain any
-            // user
-            None => Ok(false),
+    pub fn contains_user(&self, app: &App, conn: &PgConnection, user: &User) -> AppResult<bool> {
+        // This means we don't have an org_id on file for the `self` team. It much
+        // probably was deleted from github by the time we backfilled the database.
+        // Short-circuiting to false since a non-existent team cannot contain any
+        // user
+        if self.org_id.is_none() {
+            return Ok(false);
+        }
+
+        let config = &app.config;
+        let cached = TeamMembership::find(conn, self, user)?;
+        if let Some(cached) = cached {
+            // A `checked_at` in the future can't be converted, treat it as fresh
+            let age = cached.age().to_std().unwrap_or_default();
+            if age < config.team_membership_cache_ttl {
+                return Ok(cached.is_member);
+            }
+            if age < config.team_membership_max_staleness {
+                // Use a new transaction, so that a failure doesn't abort the transaction of the
+                // request
+                let enqueued = conn.transaction::<_, Box<dyn AppError>, _>(|| {
+                    if cached.claim_refresh(conn)? {
+                        worker::sync_user_team_memberships(user.id).enqueue(conn)?;
+                    }
+                    Ok(())
+                });
+                if let Err(error) = enqueued {
+                    warn!("Failed to enqueue team membership refresh: {error}");
+                }
+                return Ok(cached.is_member);
+            }
+        }
+
+        match self.refresh_membership(&app.github, app.gitlab.as_ref(), conn, user) {
+            Ok(is_member) => Ok(is_member),
+            Err(error) => match cached {
+                Some(cached) => {
+                    warn!("Using cached team membership, membership request failed: {error}");
+                    Ok(cached.is_member)
+                }
+                None => Err(error),
+            },
+        }
+    }
+
+    /// Phones home to GitHub or GitLab to ask if this User is a member of the
+    /// team, and caches the answer in the `team_memberships` table.
+    ///
+    /// Failing to update the cache is not treated as an error, since the
+    /// database might be in read-only mode. The cache is updated in its own
+    /// transaction, so that a failure doesn't abort the one of the caller.
+    pub fn refresh_membership(
+        &self,
+        github: &GitHubClient,
+        gitlab: Option<&GitLabClient>,
+        conn: &PgConnection,
+        user: &User,
+    ) -> AppResult<bool> {
+        let is_member = match (self.provider, self.org_id) {
+            (_, None) => false,
+            // Users that signed up with another provider don't have a GitHub token
+            (AccountProvider::GitHub, Some(_)) if user.gh_access_token.is_empty() => false,
+            (AccountProvider::GitHub, Some(org_id)) => {
+                team_with_gh_id_contains_user(github, org_id, self.github_id, user)?
+            }
+            (AccountProvider::GitLab, Some(_)) => {
+                let gitlab = gitlab.ok_or_else(|| internal("GitLab is not configured"))?;
+                match LinkedAccount::find_by_user(conn, user, AccountProvider::GitLab)? {
+                    Some(account) => {
+                        gitlab_group_contains_account(gitlab, self.github_id, &account)?
+                    }
+                    None => false,
+                }
+            }
+            (AccountProvider::Oidc, Some(_)) => false,
+        };
+
+        if let Err(error) = conn.transaction(|| TeamMembership::record(conn, self, user, is_member))
+        {
+            warn!("Failed to cache team membership: {error}");
         }
+
+        Ok(is_member)
     }
 
     pub fn owning(krate: &Crate, conn: &PgConnection) -> QueryResult<Vec<Owner>> {
@@ -198,7 +369,7 @@ impl Team {
 }
 
 fn team_with_gh_id_contains_user(
-    app: &App,
+    github: &GitHubClient,
     github_org_id: i32,
     github_team_id: i32,
     user: &User,
@@ -208,10 +379,7 @@ fn team_with_gh_id_contains_user(
 
     let token = AccessToken::new(user.gh_access_token.clone());
     let membership =
-        match app
-            .github
-            .team_membership(github_org_id, github_team_id, &user.gh_login, &token)
-        {
+        match github.team_membership(github_org_id, github_team_id, &user.gh_login, &token) {
             // Officially how `false` is returned
             Err(ref e) if e.is::<NotFound>() => return Ok(false),
             x => x?,
@@ -221,3 +389,22 @@ fn team_with_gh_id_contains_user(
     // some feedback, but it's not obvious how that should work.
     Ok(membership.state == "active")
 }
+
+fn gitlab_group_contains_account(
+    gitlab: &GitLabClient,
+    group_id: i32,
+    account: &LinkedAccount,
+) -> AppResult<bool> {
+    // GET /groups/:group_id/members/all/:user_id
+    // check that "state": "active"
+
+    let token = AccessToken::new(account.access_token.clone());
+    let user_id = account.account_id.parse()?;
+    let member = match gitlab.group_membership(group_id, user_id, &token) {
+        // Non-members are reported as not found
+        Err(ref e) if e.is::<NotFound>() => return Ok(false),
+        x => x?,
+    };
+
+    Ok(member.state == "active")
+}
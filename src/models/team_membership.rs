@@ -0,0 +1,80 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+
+use crate::models::{Team, User};
+use crate::schema::team_memberships;
+
+/// How long to wait for a background refresh before enqueueing another one.
+const REFRESH_RETRY_MINUTES: i64 = 10;
+
+/// The cached result of asking GitHub whether a user is a member of a team.
+#[derive(Queryable, Identifiable, Associations, Debug, Clone, Copy)]
+#[belongs_to(Team)]
+#[belongs_to(User)]
+#[primary_key(team_id, user_id)]
+#[table_name = "team_memberships"]
+pub struct TeamMembership {
+    pub team_id: i32,
+    pub user_id: i32,
+    pub is_member: bool,
+    pub checked_at: NaiveDateTime,
+    /// When a background job was last asked to refresh this membership.
+    pub refresh_enqueued_at: Option<NaiveDateTime>,
+}
+
+impl TeamMembership {
+    pub fn find(conn: &PgConnection, team: &Team, user: &User) -> QueryResult<Option<Self>> {
+        team_memberships::table
+            .find((team.id, user.id))
+            .first(conn)
+            .optional()
+    }
+
+    /// Stores the result of a membership check, replacing any previously cached result.
+    pub fn record(
+        conn: &PgConnection,
+        team: &Team,
+        user: &User,
+        is_member: bool,
+    ) -> QueryResult<Self> {
+        use diesel::dsl::now;
+
+        diesel::insert_into(team_memberships::table)
+            .values((
+                team_memberships::team_id.eq(team.id),
+                team_memberships::user_id.eq(user.id),
+                team_memberships::is_member.eq(is_member),
+            ))
+            .on_conflict((team_memberships::team_id, team_memberships::user_id))
+            .do_update()
+            .set((
+                team_memberships::is_member.eq(is_member),
+                team_memberships::checked_at.eq(now),
+            ))
+            .get_result(conn)
+    }
+
+    /// Marks the membership as being refreshed in the background, returning whether the refresh
+    /// should be enqueued. A stale membership then enqueues a single refresh, instead of one on
+    /// every check until the job runs. If the refresh doesn't update the membership, e.g. because
+    /// GitHub is down, it's enqueued again after `REFRESH_RETRY_MINUTES`.
+    pub fn claim_refresh(&self, conn: &PgConnection) -> QueryResult<bool> {
+        let now = Utc::now().naive_utc();
+        let claimed = diesel::update(self)
+            .filter(
+                team_memberships::refresh_enqueued_at
+                    .is_null()
+                    .or(team_memberships::refresh_enqueued_at.le(team_memberships::checked_at))
+                    .or(team_memberships::refresh_enqueued_at
+                        .lt(now - Duration::minutes(REFRESH_RETRY_MINUTES))),
+            )
+            .set(team_memberships::refresh_enqueued_at.eq(now))
+            .execute(conn)?;
+        Ok(claimed > 0)
+    }
+
+    /// How long ago this membership was last confirmed with GitHub.
+    pub fn age(&self) -> Duration {
+        Utc::now().naive_utc() - self.checked_at
+    }
+}
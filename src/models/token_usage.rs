@@ -0,0 +1,40 @@
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+
+use crate::models::ApiToken;
+use crate::schema::api_token_usages;
+
+/// A request that was authenticated with an API token.
+///
+/// Usages are only kept for a limited time, see the `prune_api_token_usages` background job.
+#[derive(Clone, Debug, Queryable, Identifiable, Associations)]
+#[belongs_to(ApiToken)]
+pub struct ApiTokenUsage {
+    pub id: i64,
+    pub api_token_id: i32,
+    pub used_at: NaiveDateTime,
+    /// The method and path of the request, e.g. `PUT /api/v1/crates/new`
+    pub endpoint: String,
+    pub crate_name: Option<String>,
+    pub ip_address: String,
+    pub user_agent: String,
+}
+
+#[derive(Insertable, Debug)]
+#[table_name = "api_token_usages"]
+pub struct NewApiTokenUsage<'a> {
+    pub api_token_id: i32,
+    pub endpoint: &'a str,
+    pub crate_name: Option<&'a str>,
+    pub ip_address: &'a str,
+    pub user_agent: &'a str,
+}
+
+impl NewApiTokenUsage<'_> {
+    pub fn insert(&self, conn: &PgConnection) -> QueryResult<()> {
+        diesel::insert_into(api_token_usages::table)
+            .values(self)
+            .execute(conn)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use sha2::{Digest, Sha256};
+
+use crate::models::User;
+use crate::schema::{totp_credentials, totp_recovery_codes};
+use crate::util::totp;
+
+/// How many invalid codes in a row lock the credential.
+pub const MAX_FAILED_ATTEMPTS: i32 = 5;
+
+/// How long codes are rejected after too many invalid ones.
+pub const LOCKOUT_MINUTES: i32 = 15;
+
+/// The TOTP secret of a user that enrolled in two-factor authentication.
+///
+/// Codes are only required once `confirmed_at` is set, which happens when the user entered a
+/// first code to prove that their authenticator app works.
+#[derive(Clone, Debug, Queryable, Identifiable, Associations)]
+#[primary_key(user_id)]
+#[belongs_to(User)]
+pub struct TotpCredential {
+    pub user_id: i32,
+    pub secret: Vec<u8>,
+    pub created_at: NaiveDateTime,
+    pub confirmed_at: Option<NaiveDateTime>,
+    /// The time step of the last accepted code, so that a code can't be used twice
+    pub last_used_step: Option<i64>,
+    /// The number of invalid codes entered since the last valid one or the last lockout
+    pub failed_attempts: i32,
+    /// Codes are rejected until this time, to prevent guessing them
+    pub locked_until: Option<NaiveDateTime>,
+}
+
+impl TotpCredential {
+    pub fn find(conn: &PgConnection, user_id: i32) -> QueryResult<Option<TotpCredential>> {
+        totp_credentials::table.find(user_id).first(conn).optional()
+    }
+
+    /// Returns the credential of a user, if codes are required from them.
+    pub fn find_confirmed(
+        conn: &PgConnection,
+        user_id: i32,
+    ) -> QueryResult<Option<TotpCredential>> {
+        totp_credentials::table
+            .find(user_id)
+            .filter(totp_credentials::confirmed_at.is_not_null())
+            .first(conn)
+            .optional()
+    }
+
+    /// Generates a new secret for the user, replacing the secret of an unconfirmed enrollment.
+    pub fn start_enrollment(conn: &PgConnection, user_id: i32) -> QueryResult<TotpCredential> {
+        use diesel::dsl::now;
+
+        let secret = totp::generate_secret();
+        diesel::insert_into(totp_credentials::table)
+            .values((
+                totp_credentials::user_id.eq(user_id),
+                totp_credentials::secret.eq(&secret),
+            ))
+            .on_conflict(totp_credentials::user_id)
+            .do_update()
+            .set((
+                totp_credentials::secret.eq(&secret),
+                totp_credentials::created_at.eq(now),
+                totp_credentials::last_used_step.eq(None::<i64>),
+            ))
+            .get_result(conn)
+    }
+
+    /// Marks the enrollment as confirmed and returns a fresh set of recovery codes.
+    pub fn confirm(&self, conn: &PgConnection) -> QueryResult<Vec<String>> {
+        use diesel::dsl::now;
+
+        conn.transaction(|| {
+            diesel::update(self)
+                .set(totp_credentials::confirmed_at.eq(now))
+                .execute(conn)?;
+            self.regenerate_recovery_codes(conn)
+        })
+    }
+
+    /// Returns whether codes are currently rejected because of too many invalid ones.
+    pub fn is_locked(&self) -> bool {
+        self.locked_until
+            .map_or(false, |locked_until| locked_until > Utc::now().naive_utc())
+    }
+
+    /// Counts an invalid code, and locks the credential for `LOCKOUT_MINUTES` once
+    /// `MAX_FAILED_ATTEMPTS` invalid codes were entered in a row.
+    pub fn record_failed_attempt(&self, conn: &PgConnection) -> QueryResult<()> {
+        use diesel::dsl::{now, IntervalDsl};
+
+        let failed_attempts = totp_credentials::failed_attempts;
+        conn.transaction(|| {
+            let attempts: i32 = diesel::update(self)
+                .set(failed_attempts.eq(failed_attempts + 1))
+                .returning(failed_attempts)
+                .get_result(conn)?;
+
+            if attempts >= MAX_FAILED_ATTEMPTS {
+                diesel::update(self)
+                    .set((
+                        failed_attempts.eq(0),
+                        totp_credentials::locked_until.eq(now + LOCKOUT_MINUTES.minutes()),
+                    ))
+                    .execute(conn)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Resets the count of invalid codes after a valid one was entered.
+    pub fn reset_failed_attempts(&self, conn: &PgConnection) -> QueryResult<()> {
+        if self.failed_attempts > 0 {
+            diesel::update(self)
+                .set(totp_credentials::failed_attempts.eq(0))
+                .execute(conn)?;
+        }
+        Ok(())
+    }
+
+    /// Checks a code of the authenticator app and marks it as used.
+    pub fn verify_code(&self, conn: &PgConnection, code: &str) -> QueryResult<bool> {
+        let timestamp = Utc::now().timestamp();
+        let step = match totp::verify(&self.secret, code, timestamp, self.last_used_step) {
+            Some(step) => step,
+            None => return Ok(false),
+        };
+
+        // The condition prevents concurrent requests from using the same code
+        let last_used_step = totp_credentials::last_used_step;
+        let updated = diesel::update(self)
+            .filter(last_used_step.is_null().or(last_used_step.lt(step)))
+            .set(last_used_step.eq(step))
+            .execute(conn)?;
+        Ok(updated > 0)
+    }
+
+    /// Checks a recovery code and marks it as used.
+    pub fn use_recovery_code(&self, conn: &PgConnection, code: &str) -> QueryResult<bool> {
+        use diesel::dsl::now;
+
+        let hashed = hash_recovery_code(code);
+        let updated = diesel::update(
+            totp_recovery_codes::table
+                .filter(totp_recovery_codes::user_id.eq(self.user_id))
+                .filter(totp_recovery_codes::code.eq(hashed))
+                .filter(totp_recovery_codes::used_at.is_null()),
+        )
+        .set(totp_recovery_codes::used_at.eq(now))
+        .execute(conn)?;
+        Ok(updated > 0)
+    }
+
+    /// Replaces the recovery codes of the user. Only the hashes of the codes are stored, so the
+    /// returned codes have to be shown to the user right away.
+    pub fn regenerate_recovery_codes(&self, conn: &PgConnection) -> QueryResult<Vec<String>> {
+        let codes = totp::generate_recovery_codes();
+        let rows = codes
+            .iter()
+            .map(|code| {
+                (
+                    totp_recovery_codes::user_id.eq(self.user_id),
+                    totp_recovery_codes::code.eq(hash_recovery_code(code)),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        conn.transaction(|| {
+            diesel::delete(
+                totp_recovery_codes::table.filter(totp_recovery_codes::user_id.eq(self.user_id)),
+            )
+            .execute(conn)?;
+            diesel::insert_into(totp_recovery_codes::table)
+                .values(&rows)
+                .execute(conn)
+        })?;
+
+        Ok(codes)
+    }
+
+    pub fn remaining_recovery_codes(&self, conn: &PgConnection) -> QueryResult<i64> {
+        totp_recovery_codes::table
+            .filter(totp_recovery_codes::user_id.eq(self.user_id))
+            .filter(totp_recovery_codes::used_at.is_null())
+            .count()
+            .get_result(conn)
+    }
+
+    /// Disables two-factor authentication for the user.
+    pub fn delete(&self, conn: &PgConnection) -> QueryResult<()> {
+        conn.transaction(|| {
+            diesel::delete(
+                totp_recovery_codes::table.filter(totp_recovery_codes::user_id.eq(self.user_id)),
+            )
+            .execute(conn)?;
+            diesel::delete(self).execute(conn)?;
+            Ok(())
+        })
+    }
+}
+
+fn hash_recovery_code(code: &str) -> Vec<u8> {
+    let code = totp::normalize_recovery_code(code);
+    Sha256::digest(code.as_bytes()).to_vec()
+}
@@ -1,13 +1,21 @@
-use chrono::NaiveDateTime;
+use chrono::{Duration, NaiveDateTime, Utc};
 use diesel::prelude::*;
 use std::borrow::Cow;
+use swirl::Job;
 
 use crate::app::App;
 use crate::email::Emails;
-use crate::util::errors::AppResult;
+use crate::util::errors::{AppError, AppResult};
+use crate::util::signed_token;
+use crate::worker;
 
-use crate::models::{ApiToken, Crate, CrateOwner, Email, NewEmail, Owner, OwnerKind, Rights};
-use crate::schema::{crate_owners, emails, users};
+use crate::models::{
+    ApiToken, Crate, CrateOwner, DataExport, Email, Follow, NewEmail, Owner, OwnerKind, Rights,
+};
+use crate::schema::{
+    crate_owners, crates, data_exports, emails, publish_limit_buckets, publish_rate_overrides,
+    users, version_owner_actions, versions, versions_published_by,
+};
 
 /// The model representing a row in the `users` database table.
 #[derive(Clone, Debug, PartialEq, Eq, Queryable, Identifiable, AsChangeset, Associations)]
@@ -20,8 +28,15 @@ pub struct User {
     pub gh_id: i32,
     pub account_lock_reason: Option<String>,
     pub account_lock_until: Option<NaiveDateTime>,
+    pub weekly_digest: bool,
+    pub digest_sent_at: Option<NaiveDateTime>,
 }
 
+const DIGEST_UNSUBSCRIBE_PURPOSE: &str = "weekly_digest_unsubscribe";
+
+/// How long the unsubscribe links of a weekly digest keep working.
+const DIGEST_UNSUBSCRIBE_TOKEN_DAYS: i64 = 60;
+
 /// Represents a new user record insertable to the `users` table
 #[derive(Insertable, Debug, Default)]
 #[table_name = "users"]
@@ -89,6 +104,7 @@ impl<'a> NewUser<'a> {
                 let new_email = NewEmail {
                     user_id: user.id,
                     email: user_email,
+                    is_primary: true,
                 };
 
                 let token: Option<String> = insert_into(emails::table)
@@ -100,7 +116,7 @@ impl<'a> NewUser<'a> {
 
                 if let Some(token) = token {
                     // Swallows any error. Some users might insert an invalid email address here.
-                    let _ = emails.send_user_confirm(user_email, &user.gh_login, &token);
+                    let _ = emails.send_user_confirm(conn, user_email, &user.gh_login, &token);
                 }
             }
 
@@ -133,48 +149,179 @@ impl User {
         Ok(users.collect())
     }
 
-    /// Given this set of owners, determines the strongest rights the
-    /// user has.
+    /// Given this set of owners and the rights granted to each of them, determines the
+    /// strongest rights the user has.
     ///
-    /// Shortcircuits on `Full` because you can't beat it. In practice we'll always
-    /// see `[user, user, user, ..., team, team, team]`, so we could shortcircuit on
-    /// `Publish` as well, but this is a non-obvious invariant so we don't bother.
-    /// Sweet free optimization if teams are proving burdensome to check.
-    /// More than one team isn't really expected, though.
-    pub fn rights(&self, app: &App, owners: &[Owner]) -> AppResult<Rights> {
+    /// Shortcircuits on `Full` because you can't beat it. Team memberships are only checked
+    /// if the team could improve on the rights found so far, since a check might have to phone
+    /// home to GitHub. More than one team isn't really expected, though.
+    pub fn rights(
+        &self,
+        app: &App,
+        conn: &PgConnection,
+        owners: &[(Owner, Rights)],
+    ) -> AppResult<Rights> {
         let mut best = Rights::None;
-        for owner in owners {
-            match *owner {
-                Owner::User(ref other_user) => {
-                    if other_user.id == self.id {
-                        return Ok(Rights::Full);
-                    }
-                }
-                Owner::Team(ref team) => {
-                    if team.contains_user(app, self)? {
-                        best = Rights::Publish;
-                    }
+        for (owner, rights) in owners {
+            if *rights <= best {
+                continue;
+            }
+            let is_member = match *owner {
+                Owner::User(ref other_user) => other_user.id == self.id,
+                Owner::Team(ref team) => team.contains_user(app, conn, self)?,
+            };
+            if is_member {
+                if *rights == Rights::Full {
+                    return Ok(Rights::Full);
                 }
+                best = *rights;
             }
         }
         Ok(best)
     }
 
-    /// Queries the database for the verified emails
-    /// belonging to a given user
+    /// Queries the database for the primary email
+    /// of a given user, if it is verified
     pub fn verified_email(&self, conn: &PgConnection) -> QueryResult<Option<String>> {
         Email::belonging_to(self)
             .select(emails::email)
+            .filter(emails::is_primary.eq(true))
             .filter(emails::verified.eq(true))
             .first(&*conn)
             .optional()
     }
 
-    /// Queries for the email belonging to a particular user
+    /// Queries for the primary email belonging to a particular user
     pub fn email(&self, conn: &PgConnection) -> AppResult<Option<String>> {
         Ok(Email::belonging_to(self)
             .select(emails::email)
+            .filter(emails::is_primary.eq(true))
             .first(&*conn)
             .optional()?)
     }
+
+    /// Queries for the verified email that notifications should be sent to.
+    ///
+    /// This is the address the user picked for notifications, or the primary one otherwise.
+    pub fn notification_email(&self, conn: &PgConnection) -> QueryResult<Option<String>> {
+        Email::belonging_to(self)
+            .select(emails::email)
+            .filter(emails::verified.eq(true))
+            .filter(emails::send_notifications.or(emails::is_primary))
+            .order(emails::send_notifications.desc())
+            .first(&*conn)
+            .optional()
+    }
+
+    /// Returns the names of the crates that nobody else could manage after this user deleted
+    /// their account, since no other user owner has full rights on them.
+    ///
+    /// The ownerships of the other owners are locked until the end of the transaction, so that
+    /// they can't be removed before the account is deleted.
+    pub fn solely_owned_crates(&self, conn: &PgConnection) -> QueryResult<Vec<String>> {
+        use diesel::dsl::any;
+
+        let owned_crates: Vec<(i32, String)> = CrateOwner::by_owner_kind(OwnerKind::User)
+            .inner_join(crates::table)
+            .filter(crate_owners::owner_id.eq(self.id))
+            .select((crates::id, crates::name))
+            .order(crates::name)
+            .load(conn)?;
+
+        let crate_ids = owned_crates.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        // Not using `CrateOwner::by_owner_kind`, since boxed queries can't lock rows
+        let co_owned_crate_ids: Vec<i32> = crate_owners::table
+            .filter(crate_owners::deleted.eq(false))
+            .filter(crate_owners::owner_kind.eq(OwnerKind::User as i32))
+            .filter(crate_owners::crate_id.eq(any(crate_ids)))
+            .filter(crate_owners::owner_id.ne(self.id))
+            .filter(crate_owners::rights.eq(Rights::Full))
+            .select(crate_owners::crate_id)
+            .for_update()
+            .load(conn)?;
+
+        Ok(owned_crates
+            .into_iter()
+            .filter(|(id, _)| !co_owned_crate_ids.contains(id))
+            .map(|(_, name)| name)
+            .collect())
+    }
+
+    /// Deletes the account of the user.
+    ///
+    /// The crates and versions published by the user stay around, but no longer refer to the
+    /// user. The ownerships of the user are removed, so crates that were solely owned by the
+    /// user end up without owners. The archives of the data exports of the user are deleted by
+    /// a background job.
+    pub fn delete_account(&self, conn: &PgConnection) -> AppResult<()> {
+        conn.transaction::<_, Box<dyn AppError>, _>(|| {
+            let published_versions = versions::table
+                .filter(versions::published_by.eq(self.id))
+                .select(versions::id);
+            diesel::delete(
+                versions_published_by::table
+                    .filter(versions_published_by::version_id.eq_any(published_versions)),
+            )
+            .execute(conn)?;
+            diesel::update(versions::table.filter(versions::published_by.eq(self.id)))
+                .set(versions::published_by.eq(None::<i32>))
+                .execute(conn)?;
+
+            diesel::update(
+                version_owner_actions::table.filter(version_owner_actions::user_id.eq(self.id)),
+            )
+            .set((
+                version_owner_actions::user_id.eq(None::<i32>),
+                version_owner_actions::api_token_id.eq(None::<i32>),
+            ))
+            .execute(conn)?;
+
+            diesel::update(crate_owners::table.filter(crate_owners::created_by.eq(self.id)))
+                .set(crate_owners::created_by.eq(None::<i32>))
+                .execute(conn)?;
+            diesel::delete(
+                crate_owners::table
+                    .filter(crate_owners::owner_kind.eq(OwnerKind::User as i32))
+                    .filter(crate_owners::owner_id.eq(self.id)),
+            )
+            .execute(conn)?;
+
+            diesel::delete(ApiToken::belonging_to(self)).execute(conn)?;
+            diesel::delete(Email::belonging_to(self)).execute(conn)?;
+            diesel::delete(Follow::belonging_to(self)).execute(conn)?;
+            diesel::delete(publish_limit_buckets::table.find(self.id)).execute(conn)?;
+            diesel::delete(publish_rate_overrides::table.find(self.id)).execute(conn)?;
+
+            let export_tokens = DataExport::belonging_to(self)
+                .select(data_exports::token)
+                .load(conn)?;
+            if !export_tokens.is_empty() {
+                worker::delete_data_export_files(self.id, export_tokens).enqueue(conn)?;
+            }
+
+            // Invitations, linked accounts, team memberships and data exports are deleted by
+            // the database
+            diesel::delete(self).execute(conn)?;
+            Ok(())
+        })
+    }
+
+    /// Returns a token that unsubscribes the user from the weekly digest without logging in.
+    /// The token expires after `DIGEST_UNSUBSCRIBE_TOKEN_DAYS`.
+    pub fn digest_unsubscribe_token(&self, key: &str) -> String {
+        let expires_at = Utc::now().naive_utc() + Duration::days(DIGEST_UNSUBSCRIBE_TOKEN_DAYS);
+        signed_token::sign(
+            key,
+            DIGEST_UNSUBSCRIBE_PURPOSE,
+            &self.id.to_string(),
+            expires_at,
+        )
+    }
+
+    /// Returns the ID of the user an unsubscribe token was created for, if it is valid.
+    pub fn parse_digest_unsubscribe_token(key: &str, token: &str) -> Option<i32> {
+        signed_token::verify(key, DIGEST_UNSUBSCRIBE_PURPOSE, token)?
+            .parse()
+            .ok()
+    }
 }
@@ -23,6 +23,7 @@ pub struct Version {
     pub license: Option<String>,
     pub crate_size: Option<i32>,
     pub published_by: Option<i32>,
+    pub rust_version: Option<String>,
 }
 
 #[derive(Insertable, Debug)]
@@ -34,6 +35,7 @@ pub struct NewVersion {
     license: Option<String>,
     crate_size: Option<i32>,
     published_by: i32,
+    rust_version: Option<String>,
 }
 
 /// The highest version (semver order) and the most recently updated version.
@@ -127,6 +129,7 @@ impl NewVersion {
         features: &HashMap<String, Vec<String>>,
         license: Option<String>,
         license_file: Option<&str>,
+        rust_version: Option<String>,
         crate_size: i32,
         published_by: i32,
     ) -> AppResult<Self> {
@@ -139,9 +142,11 @@ impl NewVersion {
             license,
             crate_size: Some(crate_size),
             published_by,
+            rust_version,
         };
 
         new_version.validate_license(license_file)?;
+        new_version.validate_rust_version()?;
 
         Ok(new_version)
     }
@@ -186,6 +191,25 @@ impl NewVersion {
         }
         Ok(())
     }
+
+    fn validate_rust_version(&self) -> AppResult<()> {
+        match &self.rust_version {
+            Some(rust_version) if !is_valid_rust_version(rust_version) => Err(cargo_err(
+                "invalid rust-version; expected a version like `1.56` or `1.56.1`",
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Checks that `s` is a `rust-version` as accepted by cargo, i.e. `major.minor` with an
+/// optional `.patch`, without pre-release or build metadata.
+pub(crate) fn is_valid_rust_version(s: &str) -> bool {
+    let parts = s.split('.').collect::<Vec<_>>();
+    (2..=3).contains(&parts.len())
+        && parts.iter().all(|part| {
+            !part.is_empty() && part.len() <= 9 && part.bytes().all(|b| b.is_ascii_digit())
+        })
 }
 
 fn validate_license_expr(s: &str) -> AppResult<()> {
@@ -205,7 +229,7 @@ fn validate_license_expr(s: &str) -> AppResult<()> {
 
 #[cfg(test)]
 mod tests {
-    use super::{validate_license_expr, TopVersions};
+    use super::{is_valid_rust_version, validate_license_expr, TopVersions};
     use chrono::NaiveDateTime;
 
     #[track_caller]
@@ -289,4 +313,15 @@ mod tests {
         let error = format!("{error}");
         assert!(error.starts_with("unknown or invalid license expression; see http"));
     }
+
+    #[test]
+    fn rust_versions() {
+        assert!(is_valid_rust_version("1.56"));
+        assert!(is_valid_rust_version("1.56.1"));
+        assert!(!is_valid_rust_version("1"));
+        assert!(!is_valid_rust_version("1.56.1.0"));
+        assert!(!is_valid_rust_version("1.56.0-nightly"));
+        assert!(!is_valid_rust_version("1..0"));
+        assert!(!is_valid_rust_version("^1.56"));
+    }
 }
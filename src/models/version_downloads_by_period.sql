@@ -0,0 +1,22 @@
+-- Casting to `timestamp` first keeps `date_trunc` from converting the dates to the time zone
+-- of the session
+SELECT
+    version_id,
+    period,
+    SUM(downloads)::bigint AS downloads
+FROM (
+    SELECT version_id, date_trunc($1, date::timestamp)::date AS period, downloads::bigint
+    FROM version_downloads
+    WHERE version_id = ANY($2)
+        AND date BETWEEN $3 AND $4
+    UNION ALL
+    -- The archived days are only kept as sums per month, so they can only be included when
+    -- whole months are summed up
+    SELECT version_id, date_trunc($1, month::timestamp)::date AS period, downloads
+    FROM version_downloads_monthly
+    WHERE $1 IN ('month', 'year')
+        AND version_id = ANY($2)
+        AND month BETWEEN $3 AND $4
+) AS downloads
+GROUP BY version_id, period
+ORDER BY period, version_id
@@ -0,0 +1,96 @@
+//! This module implements functionality for interacting with a generic OpenID Connect issuer.
+
+use oauth2::AccessToken;
+use reqwest::{self, header};
+
+use serde::de::DeserializeOwned;
+
+use crate::util::errors::{internal, AppResult};
+use reqwest::blocking::Client;
+
+#[derive(Debug, Clone)]
+pub struct OidcClient {
+    issuer_url: String,
+    client: Option<Client>,
+}
+
+impl OidcClient {
+    pub fn new(client: Option<Client>, issuer_url: String) -> Self {
+        Self { issuer_url, client }
+    }
+
+    /// Fetches the endpoints of the issuer from its discovery document.
+    ///
+    /// see <https://openid.net/specs/openid-connect-discovery-1_0.html#ProviderConfig>
+    pub fn discover(&self) -> AppResult<OidcProviderMetadata> {
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            self.issuer_url.trim_end_matches('/')
+        );
+        self.request(&url, None)
+    }
+
+    pub fn user_info(
+        &self,
+        metadata: &OidcProviderMetadata,
+        auth: &AccessToken,
+    ) -> AppResult<OidcUserInfo> {
+        self.request(&metadata.userinfo_endpoint, Some(auth))
+    }
+
+    fn request<T>(&self, url: &str, auth: Option<&AccessToken>) -> AppResult<T>
+    where
+        T: DeserializeOwned,
+    {
+        info!("OIDC HTTP: {url}");
+
+        let mut request = self
+            .client()
+            .get(url)
+            .header(header::ACCEPT, "application/json")
+            .header(header::USER_AGENT, "crates.io (https://crates.io)");
+        if let Some(auth) = auth {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", auth.secret()));
+        }
+
+        request
+            .send()?
+            .error_for_status()
+            .map_err(|e| internal(&format_args!("didn't get a 200 result from {url}: {e}")))?
+            .json()
+            .map_err(Into::into)
+    }
+
+    /// Returns a client for making HTTP requests to the issuer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the application was not initialized with a client.  This should only occur in
+    /// tests that were not properly initialized.
+    fn client(&self) -> &Client {
+        self.client
+            .as_ref()
+            .expect("No HTTP client is configured.  In tests, use `TestApp::with_proxy()`.")
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcProviderMetadata {
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: String,
+}
+
+/// The standard claims returned by the `userinfo` endpoint
+///
+/// see <https://openid.net/specs/openid-connect-core-1_0.html#StandardClaims>
+#[derive(Debug, Deserialize)]
+pub struct OidcUserInfo {
+    pub sub: String,
+    pub preferred_username: Option<String>,
+    pub name: Option<String>,
+    pub picture: Option<String>,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+}
@@ -14,6 +14,7 @@ pub fn build_router(app: &App) -> RouteBuilder {
 
     // Route used by both `cargo search` and the frontend
     api_router.get("/crates", C(krate::search::search));
+    api_router.get("/crate_suggestions", C(krate::search::suggest));
 
     // Routes used by `cargo`
     api_router.put("/crates/new", C(krate::publish::publish));
@@ -77,10 +78,35 @@ pub fn build_router(app: &App) -> RouteBuilder {
     api_router.get("/users/:user_id/stats", C(user::other::stats));
     api_router.get("/teams/:team_id", C(team::show_team));
     api_router.get("/me", C(user::me::me));
+    api_router.delete("/me", C(user::me::delete_account));
     api_router.get("/me/updates", C(user::me::updates));
+    api_router.get("/me/data_export", C(user::data_export::show));
+    api_router.put("/me/data_export", C(user::data_export::request));
+    api_router.get("/me/data_export/download", C(user::data_export::download));
+    api_router.get("/me/emails", C(user::emails::list));
+    api_router.put("/me/emails", C(user::emails::add));
+    api_router.delete("/me/emails/:id", C(user::emails::remove));
+    api_router.put("/me/emails/:id/primary", C(user::emails::set_primary));
+    api_router.put(
+        "/me/emails/:id/notifications",
+        C(user::emails::set_notifications),
+    );
+    api_router.put("/me/emails/:id/resend", C(user::emails::resend));
+    api_router.get("/me/sessions", C(user::session::list_sessions));
+    api_router.delete("/me/sessions", C(user::session::revoke_other_sessions));
+    api_router.delete("/me/sessions/:id", C(user::session::revoke_session));
+    api_router.get("/me/totp", C(user::totp::show));
+    api_router.put("/me/totp", C(user::totp::enroll));
+    api_router.delete("/me/totp", C(user::totp::disable));
+    api_router.put("/me/totp/confirm", C(user::totp::confirm));
+    api_router.put(
+        "/me/totp/recovery_codes",
+        C(user::totp::regenerate_recovery_codes),
+    );
     api_router.get("/me/tokens", C(token::list));
     api_router.put("/me/tokens", C(token::new));
     api_router.delete("/me/tokens/:id", C(token::revoke));
+    api_router.get("/me/tokens/:id/usage", C(token::usage));
     api_router.delete("/tokens/current", C(token::revoke_current));
     api_router.get(
         "/me/crate_owner_invitations",
@@ -98,7 +124,17 @@ pub fn build_router(app: &App) -> RouteBuilder {
         "/me/email_notifications",
         C(user::me::update_email_notifications),
     );
+    api_router.put("/me/weekly_digest", C(user::me::update_weekly_digest));
+    api_router.get(
+        "/weekly_digest/unsubscribe/:token",
+        C(user::me::show_weekly_digest_unsubscribe),
+    );
+    api_router.post(
+        "/weekly_digest/unsubscribe/:token",
+        C(user::me::unsubscribe_weekly_digest),
+    );
     api_router.get("/summary", C(krate::metadata::summary));
+    api_router.get("/downloads/archives", C(krate::downloads::archives));
     api_router.put("/confirm/:email_token", C(user::me::confirm_user_email));
     api_router.put(
         "/users/:user_id/resend",
@@ -123,6 +159,14 @@ pub fn build_router(app: &App) -> RouteBuilder {
         "/api/private/session/authorize",
         C(user::session::authorize),
     );
+    router.get(
+        "/api/private/session/:provider/begin",
+        C(user::session::begin_with_provider),
+    );
+    router.get(
+        "/api/private/session/:provider/authorize",
+        C(user::session::authorize_with_provider),
+    );
     router.delete("/api/private/session", C(user::session::logout));
 
     // Metrics
@@ -1,5 +1,58 @@
 #![allow(unused_imports)]
 
+table! {
+    use diesel::sql_types::*;
+    use diesel_full_text_search::{TsVector as Tsvector};
+
+    /// Representation of the `api_token_usages` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    api_token_usages (id) {
+        /// The `id` column of the `api_token_usages` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> Int8,
+        /// The `api_token_id` column of the `api_token_usages` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        api_token_id -> Int4,
+        /// The `used_at` column of the `api_token_usages` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        used_at -> Timestamp,
+        /// The `endpoint` column of the `api_token_usages` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        endpoint -> Varchar,
+        /// The `crate_name` column of the `api_token_usages` table.
+        ///
+        /// Its SQL type is `Nullable<Varchar>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        crate_name -> Nullable<Varchar>,
+        /// The `ip_address` column of the `api_token_usages` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        ip_address -> Varchar,
+        /// The `user_agent` column of the `api_token_usages` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        user_agent -> Varchar,
+    }
+}
+
 table! {
     use diesel::sql_types::*;
     use diesel_full_text_search::{TsVector as Tsvector};
@@ -220,6 +273,18 @@ table! {
         ///
         /// (Automatically generated by Diesel.)
         token_generated_at -> Nullable<Timestamp>,
+        /// The `rights` column of the `crate_owner_invitations` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        rights -> Int4,
+        /// The `reminder_sent_at` column of the `crate_owner_invitations` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamp>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        reminder_sent_at -> Nullable<Timestamp>,
     }
 }
 
@@ -279,6 +344,47 @@ table! {
         ///
         /// (Automatically generated by Diesel.)
         email_notifications -> Bool,
+        /// The `rights` column of the `crate_owners` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        rights -> Int4,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use diesel_full_text_search::{TsVector as Tsvector};
+
+    /// Representation of the `crate_scores` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    crate_scores (crate_id) {
+        /// The `crate_id` column of the `crate_scores` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        crate_id -> Int4,
+        /// The `trending_score` column of the `crate_scores` table.
+        ///
+        /// Its SQL type is `Float8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        trending_score -> Float8,
+        /// The `popularity_score` column of the `crate_scores` table.
+        ///
+        /// Its SQL type is `Float8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        popularity_score -> Float8,
+        /// The `updated_at` column of the `crate_scores` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        updated_at -> Timestamp,
     }
 }
 
@@ -411,6 +517,47 @@ table! {
     }
 }
 
+table! {
+    use diesel::sql_types::*;
+    use diesel_full_text_search::{TsVector as Tsvector};
+
+    /// Representation of the `data_exports` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    data_exports (id) {
+        /// The `id` column of the `data_exports` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> Int4,
+        /// The `user_id` column of the `data_exports` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        user_id -> Int4,
+        /// The `token` column of the `data_exports` table.
+        ///
+        /// Its SQL type is `Text`.
+        ///
+        /// (Automatically generated by Diesel.)
+        token -> Text,
+        /// The `requested_at` column of the `data_exports` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        requested_at -> Timestamp,
+        /// The `completed_at` column of the `data_exports` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamp>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        completed_at -> Nullable<Timestamp>,
+    }
+}
+
 table! {
     use diesel::sql_types::*;
     use diesel_full_text_search::{TsVector as Tsvector};
@@ -520,6 +667,18 @@ table! {
         ///
         /// (Automatically generated by Diesel.)
         token_generated_at -> Nullable<Timestamp>,
+        /// The `is_primary` column of the `emails` table.
+        ///
+        /// Its SQL type is `Bool`.
+        ///
+        /// (Automatically generated by Diesel.)
+        is_primary -> Bool,
+        /// The `send_notifications` column of the `emails` table.
+        ///
+        /// Its SQL type is `Bool`.
+        ///
+        /// (Automatically generated by Diesel.)
+        send_notifications -> Bool,
     }
 }
 
@@ -581,6 +740,59 @@ table! {
     }
 }
 
+table! {
+    use diesel::sql_types::*;
+    use diesel_full_text_search::{TsVector as Tsvector};
+
+    /// Representation of the `linked_accounts` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    linked_accounts (provider, account_id) {
+        /// The `user_id` column of the `linked_accounts` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        user_id -> Int4,
+        /// The `provider` column of the `linked_accounts` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        provider -> Int4,
+        /// The `account_id` column of the `linked_accounts` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        account_id -> Varchar,
+        /// The `login` column of the `linked_accounts` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        login -> Varchar,
+        /// The `access_token` column of the `linked_accounts` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        access_token -> Varchar,
+        /// The `avatar` column of the `linked_accounts` table.
+        ///
+        /// Its SQL type is `Nullable<Varchar>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        avatar -> Nullable<Varchar>,
+        /// The `created_at` column of the `linked_accounts` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamp,
+    }
+}
+
 table! {
     use diesel::sql_types::*;
     use diesel_full_text_search::{TsVector as Tsvector};
@@ -598,6 +810,76 @@ table! {
     }
 }
 
+table! {
+    use diesel::sql_types::*;
+    use diesel_full_text_search::{TsVector as Tsvector};
+
+    /// Representation of the `pending_crate_owner_invitations` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    pending_crate_owner_invitations (invited_login, crate_id) {
+        /// The `invited_login` column of the `pending_crate_owner_invitations` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        invited_login -> Varchar,
+        /// The `invited_by_user_id` column of the `pending_crate_owner_invitations` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        invited_by_user_id -> Int4,
+        /// The `crate_id` column of the `pending_crate_owner_invitations` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        crate_id -> Int4,
+        /// The `rights` column of the `pending_crate_owner_invitations` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        rights -> Int4,
+        /// The `created_at` column of the `pending_crate_owner_invitations` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use diesel_full_text_search::{TsVector as Tsvector};
+
+    /// Representation of the `processed_cdn_log_files` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    processed_cdn_log_files (path) {
+        /// The `path` column of the `processed_cdn_log_files` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        path -> Varchar,
+        /// The `downloads` column of the `processed_cdn_log_files` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        downloads -> Int4,
+        /// The `processed_at` column of the `processed_cdn_log_files` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        processed_at -> Timestamp,
+    }
+}
+
 table! {
     use diesel::sql_types::*;
     use diesel_full_text_search::{TsVector as Tsvector};
@@ -714,6 +996,94 @@ table! {
     }
 }
 
+table! {
+    use diesel::sql_types::*;
+    use diesel_full_text_search::{TsVector as Tsvector};
+
+    /// Representation of the `sessions` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    sessions (id) {
+        /// The `id` column of the `sessions` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> Int4,
+        /// The `user_id` column of the `sessions` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        user_id -> Int4,
+        /// The `created_at` column of the `sessions` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamp,
+        /// The `last_used_at` column of the `sessions` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        last_used_at -> Timestamp,
+        /// The `user_agent` column of the `sessions` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        user_agent -> Varchar,
+        /// The `ip_address` column of the `sessions` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        ip_address -> Varchar,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use diesel_full_text_search::{TsVector as Tsvector};
+
+    /// Representation of the `team_memberships` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    team_memberships (team_id, user_id) {
+        /// The `team_id` column of the `team_memberships` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        team_id -> Int4,
+        /// The `user_id` column of the `team_memberships` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        user_id -> Int4,
+        /// The `is_member` column of the `team_memberships` table.
+        ///
+        /// Its SQL type is `Bool`.
+        ///
+        /// (Automatically generated by Diesel.)
+        is_member -> Bool,
+        /// The `checked_at` column of the `team_memberships` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        checked_at -> Timestamp,
+        /// The `refresh_enqueued_at` column of the `team_memberships` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamp>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        refresh_enqueued_at -> Nullable<Timestamp>,
+    }
+}
+
 table! {
     use diesel::sql_types::*;
     use diesel_full_text_search::{TsVector as Tsvector};
@@ -758,6 +1128,100 @@ table! {
         ///
         /// (Automatically generated by Diesel.)
         org_id -> Nullable<Int4>,
+        /// The `provider` column of the `teams` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        provider -> Int4,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use diesel_full_text_search::{TsVector as Tsvector};
+
+    /// Representation of the `totp_credentials` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    totp_credentials (user_id) {
+        /// The `user_id` column of the `totp_credentials` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        user_id -> Int4,
+        /// The `secret` column of the `totp_credentials` table.
+        ///
+        /// Its SQL type is `Bytea`.
+        ///
+        /// (Automatically generated by Diesel.)
+        secret -> Bytea,
+        /// The `created_at` column of the `totp_credentials` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamp,
+        /// The `confirmed_at` column of the `totp_credentials` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamp>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        confirmed_at -> Nullable<Timestamp>,
+        /// The `last_used_step` column of the `totp_credentials` table.
+        ///
+        /// Its SQL type is `Nullable<Int8>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        last_used_step -> Nullable<Int8>,
+        /// The `failed_attempts` column of the `totp_credentials` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        failed_attempts -> Int4,
+        /// The `locked_until` column of the `totp_credentials` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamp>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        locked_until -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use diesel_full_text_search::{TsVector as Tsvector};
+
+    /// Representation of the `totp_recovery_codes` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    totp_recovery_codes (id) {
+        /// The `id` column of the `totp_recovery_codes` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> Int4,
+        /// The `user_id` column of the `totp_recovery_codes` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        user_id -> Int4,
+        /// The `code` column of the `totp_recovery_codes` table.
+        ///
+        /// Its SQL type is `Bytea`.
+        ///
+        /// (Automatically generated by Diesel.)
+        code -> Bytea,
+        /// The `used_at` column of the `totp_recovery_codes` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamp>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        used_at -> Nullable<Timestamp>,
     }
 }
 
@@ -817,6 +1281,18 @@ table! {
         ///
         /// (Automatically generated by Diesel.)
         account_lock_until -> Nullable<Timestamp>,
+        /// The `weekly_digest` column of the `users` table.
+        ///
+        /// Its SQL type is `Bool`.
+        ///
+        /// (Automatically generated by Diesel.)
+        weekly_digest -> Bool,
+        /// The `digest_sent_at` column of the `users` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamp>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        digest_sent_at -> Nullable<Timestamp>,
     }
 }
 
@@ -861,6 +1337,111 @@ table! {
     }
 }
 
+table! {
+    use diesel::sql_types::*;
+    use diesel_full_text_search::{TsVector as Tsvector};
+
+    /// Representation of the `version_downloads_archives` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    version_downloads_archives (date) {
+        /// The `date` column of the `version_downloads_archives` table.
+        ///
+        /// Its SQL type is `Date`.
+        ///
+        /// (Automatically generated by Diesel.)
+        date -> Date,
+        /// The `rows` column of the `version_downloads_archives` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        rows -> Int4,
+        /// The `downloads` column of the `version_downloads_archives` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        downloads -> Int8,
+        /// The `archived_at` column of the `version_downloads_archives` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        archived_at -> Timestamp,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use diesel_full_text_search::{TsVector as Tsvector};
+
+    /// Representation of the `version_downloads_by_client` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    version_downloads_by_client (version_id, date, client, cargo_version) {
+        /// The `version_id` column of the `version_downloads_by_client` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        version_id -> Int4,
+        /// The `date` column of the `version_downloads_by_client` table.
+        ///
+        /// Its SQL type is `Date`.
+        ///
+        /// (Automatically generated by Diesel.)
+        date -> Date,
+        /// The `client` column of the `version_downloads_by_client` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        client -> Varchar,
+        /// The `cargo_version` column of the `version_downloads_by_client` table.
+        ///
+        /// Its SQL type is `Varchar`.
+        ///
+        /// (Automatically generated by Diesel.)
+        cargo_version -> Varchar,
+        /// The `downloads` column of the `version_downloads_by_client` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        downloads -> Int4,
+    }
+}
+
+table! {
+    use diesel::sql_types::*;
+    use diesel_full_text_search::{TsVector as Tsvector};
+
+    /// Representation of the `version_downloads_monthly` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    version_downloads_monthly (version_id, month) {
+        /// The `version_id` column of the `version_downloads_monthly` table.
+        ///
+        /// Its SQL type is `Int4`.
+        ///
+        /// (Automatically generated by Diesel.)
+        version_id -> Int4,
+        /// The `month` column of the `version_downloads_monthly` table.
+        ///
+        /// Its SQL type is `Date`.
+        ///
+        /// (Automatically generated by Diesel.)
+        month -> Date,
+        /// The `downloads` column of the `version_downloads_monthly` table.
+        ///
+        /// Its SQL type is `Int8`.
+        ///
+        /// (Automatically generated by Diesel.)
+        downloads -> Int8,
+    }
+}
+
 table! {
     use diesel::sql_types::*;
     use diesel_full_text_search::{TsVector as Tsvector};
@@ -883,10 +1464,10 @@ table! {
         version_id -> Int4,
         /// The `user_id` column of the `version_owner_actions` table.
         ///
-        /// Its SQL type is `Int4`.
+        /// Its SQL type is `Nullable<Int4>`.
         ///
         /// (Automatically generated by Diesel.)
-        user_id -> Int4,
+        user_id -> Nullable<Int4>,
         /// The `api_token_id` column of the `version_owner_actions` table.
         ///
         /// Its SQL type is `Nullable<Int4>`.
@@ -982,6 +1563,12 @@ table! {
         ///
         /// (Automatically generated by Diesel.)
         published_by -> Nullable<Int4>,
+        /// The `rust_version` column of the `versions` table.
+        ///
+        /// Its SQL type is `Nullable<Varchar>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        rust_version -> Nullable<Varchar>,
     }
 }
 
@@ -1008,26 +1595,39 @@ table! {
     }
 }
 
+joinable!(api_token_usages -> api_tokens (api_token_id));
 joinable!(api_tokens -> users (user_id));
 joinable!(badges -> crates (crate_id));
 joinable!(crate_owner_invitations -> crates (crate_id));
 joinable!(crate_owners -> crates (crate_id));
 joinable!(crate_owners -> teams (owner_id));
 joinable!(crate_owners -> users (owner_id));
+joinable!(crate_scores -> crates (crate_id));
 joinable!(crates_categories -> categories (category_id));
 joinable!(crates_categories -> crates (crate_id));
 joinable!(crates_keywords -> crates (crate_id));
 joinable!(crates_keywords -> keywords (keyword_id));
+joinable!(data_exports -> users (user_id));
 joinable!(dependencies -> crates (crate_id));
 joinable!(dependencies -> versions (version_id));
 joinable!(emails -> users (user_id));
 joinable!(follows -> crates (crate_id));
 joinable!(follows -> users (user_id));
+joinable!(linked_accounts -> users (user_id));
+joinable!(pending_crate_owner_invitations -> crates (crate_id));
+joinable!(pending_crate_owner_invitations -> users (invited_by_user_id));
 joinable!(publish_limit_buckets -> users (user_id));
 joinable!(publish_rate_overrides -> users (user_id));
 joinable!(readme_renderings -> versions (version_id));
 joinable!(recent_crate_downloads -> crates (crate_id));
+joinable!(sessions -> users (user_id));
+joinable!(team_memberships -> teams (team_id));
+joinable!(team_memberships -> users (user_id));
+joinable!(totp_credentials -> users (user_id));
+joinable!(totp_recovery_codes -> users (user_id));
 joinable!(version_downloads -> versions (version_id));
+joinable!(version_downloads_by_client -> versions (version_id));
+joinable!(version_downloads_monthly -> versions (version_id));
 joinable!(version_owner_actions -> api_tokens (api_token_id));
 joinable!(version_owner_actions -> users (user_id));
 joinable!(version_owner_actions -> versions (version_id));
@@ -1036,28 +1636,41 @@ joinable!(versions -> users (published_by));
 joinable!(versions_published_by -> versions (version_id));
 
 allow_tables_to_appear_in_same_query!(
+    api_token_usages,
     api_tokens,
     background_jobs,
     badges,
     categories,
     crate_owner_invitations,
     crate_owners,
+    crate_scores,
     crates,
     crates_categories,
     crates_keywords,
+    data_exports,
     dependencies,
     emails,
     follows,
     keywords,
+    linked_accounts,
     metadata,
+    pending_crate_owner_invitations,
+    processed_cdn_log_files,
     publish_limit_buckets,
     publish_rate_overrides,
     readme_renderings,
     recent_crate_downloads,
     reserved_crate_names,
+    sessions,
+    team_memberships,
     teams,
+    totp_credentials,
+    totp_recovery_codes,
     users,
     version_downloads,
+    version_downloads_archives,
+    version_downloads_by_client,
+    version_downloads_monthly,
     version_owner_actions,
     versions,
     versions_published_by,
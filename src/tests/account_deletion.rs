@@ -0,0 +1,113 @@
+use crate::builders::CrateBuilder;
+use crate::util::{RequestHelper, Response};
+use crate::{OkBool, TestApp};
+use cargo_registry::models::{
+    insert_version_owner_action, CrateOwner, OwnerKind, Rights, User, VersionAction,
+};
+use cargo_registry::schema::{api_tokens, crate_owners, emails, users};
+use conduit::StatusCode;
+use diesel::prelude::*;
+
+const URL: &str = "/api/v1/me";
+
+fn delete_account(user: &impl RequestHelper, abandon_crates: &[&str]) -> Response<OkBool> {
+    let body = json!({ "abandon_crates": abandon_crates });
+    user.delete_with_body(URL, body.to_string().as_bytes())
+}
+
+#[test]
+fn solely_owned_crates_have_to_be_abandoned() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user_id = user.as_model().id;
+
+    let version_id = app.db(|conn| {
+        let krate = CrateBuilder::new("foo_sole", user_id)
+            .version("1.0.0")
+            .expect_build(conn);
+        let version = krate.find_version(conn, "1.0.0").unwrap();
+        insert_version_owner_action(conn, version.id, user_id, None, VersionAction::Yank).unwrap();
+        version.id
+    });
+
+    let response = delete_account(&user, &[]);
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let detail = "you are the only owner of the following crates, please add another owner or \
+                  abandon them first: foo_sole";
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": detail }] })
+    );
+
+    delete_account(&user, &["foo_sole"]).good();
+
+    let user_count: i64 = app.db(|conn| {
+        users::table
+            .filter(users::id.eq(user_id))
+            .count()
+            .get_result(conn)
+            .unwrap()
+    });
+    assert_eq!(user_count, 0);
+
+    // The crate stays published, but without any reference to the deleted user
+    assert!(anon.show_crate_owners("foo_sole").users.is_empty());
+    let json = anon.show_version("foo_sole", "1.0.0");
+    assert_eq!(json.version.id, version_id);
+    assert!(json.version.published_by.is_none());
+    assert_eq!(json.version.audit_actions.len(), 1);
+    assert!(json.version.audit_actions[0].user.is_none());
+}
+
+#[test]
+fn co_owned_crates_are_kept_by_the_other_owners() {
+    let (app, anon, user, token) = TestApp::init().with_token();
+    let user_id = user.as_model().id;
+    let other = app.db_new_user("other");
+    let other_id = other.as_model().id;
+
+    app.db(|conn| {
+        let krate = CrateBuilder::new("foo_shared", user_id).expect_build(conn);
+        diesel::insert_into(crate_owners::table)
+            .values(&CrateOwner {
+                crate_id: krate.id,
+                owner_id: other_id,
+                created_by: user_id,
+                owner_kind: OwnerKind::User as i32,
+                email_notifications: true,
+                rights: Rights::Full,
+            })
+            .execute(conn)
+            .unwrap();
+    });
+
+    // API tokens can't be used to delete an account
+    let response = delete_account(&token, &[]);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    delete_account(&user, &[]).good();
+
+    let owners = anon.show_crate_owners("foo_shared").users;
+    assert_eq!(owners.len(), 1);
+    assert_eq!(owners[0].login, "other");
+
+    // The tokens and emails of the user are gone
+    let (token_count, email_count): (i64, i64) = app.db(|conn| {
+        let token_count = api_tokens::table
+            .filter(api_tokens::user_id.eq(user_id))
+            .count()
+            .get_result(conn)
+            .unwrap();
+        let email_count = emails::table
+            .filter(emails::user_id.eq(user_id))
+            .count()
+            .get_result(conn)
+            .unwrap();
+        (token_count, email_count)
+    });
+    assert_eq!(token_count, 0);
+    assert_eq!(email_count, 0);
+
+    // The other owner is unaffected
+    let other_user = app.db(|conn| User::find(conn, other_id).unwrap());
+    assert_eq!(other_user.gh_login, "other");
+}
@@ -15,7 +15,9 @@ extern crate tracing;
 
 use crate::util::{RequestHelper, TestApp};
 use cargo_registry::{
-    models::{Crate, CrateOwner, NewCategory, NewTeam, NewUser, Team, User},
+    models::{
+        AccountProvider, Crate, CrateOwner, NewCategory, NewTeam, NewUser, Rights, Team, User,
+    },
     schema::crate_owners,
     views::{
         EncodableCategory, EncodableCategoryWithSubcategories, EncodableCrate, EncodableKeyword,
@@ -29,6 +31,7 @@ use std::{
 
 use diesel::prelude::*;
 
+mod account_deletion;
 mod account_lock;
 mod authentication;
 mod badge;
@@ -36,10 +39,13 @@ mod blocked_routes;
 mod builders;
 mod categories;
 mod category;
+mod cdn_logs;
+mod data_export;
 mod dump_db;
 mod git;
 mod keyword;
 mod krate;
+mod login_providers;
 mod metrics;
 mod not_found_error;
 mod owners;
@@ -48,8 +54,10 @@ mod record;
 mod schema_details;
 mod server;
 mod server_binary;
+mod session;
 mod team;
 mod token;
+mod totp;
 mod unhealthy_database;
 mod user;
 mod util;
@@ -135,6 +143,7 @@ fn new_team(login: &str) -> NewTeam<'_> {
         login,
         name: None,
         avatar: None,
+        provider: AccountProvider::GitHub,
     }
 }
 
@@ -145,6 +154,7 @@ fn add_team_to_crate(t: &Team, krate: &Crate, u: &User, conn: &PgConnection) ->
         created_by: u.id,
         owner_kind: 1, // Team owner kind is 1 according to owner.rs
         email_notifications: true,
+        rights: Rights::Publish,
     };
 
     diesel::insert_into(crate_owners::table)
@@ -6,8 +6,6 @@ use conduit::{header, Body, Method, StatusCode};
 
 static URL: &str = "/api/v1/me/updates";
 static MUST_LOGIN: &[u8] = br#"{"errors":[{"detail":"must be logged in to perform that action"}]}"#;
-static INTERNAL_ERROR_NO_USER: &str =
-    "user_id from cookie not found in database caused by NotFound";
 
 #[test]
 fn anonymous_user_unauthorized() {
@@ -29,11 +27,9 @@ fn token_auth_cannot_find_token() {
     assert_eq!(response.into_json().to_string().as_bytes(), MUST_LOGIN);
 }
 
-// Ensure that an unexpected authentication error is available for logging.  The user would see
-// status 500 instead of 403 as in other authentication tests.  Due to foreign-key constraints in
-// the database, it is not possible to implement this same test for a token.
+// Sessions that were revoked, or never existed, are treated like missing credentials.
 #[test]
-fn cookie_auth_cannot_find_user() {
+fn cookie_auth_cannot_find_session() {
     let (app, anon) = TestApp::init().empty();
 
     let session_key = &app.as_inner().session_key();
@@ -41,7 +37,8 @@ fn cookie_auth_cannot_find_user() {
 
     let mut request = anon.request_builder(Method::GET, URL);
     request.header(header::COOKIE, &cookie);
+    let response: Response<Body> = anon.run(request);
 
-    let error = anon.run_err(request);
-    assert_eq!(error.to_string(), INTERNAL_ERROR_NO_USER);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(response.into_json().to_string().as_bytes(), MUST_LOGIN);
 }
@@ -34,6 +34,7 @@ pub struct PublishBuilder {
     license: Option<String>,
     license_file: Option<String>,
     readme: Option<String>,
+    rust_version: Option<String>,
     tarball: Vec<u8>,
     version: semver::Version,
     features: HashMap<u::EncodableFeatureName, Vec<u::EncodableFeature>>,
@@ -54,6 +55,7 @@ impl PublishBuilder {
             license: Some("MIT".to_string()),
             license_file: None,
             readme: None,
+            rust_version: None,
             tarball: EMPTY_TARBALL_BYTES.to_vec(),
             version: semver::Version::parse("1.0.0").unwrap(),
             features: HashMap::new(),
@@ -168,6 +170,12 @@ impl PublishBuilder {
         self
     }
 
+    /// Set the minimum supported Rust version of the crate
+    pub fn rust_version(mut self, rust_version: &str) -> Self {
+        self.rust_version = Some(rust_version.to_string());
+        self
+    }
+
     // Adds a feature.
     pub fn feature(mut self, name: &str, values: &[&str]) -> Self {
         let values = values
@@ -202,6 +210,7 @@ impl PublishBuilder {
             license: self.license,
             license_file: self.license_file,
             repository: None,
+            rust_version: self.rust_version,
             badges: Some(self.badges),
             links: None,
         };
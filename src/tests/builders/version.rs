@@ -16,6 +16,7 @@ pub struct VersionBuilder<'a> {
     license: Option<&'a str>,
     license_file: Option<&'a str>,
     num: semver::Version,
+    rust_version: Option<&'a str>,
     size: i32,
     yanked: bool,
 }
@@ -39,6 +40,7 @@ impl<'a> VersionBuilder<'a> {
             license: None,
             license_file: None,
             num,
+            rust_version: None,
             size: 0,
             yanked: false,
         }
@@ -56,6 +58,12 @@ impl<'a> VersionBuilder<'a> {
         self
     }
 
+    /// Sets the version's `rust_version` value.
+    pub fn rust_version(mut self, rust_version: &'a str) -> Self {
+        self.rust_version = Some(rust_version);
+        self
+    }
+
     /// Adds a dependency to this version.
     pub fn dependency(mut self, dependency: &Crate, target: Option<&'static str>) -> Self {
         self.dependencies.push((dependency.id, target));
@@ -89,6 +97,8 @@ impl<'a> VersionBuilder<'a> {
             &self.features,
             license,
             self.license_file,
+            self.rust_version
+                .map(|rust_version| rust_version.to_owned()),
             self.size,
             published_by,
         )?
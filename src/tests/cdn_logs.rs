@@ -0,0 +1,232 @@
+use crate::builders::{CrateBuilder, VersionBuilder};
+use crate::TestApp;
+use cargo_registry::schema::{processed_cdn_log_files, version_downloads, versions};
+use cargo_registry::uploaders::Uploader;
+use cargo_registry::worker::cdn_logs;
+use chrono::{Duration, NaiveDate, Utc};
+use diesel::prelude::*;
+use reqwest::blocking::Client;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Copies the log files of `src/tests/cdn-logs` to the directory of the `Local` uploader. The
+/// files are removed again when this is dropped.
+struct LocalLogFiles {
+    dir: PathBuf,
+    prefix: String,
+}
+
+impl LocalLogFiles {
+    fn new() -> Self {
+        let prefix = format!("test-cdn-logs-{}", rand::random::<u32>());
+        let dir = std::env::current_dir()
+            .unwrap()
+            .join("local_uploads")
+            .join(&prefix);
+
+        let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/tests/cdn-logs");
+        for cdn in ["cloudfront", "fastly"] {
+            fs::create_dir_all(dir.join(cdn)).unwrap();
+            for entry in fs::read_dir(fixtures.join(cdn)).unwrap() {
+                let entry = entry.unwrap();
+                fs::copy(entry.path(), dir.join(cdn).join(entry.file_name())).unwrap();
+            }
+        }
+
+        Self { dir, prefix }
+    }
+}
+
+impl Drop for LocalLogFiles {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
+fn downloads(conn: &PgConnection, num: &str, date: NaiveDate) -> Option<i32> {
+    version_downloads::table
+        .inner_join(versions::table)
+        .select(version_downloads::downloads)
+        .filter(versions::num.eq(num))
+        .filter(version_downloads::date.eq(date))
+        .first(conn)
+        .optional()
+        .unwrap()
+}
+
+/// Ingests the pending log files like the `ingest_cdn_log_file` jobs would, returning the number
+/// of counted files.
+fn ingest(conn: &PgConnection, prefix: &str, since: NaiveDate) -> usize {
+    let client = Client::new();
+    let paths = cdn_logs::pending_files(conn, &Uploader::Local, &client, prefix).unwrap();
+    paths
+        .iter()
+        .filter(|path| {
+            cdn_logs::ingest_file(conn, &Uploader::Local, &client, prefix, path, since).unwrap()
+        })
+        .count()
+}
+
+fn first_day() -> NaiveDate {
+    NaiveDate::from_ymd(2022, 1, 1)
+}
+
+#[test]
+fn ingest_log_files() {
+    let (app, _, user) = TestApp::init().with_user();
+    let logs = LocalLogFiles::new();
+
+    app.db(|conn| {
+        CrateBuilder::new("cdn_foo", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .version(VersionBuilder::new("1.1.0"))
+            .expect_build(conn);
+
+        let files = ingest(conn, &logs.prefix, first_day());
+        assert_eq!(files, 2);
+
+        let feb_14 = NaiveDate::from_ymd(2022, 2, 14);
+        let feb_15 = NaiveDate::from_ymd(2022, 2, 15);
+        assert_eq!(downloads(conn, "1.0.0", feb_14), Some(3));
+        assert_eq!(downloads(conn, "1.1.0", feb_14), Some(3));
+        assert_eq!(downloads(conn, "1.0.0", feb_15), Some(1));
+        assert_eq!(downloads(conn, "1.1.0", feb_15), None);
+
+        let processed: Vec<(String, i32)> = processed_cdn_log_files::table
+            .select((
+                processed_cdn_log_files::path,
+                processed_cdn_log_files::downloads,
+            ))
+            .order(processed_cdn_log_files::path)
+            .load(conn)
+            .unwrap();
+        let cloudfront = format!(
+            "{}/cloudfront/E35K556QRQDZXW.2022-02-14-10.d9f2c4a1.gz",
+            logs.prefix
+        );
+        let fastly = format!(
+            "{}/fastly/2022-02-15T00:00:00.000-lB3bRsXLrfwh0Mc0YwAR.log",
+            logs.prefix
+        );
+        assert_eq!(processed, vec![(cloudfront, 4), (fastly, 3)]);
+
+        // Files are only counted once, even by duplicate jobs
+        let files = ingest(conn, &logs.prefix, first_day());
+        assert_eq!(files, 0);
+        let client = Client::new();
+        let counted = cdn_logs::ingest_file(
+            conn,
+            &Uploader::Local,
+            &client,
+            &logs.prefix,
+            &fastly,
+            first_day(),
+        )
+        .unwrap();
+        assert!(!counted);
+        assert_eq!(downloads(conn, "1.0.0", feb_14), Some(3));
+    });
+}
+
+#[test]
+fn ingested_downloads_are_counted_again() {
+    let (app, _, user) = TestApp::init().with_user();
+    let logs = LocalLogFiles::new();
+
+    app.db(|conn| {
+        CrateBuilder::new("cdn_foo", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .version(VersionBuilder::new("1.1.0"))
+            .expect_build(conn);
+
+        // Downloads of the day were already counted by `update_downloads`
+        let version_id: i32 = versions::table
+            .select(versions::id)
+            .filter(versions::num.eq("1.0.0"))
+            .first(conn)
+            .unwrap();
+        let feb_14 = NaiveDate::from_ymd(2022, 2, 14);
+        diesel::insert_into(version_downloads::table)
+            .values((
+                version_downloads::version_id.eq(version_id),
+                version_downloads::date.eq(feb_14),
+                version_downloads::downloads.eq(10),
+                version_downloads::counted.eq(10),
+                version_downloads::processed.eq(true),
+            ))
+            .execute(conn)
+            .unwrap();
+
+        ingest(conn, &logs.prefix, first_day());
+
+        let (downloads, processed): (i32, bool) = version_downloads::table
+            .select((version_downloads::downloads, version_downloads::processed))
+            .filter(version_downloads::version_id.eq(version_id))
+            .filter(version_downloads::date.eq(feb_14))
+            .first(conn)
+            .unwrap();
+        assert_eq!(downloads, 13);
+        assert!(!processed);
+    });
+}
+
+#[test]
+fn downloads_before_the_cutover_are_skipped() {
+    let (app, _, user) = TestApp::init().with_user();
+    let logs = LocalLogFiles::new();
+
+    app.db(|conn| {
+        CrateBuilder::new("cdn_foo", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .version(VersionBuilder::new("1.1.0"))
+            .expect_build(conn);
+
+        let feb_15 = NaiveDate::from_ymd(2022, 2, 15);
+        let files = ingest(conn, &logs.prefix, feb_15);
+        assert_eq!(files, 2);
+
+        assert_eq!(
+            downloads(conn, "1.0.0", NaiveDate::from_ymd(2022, 2, 14)),
+            None
+        );
+        assert_eq!(downloads(conn, "1.0.0", feb_15), Some(1));
+    });
+}
+
+#[test]
+fn only_files_after_the_delivered_ones_are_listed() {
+    let (app, _) = TestApp::init().empty();
+    let logs = LocalLogFiles::new();
+
+    app.db(|conn| {
+        let client = Client::new();
+        let later = format!("{}/fastly/2022-02-16T00:00:00.000-x.log", logs.prefix);
+        let recently_processed = format!("{}/cloudfront/E35K556QRQDZXW.2022-02-15", logs.prefix);
+        diesel::insert_into(processed_cdn_log_files::table)
+            .values(&vec![
+                (
+                    processed_cdn_log_files::path.eq(&later),
+                    processed_cdn_log_files::downloads.eq(0),
+                    processed_cdn_log_files::processed_at
+                        .eq((Utc::now() - Duration::days(3)).naive_utc()),
+                ),
+                (
+                    processed_cdn_log_files::path.eq(&recently_processed),
+                    processed_cdn_log_files::downloads.eq(0),
+                    processed_cdn_log_files::processed_at.eq(Utc::now().naive_utc()),
+                ),
+            ])
+            .execute(conn)
+            .unwrap();
+
+        // The Fastly file sorts before a file that was processed days ago, so all files before
+        // it were delivered and processed. The CloudFront file could still have been delivered
+        // late, since the file after it was only processed recently.
+        let paths = cdn_logs::pending_files(conn, &Uploader::Local, &client, &logs.prefix).unwrap();
+        let cloudfront = format!(
+            "{}/cloudfront/E35K556QRQDZXW.2022-02-14-10.d9f2c4a1.gz",
+            logs.prefix
+        );
+        assert_eq!(paths, vec![cloudfront]);
+    });
+}
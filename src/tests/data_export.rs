@@ -0,0 +1,155 @@
+use crate::builders::CrateBuilder;
+use crate::{util::RequestHelper, OkBool, TestApp};
+use cargo_registry::views::EncodableDataExport;
+use cargo_registry::worker::data_export::UserData;
+use conduit::StatusCode;
+
+const URL: &str = "/api/v1/me/data_export";
+
+#[derive(Deserialize)]
+struct DataExportResponse {
+    data_export: Option<EncodableDataExport>,
+}
+
+#[test]
+fn request_data_export() {
+    let (app, _, user) = TestApp::init().with_user();
+
+    let json: DataExportResponse = user.get(URL).good();
+    assert!(json.data_export.is_none());
+
+    let json: DataExportResponse = user.put(URL, &[]).good();
+    let export = json.data_export.unwrap();
+    assert!(export.completed_at.is_none());
+    assert!(export.url.is_none());
+
+    let json: DataExportResponse = user.get(URL).good();
+    assert_eq!(json.data_export.unwrap().requested_at, export.requested_at);
+
+    let response = user.put::<()>(URL, &[]);
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let detail = "a data export can only be requested once a day, please try again later";
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": detail }] })
+    );
+
+    discard_background_jobs(&app);
+}
+
+/// Uploads can't be replayed in tests without a proxy, so the jobs of the exports are discarded
+fn discard_background_jobs(app: &TestApp) {
+    app.db(|conn| {
+        use diesel::prelude::*;
+        use swirl::schema::background_jobs;
+
+        diesel::delete(background_jobs::table)
+            .execute(conn)
+            .unwrap();
+    });
+}
+
+#[test]
+fn only_recent_exports_can_be_downloaded() {
+    use cargo_registry::schema::data_exports;
+    use chrono::{Duration, Utc};
+    use diesel::prelude::*;
+
+    let (app, _, user) = TestApp::init().with_user();
+    let download_url = "/api/v1/me/data_export/download";
+
+    let json: DataExportResponse = user.put(URL, &[]).good();
+    assert!(json.data_export.unwrap().url.is_none());
+    assert_eq!(user.get::<()>(download_url).status(), StatusCode::NOT_FOUND);
+
+    let set_completed_at = |completed_at| {
+        app.db(|conn| {
+            diesel::update(data_exports::table)
+                .set(data_exports::completed_at.eq(completed_at))
+                .execute(conn)
+                .unwrap();
+        })
+    };
+
+    let completed_at = Utc::now().naive_utc() - Duration::days(1);
+    set_completed_at(completed_at);
+    let json: DataExportResponse = user.get(URL).good();
+    let export = json.data_export.unwrap();
+    assert_eq!(export.url.as_deref(), Some(download_url));
+    assert!(export.expires_at.unwrap() > Utc::now().naive_utc());
+
+    // Expired exports are no longer served, even before they are deleted
+    set_completed_at(Utc::now().naive_utc() - Duration::days(8));
+    let json: DataExportResponse = user.get(URL).good();
+    assert!(json.data_export.unwrap().url.is_none());
+    assert_eq!(user.get::<()>(download_url).status(), StatusCode::NOT_FOUND);
+
+    // Other users can't download the export either
+    let other = app.db_new_user("other");
+    set_completed_at(completed_at);
+    assert_eq!(
+        other.get::<()>(download_url).status(),
+        StatusCode::NOT_FOUND
+    );
+
+    discard_background_jobs(&app);
+}
+
+#[test]
+fn deleting_the_account_deletes_the_exports() {
+    use diesel::prelude::*;
+    use swirl::schema::background_jobs;
+
+    let (app, _, user) = TestApp::init().with_user();
+    user.put::<DataExportResponse>(URL, &[]).good();
+    discard_background_jobs(&app);
+
+    user.delete::<OkBool>("/api/v1/me").good();
+
+    let jobs: Vec<String> = app.db(|conn| {
+        background_jobs::table
+            .select(background_jobs::job_type)
+            .load(conn)
+            .unwrap()
+    });
+    assert_eq!(jobs, ["delete_data_export_files"]);
+    discard_background_jobs(&app);
+}
+
+#[test]
+fn data_export_requires_cookie_auth() {
+    let (_, _, _, token) = TestApp::init().with_token();
+
+    let response = token.put::<()>(URL, &[]);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn data_export_contains_user_data() {
+    let (app, _, user, token) = TestApp::init().with_token();
+    let user_model = user.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_export", user_model.id)
+            .version("1.0.0")
+            .expect_build(conn);
+    });
+    user.put::<OkBool>("/api/v1/crates/foo_export/follow", &[])
+        .good();
+
+    let data = app.db(|conn| UserData::load(conn, user_model).unwrap());
+    assert_eq!(data.user.login, "foo");
+    assert_eq!(data.emails.len(), 1);
+    assert_eq!(data.emails[0].email, "something@example.com");
+    assert_eq!(data.api_tokens.len(), 1);
+    assert_eq!(data.api_tokens[0].name, "bar");
+    assert_eq!(data.owned_crates.len(), 1);
+    assert_eq!(data.owned_crates[0].name, "foo_export");
+    assert_eq!(data.followed_crates, ["foo_export"]);
+    assert_eq!(data.published_versions.len(), 1);
+    assert_eq!(data.published_versions[0].version, "1.0.0");
+
+    // Only the metadata of the tokens is exported
+    let json = serde_json::to_string(&data).unwrap();
+    assert!(!json.contains(token.plaintext()));
+}
@@ -1,8 +1,11 @@
 use crate::builders::{CrateBuilder, VersionBuilder};
 use crate::util::{MockAnonymousUser, RequestHelper, TestApp};
-use cargo_registry::views::EncodableVersionDownload;
-use chrono::{Duration, Utc};
-use http::StatusCode;
+use cargo_registry::views::{EncodableVersionDownload, EncodableVersionDownloadsArchive};
+use cargo_registry::worker::downloads_archive::{encode_archive, load_day, ArchivedDownloads};
+use chrono::{Duration, NaiveDate, Utc};
+use http::{header, StatusCode};
+use serde_json::Value;
+use std::io::Read;
 
 #[derive(Deserialize)]
 struct Downloads {
@@ -80,6 +83,79 @@ fn download() {
     let query = format!("before_date={tomorrow}");
     assert_dl_count(&anon, "FOO_DOWNLOAD/1.0.0", Some(&query), 2);
     assert_dl_count(&anon, "FOO_DOWNLOAD", Some(&query), 2);
+
+    // Invalid dates are ignored
+    let query = "before_date=yesterday";
+    assert_dl_count(&anon, "FOO_DOWNLOAD/1.0.0", Some(query), 2);
+}
+
+#[test]
+fn downloads_by_client() {
+    let (app, anon, user) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_clients", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .version(VersionBuilder::new("1.1.0"))
+            .expect_build(conn);
+    });
+
+    let download = |version: &str, user_agent: &str| {
+        let url = format!("/api/v1/crates/foo_clients/{version}/download");
+        let mut request = anon.get_request(&url);
+        request.header(header::USER_AGENT, user_agent);
+        let response = anon.run::<()>(request);
+        assert_eq!(response.status(), StatusCode::FOUND);
+    };
+
+    download("1.0.0", "cargo 1.58.0 (f01b232bc 2022-01-19)");
+    download("1.1.0", "cargo 1.58.1 (f01b232bc 2022-01-19)");
+    download("1.1.0", "cargo 1.60.0-nightly (95bb3c92b 2022-01-18)");
+    download(
+        "1.1.0",
+        "Mozilla/5.0 (X11; Linux x86_64; rv:96.0) Gecko/20100101 Firefox/96.0",
+    );
+    download("1.1.0", "curl/7.81.0");
+    persist_downloads_count(&app);
+
+    let json: Value = anon.get("/api/v1/crates/foo_clients/downloads").good();
+    let today = Utc::today().format("%F").to_string();
+    assert_eq!(
+        json["meta"]["client_downloads"],
+        json!([
+            { "date": today, "client": "browser", "downloads": 1 },
+            { "date": today, "client": "cargo", "cargo_version": "1.58", "downloads": 2 },
+            { "date": today, "client": "cargo", "cargo_version": "1.60", "downloads": 1 },
+            { "date": today, "client": "other", "downloads": 1 },
+        ])
+    );
+}
+
+#[test]
+fn downloads_are_not_counted_after_the_cdn_logs_cutover() {
+    use cargo_registry::config::DownloadsSource;
+
+    let (app, anon, user) = TestApp::init()
+        .with_config(|config| {
+            config.downloads_source = DownloadsSource::CdnLogs {
+                since: Utc::today().naive_utc(),
+            };
+        })
+        .with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("foo_cdn_logs", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(conn);
+    });
+
+    // The first request misses the version ID cache and the second one hits it.
+    for _ in 0..2 {
+        let response = anon.get::<()>("/api/v1/crates/foo_cdn_logs/1.0.0/download");
+        assert_eq!(response.status(), StatusCode::FOUND);
+    }
+    persist_downloads_count(&app);
+    assert_dl_count(&anon, "foo_cdn_logs/1.0.0", None, 0);
 }
 
 #[test]
@@ -177,3 +253,359 @@ fn download_caches_version_id() {
     // Check download count against the new name, rather than rename it back to the original value
     assert_dl_count(&anon, "other/1.0.0", None, 2);
 }
+
+#[test]
+fn archive_contains_counted_downloads() {
+    use cargo_registry::schema::{version_downloads, versions};
+    use diesel::prelude::*;
+
+    let (app, _, user) = TestApp::init().with_user();
+    let date = Utc::today().naive_utc() - Duration::days(100);
+
+    let rows = app.db(|conn| {
+        let krate = CrateBuilder::new("foo_archive", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .version(VersionBuilder::new("1.1.0"))
+            .expect_build(conn);
+        let versions: Vec<i32> = versions::table
+            .select(versions::id)
+            .filter(versions::crate_id.eq(krate.id))
+            .order(versions::id)
+            .load(conn)
+            .unwrap();
+
+        diesel::insert_into(version_downloads::table)
+            .values(&vec![
+                (
+                    version_downloads::version_id.eq(versions[0]),
+                    version_downloads::date.eq(date),
+                    version_downloads::downloads.eq(3),
+                    version_downloads::processed.eq(true),
+                ),
+                // Downloads that were not counted yet are not archived
+                (
+                    version_downloads::version_id.eq(versions[1]),
+                    version_downloads::date.eq(date),
+                    version_downloads::downloads.eq(5),
+                    version_downloads::processed.eq(false),
+                ),
+            ])
+            .execute(conn)
+            .unwrap();
+
+        load_day(conn, date).unwrap()
+    });
+
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].crate_name, "foo_archive");
+    assert_eq!(rows[0].version, "1.0.0");
+    assert_eq!(rows[0].downloads, 3);
+
+    let archive = encode_archive(date, &rows).unwrap();
+    let mut csv = String::new();
+    flate2::read::GzDecoder::new(archive.as_slice())
+        .read_to_string(&mut csv)
+        .unwrap();
+    let expected = format!(
+        "date,crate,version,version_id,downloads\n{date},foo_archive,1.0.0,{},3\n",
+        rows[0].version_id
+    );
+    assert_eq!(csv, expected);
+
+    let empty: &[ArchivedDownloads] = &[];
+    let archive = encode_archive(date, empty).unwrap();
+    assert!(!archive.is_empty());
+}
+
+#[test]
+fn list_archives() {
+    use cargo_registry::schema::version_downloads_archives;
+    use diesel::prelude::*;
+
+    #[derive(Deserialize)]
+    struct Archives {
+        archives: Vec<EncodableVersionDownloadsArchive>,
+    }
+
+    let (app, anon) = TestApp::init().empty();
+    let first = NaiveDate::from_ymd(2021, 10, 1);
+    app.db(|conn| {
+        let rows = (0..3)
+            .map(|days| {
+                (
+                    version_downloads_archives::date.eq(first + Duration::days(days)),
+                    version_downloads_archives::rows.eq(10),
+                    version_downloads_archives::downloads.eq(100),
+                )
+            })
+            .collect::<Vec<_>>();
+        diesel::insert_into(version_downloads_archives::table)
+            .values(&rows)
+            .execute(conn)
+            .unwrap();
+    });
+
+    let url = "/api/v1/downloads/archives";
+    let json: Archives = anon.get(url).good();
+    assert_eq!(json.archives.len(), 3);
+    assert_eq!(json.archives[0].date, "2021-10-01");
+    assert_eq!(json.archives[0].downloads, 100);
+    assert!(json.archives[0]
+        .url
+        .ends_with("/archive/version-downloads/2021-10-01.csv.gz"));
+
+    let json: Archives = anon
+        .get_with_query(url, "start=2021-10-02&end=2021-10-02")
+        .good();
+    assert_eq!(json.archives.len(), 1);
+    assert_eq!(json.archives[0].date, "2021-10-02");
+
+    let response = anon.get_with_query::<()>(url, "start=yesterday");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn aggregated_downloads() {
+    use cargo_registry::schema::{version_downloads, versions};
+    use diesel::prelude::*;
+
+    let (app, anon, user) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let krate = CrateBuilder::new("foo_aggregated", user.as_model().id)
+            .version(VersionBuilder::new("0.1.0"))
+            .version(VersionBuilder::new("1.0.0"))
+            .version(VersionBuilder::new("1.1.0"))
+            .expect_build(conn);
+        let versions: Vec<i32> = versions::table
+            .select(versions::id)
+            .filter(versions::crate_id.eq(krate.id))
+            .order(versions::id)
+            .load(conn)
+            .unwrap();
+
+        let rows = [
+            (versions[0], (2022, 1, 3), 1),
+            (versions[1], (2022, 1, 3), 2),
+            (versions[1], (2022, 1, 9), 3),
+            (versions[2], (2022, 1, 10), 4),
+            (versions[2], (2022, 2, 1), 5),
+        ]
+        .iter()
+        .map(|&(version_id, (y, m, d), downloads)| {
+            (
+                version_downloads::version_id.eq(version_id),
+                version_downloads::date.eq(NaiveDate::from_ymd(y, m, d)),
+                version_downloads::downloads.eq(downloads),
+            )
+        })
+        .collect::<Vec<_>>();
+        diesel::insert_into(version_downloads::table)
+            .values(&rows)
+            .execute(conn)
+            .unwrap();
+    });
+
+    let url = "/api/v1/crates/foo_aggregated/downloads";
+    let json: Value = anon
+        .get_with_query(url, "from=2022-01-01&to=2022-01-31")
+        .good();
+    assert_eq!(
+        json,
+        json!({
+            "downloads": [
+                { "date": "2022-01-03", "version": "0.1.0", "downloads": 1 },
+                { "date": "2022-01-03", "version": "1.0.0", "downloads": 2 },
+                { "date": "2022-01-09", "version": "1.0.0", "downloads": 3 },
+                { "date": "2022-01-10", "version": "1.1.0", "downloads": 4 },
+            ],
+            "meta": {
+                "from": "2022-01-01",
+                "to": "2022-01-31",
+                "interval": "day",
+                "group_by": "version",
+                "archived_until": null,
+            },
+        })
+    );
+
+    // Weeks start on Monday
+    let json: Value = anon
+        .get_with_query(url, "from=2022-01-01&to=2022-01-31&interval=week")
+        .good();
+    assert_eq!(
+        json["downloads"],
+        json!([
+            { "date": "2022-01-03", "version": "0.1.0", "downloads": 1 },
+            { "date": "2022-01-03", "version": "1.0.0", "downloads": 5 },
+            { "date": "2022-01-10", "version": "1.1.0", "downloads": 4 },
+        ])
+    );
+
+    let json: Value = anon
+        .get_with_query(
+            url,
+            "from=2022-01-01&to=2022-12-31&interval=month&group_by=major",
+        )
+        .good();
+    assert_eq!(
+        json["downloads"],
+        json!([
+            { "date": "2022-01-01", "version": "0.1", "downloads": 1 },
+            { "date": "2022-01-01", "version": "1", "downloads": 9 },
+            { "date": "2022-02-01", "version": "1", "downloads": 5 },
+        ])
+    );
+
+    let response = anon.get_with_query::<()>(
+        url,
+        "from=2022-01-01&to=2022-12-31&interval=year&group_by=minor&format=csv",
+    );
+    assert_eq!(response.status(), StatusCode::OK);
+    let (content_type, csv) = response.into_text();
+    assert_eq!(content_type, "text/csv; charset=utf-8");
+    let expected = "date,version,downloads\n\
+        2022-01-01,0.1,1\n\
+        2022-01-01,1.0,5\n\
+        2022-01-01,1.1,9\n";
+    assert_eq!(csv, expected);
+
+    // The version endpoint supports the same parameters
+    let json: Value = anon
+        .get_with_query(
+            "/api/v1/crates/foo_aggregated/1.1.0/downloads",
+            "from=2022-01-01&to=2022-12-31&interval=year",
+        )
+        .good();
+    assert_eq!(
+        json["downloads"],
+        json!([{ "date": "2022-01-01", "version": "1.1.0", "downloads": 9 }])
+    );
+
+    for query in [
+        "from=2022-02-01&to=2022-01-01",
+        "from=2021-01-01&to=2022-01-31",
+        "from=2015-01-01&to=2022-01-31&interval=year",
+        "interval=hour",
+        "group_by=patch",
+        "format=xml",
+        "to=tomorrow",
+    ] {
+        let response = anon.get_with_query::<()>(url, query);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{query}");
+    }
+}
+
+#[test]
+fn monthly_sums_of_archived_downloads() {
+    use cargo_registry::schema::{version_downloads, version_downloads_monthly, versions};
+    use diesel::prelude::*;
+
+    let (app, anon, user) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let krate = CrateBuilder::new("foo_monthly", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(conn);
+        let version_id: i32 = versions::table
+            .select(versions::id)
+            .filter(versions::crate_id.eq(krate.id))
+            .first(conn)
+            .unwrap();
+
+        // The first days of January were archived, the rest of the month was not yet
+        diesel::insert_into(version_downloads_monthly::table)
+            .values((
+                version_downloads_monthly::version_id.eq(version_id),
+                version_downloads_monthly::month.eq(NaiveDate::from_ymd(2022, 1, 1)),
+                version_downloads_monthly::downloads.eq(10),
+            ))
+            .execute(conn)
+            .unwrap();
+        diesel::insert_into(version_downloads::table)
+            .values((
+                version_downloads::version_id.eq(version_id),
+                version_downloads::date.eq(NaiveDate::from_ymd(2022, 1, 20)),
+                version_downloads::downloads.eq(2),
+            ))
+            .execute(conn)
+            .unwrap();
+    });
+
+    // Months are not split, so `from` is moved to the start of its month
+    let url = "/api/v1/crates/foo_monthly/downloads";
+    let json: Value = anon
+        .get_with_query(url, "from=2022-01-15&to=2022-03-31&interval=month")
+        .good();
+    assert_eq!(
+        json["downloads"],
+        json!([{ "date": "2022-01-01", "version": "1.0.0", "downloads": 12 }])
+    );
+    assert_eq!(json["meta"]["from"], "2022-01-01");
+
+    // Days and weeks only include the downloads that weren't archived yet
+    let json: Value = anon
+        .get_with_query(url, "from=2022-01-01&to=2022-01-31")
+        .good();
+    assert_eq!(
+        json["downloads"],
+        json!([{ "date": "2022-01-20", "version": "1.0.0", "downloads": 2 }])
+    );
+}
+
+#[test]
+fn daily_ranges_start_after_the_archived_days() {
+    use cargo_registry::schema::{version_downloads, version_downloads_archives, versions};
+    use diesel::prelude::*;
+
+    let (app, anon, user) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        let krate = CrateBuilder::new("foo_archived", user.as_model().id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(conn);
+        let version_id: i32 = versions::table
+            .select(versions::id)
+            .filter(versions::crate_id.eq(krate.id))
+            .first(conn)
+            .unwrap();
+
+        diesel::insert_into(version_downloads_archives::table)
+            .values((
+                version_downloads_archives::date.eq(NaiveDate::from_ymd(2022, 1, 10)),
+                version_downloads_archives::rows.eq(1),
+                version_downloads_archives::downloads.eq(1),
+            ))
+            .execute(conn)
+            .unwrap();
+        diesel::insert_into(version_downloads::table)
+            .values((
+                version_downloads::version_id.eq(version_id),
+                version_downloads::date.eq(NaiveDate::from_ymd(2022, 1, 20)),
+                version_downloads::downloads.eq(2),
+            ))
+            .execute(conn)
+            .unwrap();
+    });
+
+    let url = "/api/v1/crates/foo_archived/downloads";
+    let json: Value = anon
+        .get_with_query(url, "from=2022-01-01&to=2022-01-31")
+        .good();
+    assert_eq!(
+        json["downloads"],
+        json!([{ "date": "2022-01-20", "version": "1.0.0", "downloads": 2 }])
+    );
+    assert_eq!(json["meta"]["from"], "2022-01-11");
+    assert_eq!(json["meta"]["archived_until"], "2022-01-10");
+
+    let response = anon.get_with_query::<()>(url, "from=2022-01-01&to=2022-01-10&interval=week");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // Months and years include the monthly sums of the archived days
+    let json: Value = anon
+        .get_with_query(url, "from=2022-01-01&to=2022-01-31&interval=month")
+        .good();
+    assert_eq!(json["meta"]["from"], "2022-01-01");
+    assert_eq!(json["meta"]["archived_until"], Value::Null);
+}
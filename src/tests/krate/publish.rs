@@ -673,7 +673,7 @@ fn publish_records_an_audit_action() {
     assert_eq!(actions.len(), 1);
     let action = &actions[0];
     assert_eq!(action.action, "publish");
-    assert_eq!(action.user.id, token.as_model().user_id);
+    assert_eq!(action.user.as_ref().unwrap().id, token.as_model().user_id);
 }
 
 #[test]
@@ -878,6 +878,38 @@ fn license_and_description_required() {
     );
 }
 
+#[test]
+fn new_krate_with_rust_version() {
+    use cargo_registry::schema::versions;
+
+    let (app, _, _, token) = TestApp::full().with_token();
+
+    let crate_to_publish = PublishBuilder::new("foo_msrv").rust_version("1.56");
+    token.enqueue_publish(crate_to_publish).good();
+
+    let rust_version: Option<String> = app.db(|conn| {
+        versions::table
+            .select(versions::rust_version)
+            .first(conn)
+            .unwrap()
+    });
+    assert_eq!(rust_version.as_deref(), Some("1.56"));
+}
+
+#[test]
+fn new_krate_with_invalid_rust_version() {
+    let (_, _, _, token) = TestApp::init().with_token();
+
+    let crate_to_publish = PublishBuilder::new("foo_msrv").rust_version("1.56.0-nightly");
+
+    let response = token.enqueue_publish(crate_to_publish);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "invalid rust-version; expected a version like `1.56` or `1.56.1`" }] })
+    );
+}
+
 #[test]
 fn new_krate_tarball_with_hard_links() {
     let (_, _, _, token) = TestApp::init().with_token();
@@ -981,3 +1013,48 @@ fn features_version_2() {
     )]);
     assert_eq!(crates[0].features2, Some(features2));
 }
+
+#[test]
+fn publish_notifies_owners() {
+    let (app, _, _, token) = TestApp::full().with_token();
+
+    let crate_to_publish = PublishBuilder::new("foo_notify").version("1.0.0");
+    token.enqueue_publish(crate_to_publish).good();
+    app.run_pending_background_jobs();
+
+    let emails = app.as_inner().emails.mails_in_memory().unwrap();
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0].to, "something@example.com");
+    assert_eq!(
+        emails[0].subject,
+        "Crate foo_notify version 1.0.0 was published"
+    );
+    assert!(emails[0]
+        .body
+        .starts_with("foo published version 1.0.0 of the crate foo_notify"));
+    assert!(emails[0].body.contains("using the API token \"bar\""));
+}
+
+#[test]
+fn publish_notifications_can_be_disabled() {
+    use cargo_registry::schema::crate_owners;
+
+    let (app, _, _, token) = TestApp::full().with_token();
+
+    let crate_to_publish = PublishBuilder::new("foo_notify").version("1.0.0");
+    token.enqueue_publish(crate_to_publish).good();
+    app.run_pending_background_jobs();
+    assert_eq!(app.as_inner().emails.mails_in_memory().unwrap().len(), 1);
+
+    app.db(|conn| {
+        update(crate_owners::table)
+            .set(crate_owners::email_notifications.eq(false))
+            .execute(conn)
+            .unwrap();
+    });
+
+    let crate_to_publish = PublishBuilder::new("foo_notify").version("1.0.1");
+    token.enqueue_publish(crate_to_publish).good();
+    app.run_pending_background_jobs();
+    assert_eq!(app.as_inner().emails.mails_in_memory().unwrap().len(), 1);
+}
@@ -1,10 +1,11 @@
 use crate::builders::{CrateBuilder, VersionBuilder};
 use crate::util::{RequestHelper, TestApp};
-use crate::{new_category, new_user};
+use crate::{add_team_to_crate, new_category, new_team, new_user};
 use cargo_registry::models::Category;
 use cargo_registry::schema::crates;
 use diesel::{dsl::*, prelude::*, update};
 use http::StatusCode;
+use serde_json::Value;
 
 #[test]
 fn index() {
@@ -695,31 +696,31 @@ fn pagination_links_included_if_applicable() {
         CrateBuilder::new("pagination_links_3", user.id).expect_build(conn);
     });
 
-    // This uses a filter (`letter=p`) to disable seek-based pagination, as seek-based pagination
-    // does not return page numbers. If the test fails after expanding the scope of seek-based
-    // pagination replace the filter with something else still using pages.
+    // This uses a sort order (`sort=downloads`) to disable seek-based pagination, as seek-based
+    // pagination does not return page numbers. If the test fails after expanding the scope of
+    // seek-based pagination replace the sort order with something else still using pages.
 
-    let page1 = anon.search("letter=p&per_page=1");
-    let page2 = anon.search("letter=p&page=2&per_page=1");
-    let page3 = anon.search("letter=p&page=3&per_page=1");
-    let page4 = anon.search("letter=p&page=4&per_page=1");
+    let page1 = anon.search("sort=downloads&per_page=1");
+    let page2 = anon.search("sort=downloads&page=2&per_page=1");
+    let page3 = anon.search("sort=downloads&page=3&per_page=1");
+    let page4 = anon.search("sort=downloads&page=4&per_page=1");
 
     assert_eq!(
-        Some("?letter=p&per_page=1&page=2".to_string()),
+        Some("?sort=downloads&per_page=1&page=2".to_string()),
         page1.meta.next_page
     );
     assert_eq!(None, page1.meta.prev_page);
     assert_eq!(
-        Some("?letter=p&page=3&per_page=1".to_string()),
+        Some("?sort=downloads&page=3&per_page=1".to_string()),
         page2.meta.next_page
     );
     assert_eq!(
-        Some("?letter=p&page=1&per_page=1".to_string()),
+        Some("?sort=downloads&page=1&per_page=1".to_string()),
         page2.meta.prev_page
     );
     assert_eq!(None, page4.meta.next_page);
     assert_eq!(
-        Some("?letter=p&page=2&per_page=1".to_string()),
+        Some("?sort=downloads&page=2&per_page=1".to_string()),
         page3.meta.prev_page
     );
 }
@@ -772,6 +773,74 @@ fn seek_based_pagination() {
     );
 }
 
+#[test]
+fn seek_based_pagination_with_filters_and_query() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("seek_c", user.id)
+            .keyword("kw1")
+            .expect_build(conn);
+        CrateBuilder::new("seek_a", user.id)
+            .keyword("kw1")
+            .expect_build(conn);
+        CrateBuilder::new("seek_b", user.id).expect_build(conn);
+        CrateBuilder::new("seek_d", user.id)
+            .keyword("kw1")
+            .expect_build(conn);
+
+        CrateBuilder::new("web", user.id)
+            .description("web")
+            .expect_build(conn);
+        CrateBuilder::new("web_client", user.id)
+            .description("A web client")
+            .expect_build(conn);
+        CrateBuilder::new("web_server", user.id)
+            .description("A web server for the web, with web sockets")
+            .expect_build(conn);
+        CrateBuilder::new("web_sockets", user.id)
+            .description("Web sockets")
+            .expect_build(conn);
+    });
+
+    // Follows the `next_page` links, checking that all of them are seek-based
+    let walk = |query: &str| {
+        let mut url = Some(format!("?{query}&per_page=1"));
+        let mut results = Vec::new();
+        while let Some(current_url) = url.take() {
+            let resp = anon.search(current_url.trim_start_matches('?'));
+            results.extend(resp.crates.into_iter().map(|c| c.name));
+            url = resp.meta.next_page;
+            if let Some(url) = &url {
+                assert!(url.contains("seek="), "{url}");
+            }
+            assert_eq!(None, resp.meta.prev_page);
+        }
+        results
+    };
+
+    assert_eq!(walk("keyword=kw1"), ["seek_a", "seek_c", "seek_d"]);
+    assert_eq!(anon.search("keyword=kw1&per_page=1").meta.total, 3);
+
+    let all_pages = walk("q=web");
+    let first_page = anon.search("q=web&per_page=10");
+    assert_eq!(first_page.meta.total, 4);
+    assert_eq!(
+        all_pages,
+        first_page
+            .crates
+            .into_iter()
+            .map(|c| c.name)
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(all_pages[0], "web");
+
+    // Other sort orders still use pages
+    let resp = anon.search("q=web&sort=downloads&per_page=1");
+    assert!(resp.meta.next_page.unwrap().contains("page=2"));
+}
+
 #[test]
 fn test_pages_work_even_with_seek_based_pagination() {
     let (app, anon, user) = TestApp::init().with_user();
@@ -819,3 +888,349 @@ fn pagination_parameters_only_accept_integers() {
         json!({ "errors": [{ "detail": "invalid digit found in string" }] })
     );
 }
+
+#[test]
+fn index_sorting_by_scores() {
+    use cargo_registry::schema::crate_scores;
+
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        let scores = [
+            ("foo_score", 1.0, 3.0),
+            ("bar_score", 5.0, 0.5),
+            ("baz_score", -2.0, 1.0),
+        ];
+        for (name, trending, popularity) in scores {
+            let krate = CrateBuilder::new(name, user.id).expect_build(conn);
+            diesel::insert_into(crate_scores::table)
+                .values((
+                    crate_scores::crate_id.eq(krate.id),
+                    crate_scores::trending_score.eq(trending),
+                    crate_scores::popularity_score.eq(popularity),
+                ))
+                .execute(conn)
+                .unwrap();
+        }
+        // Crates without scores yet are sorted last
+        CrateBuilder::new("new_score", user.id).expect_build(conn);
+    });
+
+    let names = |query: &str| {
+        anon.search(query)
+            .crates
+            .into_iter()
+            .map(|c| c.name)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        names("sort=trending"),
+        ["bar_score", "foo_score", "baz_score", "new_score"]
+    );
+    assert_eq!(
+        names("sort=popularity"),
+        ["foo_score", "baz_score", "bar_score", "new_score"]
+    );
+}
+
+#[test]
+fn index_combined_filters_and_facets() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        new_category("Category 1", "cat1", "Category 1 crates")
+            .create_or_update(conn)
+            .unwrap();
+
+        CrateBuilder::new("facet_a", user.id)
+            .version(
+                VersionBuilder::new("1.0.0")
+                    .license(Some("MIT OR Apache-2.0"))
+                    .rust_version("1.56"),
+            )
+            .category("cat1")
+            .keyword("kw1")
+            .downloads(100)
+            .expect_build(conn);
+
+        CrateBuilder::new("facet_b", user.id)
+            .version(
+                VersionBuilder::new("1.0.0")
+                    .license(Some("MPL-2.0"))
+                    .rust_version("1.60"),
+            )
+            .keyword("kw1")
+            .downloads(10)
+            .expect_build(conn);
+
+        CrateBuilder::new("facet_c", user.id)
+            .version(VersionBuilder::new("1.0.0").license(Some("MIT/Apache-2.0")))
+            .version(VersionBuilder::new("2.0.0").yanked(true))
+            .downloads(1000)
+            .expect_build(conn);
+
+        let other = new_user("other")
+            .create_or_update(None, &app.as_inner().emails, conn)
+            .unwrap();
+        CrateBuilder::new("facet_other", other.id)
+            .version(VersionBuilder::new("1.0.0").license(Some("MIT")))
+            .expect_build(conn);
+    });
+
+    let names = |query: &str| {
+        anon.search(query)
+            .crates
+            .into_iter()
+            .map(|c| c.name)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names("license=mit"), ["facet_a", "facet_c", "facet_other"]);
+    assert_eq!(
+        names("license=mpl-2.0,apache-2.0&owner=foo"),
+        ["facet_a", "facet_b", "facet_c"]
+    );
+    // Versions without a `rust-version` are treated as compatible
+    assert_eq!(
+        names("license=mpl-2.0,apache-2.0&max_rust_version=1.58"),
+        ["facet_a", "facet_c"]
+    );
+    assert_eq!(
+        names("max_rust_version=1.60.0"),
+        ["facet_a", "facet_b", "facet_c", "facet_other"]
+    );
+    assert_eq!(
+        names("no_yanked_latest=yes&owner=FOO"),
+        ["facet_a", "facet_b"]
+    );
+    assert_eq!(names("min_downloads=50"), ["facet_a", "facet_c"]);
+    assert_eq!(names("keyword=kw1&min_downloads=50"), ["facet_a"]);
+    assert_eq!(names("owner=other"), ["facet_other"]);
+    assert_eq!(names("updated_since=2100-01-01").len(), 0);
+
+    let json: Value = anon
+        .get_with_query("/api/v1/crates", "keyword=kw1&include_facets=yes")
+        .good();
+    assert_eq!(
+        json["meta"]["facets"],
+        json!({
+            "categories": [{ "category": "cat1", "count": 1 }],
+            "keywords": [{ "keyword": "kw1", "count": 2 }],
+            "licenses": [
+                { "license": "MIT OR Apache-2.0", "count": 1 },
+                { "license": "MPL-2.0", "count": 1 },
+            ],
+        })
+    );
+    let json: Value = anon.get_with_query("/api/v1/crates", "keyword=kw1").good();
+    assert!(json["meta"].get("facets").is_none());
+
+    for query in [
+        "max_rust_version=1.56-beta",
+        "updated_since=yesterday",
+        "min_downloads=many",
+    ] {
+        let response = anon.get_with_query::<()>("/api/v1/crates", query);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{query}");
+    }
+}
+
+#[test]
+fn index_structured_query() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        new_category("Category 1", "cat1", "Category 1 crates")
+            .create_or_update(conn)
+            .unwrap();
+
+        CrateBuilder::new("query_a", user.id)
+            .description("A fast web server")
+            .version(
+                VersionBuilder::new("1.0.0")
+                    .license(Some("MIT"))
+                    .rust_version("1.56"),
+            )
+            .category("cat1")
+            .keyword("http")
+            .expect_build(conn);
+
+        CrateBuilder::new("query_b", user.id)
+            .description("A server for the web")
+            .version(
+                VersionBuilder::new("1.0.0")
+                    .license(Some("Apache-2.0"))
+                    .rust_version("1.60"),
+            )
+            .keyword("http")
+            .keyword("async")
+            .expect_build(conn);
+
+        let other = new_user("other")
+            .create_or_update(None, &app.as_inner().emails, conn)
+            .unwrap();
+        let krate = CrateBuilder::new("query_c", other.id)
+            .description("Blocking web client")
+            .expect_build(conn);
+        let team = new_team("github:some-org:core_team")
+            .create_or_update(conn)
+            .unwrap();
+        add_team_to_crate(&team, &krate, &other, conn).unwrap();
+    });
+
+    let names = |query: &str| {
+        anon.search(query)
+            .crates
+            .into_iter()
+            .map(|c| c.name)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(names("q=web"), ["query_a", "query_b", "query_c"]);
+    assert_eq!(names("q=%22web%20server%22"), ["query_a"]);
+    assert_eq!(names("q=web%20-blocking"), ["query_a", "query_b"]);
+    assert_eq!(names("q=keyword:HTTP%20-keyword:async"), ["query_a"]);
+    assert_eq!(names("q=web%20category:cat1"), ["query_a"]);
+    assert_eq!(names("q=-category:cat1"), ["query_b", "query_c"]);
+    assert_eq!(names("q=license:apache-2.0"), ["query_b"]);
+    assert_eq!(names("q=-license:mit%20keyword:http"), ["query_b"]);
+    assert_eq!(names("q=rust-version:1.58"), ["query_a"]);
+    assert_eq!(names("q=owner:foo"), ["query_a", "query_b"]);
+    assert_eq!(names("q=owner:some-org"), ["query_c"]);
+    assert_eq!(names("q=owner:github:some-org:core_team"), ["query_c"]);
+    // `_` and `%` in owners are matched literally
+    assert_eq!(names("q=owner:github:some-org:core%25").len(), 0);
+    assert_eq!(names("q=owner:some_org").len(), 0);
+    assert_eq!(names("q=web%20-owner:other"), ["query_a", "query_b"]);
+    // Words with colons that don't look like qualifiers are searched for
+    assert_eq!(names("q=std::io").len(), 0);
+
+    for (query, detail) in [
+        (
+            "q=author:foo",
+            "unknown search qualifier `author:`, expected one of `keyword`, `category`, \
+             `owner`, `license`, `rust-version`",
+        ),
+        (
+            "q=keyword:",
+            "missing value for the search qualifier `keyword:`",
+        ),
+        ("q=%22web", "unterminated quote in the search query"),
+        (
+            "q=rust-version:latest",
+            "invalid rust-version, expected a version like `1.56` or `1.56.1`",
+        ),
+    ] {
+        let response = anon.get_with_query::<()>("/api/v1/crates", query);
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{query}");
+        assert_eq!(
+            response.into_json(),
+            json!({ "errors": [{ "detail": detail }] })
+        );
+    }
+}
+
+#[test]
+fn index_fuzzy_fallback() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("tokio", user.id)
+            .description("An event-driven runtime")
+            .expect_build(conn);
+        CrateBuilder::new("tokio-util", user.id).expect_build(conn);
+        CrateBuilder::new("serde", user.id).expect_build(conn);
+    });
+
+    let json: Value = anon.get_with_query("/api/v1/crates", "q=tokoi").good();
+    let names = json["crates"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|krate| krate["name"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["tokio"]);
+    assert_eq!(json["meta"]["fuzzy"], json!(true));
+
+    // Nothing falls back to similar names when something matches
+    let json: Value = anon.get_with_query("/api/v1/crates", "q=runtime").good();
+    assert_eq!(json["crates"].as_array().unwrap().len(), 1);
+    assert!(json["meta"].get("fuzzy").is_none());
+
+    // Other filters still apply
+    assert_eq!(anon.search("q=tokoi&keyword=async").meta.total, 0);
+}
+
+#[test]
+fn suggest() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+
+    app.db(|conn| {
+        CrateBuilder::new("serde", user.id)
+            .description("A serialization framework")
+            .downloads(1000)
+            .expect_build(conn);
+        CrateBuilder::new("serde_json", user.id)
+            .downloads(500)
+            .expect_build(conn);
+        CrateBuilder::new("serde-xml", user.id)
+            .downloads(10)
+            .expect_build(conn);
+        CrateBuilder::new("serdeless", user.id).expect_build(conn);
+        CrateBuilder::new("tokio", user.id).expect_build(conn);
+    });
+
+    let names = |query: &str| {
+        let json: Value = anon
+            .get_with_query("/api/v1/crate_suggestions", query)
+            .good();
+        json["crates"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|krate| krate["name"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        names("q=ser"),
+        ["serde", "serde_json", "serde-xml", "serdeless"]
+    );
+    assert_eq!(names("q=SERDE-&limit=1"), ["serde_json"]);
+    assert_eq!(names("q=serde_x"), ["serde-xml"]);
+    // Typos fall back to similar names
+    assert_eq!(names("q=tokoi"), ["tokio"]);
+    assert_eq!(names("q=").len(), 0);
+    assert_eq!(names("q=a%20b").len(), 0);
+
+    let json: Value = anon
+        .get_with_query("/api/v1/crate_suggestions", "q=serde&limit=1")
+        .good();
+    assert_eq!(
+        json,
+        json!({
+            "crates": [{
+                "name": "serde",
+                "description": "A serialization framework",
+                "downloads": 1000,
+            }]
+        })
+    );
+
+    let response = anon.get_with_query::<()>("/api/v1/crate_suggestions", "q=serde&limit=100");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn suggestions_do_not_hide_crates() {
+    let (app, anon, user) = TestApp::init().with_user();
+
+    app.db(|conn| {
+        CrateBuilder::new("suggest", user.as_model().id).expect_build(conn);
+    });
+
+    let json: Value = anon.get("/api/v1/crates/suggest").good();
+    assert_eq!(json["crate"]["name"], "suggest");
+}
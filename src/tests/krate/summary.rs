@@ -14,6 +14,8 @@ struct SummaryResponse {
     most_downloaded: Vec<EncodableCrate>,
     most_recently_downloaded: Vec<EncodableCrate>,
     just_updated: Vec<EncodableCrate>,
+    trending: Vec<EncodableCrate>,
+    most_popular: Vec<EncodableCrate>,
     popular_keywords: Vec<EncodableKeyword>,
     popular_categories: Vec<EncodableCategory>,
 }
@@ -107,3 +109,59 @@ fn summary_new_crates() {
 
     assert_eq!(json.new_crates.len(), 5);
 }
+
+#[test]
+fn summary_trending_and_most_popular() {
+    use cargo_registry::schema::{version_downloads, versions};
+    use cargo_registry::worker::crate_scores::update_scores;
+    use chrono::Duration;
+    use diesel::QueryDsl;
+
+    let (app, anon, user) = TestApp::init().with_user();
+    let user = user.as_model();
+    app.db(|conn| {
+        let library = CrateBuilder::new("library", user.id)
+            .version(VersionBuilder::new("1.0.0"))
+            .expect_build(conn);
+        let trending = CrateBuilder::new("trending", user.id)
+            .version(VersionBuilder::new("0.1.0").dependency(&library, None))
+            .expect_build(conn);
+        let steady = CrateBuilder::new("steady", user.id)
+            .version(VersionBuilder::new("0.1.0").dependency(&library, None))
+            .expect_build(conn);
+
+        // `trending` went from 100 to 500 weekly downloads, `steady` kept its 100
+        let today = Utc::today().naive_utc();
+        for (krate, recent, previous) in [(&trending, 500, 400), (&steady, 100, 400)] {
+            let version_id: i32 = versions::table
+                .select(versions::id)
+                .filter(versions::crate_id.eq(krate.id))
+                .first(conn)
+                .unwrap();
+            diesel::insert_into(version_downloads::table)
+                .values(&vec![
+                    (
+                        version_downloads::version_id.eq(version_id),
+                        version_downloads::date.eq(today - Duration::days(2)),
+                        version_downloads::downloads.eq(recent),
+                    ),
+                    (
+                        version_downloads::version_id.eq(version_id),
+                        version_downloads::date.eq(today - Duration::days(20)),
+                        version_downloads::downloads.eq(previous),
+                    ),
+                ])
+                .execute(conn)
+                .unwrap();
+        }
+
+        assert_eq!(update_scores(conn).unwrap(), 3);
+    });
+
+    let json: SummaryResponse = anon.get("/api/v1/summary").good();
+    let names =
+        |crates: &[EncodableCrate]| crates.iter().map(|c| c.name.clone()).collect::<Vec<_>>();
+    assert_eq!(names(&json.trending), ["trending"]);
+    assert_eq!(names(&json.most_popular)[0], "library");
+    assert_eq!(json.most_popular.len(), 3);
+}
@@ -220,7 +220,7 @@ fn yank_records_an_audit_action() {
     assert_eq!(actions.len(), 2);
     let action = &actions[1];
     assert_eq!(action.action, "yank");
-    assert_eq!(action.user.id, token.as_model().user_id);
+    assert_eq!(action.user.as_ref().unwrap().id, token.as_model().user_id);
 }
 
 #[test]
@@ -244,5 +244,5 @@ fn unyank_records_an_audit_action() {
     assert_eq!(actions.len(), 3);
     let action = &actions[2];
     assert_eq!(action.action, "unyank");
-    assert_eq!(action.user.id, token.as_model().user_id);
+    assert_eq!(action.user.as_ref().unwrap().id, token.as_model().user_id);
 }
@@ -0,0 +1,226 @@
+use crate::{
+    builders::CrateBuilder,
+    user::{UserShowPrivateResponse, UserShowPublicResponse},
+    util::{encode_session, FakeLoginProvider, MockAnonymousUser, MockCookieUser, Response},
+    OkBool, OwnerTeamsResponse, RequestHelper, TestApp,
+};
+use cargo_registry::{
+    models::{AccountProvider, LinkedAccount, User},
+    schema::users,
+};
+use std::collections::HashMap;
+
+use conduit::{header, Method, StatusCode};
+use diesel::prelude::*;
+
+#[derive(Deserialize)]
+struct AuthResponse {
+    url: String,
+    state: String,
+}
+
+impl MockAnonymousUser {
+    /// Completes the OAuth flow of the provider, as if the session was started by the
+    /// `begin` route.
+    fn authorize_with(&self, provider: &str) -> Response<UserShowPrivateResponse> {
+        let mut session = HashMap::new();
+        session.insert(format!("{provider}_oauth_state"), "some-state".to_string());
+        let cookie = encode_session(self.app().as_inner().session_key(), &session);
+
+        let url = format!("/api/private/session/{provider}/authorize");
+        let mut request = self.request_builder(Method::GET, &url);
+        request.header(header::COOKIE, &cookie);
+        request.with_query("code=some-code&state=some-state");
+        self.run(request)
+    }
+}
+
+fn find_user(app: &TestApp, login: &str) -> User {
+    app.db(|conn| {
+        users::table
+            .filter(users::gh_login.eq(login))
+            .first(conn)
+            .unwrap()
+    })
+}
+
+#[test]
+fn begin_returns_provider_authorize_url() {
+    let provider = FakeLoginProvider::start();
+    let (_, anon) = TestApp::init().with_fake_login_provider(&provider).empty();
+
+    let json: AuthResponse = anon.get("/api/private/session/gitlab/begin").good();
+    assert!(json
+        .url
+        .starts_with(&format!("{}/oauth/authorize", provider.url())));
+    assert!(json.url.contains(&json.state));
+
+    let json: AuthResponse = anon.get("/api/private/session/oidc/begin").good();
+    assert!(json
+        .url
+        .starts_with(&format!("{}/authorize", provider.url())));
+    assert!(json.url.contains(&json.state));
+}
+
+#[test]
+fn unconfigured_providers_are_not_found() {
+    let (_, anon) = TestApp::init().empty();
+    anon.get::<()>("/api/private/session/gitlab/begin")
+        .assert_not_found();
+    anon.get::<()>("/api/private/session/unknown/begin")
+        .assert_not_found();
+}
+
+#[test]
+fn authorize_requires_matching_state() {
+    let provider = FakeLoginProvider::start();
+    let (_, anon) = TestApp::init().with_fake_login_provider(&provider).empty();
+
+    let response = anon.get_with_query::<()>(
+        "/api/private/session/gitlab/authorize",
+        "code=some-code&state=some-state",
+    );
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "invalid state parameter" }] })
+    );
+}
+
+#[test]
+fn gitlab_login_creates_user() {
+    let provider = FakeLoginProvider::start();
+    let (app, anon) = TestApp::init().with_fake_login_provider(&provider).empty();
+    provider.set_account(42, "gitlabber", Some("gitlabber@example.com"));
+
+    let json = anon.authorize_with("gitlab").good();
+    assert_eq!(json.user.login, "gitlabber@gitlab");
+    assert_eq!(json.user.email.as_deref(), Some("gitlabber@example.com"));
+
+    let user = find_user(&app, "gitlabber@gitlab");
+    assert_eq!(user.gh_id, 0);
+    let account = app.db(|conn| {
+        LinkedAccount::find_by_user(conn, &user, AccountProvider::GitLab)
+            .unwrap()
+            .unwrap()
+    });
+    assert_eq!(account.account_id, "42");
+    assert_eq!(account.login, "gitlabber");
+
+    // Logging in again uses the same account
+    let json = anon.authorize_with("gitlab").good();
+    assert_eq!(json.user.id, user.id);
+}
+
+#[test]
+fn oidc_login_links_user_with_verified_email() {
+    let provider = FakeLoginProvider::start();
+    let (app, anon) = TestApp::init().with_fake_login_provider(&provider).empty();
+    let user = app.db_new_user("foo");
+    provider.set_account(7, "someone", Some("Something@Example.com"));
+
+    let json = anon.authorize_with("oidc").good();
+    assert_eq!(json.user.id, user.as_model().id);
+    assert_eq!(json.user.login, "foo");
+
+    let account = app.db(|conn| {
+        LinkedAccount::find_by_user(conn, user.as_model(), AccountProvider::Oidc)
+            .unwrap()
+            .unwrap()
+    });
+    assert_eq!(account.account_id, "7");
+}
+
+#[test]
+fn unconfirmed_email_is_not_linked() {
+    let provider = FakeLoginProvider::start();
+    let (app, anon) = TestApp::init().with_fake_login_provider(&provider).empty();
+    let user = app.db_new_user("foo");
+    provider.set_unverified_account(7, "someone", "something@example.com");
+
+    for provider_name in ["gitlab", "oidc"] {
+        let json = anon.authorize_with(provider_name).good();
+        assert_ne!(json.user.id, user.as_model().id);
+    }
+}
+
+#[test]
+fn provider_logins_do_not_collide_with_github_logins() {
+    let provider = FakeLoginProvider::start();
+    let (app, anon) = TestApp::init().with_fake_login_provider(&provider).empty();
+    let github_user = app.db_new_user("foo");
+    provider.set_account(7, "foo", None);
+
+    let json = anon.authorize_with("gitlab").good();
+    assert_eq!(json.user.login, "foo@gitlab");
+    assert_ne!(json.user.id, github_user.as_model().id);
+
+    let json: UserShowPublicResponse = anon.get("/api/v1/users/foo").good();
+    assert_eq!(json.user.id, github_user.as_model().id);
+    let json: UserShowPublicResponse = anon.get("/api/v1/users/foo@gitlab").good();
+    assert_ne!(json.user.id, github_user.as_model().id);
+}
+
+#[test]
+fn login_with_taken_username_is_rejected() {
+    let provider = FakeLoginProvider::start();
+    let (_, anon) = TestApp::init().with_fake_login_provider(&provider).empty();
+    provider.set_account(7, "foo", None);
+    anon.authorize_with("oidc").good();
+
+    // OpenID Connect providers don't guarantee that usernames are unique
+    provider.set_account(8, "foo", None);
+    let response = anon.authorize_with("oidc");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(response.into_json()["errors"][0]["detail"]
+        .as_str()
+        .unwrap()
+        .contains("the login `foo@oidc` is already used"));
+}
+
+#[test]
+fn gitlab_group_can_be_added_as_owner() {
+    let provider = FakeLoginProvider::start();
+    let (app, anon) = TestApp::init().with_fake_login_provider(&provider).empty();
+    provider.set_account(42, "gitlabber", None);
+    provider.add_group(300, "acme/core");
+    provider.add_group_member(300, 42);
+
+    anon.authorize_with("gitlab").good();
+    let user = MockCookieUser::new(&app, find_user(&app, "gitlabber@gitlab"));
+    let token = user.db_new_token("bar");
+    app.db(|conn| {
+        CrateBuilder::new("foo_gitlab_group", user.as_model().id).expect_build(conn);
+    });
+
+    let response = token.add_named_owner("foo_gitlab_group", "gitlab:acme:core");
+    assert!(response.good().ok);
+
+    let json: OwnerTeamsResponse = anon
+        .get("/api/v1/crates/foo_gitlab_group/owner_team")
+        .good();
+    assert_eq!(json.teams.len(), 1);
+    assert_eq!(json.teams[0].login, "gitlab:acme:core");
+}
+
+#[test]
+fn gitlab_group_requires_membership() {
+    let provider = FakeLoginProvider::start();
+    let (app, anon) = TestApp::init().with_fake_login_provider(&provider).empty();
+    provider.set_account(42, "gitlabber", None);
+    provider.add_group(300, "acme/core");
+
+    anon.authorize_with("gitlab").good();
+    let user = MockCookieUser::new(&app, find_user(&app, "gitlabber@gitlab"));
+    let token = user.db_new_token("bar");
+    app.db(|conn| {
+        CrateBuilder::new("foo_gitlab_group", user.as_model().id).expect_build(conn);
+    });
+
+    let response: Response<OkBool> = token.add_named_owner("foo_gitlab_group", "gitlab:acme:core");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "only members of a group can add it as an owner" }] })
+    );
+}
@@ -1,22 +1,25 @@
 use crate::{
     add_team_to_crate,
     builders::{CrateBuilder, PublishBuilder},
-    new_team,
+    new_team, new_user,
     util::{MockAnonymousUser, MockCookieUser, MockTokenUser, RequestHelper, Response},
-    TestApp,
+    OkBool, TestApp,
 };
 use cargo_registry::{
-    models::Crate,
+    controllers::krate::publish::MISSING_RIGHTS_ERROR_MESSAGE,
+    email::StoredEmail,
+    models::{Crate, CrateOwnerInvitation, User},
     views::{
         EncodableCrateOwnerInvitation, EncodableCrateOwnerInvitationV1, EncodableOwner,
         EncodablePublicUser, InvitationResponse,
     },
-    Emails,
+    worker,
 };
 
 use chrono::{Duration, Utc};
 use conduit::StatusCode;
 use diesel::prelude::*;
+use swirl::Job;
 
 #[derive(Deserialize)]
 struct TeamResponse {
@@ -263,6 +266,171 @@ fn modify_multiple_owners() {
     assert_eq!(app.db(|conn| krate.owners(conn).unwrap()).len(), 3);
 }
 
+impl MockTokenUser {
+    /// Add the specified owner to the specified crate, granting it the specified rights.
+    fn add_named_owner_with_rights(
+        &self,
+        krate_name: &str,
+        owner: &str,
+        rights: &str,
+    ) -> Response<OkBool> {
+        let url = format!("/api/v1/crates/{krate_name}/owners");
+        let body = json!({ "owners": [owner], "rights": rights }).to_string();
+        self.put(&url, body.as_bytes())
+    }
+}
+
+#[test]
+fn owners_with_publish_rights_cannot_modify_owners() {
+    let (app, _, user, token) = TestApp::init().with_token();
+    let krate = app.db(|conn| {
+        CrateBuilder::new("owners_publish_rights", user.as_model().id).expect_build(conn)
+    });
+
+    let user2 = app.db_new_user("user2");
+    token
+        .add_named_owner_with_rights("owners_publish_rights", "user2", "publish")
+        .good();
+    user2.accept_ownership_invitation(&krate.name, krate.id);
+
+    let response = user2
+        .db_new_token("bar")
+        .add_named_owner("owners_publish_rights", "user3");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "only owners with full rights have permission to modify owners" }] })
+    );
+}
+
+#[test]
+fn owners_with_yank_rights_cannot_publish() {
+    let (app, _, user, token) = TestApp::init().with_token();
+    let krate = app.db(|conn| {
+        CrateBuilder::new("owners_yank_rights", user.as_model().id)
+            .version("1.0.0")
+            .expect_build(conn)
+    });
+
+    let user2 = app.db_new_user("user2");
+    token
+        .add_named_owner_with_rights("owners_yank_rights", "user2", "yank")
+        .good();
+    user2.accept_ownership_invitation(&krate.name, krate.id);
+
+    let crate_to_publish = PublishBuilder::new("owners_yank_rights").version("2.0.0");
+    let response = user2.db_new_token("bar").enqueue_publish(crate_to_publish);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": MISSING_RIGHTS_ERROR_MESSAGE }] })
+    );
+}
+
+#[test]
+fn change_rights_of_existing_owner() {
+    let (app, _, user, token) = TestApp::init().with_token();
+    let username = &user.as_model().gh_login;
+    let krate = app.db(|conn| {
+        CrateBuilder::new("owners_change_rights", user.as_model().id).expect_build(conn)
+    });
+    let user2 = create_and_add_owner(&app, &token, "user2", &krate);
+    let user2_token = user2.db_new_token("bar");
+
+    let response = token.add_named_owner_with_rights("owners_change_rights", "user2", "publish");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.into_json(),
+        json!({ "msg": "user2 now has publish rights on crate owners_change_rights", "ok": true })
+    );
+
+    let response = user2_token.add_named_owner("owners_change_rights", "user3");
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "only owners with full rights have permission to modify owners" }] })
+    );
+
+    // The last individual owner with full rights can't give them up.
+    let response = token.add_named_owner_with_rights("owners_change_rights", username, "yank");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "cannot remove full rights from all individual owners of a crate. At least one individual owner with full rights is required." }] })
+    );
+
+    token
+        .add_named_owner_with_rights("owners_change_rights", "user2", "full")
+        .good();
+    user2_token
+        .add_named_owner_with_rights("owners_change_rights", username, "yank")
+        .good();
+}
+
+#[test]
+fn owners_cannot_be_granted_no_rights() {
+    let (app, _, user, token) = TestApp::init().with_token();
+    app.db(|conn| CrateBuilder::new("owners_no_rights", user.as_model().id).expect_build(conn));
+    app.db_new_user("user2");
+
+    let response = token.add_named_owner_with_rights("owners_no_rights", "user2", "none");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "owners must be granted at least `yank` rights" }] })
+    );
+}
+
+#[test]
+fn modifying_owners_notifies_owners() {
+    let (app, _, owner, owner_token) = TestApp::init().with_token();
+    let krate =
+        app.db(|conn| CrateBuilder::new("crate_name", owner.as_model().id).expect_build(conn));
+    let second_owner = create_and_add_owner(&app, &owner_token, "second_owner", &krate);
+    second_owner
+        .db_new_token("second_token")
+        .remove_named_owner("crate_name", "foo")
+        .good();
+
+    // Failed changes don't send notifications
+    let response = owner_token.add_named_owner("crate_name", "second_owner");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "only owners have permission to modify owners" }] })
+    );
+
+    app.run_pending_background_jobs();
+    let emails = app.as_inner().emails.mails_in_memory().unwrap();
+    let notices = emails
+        .iter()
+        .filter(|email| email.subject == "The owners of the crate crate_name were changed")
+        .collect::<Vec<_>>();
+
+    // Inviting the second owner notifies the first one, removing the first one notifies both
+    assert_eq!(notices.len(), 3);
+    assert!(notices[0].body.starts_with(
+        "foo changed the owners of the crate crate_name:\n\
+         - user second_owner has been invited to be an owner of crate crate_name\n"
+    ));
+    assert!(notices[1..].iter().all(|email| email.body.starts_with(
+        "second_owner changed the owners of the crate crate_name:\n- foo was removed as an owner\n"
+    )));
+    assert!(notices[0].body.contains("User agent: conduit-test"));
+}
+
+/// Returns the emails about ownership invitations sent so far, ignoring notifications about
+/// owner changes sent to the existing owners.
+fn invitation_emails(app: &TestApp) -> Vec<StoredEmail> {
+    app.run_pending_background_jobs();
+    app.as_inner()
+        .emails
+        .mails_in_memory()
+        .unwrap()
+        .into_iter()
+        .filter(|email| email.subject.starts_with("Crate ownership invitation"))
+        .collect()
+}
+
 #[test]
 fn invite_already_invited_user() {
     let (app, _, _, owner) = TestApp::init().with_token();
@@ -284,7 +452,7 @@ fn invite_already_invited_user() {
     );
 
     // Check one email was sent, this will be the ownership invite email
-    assert_eq!(1, app.as_inner().emails.mails_in_memory().unwrap().len());
+    assert_eq!(1, invitation_emails(&app).len());
 
     // Then invite the user a second time, the message should point out the user is already invited
     let response = owner.add_named_owner("crate_name", "invited_user");
@@ -298,7 +466,7 @@ fn invite_already_invited_user() {
     );
 
     // Check that no new email is sent after the second invitation
-    assert_eq!(1, app.as_inner().emails.mails_in_memory().unwrap().len());
+    assert_eq!(1, invitation_emails(&app).len());
 }
 
 #[test]
@@ -323,7 +491,7 @@ fn invite_with_existing_expired_invite() {
     );
 
     // Check one email was sent, this will be the ownership invite email
-    assert_eq!(1, app.as_inner().emails.mails_in_memory().unwrap().len());
+    assert_eq!(1, invitation_emails(&app).len());
 
     // Simulate the previous invite expiring
     expire_invitation(&app, krate.id);
@@ -340,7 +508,238 @@ fn invite_with_existing_expired_invite() {
     );
 
     // Check that the email for the second invite was sent
-    assert_eq!(2, app.as_inner().emails.mails_in_memory().unwrap().len());
+    assert_eq!(2, invitation_emails(&app).len());
+}
+
+/// Creates the user as if they just logged in for the first time, and returns the number of
+/// invitations that were created from pending ones.
+fn sign_up(app: &TestApp, login: &str) -> (MockCookieUser, usize) {
+    use cargo_registry::schema::emails;
+
+    let user: User = app.db(|conn| {
+        let user = new_user(login)
+            .create_or_update(None, &app.as_inner().emails, conn)
+            .unwrap();
+        diesel::insert_into(emails::table)
+            .values((
+                emails::user_id.eq(user.id),
+                emails::email.eq(format!("{login}@example.com")),
+                emails::verified.eq(true),
+                emails::is_primary.eq(true),
+            ))
+            .execute(conn)
+            .unwrap();
+        user
+    });
+    let created = app.db(|conn| {
+        let app = app.as_inner();
+        CrateOwnerInvitation::create_from_pending(&user, conn, &app.config, &app.emails).unwrap()
+    });
+    (MockCookieUser::new(app, user), created)
+}
+
+fn expire_pending_invitation(app: &TestApp, crate_id: i32) {
+    use cargo_registry::schema::pending_crate_owner_invitations;
+
+    app.db(|conn| {
+        let expiration = app.as_inner().config.ownership_invitations_expiration_days as i64;
+        let created_at = (Utc::now() - Duration::days(expiration)).naive_utc();
+
+        diesel::update(pending_crate_owner_invitations::table)
+            .set(pending_crate_owner_invitations::created_at.eq(created_at))
+            .filter(pending_crate_owner_invitations::crate_id.eq(crate_id))
+            .execute(conn)
+            .expect("failed to override the creation time");
+    });
+}
+
+#[test]
+fn invite_user_without_account() {
+    let (app, _, _, owner) = TestApp::init().with_token();
+    let krate =
+        app.db(|conn| CrateBuilder::new("crate_name", owner.as_model().user_id).expect_build(conn));
+
+    let response = owner.add_named_owner("crate_name", "New-User");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.into_json(),
+        json!({
+            "msg": "user New-User does not have a crates.io account yet, they will be invited to be an owner of crate crate_name when they sign up",
+            "ok": true,
+        })
+    );
+
+    // Inviting the same login again doesn't create a second invitation
+    let response = owner.add_named_owner("crate_name", "new-user");
+    assert_eq!(
+        response.into_json(),
+        json!({
+            "msg": "user new-user already has a pending invitation to be an owner of crate crate_name",
+            "ok": true,
+        })
+    );
+
+    // There is nobody to send an email to yet
+    assert_eq!(0, invitation_emails(&app).len());
+
+    let (user, created) = sign_up(&app, "new-user");
+    assert_eq!(created, 1);
+
+    // The invitation is emailed once it's created
+    let sent = invitation_emails(&app);
+    assert_eq!(1, sent.len());
+    assert_eq!(sent[0].to, "new-user@example.com");
+
+    let invitations = user.list_invitations().crate_owner_invitations;
+    assert_eq!(invitations.len(), 1);
+    assert_eq!(invitations[0].crate_name, "crate_name");
+    assert_eq!(invitations[0].inviter_id, owner.as_model().user_id);
+
+    user.accept_ownership_invitation("crate_name", krate.id);
+
+    // Logging in again doesn't recreate the invitation
+    let created = app.db(|conn| {
+        let app = app.as_inner();
+        CrateOwnerInvitation::create_from_pending(user.as_model(), conn, &app.config, &app.emails)
+            .unwrap()
+    });
+    assert_eq!(created, 0);
+}
+
+#[test]
+fn expired_pending_invitation_is_discarded() {
+    let (app, _, _, owner) = TestApp::init().with_token();
+    let krate =
+        app.db(|conn| CrateBuilder::new("crate_name", owner.as_model().user_id).expect_build(conn));
+
+    owner.add_named_owner("crate_name", "new-user").good();
+    expire_pending_invitation(&app, krate.id);
+
+    let (user, created) = sign_up(&app, "new-user");
+    assert_eq!(created, 0);
+    assert!(user.list_invitations().crate_owner_invitations.is_empty());
+}
+
+#[test]
+fn remove_pending_invitation() {
+    let (app, _, _, owner) = TestApp::init().with_token();
+    app.db(|conn| CrateBuilder::new("crate_name", owner.as_model().user_id).expect_build(conn));
+
+    owner.add_named_owner("crate_name", "new-user").good();
+    owner.remove_named_owner("crate_name", "new-user").good();
+
+    let (_, created) = sign_up(&app, "new-user");
+    assert_eq!(created, 0);
+}
+
+#[test]
+fn invite_invalid_github_login() {
+    let (app, _, _, owner) = TestApp::init().with_token();
+    app.db(|conn| CrateBuilder::new("crate_name", owner.as_model().user_id).expect_build(conn));
+
+    let response = owner.add_named_owner("crate_name", "not_a_github_login");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "could not find user with login `not_a_github_login`" }] })
+    );
+}
+
+fn run_expire_ownership_invitations(app: &TestApp) {
+    let expiration_days = app.as_inner().config.ownership_invitations_expiration_days as i64;
+    app.db(|conn| {
+        worker::expire_ownership_invitations(expiration_days, 3)
+            .enqueue(conn)
+            .unwrap();
+    });
+    app.run_pending_background_jobs();
+}
+
+#[test]
+fn expired_invitations_are_deleted() {
+    let (app, _, owner, owner_token) = TestApp::full().with_token();
+    let invited_user = app.db_new_user("invited_user");
+    let krate =
+        app.db(|conn| CrateBuilder::new("crate_name", owner.as_model().id).expect_build(conn));
+
+    owner_token.add_user_owner("crate_name", "invited_user");
+    owner_token.add_named_owner("crate_name", "new-user").good();
+    expire_invitation(&app, krate.id);
+    expire_pending_invitation(&app, krate.id);
+
+    run_expire_ownership_invitations(&app);
+
+    let invitations: i64 = app.db(|conn| {
+        use cargo_registry::schema::crate_owner_invitations;
+        crate_owner_invitations::table
+            .count()
+            .get_result(conn)
+            .unwrap()
+    });
+    assert_eq!(invitations, 0);
+    let (_, created) = sign_up(&app, "new-user");
+    assert_eq!(created, 0);
+    assert!(invited_user
+        .list_invitations()
+        .crate_owner_invitations
+        .is_empty());
+
+    // The inviter is told about both lapsed invitations
+    let emails = app.as_inner().emails.mails_in_memory().unwrap();
+    let expired = emails
+        .iter()
+        .filter(|email| email.subject == "Crate ownership invitation expired")
+        .collect::<Vec<_>>();
+    assert_eq!(expired.len(), 2);
+    assert!(expired
+        .iter()
+        .all(|email| email.to == "something@example.com"));
+    assert!(expired
+        .iter()
+        .any(|email| email.body.contains("invited_user")));
+    assert!(expired.iter().any(|email| email.body.contains("new-user")));
+}
+
+#[test]
+fn invitees_are_reminded_before_expiry() {
+    use cargo_registry::schema::crate_owner_invitations;
+
+    let (app, _, owner, owner_token) = TestApp::full().with_token();
+    let invited_user = app.db_new_user("invited_user");
+    app.db(|conn| CrateBuilder::new("crate_name", owner.as_model().id).expect_build(conn));
+    owner_token.add_user_owner("crate_name", "invited_user");
+
+    // Invitations that don't expire soon are left alone
+    run_expire_ownership_invitations(&app);
+    assert_eq!(invitation_emails(&app).len(), 1);
+
+    // Simulate the invitation expiring in two days
+    app.db(|conn| {
+        let expiration = app.as_inner().config.ownership_invitations_expiration_days as i64;
+        let created_at = (Utc::now() - Duration::days(expiration - 2)).naive_utc();
+        diesel::update(crate_owner_invitations::table)
+            .set(crate_owner_invitations::created_at.eq(created_at))
+            .execute(conn)
+            .unwrap();
+    });
+
+    run_expire_ownership_invitations(&app);
+    let emails = invitation_emails(&app);
+    assert_eq!(emails.len(), 2);
+    assert_eq!(emails[1].subject, "Crate ownership invitation expires soon");
+    assert_eq!(emails[1].to, "something@example.com");
+    assert!(emails[1].body.contains("crate_name"));
+
+    // The reminder is only sent once, and the invitation can still be accepted
+    run_expire_ownership_invitations(&app);
+    assert_eq!(invitation_emails(&app).len(), 2);
+    assert_eq!(
+        invited_user
+            .list_invitations()
+            .crate_owner_invitations
+            .len(),
+        1
+    );
 }
 
 /*  Testing the crate ownership between two crates and one team.
@@ -575,7 +974,7 @@ fn test_accept_invitation_by_mail() {
     owner_token.add_user_owner("accept_invitation", "user_bar");
 
     // Retrieve the ownership invitation
-    let invite_token = extract_token_from_invite_email(&app.as_inner().emails);
+    let invite_token = extract_token_from_invite_email(&app);
 
     // Accept the invitation anonymously with a token
     anon.accept_ownership_invitation_by_token(&invite_token);
@@ -674,7 +1073,7 @@ fn test_accept_expired_invitation_by_mail() {
     expire_invitation(&app, krate.id);
 
     // Retrieve the ownership invitation
-    let invite_token = extract_token_from_invite_email(&app.as_inner().emails);
+    let invite_token = extract_token_from_invite_email(&app);
 
     // Try to accept the invitation, and ensure it fails.
     let resp = anon.try_accept_ownership_invitation_by_token::<()>(&invite_token);
@@ -753,8 +1152,11 @@ fn highest_gh_id_is_most_recent_account_we_know_of() {
     assert_eq!(json.crate_owner_invitations.len(), 1);
 }
 
-fn extract_token_from_invite_email(emails: &Emails) -> String {
-    let message = emails
+fn extract_token_from_invite_email(app: &TestApp) -> String {
+    app.run_pending_background_jobs();
+    let message = app
+        .as_inner()
+        .emails
         .mails_in_memory()
         .unwrap()
         .into_iter()
@@ -0,0 +1,206 @@
+use crate::{util::RequestHelper, OkBool, TestApp};
+use cargo_registry::models::Session;
+use cargo_registry::views::EncodableSession;
+use conduit::StatusCode;
+
+const URL: &str = "/api/v1/me/sessions";
+
+#[derive(Deserialize)]
+struct SessionList {
+    sessions: Vec<EncodableSession>,
+}
+
+#[test]
+fn list_sessions() {
+    let (app, _, user) = TestApp::init().with_user();
+    let other = app.db(|conn| Session::create(conn, user.as_model().id, "other", "10.0.0.1"));
+    let other = other.unwrap();
+
+    let json: SessionList = user.get(URL).good();
+    assert_eq!(json.sessions.len(), 2);
+
+    let current = json.sessions.iter().find(|s| s.current).unwrap();
+    assert_eq!(current.id, user.session_id());
+    assert_eq!(current.user_agent, "conduit-test");
+
+    let session = json.sessions.iter().find(|s| !s.current).unwrap();
+    assert_eq!(session.id, other.id);
+    assert_eq!(session.user_agent, "other");
+    assert_eq!(session.ip_address, "10.0.0.1");
+}
+
+#[test]
+fn list_sessions_only_shows_own_sessions() {
+    let (app, _, user) = TestApp::init().with_user();
+    let _other_user = app.db_new_user("other");
+
+    let json: SessionList = user.get(URL).good();
+    assert_eq!(json.sessions.len(), 1);
+    assert_eq!(json.sessions[0].id, user.session_id());
+}
+
+#[test]
+fn revoked_session_can_no_longer_be_used() {
+    let (_, _, user) = TestApp::init().with_user();
+
+    let url = format!("{URL}/{}", user.session_id());
+    let json: OkBool = user.delete(&url).good();
+    assert!(json.ok);
+
+    let response = user.get::<()>(URL);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn logout_revokes_session() {
+    let (_, _, user) = TestApp::init().with_user();
+
+    let json: bool = user.delete("/api/private/session").good();
+    assert!(json);
+
+    let response = user.get::<()>(URL);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn sessions_of_other_users_cannot_be_revoked() {
+    let (app, _, user) = TestApp::init().with_user();
+    let other = app.db_new_user("other");
+
+    let url = format!("{URL}/{}", other.session_id());
+    let json: OkBool = user.delete(&url).good();
+    assert!(json.ok);
+
+    let json: SessionList = other.get(URL).good();
+    assert_eq!(json.sessions.len(), 1);
+}
+
+#[test]
+fn revoke_other_sessions() {
+    let (app, _, user) = TestApp::init().with_user();
+    let other = crate::util::MockCookieUser::new(&app, user.as_model().clone());
+
+    let json: OkBool = user.delete(URL).good();
+    assert!(json.ok);
+
+    let json: SessionList = user.get(URL).good();
+    assert_eq!(json.sessions.len(), 1);
+    assert_eq!(json.sessions[0].id, user.session_id());
+
+    let response = other.get::<()>(URL);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn sessions_require_cookie_auth() {
+    let (_, _, _, token) = TestApp::init().with_token();
+
+    let response = token.get::<()>(URL);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+fn set_session_times(app: &TestApp, id: i32, created_days_ago: i64, used_minutes_ago: i64) {
+    use cargo_registry::schema::sessions;
+    use chrono::{Duration, Utc};
+    use diesel::prelude::*;
+
+    let now = Utc::now().naive_utc();
+    app.db(|conn| {
+        diesel::update(sessions::table.find(id))
+            .set((
+                sessions::created_at.eq(now - Duration::days(created_days_ago)),
+                sessions::last_used_at.eq(now - Duration::minutes(used_minutes_ago)),
+            ))
+            .execute(conn)
+            .unwrap();
+    });
+}
+
+#[test]
+fn last_used_at_is_only_updated_every_few_minutes() {
+    let (app, _, user) = TestApp::init().with_user();
+    let last_used_at = || {
+        app.db(|conn| {
+            Session::find(conn, user.session_id())
+                .unwrap()
+                .unwrap()
+                .last_used_at
+        })
+    };
+
+    set_session_times(&app, user.session_id(), 1, 1);
+    let before = last_used_at();
+    user.get::<SessionList>(URL).good();
+    assert_eq!(last_used_at(), before);
+
+    set_session_times(&app, user.session_id(), 1, 10);
+    let before = last_used_at();
+    user.get::<SessionList>(URL).good();
+    assert!(last_used_at() > before);
+}
+
+#[test]
+fn expired_sessions_can_no_longer_be_used() {
+    let (app, _, user) = TestApp::init().with_user();
+
+    // Sessions expire some time after logging in, even if they are used regularly
+    set_session_times(&app, user.session_id(), 91, 0);
+    let response = user.get::<()>(URL);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // And when they weren't used for a while
+    set_session_times(&app, user.session_id(), 40, 31 * 24 * 60);
+    let response = user.get::<()>(URL);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    set_session_times(&app, user.session_id(), 40, 60);
+    user.get::<SessionList>(URL).good();
+}
+
+#[test]
+fn expired_sessions_are_deleted() {
+    use cargo_registry::worker;
+    use swirl::Job;
+
+    let (app, _, user) = TestApp::init().with_user();
+    let too_old = app.db(|conn| Session::create(conn, user.as_model().id, "old", "10.0.0.1"));
+    let idle = app.db(|conn| Session::create(conn, user.as_model().id, "idle", "10.0.0.2"));
+    set_session_times(&app, too_old.unwrap().id, 91, 0);
+    set_session_times(&app, idle.unwrap().id, 40, 31 * 24 * 60);
+
+    app.db(|conn| worker::delete_expired_sessions().enqueue(conn).unwrap());
+    app.run_pending_background_jobs();
+
+    let json: SessionList = user.get(URL).good();
+    assert_eq!(json.sessions.len(), 1);
+    assert_eq!(json.sessions[0].id, user.session_id());
+}
+
+#[test]
+fn cookie_sessions_expire_after_a_day() {
+    use crate::util::encode_session;
+    use chrono::{Duration, Utc};
+    use conduit::{header, Method};
+    use std::collections::HashMap;
+
+    let (app, anon, user) = TestApp::init().with_user();
+
+    // Sessions started in read only mode are only held in the cookie
+    let get_me = |created_at: chrono::DateTime<Utc>| {
+        let mut session = HashMap::new();
+        session.insert("user_id".to_string(), user.as_model().id.to_string());
+        let created_at = created_at.timestamp().to_string();
+        session.insert("cookie_session_created_at".to_string(), created_at);
+        let cookie = encode_session(app.as_inner().session_key(), &session);
+
+        let mut request = anon.request_builder(Method::GET, "/api/v1/me");
+        request.header(header::COOKIE, &cookie);
+        anon.run::<()>(request).status()
+    };
+
+    assert_eq!(get_me(Utc::now() - Duration::hours(23)), StatusCode::OK);
+    assert_eq!(
+        get_me(Utc::now() - Duration::hours(25)),
+        StatusCode::FORBIDDEN
+    );
+}
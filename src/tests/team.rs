@@ -3,10 +3,14 @@ use crate::{
     builders::{CrateBuilder, PublishBuilder},
     new_team,
     record::GhUser,
-    OwnerTeamsResponse, RequestHelper, TestApp,
+    util::{FakeGitHub, MockTokenUser, Response},
+    OkBool, OwnerTeamsResponse, RequestHelper, TestApp,
 };
-use cargo_registry::models::{Crate, NewTeam, NewUser};
+use cargo_registry::models::{Crate, NewTeam, NewUser, Team};
+use cargo_registry::schema::team_memberships;
 use std::sync::Once;
+use std::time::Duration;
+use swirl::schema::background_jobs::dsl::background_jobs;
 
 use conduit::StatusCode;
 use diesel::*;
@@ -374,3 +378,143 @@ fn crates_by_team_id_not_including_deleted_owners() {
     let json = anon.search(&format!("team_id={}", team.id));
     assert_eq!(json.crates.len(), 0);
 }
+
+/// Creates a crate owned by `foo` and by a team whose members are managed by `github`, and
+/// returns the team together with a token of `member`.
+fn crate_with_fake_team(
+    app: &TestApp,
+    github: &FakeGitHub,
+    krate_name: &str,
+) -> (Team, MockTokenUser) {
+    let owner = app.db_new_user("foo");
+    let member = app.db_new_user("member");
+
+    let team = app.db(|conn| {
+        let team = NewTeam::new("github:test-org:core", 1000, 2000, None, None)
+            .create_or_update(conn)
+            .unwrap();
+        let krate = CrateBuilder::new(krate_name, owner.as_model().id).expect_build(conn);
+        add_team_to_crate(&team, &krate, owner.as_model(), conn).unwrap();
+        team
+    });
+    github.add_team_member(1000, 2000, "member");
+
+    (team, member.db_new_token("bar"))
+}
+
+const TEAM_MEMBER_ERROR: &str = "team members don't have permission to modify owners";
+const NOT_OWNER_ERROR: &str = "only owners have permission to modify owners";
+
+fn assert_owners_error(response: Response<OkBool>, detail: &str) {
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": detail }] })
+    );
+}
+
+#[test]
+fn team_membership_is_cached() {
+    let github = FakeGitHub::start();
+    let (app, _) = TestApp::init().with_fake_github(&github).empty();
+    let (team, token) = crate_with_fake_team(&app, &github, "foo_cached_membership");
+
+    let response = token.add_named_owner("foo_cached_membership", "arbitrary_username");
+    assert_owners_error(response, TEAM_MEMBER_ERROR);
+    assert_eq!(github.request_count(), 1);
+
+    // The membership is kept even though the request was rejected
+    let membership = app.db(|conn| {
+        team_memberships::table
+            .filter(team_memberships::team_id.eq(team.id))
+            .select(team_memberships::is_member)
+            .first::<bool>(conn)
+            .unwrap()
+    });
+    assert!(membership);
+
+    // Leaving the team isn't noticed until the cached membership expires
+    github.remove_team_member(1000, 2000, "member");
+    let response = token.add_named_owner("foo_cached_membership", "arbitrary_username");
+    assert_owners_error(response, TEAM_MEMBER_ERROR);
+    assert_eq!(github.request_count(), 1);
+}
+
+#[test]
+fn expired_team_membership_is_revalidated() {
+    let github = FakeGitHub::start();
+    let (app, _) = TestApp::init()
+        .with_fake_github(&github)
+        .with_config(|config| {
+            config.team_membership_cache_ttl = Duration::from_secs(0);
+            config.team_membership_max_staleness = Duration::from_secs(0);
+        })
+        .empty();
+    let (_, token) = crate_with_fake_team(&app, &github, "foo_expired_membership");
+
+    let response = token.add_named_owner("foo_expired_membership", "arbitrary_username");
+    assert_owners_error(response, TEAM_MEMBER_ERROR);
+
+    github.remove_team_member(1000, 2000, "member");
+    let response = token.add_named_owner("foo_expired_membership", "arbitrary_username");
+    assert_owners_error(response, NOT_OWNER_ERROR);
+    assert_eq!(github.request_count(), 2);
+}
+
+#[test]
+fn cached_team_membership_is_used_when_github_fails() {
+    let github = FakeGitHub::start();
+    let (app, _) = TestApp::init()
+        .with_fake_github(&github)
+        .with_config(|config| {
+            config.team_membership_cache_ttl = Duration::from_secs(0);
+            config.team_membership_max_staleness = Duration::from_secs(0);
+        })
+        .empty();
+    let (_, token) = crate_with_fake_team(&app, &github, "foo_github_fails");
+
+    let response = token.add_named_owner("foo_github_fails", "arbitrary_username");
+    assert_owners_error(response, TEAM_MEMBER_ERROR);
+
+    github.set_unavailable(true);
+    let response = token.add_named_owner("foo_github_fails", "arbitrary_username");
+    assert_owners_error(response, TEAM_MEMBER_ERROR);
+    assert_eq!(github.request_count(), 2);
+}
+
+#[test]
+fn stale_team_membership_is_refreshed_in_background() {
+    let github = FakeGitHub::start();
+    let (app, _) = TestApp::full()
+        .with_fake_github(&github)
+        .with_config(|config| config.team_membership_cache_ttl = Duration::from_secs(0))
+        .empty();
+    let (_, token) = crate_with_fake_team(&app, &github, "foo_stale_membership");
+
+    let response = token.add_named_owner("foo_stale_membership", "arbitrary_username");
+    assert_owners_error(response, TEAM_MEMBER_ERROR);
+    assert_eq!(github.request_count(), 1);
+
+    // The stale membership is used, and a single refresh is enqueued
+    github.remove_team_member(1000, 2000, "member");
+    for _ in 0..2 {
+        let response = token.add_named_owner("foo_stale_membership", "arbitrary_username");
+        assert_owners_error(response, TEAM_MEMBER_ERROR);
+    }
+    assert_eq!(github.request_count(), 1);
+    let pending_jobs: i64 = app.db(|conn| background_jobs.count().get_result(conn).unwrap());
+    assert_eq!(pending_jobs, 1);
+    let refresh_enqueued_at = app.db(|conn| {
+        team_memberships::table
+            .select(team_memberships::refresh_enqueued_at)
+            .first::<Option<chrono::NaiveDateTime>>(conn)
+            .unwrap()
+    });
+    assert!(refresh_enqueued_at.is_some());
+
+    app.run_pending_background_jobs();
+    assert_eq!(github.request_count(), 2);
+
+    let response = token.add_named_owner("foo_stale_membership", "arbitrary_username");
+    assert_owners_error(response, NOT_OWNER_ERROR);
+}
@@ -1,14 +1,17 @@
+use crate::builders::{CrateBuilder, PublishBuilder};
 use crate::{RequestHelper, TestApp};
 use cargo_registry::{
     models::ApiToken,
-    schema::api_tokens,
+    schema::{api_token_usages, api_tokens},
     util::errors::TOKEN_FORMAT_ERROR,
-    views::{EncodableApiTokenWithToken, EncodableMe},
+    views::{EncodableApiTokenUsage, EncodableApiTokenWithToken, EncodableMe},
+    worker,
 };
 use std::collections::HashSet;
 
 use conduit::{header, StatusCode};
 use diesel::prelude::*;
+use swirl::Job;
 
 #[derive(Deserialize)]
 struct DecodableApiToken {
@@ -25,6 +28,10 @@ struct NewResponse {
 }
 #[derive(Deserialize)]
 struct RevokedResponse {}
+#[derive(Deserialize)]
+struct UsageResponse {
+    usage: Vec<EncodableApiTokenUsage>,
+}
 
 // Default values used by many tests
 static URL: &str = "/api/v1/me/tokens";
@@ -178,6 +185,23 @@ fn create_token_success() {
     assert_eq!(tokens[0].last_used_at, None);
 }
 
+#[test]
+fn create_token_notifies_user() {
+    let (app, _, user) = TestApp::init().with_user();
+    let _json: NewResponse = user.put(URL, NEW_BAR).good();
+    app.run_pending_background_jobs();
+
+    let emails = app.as_inner().emails.mails_in_memory().unwrap();
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0].to, "something@example.com");
+    assert_eq!(emails[0].subject, "A new API token was created");
+    assert!(emails[0]
+        .body
+        .contains("A new API token named \"bar\" was created for your crates.io account."));
+    assert!(emails[0].body.contains("IP address: unknown"));
+    assert!(emails[0].body.contains("User agent: conduit-test"));
+}
+
 #[test]
 fn create_token_multiple_have_different_values() {
     let (_, _, user) = TestApp::init().with_user();
@@ -271,6 +295,29 @@ fn revoke_token_success() {
     });
 }
 
+#[test]
+fn revoke_token_notifies_user() {
+    let (app, _, user, token) = TestApp::init().with_token();
+    let url = format!("/api/v1/me/tokens/{}", token.as_model().id);
+    let _json: RevokedResponse = user.delete(&url).good();
+
+    // Revoking a token that is already revoked doesn't notify the user again
+    let _json: RevokedResponse = user.delete(&url).good();
+
+    let other_token = user.db_new_token("other");
+    let response = other_token.delete::<()>("/api/v1/tokens/current");
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    app.run_pending_background_jobs();
+    let emails = app.as_inner().emails.mails_in_memory().unwrap();
+    assert_eq!(emails.len(), 2);
+    assert!(emails
+        .iter()
+        .all(|email| email.subject == "An API token was revoked"));
+    assert!(emails[0].body.contains("The API token named \"bar\""));
+    assert!(emails[1].body.contains("The API token named \"other\""));
+}
+
 #[test]
 fn revoke_current_token_success() {
     let (app, _, user, token) = TestApp::init().with_token();
@@ -360,6 +407,83 @@ fn using_token_updates_last_used_at() {
     // this test framework.
 }
 
+#[test]
+fn token_usage_is_recorded() {
+    let (app, _, user, token) = TestApp::full().with_token();
+    let url = format!("/api/v1/me/tokens/{}/usage", token.as_model().id);
+
+    let json: UsageResponse = user.get(&url).good();
+    assert!(json.usage.is_empty());
+
+    token
+        .enqueue_publish(PublishBuilder::new("usage_publish"))
+        .good();
+    app.db(|conn| CrateBuilder::new("usage_owners", user.as_model().id).expect_build(conn));
+    app.db_new_user("other");
+    token.add_named_owner("usage_owners", "other").good();
+
+    // Requests with a cookie are not recorded
+    user.search("following=1");
+
+    let json: UsageResponse = user.get(&url).good();
+    assert_eq!(json.usage.len(), 2);
+
+    let owners = &json.usage[0];
+    assert_eq!(owners.endpoint, "PUT /api/v1/crates/usage_owners/owners");
+    assert_eq!(owners.crate_name.as_deref(), Some("usage_owners"));
+    assert_eq!(owners.user_agent, "conduit-test");
+
+    let publish = &json.usage[1];
+    assert_eq!(publish.endpoint, "PUT /api/v1/crates/new");
+    assert_eq!(publish.crate_name.as_deref(), Some("usage_publish"));
+}
+
+#[test]
+fn token_usage_of_other_users_is_not_found() {
+    let (app, _, _, token) = TestApp::init().with_token();
+    let other = app.db_new_user("other");
+
+    let url = format!("/api/v1/me/tokens/{}/usage", token.as_model().id);
+    let response = other.get::<()>(&url);
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // The usage can't be listed with the token itself either
+    token.get::<()>(&url).assert_forbidden();
+}
+
+#[test]
+fn old_token_usage_is_pruned() {
+    let (app, _, user, token) = TestApp::init().with_token();
+    token.search("following=1");
+    token.search("following=1");
+
+    app.db(|conn| {
+        use chrono::{Duration, Utc};
+
+        let old = Utc::now().naive_utc() - Duration::days(100);
+        let oldest = api_token_usages::table
+            .select(diesel::dsl::min(api_token_usages::id))
+            .first::<Option<i64>>(conn)
+            .unwrap()
+            .unwrap();
+        diesel::update(api_token_usages::table.find(oldest))
+            .set(api_token_usages::used_at.eq(old))
+            .execute(conn)
+            .unwrap();
+    });
+
+    // Old usages are not listed, even before they are deleted
+    let url = format!("/api/v1/me/tokens/{}/usage", token.as_model().id);
+    let json: UsageResponse = user.get(&url).good();
+    assert_eq!(json.usage.len(), 1);
+
+    app.db(|conn| worker::prune_api_token_usages(90).enqueue(conn).unwrap());
+    app.run_pending_background_jobs();
+
+    let remaining: i64 = app.db(|conn| api_token_usages::table.count().get_result(conn).unwrap());
+    assert_eq!(remaining, 1);
+}
+
 #[test]
 fn old_tokens_give_specific_error_message() {
     let url = "/api/v1/me";
@@ -0,0 +1,263 @@
+use crate::builders::CrateBuilder;
+use crate::util::{MockCookieUser, RequestHelper, Response};
+use crate::{OkBool, TestApp};
+use cargo_registry::models::{TotpCredential, MAX_FAILED_ATTEMPTS};
+use cargo_registry::util::totp;
+use chrono::Utc;
+use conduit::{Method, StatusCode};
+use serde_json::Value;
+
+const URL: &str = "/api/v1/me/totp";
+const TOKENS_URL: &str = "/api/v1/me/tokens";
+const NEW_TOKEN: &[u8] = br#"{ "api_token": { "name": "bar" } }"#;
+
+#[derive(Deserialize)]
+struct RecoveryCodes {
+    recovery_codes: Vec<String>,
+}
+
+/// Returns a valid code of the user's authenticator app.
+fn current_code(app: &TestApp, user: &MockCookieUser) -> String {
+    let credential = app.db(|conn| {
+        use cargo_registry::schema::totp_credentials;
+        use diesel::prelude::*;
+
+        // Codes can only be used once, so the step of the last used code is forgotten to allow
+        // several requests within the same 30 seconds
+        diesel::update(totp_credentials::table.find(user.as_model().id))
+            .set(totp_credentials::last_used_step.eq(None::<i64>))
+            .execute(conn)
+            .unwrap();
+        TotpCredential::find(conn, user.as_model().id)
+            .unwrap()
+            .unwrap()
+    });
+
+    let step = totp::step_at(Utc::now().timestamp());
+    totp::code_at(&credential.secret, step)
+}
+
+fn enable_totp(app: &TestApp, user: &MockCookieUser) -> Vec<String> {
+    let _: Value = user.put(URL, &[]).good();
+
+    let body = json!({ "code": current_code(app, user) }).to_string();
+    let json: RecoveryCodes = user.put(&format!("{URL}/confirm"), body.as_bytes()).good();
+    json.recovery_codes
+}
+
+fn put_with_code<T>(user: &MockCookieUser, path: &str, body: &[u8], code: &str) -> Response<T> {
+    let mut request = user.request_builder(Method::PUT, path);
+    request.header("x-totp-code", code);
+    request.with_body(body);
+    user.run(request)
+}
+
+#[track_caller]
+fn assert_totp_error<T>(response: Response<T>, detail: &str) {
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": detail }] })
+    );
+}
+
+#[test]
+fn enroll_and_confirm() {
+    let (app, _, user) = TestApp::init().with_user();
+
+    let json: Value = user.get(URL).good();
+    assert_eq!(json["totp"]["enabled"], false);
+
+    let json: Value = user.put(URL, &[]).good();
+    let secret = json["totp"]["secret"].as_str().unwrap();
+    let uri = json["totp"]["uri"].as_str().unwrap();
+    assert!(uri.starts_with(&format!("otpauth://totp/crates.io:foo?secret={secret}&")));
+
+    // Codes are not required before the enrollment is confirmed
+    let _: Value = user.put(TOKENS_URL, NEW_TOKEN).good();
+
+    let response = user.put::<()>(&format!("{URL}/confirm"), br#"{ "code": "000000" }"#);
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let body = json!({ "code": current_code(&app, &user) }).to_string();
+    let json: RecoveryCodes = user.put(&format!("{URL}/confirm"), body.as_bytes()).good();
+    assert_eq!(json.recovery_codes.len(), 10);
+
+    let json: Value = user.get(URL).good();
+    assert_eq!(json["totp"]["enabled"], true);
+    assert_eq!(json["totp"]["recovery_codes_remaining"], 10);
+
+    let response = user.put::<()>(URL, &[]);
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn token_creation_requires_code() {
+    let (app, _, user) = TestApp::init().with_user();
+    enable_totp(&app, &user);
+
+    let response = user.put::<()>(TOKENS_URL, NEW_TOKEN);
+    let detail = "a two-factor authentication code is required for this action";
+    assert_totp_error(response, detail);
+
+    let response = put_with_code::<()>(&user, TOKENS_URL, NEW_TOKEN, "000000");
+    assert_totp_error(response, "invalid two-factor authentication code");
+
+    let code = current_code(&app, &user);
+    let _: Value = put_with_code(&user, TOKENS_URL, NEW_TOKEN, &code).good();
+
+    // The same code can't be used twice
+    let response = put_with_code::<()>(&user, TOKENS_URL, NEW_TOKEN, &code);
+    assert_totp_error(response, "invalid two-factor authentication code");
+}
+
+#[test]
+fn recovery_codes_can_only_be_used_once() {
+    let (app, _, user) = TestApp::init().with_user();
+    let recovery_codes = enable_totp(&app, &user);
+    let code = &recovery_codes[0];
+
+    let _: Value = put_with_code(&user, TOKENS_URL, NEW_TOKEN, code).good();
+
+    let response = put_with_code::<()>(&user, TOKENS_URL, NEW_TOKEN, code);
+    assert_totp_error(response, "invalid two-factor authentication code");
+
+    let json: Value = user.get(URL).good();
+    assert_eq!(json["totp"]["recovery_codes_remaining"], 9);
+
+    // New codes replace all previous codes
+    let code = current_code(&app, &user);
+    let url = format!("{URL}/recovery_codes");
+    let json: RecoveryCodes = put_with_code(&user, &url, &[], &code).good();
+    assert!(!json.recovery_codes.contains(&recovery_codes[1]));
+
+    let response = put_with_code::<()>(&user, TOKENS_URL, NEW_TOKEN, &recovery_codes[1]);
+    assert_totp_error(response, "invalid two-factor authentication code");
+
+    let code = &json.recovery_codes[0].to_uppercase();
+    let _: Value = put_with_code(&user, TOKENS_URL, NEW_TOKEN, code).good();
+}
+
+#[test]
+fn owner_changes_require_code() {
+    let (app, _, user, token) = TestApp::init().with_token();
+    app.db(|conn| CrateBuilder::new("totp_owners", user.as_model().id).expect_build(conn));
+    app.db_new_user("user2");
+    app.db_new_user("user3");
+    enable_totp(&app, &user);
+
+    let url = "/api/v1/crates/totp_owners/owners";
+    let body = br#"{ "owners": ["user2"] }"#;
+
+    let response = user.put::<()>(url, body);
+    let detail = "a two-factor authentication code is required for this action";
+    assert_totp_error(response, detail);
+
+    let code = current_code(&app, &user);
+    let json: OkBool = put_with_code(&user, url, body, &code).good();
+    assert!(json.ok);
+
+    // cargo has no way to provide a code, so API tokens are not affected
+    let body = br#"{ "owners": ["user3"] }"#;
+    let json: OkBool = token.put(url, body).good();
+    assert!(json.ok);
+}
+
+#[test]
+fn repeated_invalid_codes_lock_the_credential() {
+    let (app, _, user) = TestApp::init().with_user();
+    enable_totp(&app, &user);
+
+    for _ in 0..MAX_FAILED_ATTEMPTS {
+        let response = put_with_code::<()>(&user, TOKENS_URL, NEW_TOKEN, "000000");
+        assert_totp_error(response, "invalid two-factor authentication code");
+    }
+
+    // Even valid codes are rejected while the credential is locked
+    let code = current_code(&app, &user);
+    let response = put_with_code::<()>(&user, TOKENS_URL, NEW_TOKEN, &code);
+    let detail = "too many invalid two-factor authentication codes, please try again later";
+    assert_totp_error(response, detail);
+
+    app.db(|conn| {
+        use cargo_registry::schema::totp_credentials;
+        use diesel::prelude::*;
+
+        diesel::update(totp_credentials::table.find(user.as_model().id))
+            .set(totp_credentials::locked_until.eq(None::<chrono::NaiveDateTime>))
+            .execute(conn)
+            .unwrap();
+    });
+
+    let code = current_code(&app, &user);
+    let _: Value = put_with_code(&user, TOKENS_URL, NEW_TOKEN, &code).good();
+}
+
+#[test]
+fn yanking_requires_code() {
+    let (app, _, user, token) = TestApp::init().with_token();
+    app.db(|conn| {
+        CrateBuilder::new("totp_yank", user.as_model().id)
+            .version("1.0.0")
+            .expect_build(conn)
+    });
+    enable_totp(&app, &user);
+
+    let url = "/api/v1/crates/totp_yank/1.0.0/yank";
+    let response = user.delete::<()>(url);
+    let detail = "a two-factor authentication code is required for this action";
+    assert_totp_error(response, detail);
+
+    let mut request = user.request_builder(Method::DELETE, url);
+    request.header("x-totp-code", &current_code(&app, &user));
+    let json: OkBool = user.run(request).good();
+    assert!(json.ok);
+
+    // cargo has no way to provide a code, so API tokens are not affected
+    let json: OkBool = token
+        .put("/api/v1/crates/totp_yank/1.0.0/unyank", &[])
+        .good();
+    assert!(json.ok);
+}
+
+#[test]
+fn account_deletion_requires_code() {
+    let (app, _, user) = TestApp::init().with_user();
+    enable_totp(&app, &user);
+
+    let response = user.delete::<()>("/api/v1/me");
+    let detail = "a two-factor authentication code is required for this action";
+    assert_totp_error(response, detail);
+
+    let mut request = user.request_builder(Method::DELETE, "/api/v1/me");
+    request.header("x-totp-code", &current_code(&app, &user));
+    let json: OkBool = user.run(request).good();
+    assert!(json.ok);
+}
+
+#[test]
+fn disable_totp() {
+    let (app, _, user) = TestApp::init().with_user();
+    enable_totp(&app, &user);
+
+    let response = user.delete::<()>(URL);
+    let detail = "a two-factor authentication code is required for this action";
+    assert_totp_error(response, detail);
+
+    let mut request = user.request_builder(Method::DELETE, URL);
+    request.header("x-totp-code", &current_code(&app, &user));
+    let json: OkBool = user.run(request).good();
+    assert!(json.ok);
+
+    let json: Value = user.get(URL).good();
+    assert_eq!(json["totp"]["enabled"], false);
+    let _: Value = user.put(TOKENS_URL, NEW_TOKEN).good();
+}
+
+#[test]
+fn totp_requires_cookie_auth() {
+    let (_, _, _, token) = TestApp::init().with_token();
+
+    let response = token.put::<()>(URL, &[]);
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
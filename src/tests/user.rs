@@ -7,7 +7,9 @@ use crate::{
 use cargo_registry::{
     models::{Email, NewUser, User},
     schema::crate_owners,
-    views::{EncodablePrivateUser, EncodablePublicUser, EncodableVersion, OwnedCrate},
+    views::{
+        EncodableEmail, EncodablePrivateUser, EncodablePublicUser, EncodableVersion, OwnedCrate,
+    },
 };
 
 use conduit::StatusCode;
@@ -35,6 +37,16 @@ struct UserStats {
     total_downloads: i64,
 }
 
+#[derive(Deserialize)]
+struct EmailList {
+    emails: Vec<EncodableEmail>,
+}
+
+#[derive(Deserialize)]
+struct NewEmailResponse {
+    email: EncodableEmail,
+}
+
 #[derive(Serialize)]
 struct EmailNotificationsUpdate {
     id: i32,
@@ -480,6 +492,95 @@ fn test_email_get_and_put() {
     assert!(json.user.email_verification_sent);
 }
 
+#[test]
+fn changing_email_notifies_previous_address() {
+    let (app, _, user) = TestApp::init().with_user();
+    user.update_email("mango@mangos.mango");
+
+    // Setting the same address again doesn't send another notification
+    user.update_email("mango@mangos.mango");
+
+    app.run_pending_background_jobs();
+    let emails = app.as_inner().emails.mails_in_memory().unwrap();
+    let notices = emails
+        .iter()
+        .filter(|email| email.subject == "The email address of your account was changed")
+        .collect::<Vec<_>>();
+    assert_eq!(notices.len(), 1);
+    assert_eq!(notices[0].to, "something@example.com");
+    assert!(notices[0]
+        .body
+        .contains("was changed to mango@mangos.mango."));
+    assert!(emails.iter().any(|email| email.to == "mango@mangos.mango"
+        && email.subject == "Please confirm your email address"));
+}
+
+#[test]
+fn every_primary_email_change_is_notified() {
+    use cargo_registry::schema::emails;
+
+    let (app, _, user) = TestApp::init().with_user();
+    let notices = |subject: &str| {
+        app.run_pending_background_jobs();
+        app.as_inner()
+            .emails
+            .mails_in_memory()
+            .unwrap()
+            .into_iter()
+            .filter(|email| email.subject == subject)
+            .map(|email| (email.to, email.body))
+            .collect::<Vec<_>>()
+    };
+
+    // Adding an address is announced at the current one
+    let body = json!({ "email": "second@example.com" });
+    let json: NewEmailResponse = user
+        .put("/api/v1/me/emails", body.to_string().as_bytes())
+        .good();
+    let second = json.email;
+    let added = notices("An email address was added to your account");
+    assert_eq!(added.len(), 1);
+    assert_eq!(added[0].0, "something@example.com");
+    assert!(added[0].1.contains("second@example.com was added"));
+
+    let token: String = app.db(|conn| {
+        emails::table
+            .find(second.id)
+            .select(emails::token)
+            .first(conn)
+            .unwrap()
+    });
+    user.confirm_email(&token);
+
+    // Picking another primary address is announced at the previous one
+    let url = format!("/api/v1/me/emails/{}/primary", second.id);
+    user.put::<OkBool>(&url, &[]).good();
+    let changed = notices("The email address of your account was changed");
+    assert_eq!(changed.len(), 1);
+    assert_eq!(changed[0].0, "something@example.com");
+    assert!(changed[0].1.contains("was changed to second@example.com."));
+
+    // Setting an address that was already added promotes it instead of failing
+    user.update_email("Something@example.com");
+    let changed = notices("The email address of your account was changed");
+    assert_eq!(changed.len(), 2);
+    assert_eq!(changed[1].0, "second@example.com");
+
+    let json: EmailList = user.get("/api/v1/me/emails").good();
+    let addresses = json
+        .emails
+        .iter()
+        .map(|email| (email.email.as_str(), email.primary, email.verified))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        addresses,
+        [
+            ("something@example.com", true, true),
+            ("second@example.com", false, true)
+        ]
+    );
+}
+
 /*  Given a crates.io user, check to make sure that the user
     cannot add to the database an empty string or null as
     their email. If an attempt is made, update_user.rs will
@@ -585,6 +686,107 @@ fn test_confirm_user_email() {
     assert!(json.user.email_verification_sent);
 }
 
+#[test]
+fn manage_multiple_emails() {
+    use cargo_registry::schema::emails;
+
+    let (app, _, user) = TestApp::init().with_user();
+
+    let body = json!({ "email": "second@example.com" });
+    let json: NewEmailResponse = user
+        .put("/api/v1/me/emails", body.to_string().as_bytes())
+        .good();
+    let second = json.email;
+    assert_eq!(second.email, "second@example.com");
+    assert!(!second.verified);
+    assert!(second.verification_sent);
+    assert!(!second.primary);
+
+    // The same address can't be added twice
+    let body = json!({ "email": "Second@example.com" });
+    let response = user.put::<()>("/api/v1/me/emails", body.to_string().as_bytes());
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // Only verified addresses can become the primary address
+    let url = format!("/api/v1/me/emails/{}/primary", second.id);
+    let response = user.put::<()>(&url, &[]);
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    app.run_pending_background_jobs();
+    let emails = app.as_inner().emails.mails_in_memory().unwrap();
+    assert!(emails.iter().any(|email| email.to == "second@example.com"
+        && email.subject == "Please confirm your email address"));
+
+    let token: String = app.db(|conn| {
+        emails::table
+            .find(second.id)
+            .select(emails::token)
+            .first(conn)
+            .unwrap()
+    });
+    user.confirm_email(&token);
+    user.put::<OkBool>(&url, &[]).good();
+
+    let json = user.show_me();
+    assert_eq!(json.user.email.unwrap(), "second@example.com");
+    assert!(json.user.email_verified);
+
+    let json: EmailList = user.get("/api/v1/me/emails").good();
+    let addresses = json
+        .emails
+        .iter()
+        .map(|email| (email.email.as_str(), email.primary))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        addresses,
+        [
+            ("second@example.com", true),
+            ("something@example.com", false)
+        ]
+    );
+
+    // The primary address can't be removed, but the others can
+    let response = user.delete::<()>(&format!("/api/v1/me/emails/{}", second.id));
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let first_id = json.emails[1].id;
+    user.delete::<OkBool>(&format!("/api/v1/me/emails/{first_id}"))
+        .good();
+
+    let json: EmailList = user.get("/api/v1/me/emails").good();
+    assert_eq!(json.emails.len(), 1);
+}
+
+#[test]
+fn notifications_go_to_the_selected_email() {
+    use cargo_registry::schema::emails;
+
+    let (app, _, user) = TestApp::init().with_user();
+    let notification_email = || app.db(|conn| user.as_model().notification_email(conn).unwrap());
+    assert_eq!(notification_email().unwrap(), "something@example.com");
+
+    let body = json!({ "email": "notifications@example.com" });
+    let json: NewEmailResponse = user
+        .put("/api/v1/me/emails", body.to_string().as_bytes())
+        .good();
+
+    let url = format!("/api/v1/me/emails/{}/notifications", json.email.id);
+    let response = user.put::<()>(&url, &[]);
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    app.db(|conn| {
+        diesel::update(emails::table.find(json.email.id))
+            .set(emails::verified.eq(true))
+            .execute(conn)
+            .unwrap();
+    });
+    user.put::<OkBool>(&url, &[]).good();
+    assert_eq!(notification_email().unwrap(), "notifications@example.com");
+
+    // The primary address is still used for everything else
+    let json = user.show_me();
+    assert_eq!(json.user.email.unwrap(), "something@example.com");
+}
+
 /* Given a user who existed before we added email confirmation,
    test that `email_verification_sent` is false so that we don't
    make the user think we've sent an email when we haven't.
@@ -754,3 +956,157 @@ fn test_update_email_notifications_not_owned() {
     // There should be no change to the `email_notifications` value for a crate not belonging to me
     assert!(email_notifications);
 }
+
+fn run_weekly_digests(app: &TestApp) {
+    use cargo_registry::worker;
+    use swirl::Job;
+
+    app.db(|conn| worker::send_weekly_digests().enqueue(conn).unwrap());
+    app.run_pending_background_jobs();
+}
+
+#[test]
+fn weekly_digest() {
+    use cargo_registry::models::{insert_version_owner_action, Badge, VersionAction};
+    use cargo_registry::schema::versions;
+    use chrono::{Duration, Utc};
+    use std::collections::HashMap;
+
+    let (app, anon, user) = TestApp::init().with_user();
+    let user_id = user.as_model().id;
+    let two_weeks_ago = (Utc::now() - Duration::weeks(2)).naive_utc();
+
+    app.db(|conn| {
+        CrateBuilder::new("digest_new", user_id)
+            .version(VersionBuilder::new("1.0.0").created_at(two_weeks_ago))
+            .version("1.1.0")
+            .expect_build(conn);
+
+        let yanked = CrateBuilder::new("digest_yanked", user_id)
+            .version(VersionBuilder::new("1.0.0").created_at(two_weeks_ago))
+            .expect_build(conn);
+        let version_id = diesel::update(versions::table.filter(versions::crate_id.eq(yanked.id)))
+            .set(versions::yanked.eq(true))
+            .returning(versions::id)
+            .get_result(conn)
+            .unwrap();
+        insert_version_owner_action(conn, version_id, user_id, None, VersionAction::Yank).unwrap();
+
+        let deprecated = CrateBuilder::new("digest_deprecated", user_id)
+            .version(VersionBuilder::new("1.0.0").created_at(two_weeks_ago))
+            .expect_build(conn);
+        let mut badges = HashMap::new();
+        let mut attributes = HashMap::new();
+        attributes.insert("status".to_string(), "deprecated".to_string());
+        badges.insert("maintenance".to_string(), attributes);
+        Badge::update_crate(conn, &deprecated, Some(&badges)).unwrap();
+
+        CrateBuilder::new("digest_quiet", user_id)
+            .version(VersionBuilder::new("1.0.0").created_at(two_weeks_ago))
+            .updated_at(two_weeks_ago)
+            .expect_build(conn);
+
+        CrateBuilder::new("digest_not_followed", user_id)
+            .version("1.0.0")
+            .expect_build(conn);
+    });
+
+    for name in [
+        "digest_new",
+        "digest_yanked",
+        "digest_deprecated",
+        "digest_quiet",
+    ] {
+        user.put::<OkBool>(&format!("/api/v1/crates/{name}/follow"), b"")
+            .good();
+    }
+
+    // The digest is opt-in
+    assert!(!user.show_me().user.weekly_digest);
+    run_weekly_digests(&app);
+    assert_eq!(app.as_inner().emails.mails_in_memory().unwrap().len(), 0);
+
+    let body = json!({ "weekly_digest": true });
+    user.put::<OkBool>("/api/v1/me/weekly_digest", body.to_string().as_bytes())
+        .good();
+    assert!(user.show_me().user.weekly_digest);
+
+    run_weekly_digests(&app);
+    let emails = app.as_inner().emails.mails_in_memory().unwrap();
+    assert_eq!(emails.len(), 1);
+    let email = &emails[0];
+    assert_eq!(email.to, "something@example.com");
+    assert_eq!(email.subject, "Your weekly crates.io digest");
+    assert!(email.body.contains("digest_new 1.1.0"));
+    assert!(!email.body.contains("digest_new 1.0.0"));
+    assert!(email
+        .body
+        .contains("Yanked versions:\n- digest_yanked 1.0.0"));
+    assert!(email
+        .body
+        .contains("Deprecated crates:\n- digest_deprecated"));
+    assert!(!email.body.contains("digest_quiet"));
+    assert!(!email.body.contains("digest_not_followed"));
+
+    // Nothing happened since the last digest, so no email is sent
+    run_weekly_digests(&app);
+    assert_eq!(app.as_inner().emails.mails_in_memory().unwrap().len(), 1);
+
+    // The unsubscribe link leads to a confirmation page, loading it doesn't unsubscribe
+    let url = email
+        .body
+        .split_whitespace()
+        .find(|word| word.contains("/weekly_digest/unsubscribe/"))
+        .unwrap();
+    let token = url.rsplit('/').next().unwrap();
+    let path = format!("/api/v1/weekly_digest/unsubscribe/{token}");
+    let response = anon.get::<()>(&path);
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.into_json(),
+        json!({ "login": "foo", "weekly_digest": true })
+    );
+    assert!(user.show_me().user.weekly_digest);
+
+    // Mail clients unsubscribe with a POST to the `List-Unsubscribe` URL, without logging in
+    let list_unsubscribe = email.list_unsubscribe.as_deref().unwrap();
+    assert!(list_unsubscribe.ends_with(&path));
+    anon.post::<OkBool>(&path, b"List-Unsubscribe=One-Click")
+        .good();
+    assert!(!user.show_me().user.weekly_digest);
+}
+
+#[test]
+fn weekly_digest_unsubscribe_rejects_invalid_tokens() {
+    let (app, anon, user) = TestApp::init().with_user();
+    let user_id = user.as_model().id;
+
+    let body = json!({ "weekly_digest": true });
+    user.put::<OkBool>("/api/v1/me/weekly_digest", body.to_string().as_bytes())
+        .good();
+
+    // A token for another user can't be forged from the user ID
+    let other_user = app.db_new_user("other_user");
+    let token = other_user
+        .as_model()
+        .digest_unsubscribe_token(app.as_inner().email_token_key());
+    let (_, expiry_and_signature) = token.split_once('.').unwrap();
+    let url = format!("/api/v1/weekly_digest/unsubscribe/{user_id}.{expiry_and_signature}");
+
+    let response = anon.post::<()>(&url, b"");
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.into_json(),
+        json!({ "errors": [{ "detail": "invalid unsubscribe token" }] })
+    );
+    assert_eq!(anon.get::<()>(&url).status(), StatusCode::BAD_REQUEST);
+    assert!(user.show_me().user.weekly_digest);
+
+    // Tokens signed with the session key aren't accepted either
+    let token = user
+        .as_model()
+        .digest_unsubscribe_token(app.as_inner().session_key());
+    let url = format!("/api/v1/weekly_digest/unsubscribe/{token}");
+    assert_eq!(anon.post::<()>(&url, b"").status(), StatusCode::BAD_REQUEST);
+    assert!(user.show_me().user.weekly_digest);
+}
@@ -23,7 +23,7 @@ use crate::{
     builders::PublishBuilder, CategoryListResponse, CategoryResponse, CrateList, CrateResponse,
     GoodCrate, OkBool, OwnersResponse, VersionResponse,
 };
-use cargo_registry::models::{ApiToken, CreatedApiToken, User};
+use cargo_registry::models::{ApiToken, CreatedApiToken, Session, User};
 
 use conduit::{BoxError, Handler, Method};
 use conduit_cookie::SessionMiddleware;
@@ -35,11 +35,16 @@ use std::collections::HashMap;
 
 mod chaosproxy;
 mod fresh_schema;
+mod github;
+mod login_provider;
 mod response;
+mod stub_server;
 mod test_app;
 
 pub(crate) use chaosproxy::ChaosProxy;
 pub(crate) use fresh_schema::FreshSchema;
+pub use github::FakeGitHub;
+pub use login_provider::FakeLoginProvider;
 pub use response::Response;
 pub use test_app::TestApp;
 
@@ -47,22 +52,27 @@ pub use test_app::TestApp;
 /// include cookie-based authentication.
 ///
 /// ```
-/// let cookie = encode_session_header(session_key, user_id);
+/// let cookie = encode_session_header(session_key, session_id);
 /// request.header(header::COOKIE, &cookie);
 /// ```
 ///
 /// The implementation matches roughly what is happening inside of the
 /// `SessionMiddleware` from `conduit_cookie`.
-pub fn encode_session_header(session_key: &str, user_id: i32) -> String {
-    let cookie_name = "cargo_session";
-    let cookie_key = cookie::Key::derive_from(session_key.as_bytes());
-
+pub fn encode_session_header(session_key: &str, session_id: i32) -> String {
     // build session data map
     let mut map = HashMap::new();
-    map.insert("user_id".into(), user_id.to_string());
+    map.insert("session_id".into(), session_id.to_string());
+
+    encode_session(session_key, &map)
+}
+
+/// Encodes arbitrary session data, e.g. the state of an OAuth flow, into a cookie header
+pub fn encode_session(session_key: &str, map: &HashMap<String, String>) -> String {
+    let cookie_name = "cargo_session";
+    let cookie_key = cookie::Key::derive_from(session_key.as_bytes());
 
     // encode the map into a cookie value string
-    let encoded = SessionMiddleware::encode(&map);
+    let encoded = SessionMiddleware::encode(map);
 
     // put the cookie into a signed cookie jar
     let cookie = Cookie::build(cookie_name, encoded).finish();
@@ -119,6 +129,14 @@ pub trait RequestHelper {
         self.run(request)
     }
 
+    /// Issue a POST request
+    #[track_caller]
+    fn post<T>(&self, path: &str, body: &[u8]) -> Response<T> {
+        let mut request = self.request_builder(Method::POST, path);
+        request.with_body(body);
+        self.run(request)
+    }
+
     /// Issue a DELETE request
     #[track_caller]
     fn delete<T>(&self, path: &str) -> Response<T> {
@@ -208,17 +226,18 @@ impl RequestHelper for MockAnonymousUser {
 
 /// A type that can generate cookie authenticated requests
 ///
-/// The `user.id` value is directly injected into a request extension and thus the conduit_cookie
-/// session logic is not exercised.
+/// A session is stored in the database for the user and its ID is encoded into the cookie of
+/// every request.
 pub struct MockCookieUser {
     app: TestApp,
     user: User,
+    session_id: i32,
 }
 
 impl RequestHelper for MockCookieUser {
     fn request_builder(&self, method: Method, path: &str) -> MockRequest {
         let session_key = &self.app.as_inner().session_key();
-        let cookie = encode_session_header(session_key, self.user.id);
+        let cookie = encode_session_header(session_key, self.session_id);
 
         let mut request = req(method, path);
         request.header(header::COOKIE, &cookie);
@@ -231,11 +250,16 @@ impl RequestHelper for MockCookieUser {
 }
 
 impl MockCookieUser {
-    /// Creates an instance from a database `User` instance
+    /// Creates an instance from a database `User` instance, starting a new session for it
+    ///
+    /// This method updates the database directly
     pub fn new(app: &TestApp, user: User) -> Self {
+        let session =
+            app.db(|conn| Session::create(conn, user.id, "conduit-test", "127.0.0.1").unwrap());
         Self {
             app: app.clone(),
             user,
+            session_id: session.id,
         }
     }
 
@@ -244,6 +268,11 @@ impl MockCookieUser {
         &self.user
     }
 
+    /// Returns the ID of the session used by this user's requests
+    pub fn session_id(&self) -> i32 {
+        self.session_id
+    }
+
     /// Creates a token and wraps it in a helper struct
     ///
     /// This method updates the database directly
@@ -0,0 +1,83 @@
+//! A fake GitHub API server
+//!
+//! Unlike the record/replay proxy, the fake server keeps its team memberships in memory, so tests
+//! can add or remove members between requests and simulate GitHub being unavailable.
+
+use super::stub_server::{json_response, StubServer};
+use hyper::{Body, Request, Response, StatusCode};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+#[derive(Default)]
+struct State {
+    /// Active memberships as `(org_id, team_id, login)`
+    members: HashSet<(i32, i32, String)>,
+    unavailable: bool,
+    requests: usize,
+}
+
+pub struct FakeGitHub {
+    server: StubServer,
+    state: Arc<Mutex<State>>,
+}
+
+impl FakeGitHub {
+    pub fn start() -> Self {
+        let state = Arc::new(Mutex::new(State::default()));
+        let handler_state = Arc::clone(&state);
+        let server = StubServer::start(move |req| respond(&handler_state, req));
+
+        Self { server, state }
+    }
+
+    /// The base URL to configure as `gh_base_url`
+    pub fn url(&self) -> &str {
+        self.server.url()
+    }
+
+    pub fn add_team_member(&self, org_id: i32, team_id: i32, login: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.members.insert((org_id, team_id, login.into()));
+    }
+
+    pub fn remove_team_member(&self, org_id: i32, team_id: i32, login: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.members.remove(&(org_id, team_id, login.into()));
+    }
+
+    /// Makes every following request fail with a `503 Service Unavailable` status
+    pub fn set_unavailable(&self, unavailable: bool) {
+        self.state.lock().unwrap().unavailable = unavailable;
+    }
+
+    /// The number of requests received so far
+    pub fn request_count(&self) -> usize {
+        self.state.lock().unwrap().requests
+    }
+}
+
+fn respond(state: &Mutex<State>, req: &Request<Body>) -> Response<Body> {
+    let mut state = state.lock().unwrap();
+    state.requests += 1;
+
+    if state.unavailable {
+        return json_response(StatusCode::SERVICE_UNAVAILABLE, "{}");
+    }
+
+    // GET /organizations/:org_id/team/:team_id/memberships/:username
+    let segments = req.uri().path().split('/').collect::<Vec<_>>();
+    if let ["", "organizations", org_id, "team", team_id, "memberships", login] = &*segments {
+        let key = (
+            org_id.parse().unwrap_or_default(),
+            team_id.parse().unwrap_or_default(),
+            login.to_string(),
+        );
+        if state.members.contains(&key) {
+            return json_response(StatusCode::OK, r#"{"state":"active"}"#);
+        }
+    }
+
+    json_response(StatusCode::NOT_FOUND, r#"{"message":"Not Found"}"#)
+}
@@ -0,0 +1,170 @@
+//! A fake OAuth server implementing the parts of the GitLab and OpenID Connect APIs used to
+//! log in and to check group memberships.
+//!
+//! Every authorization code is exchanged for an access token of the account set with
+//! `set_account`, so tests don't need to know the codes handed out to the browser.
+
+use super::stub_server::{json_response, StubServer};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+const ACCESS_TOKEN: &str = "fake-access-token";
+
+#[derive(Clone)]
+struct Account {
+    id: i32,
+    login: String,
+    email: Option<String>,
+    email_verified: bool,
+}
+
+#[derive(Default)]
+struct State {
+    account: Option<Account>,
+    /// Group IDs by their full path
+    groups: HashMap<String, i32>,
+    /// Active memberships as `(group_id, account_id)`
+    members: HashSet<(i32, i32)>,
+}
+
+pub struct FakeLoginProvider {
+    server: StubServer,
+    state: Arc<Mutex<State>>,
+}
+
+impl FakeLoginProvider {
+    pub fn start() -> Self {
+        let state = Arc::new(Mutex::new(State::default()));
+        let handler_state = Arc::clone(&state);
+        let server = StubServer::start(move |req| respond(&handler_state, req));
+
+        Self { server, state }
+    }
+
+    /// The base URL to configure for GitLab and as the OpenID Connect issuer
+    pub fn url(&self) -> &str {
+        self.server.url()
+    }
+
+    /// Sets the account that logs in next. The email address is reported as verified.
+    pub fn set_account(&self, id: i32, login: &str, email: Option<&str>) {
+        self.state.lock().unwrap().account = Some(Account {
+            id,
+            login: login.into(),
+            email: email.map(Into::into),
+            email_verified: true,
+        });
+    }
+
+    /// Like `set_account`, but the email address is reported as unconfirmed.
+    pub fn set_unverified_account(&self, id: i32, login: &str, email: &str) {
+        self.state.lock().unwrap().account = Some(Account {
+            id,
+            login: login.into(),
+            email: Some(email.into()),
+            email_verified: false,
+        });
+    }
+
+    pub fn add_group(&self, id: i32, path: &str) {
+        self.state.lock().unwrap().groups.insert(path.into(), id);
+    }
+
+    pub fn add_group_member(&self, group_id: i32, account_id: i32) {
+        let mut state = self.state.lock().unwrap();
+        state.members.insert((group_id, account_id));
+    }
+}
+
+fn respond(state: &Mutex<State>, req: &Request<Body>) -> Response<Body> {
+    let state = state.lock().unwrap();
+    let path = req.uri().path();
+
+    if req.method() == Method::POST {
+        // POST /oauth/token (GitLab) or POST /token (OpenID Connect)
+        if path == "/oauth/token" || path == "/token" {
+            let body = json!({ "access_token": ACCESS_TOKEN, "token_type": "bearer" });
+            return json_response(StatusCode::OK, body.to_string());
+        }
+        return not_found();
+    }
+
+    if path == "/.well-known/openid-configuration" {
+        let host = req.headers()["host"].to_str().unwrap();
+        let body = json!({
+            "authorization_endpoint": format!("http://{host}/authorize"),
+            "token_endpoint": format!("http://{host}/token"),
+            "userinfo_endpoint": format!("http://{host}/userinfo"),
+        });
+        return json_response(StatusCode::OK, body.to_string());
+    }
+
+    let expected_authorization = format!("Bearer {ACCESS_TOKEN}");
+    let authorized = req
+        .headers()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        == Some(expected_authorization.as_str());
+    let account = match (&state.account, authorized) {
+        (Some(account), true) => account,
+        _ => return json_response(StatusCode::UNAUTHORIZED, r#"{"message":"Unauthorized"}"#),
+    };
+
+    let segments = path.split('/').collect::<Vec<_>>();
+    match &*segments {
+        ["", "userinfo"] => {
+            let body = json!({
+                "sub": account.id.to_string(),
+                "preferred_username": account.login,
+                "email": account.email,
+                "email_verified": account.email.is_some() && account.email_verified,
+            });
+            json_response(StatusCode::OK, body.to_string())
+        }
+        ["", "api", "v4", "user"] => {
+            let body = json!({
+                "id": account.id,
+                "username": account.login,
+                "name": null,
+                "avatar_url": null,
+                "email": account.email,
+                "confirmed_at": account.email_verified.then(|| "2022-01-01T00:00:00Z"),
+            });
+            json_response(StatusCode::OK, body.to_string())
+        }
+        ["", "api", "v4", "groups", group_id, "members", "all", account_id] => {
+            let key = (
+                group_id.parse().unwrap_or_default(),
+                account_id.parse().unwrap_or_default(),
+            );
+            if state.members.contains(&key) {
+                json_response(StatusCode::OK, r#"{"state":"active"}"#)
+            } else {
+                not_found()
+            }
+        }
+        ["", "api", "v4", "groups", group_path] => {
+            let group_path = group_path.replace("%2F", "/");
+            match state.groups.get(&group_path) {
+                Some(id) => {
+                    let body = json!({
+                        "id": id,
+                        "full_path": group_path,
+                        "name": group_path,
+                        "avatar_url": null,
+                    });
+                    json_response(StatusCode::OK, body.to_string())
+                }
+                None => not_found(),
+            }
+        }
+        _ => not_found(),
+    }
+}
+
+fn not_found() -> Response<Body> {
+    json_response(StatusCode::NOT_FOUND, r#"{"message":"404 Not Found"}"#)
+}
@@ -42,6 +42,21 @@ impl<T> Response<T> {
         json(&mut self.response)
     }
 
+    /// Consume the response body and return it along with its `Content-Type` header
+    #[track_caller]
+    pub fn into_text(mut self) -> (String, String) {
+        let content_type = self
+            .response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .expect("Missing content-type header")
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = take_body(&mut self.response);
+        (content_type, String::from_utf8(body.into_owned()).unwrap())
+    }
+
     pub fn status(&self) -> StatusCode {
         self.response.status()
     }
@@ -78,15 +93,7 @@ fn json<T>(r: &mut AppResponse) -> T
 where
     for<'de> T: serde::Deserialize<'de>,
 {
-    use conduit::Body::*;
-
-    let mut body = Body::empty();
-    std::mem::swap(r.body_mut(), &mut body);
-    let body: std::borrow::Cow<'static, [u8]> = match body {
-        Static(slice) => slice.into(),
-        Owned(vec) => vec.into(),
-        File(_) => unimplemented!(),
-    };
+    let body = take_body(r);
 
     assert_eq!(
         r.headers()
@@ -110,3 +117,15 @@ where
         Err(e) => panic!("failed to decode: {:?}", e),
     }
 }
+
+fn take_body(r: &mut AppResponse) -> std::borrow::Cow<'static, [u8]> {
+    use conduit::Body::*;
+
+    let mut body = Body::empty();
+    std::mem::swap(r.body_mut(), &mut body);
+    match body {
+        Static(slice) => slice.into(),
+        Owned(vec) => vec.into(),
+        File(_) => unimplemented!(),
+    }
+}
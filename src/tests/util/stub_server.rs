@@ -0,0 +1,124 @@
+//! A minimal HTTP server for faking third party APIs in tests
+//!
+//! Unlike the record/replay proxy, the responses are computed by a handler function, which
+//! usually reads them from state that the test can change between requests.
+
+use futures_channel::oneshot;
+use futures_util::future;
+use hyper::{Body, Error, Request, Response, Server, StatusCode};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{mpsc, Arc},
+    task::{Context, Poll},
+    thread,
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    runtime,
+};
+
+type Handler = dyn Fn(&Request<Body>) -> Response<Body> + Send + Sync;
+
+pub struct StubServer {
+    url: String,
+    quittx: Option<oneshot::Sender<()>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl StubServer {
+    pub fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&Request<Body>) -> Response<Body> + Send + Sync + 'static,
+    {
+        let (url_tx, url_rx) = mpsc::channel();
+        let (quittx, quitrx) = oneshot::channel();
+
+        let service = StubService {
+            handler: Arc::new(handler),
+        };
+        let thread = thread::spawn(move || {
+            let rt = assert_ok!(runtime::Builder::new_current_thread().enable_io().build());
+
+            let listener = assert_ok!(rt.block_on(TcpListener::bind("127.0.0.1:0")));
+            url_tx
+                .send(format!("http://{}", assert_ok!(listener.local_addr())))
+                .unwrap();
+
+            let srv = Server::builder(hyper::server::accept::poll_fn(move |cx| {
+                listener
+                    .poll_accept(cx)
+                    .map(|res| Some(res.map(|(stream, _)| stream)))
+            }))
+            .serve(service)
+            .with_graceful_shutdown(async {
+                quitrx.await.ok();
+            });
+
+            rt.block_on(srv).ok();
+        });
+
+        Self {
+            url: url_rx.recv().unwrap(),
+            quittx: Some(quittx),
+            thread: Some(thread),
+        }
+    }
+
+    /// The base URL of the server, e.g. `http://127.0.0.1:1234`
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+}
+
+impl Drop for StubServer {
+    fn drop(&mut self) {
+        drop(self.quittx.take());
+        let res = self.thread.take().unwrap().join();
+        if res.is_err() && !thread::panicking() {
+            panic!("stub server failed");
+        }
+    }
+}
+
+/// Builds a JSON response with the given status
+pub fn json_response(status: StatusCode, body: impl Into<Body>) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(body.into())
+        .unwrap()
+}
+
+#[derive(Clone)]
+struct StubService {
+    handler: Arc<Handler>,
+}
+
+impl tower_service::Service<Request<Body>> for StubService {
+    type Response = Response<Body>;
+    type Error = Error;
+    type Future = future::Ready<Result<Response<Body>, Error>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        future::ok((self.handler)(&req))
+    }
+}
+
+impl<'a> tower_service::Service<&'a TcpStream> for StubService {
+    type Response = StubService;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<StubService, Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _: &'a TcpStream) -> Self::Future {
+        Box::pin(future::ok(self.clone()))
+    }
+}
@@ -1,4 +1,4 @@
-use super::{MockAnonymousUser, MockCookieUser, MockTokenUser};
+use super::{FakeGitHub, FakeLoginProvider, MockAnonymousUser, MockCookieUser, MockTokenUser};
 use crate::git::UpstreamIndex;
 use crate::record;
 use crate::util::{chaosproxy::ChaosProxy, fresh_schema::FreshSchema};
@@ -7,7 +7,9 @@ use cargo_registry::{
     background_jobs::Environment,
     db::DieselPool,
     git::{Credentials, RepositoryConfig},
-    App, Emails,
+    github::GitHubClient,
+    gitlab::GitLabClient,
+    login_providers, App, Emails,
 };
 use std::{rc::Rc, sync::Arc, time::Duration};
 
@@ -23,7 +25,7 @@ struct TestAppInner {
     _bomb: Option<record::Bomb>,
     middle: conduit_middleware::MiddlewareBuilder,
     index: Option<UpstreamIndex>,
-    runner: Option<Runner<Environment, DieselPool>>,
+    runner: Runner<Environment, DieselPool>,
     db_chaosproxy: Option<Arc<ChaosProxy>>,
 
     // Must be the last field of the struct!
@@ -41,13 +43,14 @@ impl Drop for TestAppInner {
         }
 
         // Lazily run any remaining jobs
-        if let Some(runner) = &self.runner {
-            runner.run_all_pending_jobs().expect("Could not run jobs");
-            runner.check_for_failed_jobs().expect("Failed jobs remain");
-        }
+        self.runner
+            .run_all_pending_jobs()
+            .expect("Could not run jobs");
+        self.runner
+            .check_for_failed_jobs()
+            .expect("Failed jobs remain");
 
         // Manually verify that all jobs have completed successfully
-        // This will catch any tests that enqueued a job but forgot to initialize the runner
         let conn = self.app.primary_database.get().unwrap();
         let job_count: i64 = background_jobs.count().get_result(&*conn).unwrap();
         assert_eq!(
@@ -74,6 +77,8 @@ impl TestApp {
             bomb: None,
             index: None,
             build_job_runner: false,
+            fake_github: false,
+            fake_login_provider: false,
         }
     }
 
@@ -116,15 +121,13 @@ impl TestApp {
                     emails::user_id.eq(user.id),
                     emails::email.eq(email),
                     emails::verified.eq(true),
+                    emails::is_primary.eq(true),
                 ))
                 .execute(conn)
                 .unwrap();
             user
         });
-        MockCookieUser {
-            app: self.clone(),
-            user,
-        }
+        MockCookieUser::new(self, user)
     }
 
     /// Obtain a reference to the upstream repository ("the index")
@@ -141,7 +144,6 @@ impl TestApp {
 
     pub fn run_pending_background_jobs(&self) {
         let runner = &self.0.runner;
-        let runner = runner.as_ref().expect("Index has not been initialized");
 
         runner.run_all_pending_jobs().expect("Could not run jobs");
         runner
@@ -172,6 +174,8 @@ pub struct TestAppBuilder {
     bomb: Option<record::Bomb>,
     index: Option<UpstreamIndex>,
     build_job_runner: bool,
+    fake_github: bool,
+    fake_login_provider: bool,
 }
 
 impl TestAppBuilder {
@@ -189,33 +193,48 @@ impl TestAppBuilder {
             (None, None)
         };
 
-        let (app, middle) = build_app(self.config, self.proxy);
+        let (app, middle) = build_app(
+            self.config,
+            self.proxy,
+            self.fake_github,
+            self.fake_login_provider,
+        );
 
-        let runner = if self.build_job_runner {
+        let environment = if self.build_job_runner {
             let repository_config = RepositoryConfig {
                 index_location: UpstreamIndex::url(),
                 credentials: Credentials::Missing,
             };
             let index = WorkerRepository::open(&repository_config).expect("Could not clone index");
-            let environment = Environment::new(
+            Environment::new(
                 index,
                 app.config.uploader().clone(),
                 app.http_client().clone(),
-            );
-
-            Some(
-                Runner::builder(environment)
-                    // We only have 1 connection in tests, so trying to run more than
-                    // 1 job concurrently will just block
-                    .thread_count(1)
-                    .connection_pool(app.primary_database.clone())
-                    .job_start_timeout(Duration::from_secs(5))
-                    .build(),
+                app.github.clone(),
+                app.gitlab.clone(),
+                app.emails.clone(),
+                app.email_token_key().into(),
             )
         } else {
-            None
+            // Emails are sent from a background job, so every test needs a runner for them
+            Environment::without_index(
+                app.config.uploader().clone(),
+                Client::new(),
+                app.github.clone(),
+                app.gitlab.clone(),
+                app.emails.clone(),
+                app.email_token_key().into(),
+            )
         };
 
+        let runner = Runner::builder(environment)
+            // We only have 1 connection in tests, so trying to run more than
+            // 1 job concurrently will just block
+            .thread_count(1)
+            .connection_pool(app.primary_database.clone())
+            .job_start_timeout(Duration::from_secs(5))
+            .build();
+
         let test_app_inner = TestAppInner {
             app,
             _fresh_schema: fresh_schema,
@@ -267,6 +286,27 @@ impl TestAppBuilder {
         })
     }
 
+    /// Send all GitHub API requests to the given fake GitHub server instead of the proxy
+    pub fn with_fake_github(mut self, github: &FakeGitHub) -> Self {
+        self.config.gh_base_url = github.url().into();
+        self.fake_github = true;
+        self
+    }
+
+    /// Enable the GitLab and OpenID Connect logins, both served by the given fake server
+    pub fn with_fake_login_provider(mut self, provider: &FakeLoginProvider) -> Self {
+        let login_provider = |name: &str| config::LoginProvider {
+            base_url: provider.url().into(),
+            client_id: "client-id".into(),
+            client_secret: "client-secret".into(),
+            redirect_url: format!("https://crates.io/{name}-redirect.html"),
+        };
+        self.config.gitlab = Some(login_provider("gitlab"));
+        self.config.oidc = Some(login_provider("oidc"));
+        self.fake_login_provider = true;
+        self
+    }
+
     pub fn with_git_index(mut self) -> Self {
         self.index = Some(UpstreamIndex::new().unwrap());
         self
@@ -296,9 +336,12 @@ fn simple_config() -> config::Server {
         base: config::Base::test(),
         db: config::DatabasePools::test_from_environment(),
         session_key: "test this has to be over 32 bytes long".to_string(),
+        email_token_key: "test key for the tokens in emails".to_string(),
         gh_client_id: dotenv::var("GH_CLIENT_ID").unwrap_or_default(),
         gh_client_secret: dotenv::var("GH_CLIENT_SECRET").unwrap_or_default(),
         gh_base_url: "http://api.github.com".to_string(),
+        gitlab: None,
+        oidc: None,
         max_upload_size: 3000,
         max_unpack_size: 2000,
         publish_rate_limit: Default::default(),
@@ -308,7 +351,11 @@ fn simple_config() -> config::Server {
         domain_name: "crates.io".into(),
         allowed_origins: Vec::new(),
         downloads_persist_interval_ms: 1000,
+        downloads_journal_path: None,
+        downloads_journal_flush_interval_ms: 1000,
+        downloads_source: config::DownloadsSource::Endpoint,
         ownership_invitations_expiration_days: 30,
+        api_token_usage_retention_days: 90,
         metrics_authorization_token: None,
         use_test_database_pool: true,
         instance_metrics_log_every_seconds: None,
@@ -316,12 +363,16 @@ fn simple_config() -> config::Server {
         blocked_routes: HashSet::new(),
         version_id_cache_size: 10000,
         version_id_cache_ttl: Duration::from_secs(5 * 60),
+        team_membership_cache_ttl: Duration::from_secs(60 * 60),
+        team_membership_max_staleness: Duration::from_secs(7 * 24 * 60 * 60),
     }
 }
 
 fn build_app(
     config: config::Server,
     proxy: Option<String>,
+    fake_github: bool,
+    fake_login_provider: bool,
 ) -> (Arc<App>, conduit_middleware::MiddlewareBuilder) {
     let client = if let Some(proxy) = proxy {
         let mut builder = Client::builder();
@@ -338,6 +389,16 @@ fn build_app(
     // the application. This will also prevent cluttering the filesystem.
     app.emails = Emails::new_in_memory();
 
+    // The fake GitHub server runs locally, so requests to it must not go through the proxy
+    if fake_github {
+        app.github = GitHubClient::new(Some(Client::new()), app.config.gh_base_url.clone());
+    }
+    if fake_login_provider {
+        let base_url = app.config.gitlab.as_ref().unwrap().base_url.clone();
+        app.gitlab = Some(GitLabClient::new(Some(Client::new()), base_url));
+        app.login_providers = login_providers::from_config(&app.config, Some(Client::new()));
+    }
+
     let app = Arc::new(app);
     let handler = cargo_registry::build_handler(Arc::clone(&app));
     (app, handler)
@@ -1,4 +1,5 @@
 use anyhow::Result;
+use chrono::NaiveDate;
 use reqwest::{blocking::Client, header};
 
 use crate::app::App;
@@ -7,12 +8,14 @@ use crate::util::errors::{internal, AppResult};
 use std::env;
 use std::fs::{self, File};
 use std::io::Cursor;
+use std::path::Path;
 use std::sync::Arc;
 
 use crate::models::Crate;
 
 const CACHE_CONTROL_IMMUTABLE: &str = "public,max-age=31536000,immutable";
 const CACHE_CONTROL_README: &str = "public,max-age=604800";
+const CACHE_CONTROL_DATA_EXPORT: &str = "private,no-store";
 
 #[derive(Clone, Debug)]
 pub enum Uploader {
@@ -71,6 +74,27 @@ impl Uploader {
         }
     }
 
+    /// Returns the URL of the archived download counts of a day.
+    ///
+    /// The function doesn't check for the existence of the file.
+    pub fn downloads_archive_location(&self, date: NaiveDate) -> String {
+        match *self {
+            Uploader::S3 {
+                ref bucket,
+                ref cdn,
+                ..
+            } => {
+                let host = match *cdn {
+                    Some(ref s) => s.clone(),
+                    None => bucket.host(),
+                };
+                let path = Uploader::downloads_archive_path(date);
+                format!("https://{host}/{path}")
+            }
+            Uploader::Local => format!("/{}", Uploader::downloads_archive_path(date)),
+        }
+    }
+
     /// Returns the internal path of an uploaded crate's version archive.
     fn crate_path(name: &str, version: &str) -> String {
         // No slash in front so we can use join
@@ -82,6 +106,19 @@ impl Uploader {
         format!("readmes/{name}/{name}-{version}.html")
     }
 
+    /// Returns the internal path of an uploaded user data export.
+    ///
+    /// Exports aren't public, they are only served through the API to the user they belong
+    /// to. The token keeps the path from being guessed anyway.
+    fn data_export_path(user_id: i32, token: &str) -> String {
+        format!("data-exports/{user_id}/{token}.json")
+    }
+
+    /// Returns the internal path of the archived download counts of a day.
+    fn downloads_archive_path(date: NaiveDate) -> String {
+        format!("archive/version-downloads/{date}.csv.gz")
+    }
+
     /// Uploads a file using the configured uploader (either `S3`, `Local`).
     ///
     /// It returns the path of the uploaded file.
@@ -122,6 +159,55 @@ impl Uploader {
         }
     }
 
+    /// Returns the paths of the files starting with `prefix`, e.g. `cdn-logs/`, that sort after
+    /// `start_after`. An empty `start_after` returns all of them.
+    pub(crate) fn list(
+        &self,
+        client: &Client,
+        prefix: &str,
+        start_after: &str,
+    ) -> Result<Vec<String>> {
+        match *self {
+            Uploader::S3 { ref bucket, .. } => Ok(bucket.list(client, prefix, start_after)?),
+            Uploader::Local => {
+                let root = env::current_dir()?.join("local_uploads");
+                let mut paths = Vec::new();
+                list_local_files(&root, &root, &mut paths)?;
+                paths.retain(|path| path.starts_with(prefix) && path.as_str() > start_after);
+                paths.sort();
+                Ok(paths)
+            }
+        }
+    }
+
+    /// Deletes a previously uploaded file. Deleting a file that doesn't exist isn't an error.
+    pub(crate) fn delete(&self, client: &Client, path: &str) -> Result<()> {
+        match *self {
+            Uploader::S3 { ref bucket, .. } => {
+                bucket.delete(client, path)?;
+                Ok(())
+            }
+            Uploader::Local => {
+                let filename = env::current_dir()?.join("local_uploads").join(path);
+                match fs::remove_file(filename) {
+                    Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+
+    /// Downloads the contents of a previously uploaded file.
+    pub(crate) fn get(&self, client: &Client, path: &str) -> Result<Vec<u8>> {
+        match *self {
+            Uploader::S3 { ref bucket, .. } => Ok(bucket.get(client, path)?.bytes()?.to_vec()),
+            Uploader::Local => {
+                let filename = env::current_dir()?.join("local_uploads").join(path);
+                Ok(fs::read(filename)?)
+            }
+        }
+    }
+
     /// Uploads a crate and returns the checksum of the uploaded crate file.
     pub fn upload_crate(
         &self,
@@ -175,4 +261,100 @@ impl Uploader {
         )?;
         Ok(())
     }
+
+    pub(crate) fn upload_data_export(
+        &self,
+        http_client: &Client,
+        user_id: i32,
+        token: &str,
+        export: Vec<u8>,
+    ) -> Result<()> {
+        let path = Uploader::data_export_path(user_id, token);
+        let content_length = export.len() as u64;
+        let content = Cursor::new(export);
+        let mut extra_headers = header::HeaderMap::new();
+        extra_headers.insert(
+            header::CACHE_CONTROL,
+            header::HeaderValue::from_static(CACHE_CONTROL_DATA_EXPORT),
+        );
+        // Only readable with the credentials of the bucket, so that neither the bucket nor the
+        // CDN serve it publicly
+        extra_headers.insert("x-amz-acl", header::HeaderValue::from_static("private"));
+        self.upload(
+            http_client,
+            &path,
+            content,
+            content_length,
+            "application/json",
+            extra_headers,
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn get_data_export(
+        &self,
+        http_client: &Client,
+        user_id: i32,
+        token: &str,
+    ) -> Result<Vec<u8>> {
+        self.get(http_client, &Uploader::data_export_path(user_id, token))
+    }
+
+    pub(crate) fn delete_data_export(
+        &self,
+        http_client: &Client,
+        user_id: i32,
+        token: &str,
+    ) -> Result<()> {
+        self.delete(http_client, &Uploader::data_export_path(user_id, token))
+    }
+
+    pub(crate) fn upload_downloads_archive(
+        &self,
+        http_client: &Client,
+        date: NaiveDate,
+        archive: Vec<u8>,
+    ) -> Result<()> {
+        let path = Uploader::downloads_archive_path(date);
+        let content_length = archive.len() as u64;
+        let content = Cursor::new(archive);
+        let mut extra_headers = header::HeaderMap::new();
+        extra_headers.insert(
+            header::CACHE_CONTROL,
+            header::HeaderValue::from_static(CACHE_CONTROL_IMMUTABLE),
+        );
+        self.upload(
+            http_client,
+            &path,
+            content,
+            content_length,
+            "application/gzip",
+            extra_headers,
+        )?;
+        Ok(())
+    }
+}
+
+/// Collects the paths of the files in `dir` relative to `root`, using `/` as separator like the
+/// keys of S3 objects.
+fn list_local_files(root: &Path, dir: &Path, paths: &mut Vec<String>) -> std::io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            list_local_files(root, &path, paths)?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            let components = relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>();
+            paths.push(components.join("/"));
+        }
+    }
+    Ok(())
 }
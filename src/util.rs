@@ -12,7 +12,9 @@ mod io_util;
 mod request_helpers;
 mod request_proxy;
 pub mod rfc3339;
+pub(crate) mod signed_token;
 pub(crate) mod token;
+pub mod totp;
 
 pub type AppResponse = Response<conduit::Body>;
 pub type EndpointResult = Result<AppResponse, Box<dyn errors::AppError>>;
@@ -33,6 +35,17 @@ pub fn json_response<T: Serialize>(t: &T) -> AppResponse {
         .unwrap() // Header values are well formed, so should not panic
 }
 
+/// Build a status 200 Response with a CSV body
+///
+/// This helper sets appropriate values for `Content-Type` and `Content-Length`.
+pub fn csv_response(csv: String) -> AppResponse {
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/csv; charset=utf-8")
+        .header(header::CONTENT_LENGTH, csv.len())
+        .body(Body::from_vec(csv.into_bytes()))
+        .unwrap() // Header values are well formed, so should not panic
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct Maximums {
     pub max_upload_size: u64,
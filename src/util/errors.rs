@@ -65,6 +65,12 @@ pub fn not_found() -> Box<dyn AppError> {
     Box::new(json::NotFound)
 }
 
+/// Returns an error with status 403 and the provided description as JSON, for requests that
+/// lack a valid two-factor authentication code
+pub fn two_factor_required<S: ToString + ?Sized>(error: &S) -> Box<dyn AppError> {
+    Box::new(json::TwoFactorRequired(error.to_string()))
+}
+
 /// Returns an error with status 500 and the provided description as JSON
 pub fn server_error<S: ToString + ?Sized>(error: &S) -> Box<dyn AppError> {
     Box::new(json::ServerError(error.to_string()))
@@ -82,6 +82,8 @@ pub(super) struct ServerError(pub(super) String);
 #[derive(Debug)]
 pub(crate) struct ServiceUnavailable(pub(super) String);
 #[derive(Debug)]
+pub(super) struct TwoFactorRequired(pub(super) String);
+#[derive(Debug)]
 pub(crate) struct TooManyRequests {
     pub retry_after: NaiveDateTime,
 }
@@ -122,6 +124,18 @@ impl fmt::Display for ServerError {
     }
 }
 
+impl AppError for TwoFactorRequired {
+    fn response(&self) -> Option<AppResponse> {
+        Some(json_error(&self.0, StatusCode::FORBIDDEN))
+    }
+}
+
+impl fmt::Display for TwoFactorRequired {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
 impl AppError for ServiceUnavailable {
     fn response(&self) -> Option<AppResponse> {
         Some(json_error(&self.0, StatusCode::SERVICE_UNAVAILABLE))
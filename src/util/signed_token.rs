@@ -0,0 +1,89 @@
+//! Tokens that carry their own proof of authenticity, so that they don't have to be stored in
+//! the database. They are used for links in emails that work without logging in, like the
+//! unsubscribe links of the weekly digest.
+//!
+//! A token has the format `{value}.{expires_at}.{signature}`, where `expires_at` is a Unix
+//! timestamp and the signature is the hex encoded HMAC-SHA256 of the value and the expiry. The
+//! purpose of the token is part of the signed message, so that a token can't be used for
+//! anything else than what it was created for.
+//!
+//! The key must not be shared with anything else, like the session cookies, so that leaking
+//! or rotating one of them doesn't affect the other.
+
+use chrono::{NaiveDateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Creates a token for `value` signed with `key`, which is valid until `expires_at`.
+pub(crate) fn sign(key: &str, purpose: &str, value: &str, expires_at: NaiveDateTime) -> String {
+    let expires_at = expires_at.timestamp();
+    let signature = mac(key, purpose, value, expires_at).finalize().into_bytes();
+    format!("{value}.{expires_at}.{}", hex::encode(signature))
+}
+
+/// Returns the value of the token if its signature is valid and it didn't expire yet.
+pub(crate) fn verify<'a>(key: &str, purpose: &str, token: &'a str) -> Option<&'a str> {
+    let (rest, signature) = token.rsplit_once('.')?;
+    let (value, expires_at) = rest.rsplit_once('.')?;
+    let expires_at = expires_at.parse().ok()?;
+    let signature = hex::decode(signature).ok()?;
+
+    // `verify_slice` compares in constant time, to avoid leaking how much of the signature
+    // is correct
+    mac(key, purpose, value, expires_at)
+        .verify_slice(&signature)
+        .ok()?;
+
+    (Utc::now().timestamp() < expires_at).then(|| value)
+}
+
+fn mac(key: &str, purpose: &str, value: &str, expires_at: i64) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC accepts any key size");
+    mac.update(format!("{purpose}:{value}.{expires_at}").as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn in_a_day() -> NaiveDateTime {
+        Utc::now().naive_utc() + Duration::days(1)
+    }
+
+    #[test]
+    fn signed_tokens_can_be_verified() {
+        let token = sign("secret", "test", "42", in_a_day());
+        assert!(token.starts_with("42."));
+        assert_eq!(verify("secret", "test", &token), Some("42"));
+    }
+
+    #[test]
+    fn tampered_tokens_are_rejected() {
+        let expires_at = in_a_day();
+        let token = sign("secret", "test", "42", expires_at);
+        let signature = token.rsplit_once('.').unwrap().1;
+        let later = (expires_at + Duration::days(1)).timestamp();
+
+        assert_none!(verify("other secret", "test", &token));
+        assert_none!(verify("secret", "other purpose", &token));
+        assert_none!(verify(
+            "secret",
+            "test",
+            &format!("43.{}.{signature}", expires_at.timestamp())
+        ));
+        assert_none!(verify("secret", "test", &format!("42.{later}.{signature}")));
+        assert_none!(verify("secret", "test", "42"));
+        assert_none!(verify("secret", "test", "42.1.not-hex"));
+    }
+
+    #[test]
+    fn expired_tokens_are_rejected() {
+        let expires_at = Utc::now().naive_utc() - Duration::seconds(1);
+        let token = sign("secret", "test", "42", expires_at);
+        assert_none!(verify("secret", "test", &token));
+    }
+}
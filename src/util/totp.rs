@@ -0,0 +1,213 @@
+//! Time-based one-time passwords as described in RFC 6238, used as a second factor for sensitive
+//! actions of users that enrolled.
+//!
+//! Codes have six digits and change every 30 seconds. HMAC-SHA1 is used as the hash function,
+//! since it is the default of RFC 6238 and many authenticator apps ignore the `algorithm`
+//! parameter of the provisioning URI.
+
+use hmac::{Hmac, Mac};
+use rand::{distributions::Uniform, rngs::OsRng, Rng, RngCore};
+use sha1::Sha1;
+
+const SECRET_LENGTH: usize = 20;
+const DIGITS: u32 = 6;
+const STEP_SECONDS: i64 = 30;
+
+/// How many steps a code may be off, to account for clock drift and slow typing.
+const ALLOWED_DRIFT: i64 = 1;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+/// Generates a random secret for a new enrollment.
+pub fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0; SECRET_LENGTH];
+    OsRng.fill_bytes(&mut secret);
+    secret
+}
+
+/// Returns the `otpauth://` URI that authenticator apps use to set up the secret, usually by
+/// scanning it as a QR code.
+pub fn provisioning_uri(secret: &[u8], account: &str) -> String {
+    let issuer = "crates.io";
+    let account = url::form_urlencoded::byte_serialize(account.as_bytes()).collect::<String>();
+    let secret = encode_base32(secret);
+    let parameters = format!("algorithm=SHA1&digits={DIGITS}&period={STEP_SECONDS}");
+    format!("otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&{parameters}")
+}
+
+/// Returns the time step of a unix timestamp.
+pub fn step_at(timestamp: i64) -> i64 {
+    timestamp.div_euclid(STEP_SECONDS)
+}
+
+/// Returns the code of a time step.
+pub fn code_at(secret: &[u8], step: i64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts any key size");
+    mac.update(&step.to_be_bytes());
+    let mac = mac.finalize().into_bytes();
+
+    // Dynamic truncation, see section 5.3 of RFC 4226
+    let offset = (mac[mac.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        mac[offset],
+        mac[offset + 1],
+        mac[offset + 2],
+        mac[offset + 3],
+    ]) & 0x7fff_ffff;
+
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+/// Returns the time step that `code` belongs to, if it is valid at `timestamp`.
+///
+/// Steps up to `last_used_step` are rejected, so that a code can't be used twice.
+pub fn verify(
+    secret: &[u8],
+    code: &str,
+    timestamp: i64,
+    last_used_step: Option<i64>,
+) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize {
+        return None;
+    }
+
+    let current = step_at(timestamp);
+    (current - ALLOWED_DRIFT..=current + ALLOWED_DRIFT)
+        .filter(|step| last_used_step.map_or(true, |last| *step > last))
+        .find(|step| constant_time_eq(code_at(secret, *step).as_bytes(), code.as_bytes()))
+}
+
+/// Generates the single-use codes that can be used instead of a TOTP code, e.g. when the
+/// authenticator app was lost.
+pub fn generate_recovery_codes() -> Vec<String> {
+    const CHARS: &[u8] = b"abcdefghijkmnpqrstuvwxyz23456789";
+
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code: String = OsRng
+                .sample_iter(Uniform::from(0..CHARS.len()))
+                .map(|idx| CHARS[idx] as char)
+                .take(RECOVERY_CODE_LENGTH)
+                .collect();
+            let (first, second) = code.split_at(RECOVERY_CODE_LENGTH / 2);
+            format!("{first}-{second}")
+        })
+        .collect()
+}
+
+/// Normalizes a recovery code as entered by a user, so that its hash can be looked up.
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.trim().to_lowercase()
+}
+
+/// Encodes bytes as base32 without padding, as described in RFC 4648. Authenticator apps expect
+/// secrets in this format when they are entered manually.
+pub fn encode_base32(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+    let mut encoded = String::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for byte in bytes {
+        buffer = (buffer << 8) | u32::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    encoded
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The SHA-1 seed of the test vectors in appendix B of RFC 6238
+    const SEED: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn codes_match_rfc_6238_test_vectors() {
+        // The RFC uses eight digits, the last six of them are the same as with six digits
+        assert_eq!(code_at(SEED, step_at(59)), "287082");
+        assert_eq!(code_at(SEED, step_at(1111111109)), "081804");
+        assert_eq!(code_at(SEED, step_at(1234567890)), "005924");
+        assert_eq!(code_at(SEED, step_at(20000000000)), "353130");
+    }
+
+    #[test]
+    fn codes_of_neighbouring_steps_are_accepted() {
+        let timestamp = 1111111109;
+        let step = step_at(timestamp);
+
+        let code = code_at(SEED, step);
+        assert_eq!(verify(SEED, &code, timestamp, None), Some(step));
+        assert_eq!(
+            verify(SEED, &format!(" {code}\n"), timestamp, None),
+            Some(step)
+        );
+
+        let previous = code_at(SEED, step - 1);
+        assert_eq!(verify(SEED, &previous, timestamp, None), Some(step - 1));
+
+        let too_old = code_at(SEED, step - 2);
+        assert_none!(verify(SEED, &too_old, timestamp, None));
+    }
+
+    #[test]
+    fn used_codes_are_rejected() {
+        let timestamp = 1111111109;
+        let step = step_at(timestamp);
+        let code = code_at(SEED, step);
+
+        assert_none!(verify(SEED, &code, timestamp, Some(step)));
+        assert_eq!(verify(SEED, &code, timestamp, Some(step - 1)), Some(step));
+    }
+
+    #[test]
+    fn malformed_codes_are_rejected() {
+        assert_none!(verify(SEED, "", 59, None));
+        assert_none!(verify(SEED, "1192460", 59, None));
+        assert_none!(verify(SEED, "abcdef", 59, None));
+    }
+
+    #[test]
+    fn base32_matches_rfc_4648_test_vectors() {
+        assert_eq!(encode_base32(b""), "");
+        assert_eq!(encode_base32(b"f"), "MY");
+        assert_eq!(encode_base32(b"fo"), "MZXQ");
+        assert_eq!(encode_base32(b"foo"), "MZXW6");
+        assert_eq!(encode_base32(b"foob"), "MZXW6YQ");
+        assert_eq!(encode_base32(b"fooba"), "MZXW6YTB");
+        assert_eq!(encode_base32(b"foobar"), "MZXW6YTBOI");
+    }
+
+    #[test]
+    fn recovery_codes_are_unique() {
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        assert!(codes
+            .iter()
+            .all(|code| code.len() == RECOVERY_CODE_LENGTH + 1));
+
+        let mut deduplicated = codes.clone();
+        deduplicated.sort();
+        deduplicated.dedup();
+        assert_eq!(deduplicated.len(), codes.len());
+
+        assert_eq!(normalize_recovery_code(" ABCDE-fghij "), "abcde-fghij");
+    }
+}
@@ -2,12 +2,12 @@ use chrono::NaiveDateTime;
 use std::collections::HashMap;
 use url::Url;
 
-use crate::github;
 use crate::models::{
-    Badge, Category, Crate, CrateOwnerInvitation, CreatedApiToken, Dependency, DependencyKind,
-    Keyword, Owner, ReverseDependency, Team, TopVersions, User, Version, VersionDownload,
-    VersionOwnerAction,
+    ApiTokenUsage, Badge, Category, Crate, CrateOwnerInvitation, CreatedApiToken, DataExport,
+    Dependency, DependencyKind, Email, Keyword, Owner, ReverseDependency, Session, Team,
+    TopVersions, User, Version, VersionDownload, VersionDownloadsArchive, VersionOwnerAction,
 };
+use crate::uploaders::Uploader;
 use crate::util::rfc3339;
 
 /// Hosts in this list are known to not be hosting documentation,
@@ -181,6 +181,26 @@ impl From<VersionDownload> for EncodableVersionDownload {
     }
 }
 
+/// The serialization format for the `VersionDownloadsArchive` model.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EncodableVersionDownloadsArchive {
+    pub date: String,
+    pub rows: i32,
+    pub downloads: i64,
+    pub url: String,
+}
+
+impl EncodableVersionDownloadsArchive {
+    pub fn from(archive: VersionDownloadsArchive, uploader: &Uploader) -> Self {
+        Self {
+            date: archive.date.to_string(),
+            rows: archive.rows,
+            downloads: archive.downloads,
+            url: uploader.downloads_archive_location(archive.date),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct EncodableKeyword {
     pub id: String,
@@ -398,14 +418,15 @@ impl From<Owner> for EncodableOwner {
                     kind: String::from("user"),
                 }
             }
-            Owner::Team(Team {
-                id,
-                name,
-                login,
-                avatar,
-                ..
-            }) => {
-                let url = github::team_url(&login);
+            Owner::Team(team) => {
+                let url = team.url();
+                let Team {
+                    id,
+                    name,
+                    login,
+                    avatar,
+                    ..
+                } = team;
                 Self {
                     id,
                     login,
@@ -430,6 +451,7 @@ pub struct EncodableTeam {
 
 impl From<Team> for EncodableTeam {
     fn from(team: Team) -> Self {
+        let url = team.url();
         let Team {
             id,
             name,
@@ -437,7 +459,6 @@ impl From<Team> for EncodableTeam {
             avatar,
             ..
         } = team;
-        let url = github::team_url(&login);
 
         EncodableTeam {
             id,
@@ -477,6 +498,110 @@ impl From<CreatedApiToken> for EncodableApiTokenWithToken {
     }
 }
 
+/// The serialization format for the `ApiTokenUsage` model.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct EncodableApiTokenUsage {
+    #[serde(with = "rfc3339")]
+    pub used_at: NaiveDateTime,
+    pub endpoint: String,
+    pub crate_name: Option<String>,
+    pub ip_address: String,
+    pub user_agent: String,
+}
+
+impl From<ApiTokenUsage> for EncodableApiTokenUsage {
+    fn from(usage: ApiTokenUsage) -> Self {
+        EncodableApiTokenUsage {
+            used_at: usage.used_at,
+            endpoint: usage.endpoint,
+            crate_name: usage.crate_name,
+            ip_address: usage.ip_address,
+            user_agent: usage.user_agent,
+        }
+    }
+}
+
+/// The serialization format for the `DataExport` model.
+///
+/// The `url` is only set while the archive can be downloaded. It points to an API route that
+/// requires logging in, since the archive isn't public.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct EncodableDataExport {
+    #[serde(with = "rfc3339")]
+    pub requested_at: NaiveDateTime,
+    #[serde(with = "rfc3339::option")]
+    pub completed_at: Option<NaiveDateTime>,
+    #[serde(with = "rfc3339::option")]
+    pub expires_at: Option<NaiveDateTime>,
+    pub url: Option<String>,
+}
+
+impl From<DataExport> for EncodableDataExport {
+    fn from(export: DataExport) -> Self {
+        let url = export
+            .is_downloadable()
+            .then(|| "/api/v1/me/data_export/download".to_string());
+
+        EncodableDataExport {
+            requested_at: export.requested_at,
+            completed_at: export.completed_at,
+            expires_at: export.expires_at(),
+            url,
+        }
+    }
+}
+
+/// The serialization format for the `Email` model.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct EncodableEmail {
+    pub id: i32,
+    pub email: String,
+    pub verified: bool,
+    pub verification_sent: bool,
+    pub primary: bool,
+    pub notifications: bool,
+}
+
+impl From<Email> for EncodableEmail {
+    fn from(email: Email) -> Self {
+        EncodableEmail {
+            id: email.id,
+            email: email.email,
+            verified: email.verified,
+            verification_sent: email.verified || email.token_generated_at.is_some(),
+            primary: email.is_primary,
+            notifications: email.send_notifications,
+        }
+    }
+}
+
+/// The serialization format for the `Session` model.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct EncodableSession {
+    pub id: i32,
+    #[serde(with = "rfc3339")]
+    pub created_at: NaiveDateTime,
+    #[serde(with = "rfc3339")]
+    pub last_used_at: NaiveDateTime,
+    pub user_agent: String,
+    pub ip_address: String,
+    /// Whether this is the session of the request
+    pub current: bool,
+}
+
+impl EncodableSession {
+    pub fn from(session: Session, current_session_id: Option<i32>) -> Self {
+        EncodableSession {
+            current: current_session_id == Some(session.id),
+            id: session.id,
+            created_at: session.created_at,
+            last_used_at: session.last_used_at,
+            user_agent: session.user_agent,
+            ip_address: session.ip_address,
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct OwnedCrate {
     pub id: i32,
@@ -503,6 +628,7 @@ pub struct EncodablePrivateUser {
     pub email: Option<String>,
     pub avatar: Option<String>,
     pub url: Option<String>,
+    pub weekly_digest: bool,
 }
 
 impl EncodablePrivateUser {
@@ -518,6 +644,7 @@ impl EncodablePrivateUser {
             name,
             gh_login,
             gh_avatar,
+            weekly_digest,
             ..
         } = user;
         let url = format!("https://github.com/{gh_login}");
@@ -531,6 +658,7 @@ impl EncodablePrivateUser {
             login: gh_login,
             name,
             url: Some(url),
+            weekly_digest,
         }
     }
 }
@@ -570,7 +698,8 @@ impl From<User> for EncodablePublicUser {
 #[derive(Deserialize, Serialize, Debug)]
 pub struct EncodableAuditAction {
     pub action: String,
-    pub user: EncodablePublicUser,
+    /// `None` if the user deleted their account
+    pub user: Option<EncodablePublicUser>,
     #[serde(with = "rfc3339")]
     pub time: NaiveDateTime,
 }
@@ -593,6 +722,7 @@ pub struct EncodableVersion {
     pub yanked: bool,
     // NOTE: Used by shields.io, altering `license` requires a PR with shields.io
     pub license: Option<String>,
+    pub rust_version: Option<String>,
     pub links: EncodableVersionLinks,
     pub crate_size: Option<i32>,
     pub published_by: Option<EncodablePublicUser>,
@@ -604,7 +734,7 @@ impl EncodableVersion {
         version: Version,
         crate_name: &str,
         published_by: Option<User>,
-        audit_actions: Vec<(VersionOwnerAction, User)>,
+        audit_actions: Vec<(VersionOwnerAction, Option<User>)>,
     ) -> Self {
         let Version {
             id,
@@ -616,6 +746,7 @@ impl EncodableVersion {
             yanked,
             license,
             crate_size,
+            rust_version,
             ..
         } = version;
 
@@ -637,6 +768,7 @@ impl EncodableVersion {
             features,
             yanked,
             license,
+            rust_version,
             links,
             crate_size,
             published_by: published_by.map(User::into),
@@ -644,7 +776,7 @@ impl EncodableVersion {
                 .into_iter()
                 .map(|(audit_action, user)| EncodableAuditAction {
                     action: audit_action.action.into(),
-                    user: user.into(),
+                    user: user.map(User::into),
                     time: audit_action.time,
                 })
                 .collect(),
@@ -743,6 +875,7 @@ mod tests {
             features: serde_json::from_str("{}").unwrap(),
             yanked: false,
             license: None,
+            rust_version: None,
             links: EncodableVersionLinks {
                 dependencies: "".to_string(),
                 version_downloads: "".to_string(),
@@ -752,13 +885,13 @@ mod tests {
             published_by: None,
             audit_actions: vec![EncodableAuditAction {
                 action: "publish".to_string(),
-                user: EncodablePublicUser {
+                user: Some(EncodablePublicUser {
                     id: 0,
                     login: String::new(),
                     name: None,
                     avatar: None,
                     url: None,
-                },
+                }),
                 time: NaiveDate::from_ymd(2017, 1, 6).and_hms(14, 23, 12),
             }],
         };
@@ -30,6 +30,8 @@ pub struct EncodableCrateUpload {
     pub license: Option<String>,
     pub license_file: Option<String>,
     pub repository: Option<String>,
+    #[serde(default)]
+    pub rust_version: Option<String>,
     pub badges: Option<HashMap<String, HashMap<String, String>>>,
     #[serde(default)]
     pub links: Option<String>,
@@ -0,0 +1,25 @@
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use swirl::PerformError;
+
+use crate::schema::api_token_usages;
+
+/// Deletes the recorded usages of API tokens that are older than `retention_days`.
+///
+/// Needs to be enqueued daily, by running `enqueue-job prune_api_token_usages` from a scheduler,
+/// which passes `API_TOKEN_USAGE_RETENTION_DAYS` like the server uses it. Usages that weren't
+/// deleted yet are not listed anyway.
+#[swirl::background_job]
+pub fn prune_api_token_usages(
+    conn: &PgConnection,
+    retention_days: i64,
+) -> Result<(), PerformError> {
+    let cutoff = Utc::now().naive_utc() - Duration::days(retention_days);
+
+    let deleted =
+        diesel::delete(api_token_usages::table.filter(api_token_usages::used_at.lt(cutoff)))
+            .execute(conn)?;
+    info!("Deleted {deleted} API token usages older than {retention_days} days");
+
+    Ok(())
+}
@@ -0,0 +1,463 @@
+//! Counts the downloads that were served by the CDN without going through the `download`
+//! endpoint, using the access logs that the CDNs write to the storage bucket.
+//!
+//! CloudFront logs are expected below `{prefix}/cloudfront/` and Fastly logs below
+//! `{prefix}/fastly/`, either plain or gzip compressed. Every log file is only counted once: the
+//! processed files are recorded in the `processed_cdn_log_files` table.
+//!
+//! The logs are only ingested with `DOWNLOADS_SOURCE=cdn-logs`, since the `download` endpoint
+//! counts the same downloads otherwise. See `config::DownloadsSource` for the cutover.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+
+use anyhow::{anyhow, Result};
+use chrono::{Duration, NaiveDate, Utc};
+use diesel::dsl::any;
+use diesel::pg::upsert::excluded;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{Nullable, Text, Timestamp};
+use flate2::read::GzDecoder;
+use percent_encoding::percent_decode_str;
+use reqwest::blocking::Client;
+use swirl::{Job, PerformError};
+
+use crate::background_jobs::Environment;
+use crate::schema::{crates, processed_cdn_log_files, version_downloads, versions};
+use crate::uploaders::Uploader;
+
+/// Enqueues an `ingest_cdn_log_file` job for each log file below `prefix` that was not processed
+/// yet. Downloads before `since` are not counted, see `config::DownloadsSource`.
+#[swirl::background_job]
+pub fn ingest_cdn_logs(
+    conn: &PgConnection,
+    env: &Environment,
+    prefix: String,
+    since: NaiveDate,
+) -> Result<(), PerformError> {
+    let paths = pending_files(conn, &env.uploader, env.http_client(), &prefix)?;
+    for path in &paths {
+        ingest_cdn_log_file(prefix.clone(), path.clone(), since).enqueue(conn)?;
+    }
+    println!("Enqueued the ingestion of {} CDN log files", paths.len());
+    Ok(())
+}
+
+/// Counts the downloads of a single log file.
+///
+/// Every file is ingested by its own job, so that the downloads of each file are committed
+/// together with the file being marked as processed, instead of all files being committed at
+/// once when a long job finishes.
+#[swirl::background_job]
+pub fn ingest_cdn_log_file(
+    conn: &PgConnection,
+    env: &Environment,
+    prefix: String,
+    path: String,
+    since: NaiveDate,
+) -> Result<(), PerformError> {
+    if ingest_file(
+        conn,
+        &env.uploader,
+        env.http_client(),
+        &prefix,
+        &path,
+        since,
+    )? {
+        println!("Counted the downloads of the CDN log file {path}");
+    }
+    Ok(())
+}
+
+/// How long after its requests a log file may be delivered, at most. CloudFront delivers most
+/// files within an hour, and all of them within 24 hours.
+const MAX_DELIVERY_DELAY_DAYS: i64 = 2;
+
+/// Returns the log files below `prefix` that were not processed yet.
+///
+/// Listing all files would get slower with every file the CDNs write, so only the files after the
+/// greatest path that was processed `MAX_DELIVERY_DELAY_DAYS` ago are listed. This relies on the
+/// names of the files sorting by the time of their requests, like the
+/// `{distribution}.{YYYY-MM-DD-HH}.{id}.gz` names of a CloudFront distribution and Fastly paths
+/// starting with `%Y-%m-%dT%H`.
+pub fn pending_files(
+    conn: &PgConnection,
+    uploader: &Uploader,
+    http_client: &Client,
+    prefix: &str,
+) -> Result<Vec<String>> {
+    let prefix = prefix.trim_end_matches('/');
+    let cutoff = Utc::now().naive_utc() - Duration::days(MAX_DELIVERY_DELAY_DAYS);
+
+    let mut paths = Vec::new();
+    for format in [LogFormat::CloudFront, LogFormat::Fastly] {
+        let dir = format.dir(prefix);
+        let marker = sql_query(include_str!("cdn_logs_list_marker.sql"))
+            .bind::<Text, _>(&dir)
+            .bind::<Timestamp, _>(cutoff)
+            .get_result::<ListMarker>(conn)?;
+        paths.extend(uploader.list(http_client, &dir, marker.path.as_deref().unwrap_or(""))?);
+    }
+
+    let processed: HashSet<String> = processed_cdn_log_files::table
+        .select(processed_cdn_log_files::path)
+        .filter(processed_cdn_log_files::path.eq(any(&paths)))
+        .load::<String>(conn)?
+        .into_iter()
+        .collect();
+    paths.retain(|path| !processed.contains(path));
+
+    Ok(paths)
+}
+
+#[derive(QueryableByName)]
+struct ListMarker {
+    #[sql_type = "Nullable<Text>"]
+    path: Option<String>,
+}
+
+/// Counts the downloads of `since` and later days in a log file, returning `false` if the file
+/// was processed already, e.g. by a duplicate job.
+pub fn ingest_file(
+    conn: &PgConnection,
+    uploader: &Uploader,
+    http_client: &Client,
+    prefix: &str,
+    path: &str,
+    since: NaiveDate,
+) -> Result<bool> {
+    let prefix = prefix.trim_end_matches('/');
+    let format =
+        LogFormat::from_path(prefix, path).ok_or_else(|| anyhow!("unknown CDN log file {path}"))?;
+
+    let processed = processed_cdn_log_files::table
+        .find(path)
+        .select(processed_cdn_log_files::path)
+        .first::<String>(conn)
+        .optional()?;
+    if processed.is_some() {
+        return Ok(false);
+    }
+
+    let contents = decompress(uploader.get(http_client, path)?)?;
+    let mut downloads = format.count_downloads(&contents);
+    downloads.retain(|(_, _, date), _| *date >= since);
+    Ok(save_downloads(conn, path, &downloads)?)
+}
+
+/// The downloads of a log file, by crate name, version number and date.
+pub type Downloads = HashMap<(String, String, NaiveDate), i32>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// The standard log file format of CloudFront: tab separated values, with the names of the
+    /// fields in a `#Fields:` comment.
+    CloudFront,
+    /// One JSON object per line, with at least the `date_time`, `method`, `url` and `status`
+    /// fields.
+    Fastly,
+}
+
+impl LogFormat {
+    /// The directory below `prefix` with the log files of this format.
+    fn dir(self, prefix: &str) -> String {
+        match self {
+            LogFormat::CloudFront => format!("{prefix}/cloudfront/"),
+            LogFormat::Fastly => format!("{prefix}/fastly/"),
+        }
+    }
+
+    /// Returns the format of a log file, based on the directory below `prefix` it's in.
+    fn from_path(prefix: &str, path: &str) -> Option<Self> {
+        [LogFormat::CloudFront, LogFormat::Fastly]
+            .into_iter()
+            .find(|format| path.starts_with(&format.dir(prefix)))
+    }
+
+    /// Counts the successful requests for crate files in a log file. Lines that can't be parsed
+    /// are skipped.
+    pub fn count_downloads(self, contents: &str) -> Downloads {
+        let requests: Box<dyn Iterator<Item = Request> + '_> = match self {
+            LogFormat::CloudFront => Box::new(cloudfront_requests(contents)),
+            LogFormat::Fastly => Box::new(fastly_requests(contents)),
+        };
+
+        let mut downloads = Downloads::new();
+        for request in requests {
+            if request.method != "GET" || request.status != 200 {
+                continue;
+            }
+            if let Some((name, version)) = parse_crate_path(&request.path) {
+                *downloads.entry((name, version, request.date)).or_default() += 1;
+            }
+        }
+        downloads
+    }
+}
+
+struct Request {
+    date: NaiveDate,
+    method: String,
+    path: String,
+    status: u16,
+}
+
+fn cloudfront_requests(contents: &str) -> impl Iterator<Item = Request> + '_ {
+    // The order of the fields in the default configuration, used if the header is missing
+    let mut fields: Vec<&str> = vec![
+        "date",
+        "time",
+        "x-edge-location",
+        "sc-bytes",
+        "c-ip",
+        "cs-method",
+        "cs(Host)",
+        "cs-uri-stem",
+        "sc-status",
+    ];
+
+    contents.lines().filter_map(move |line| {
+        if let Some(header) = line.strip_prefix("#Fields:") {
+            fields = header.split_whitespace().collect();
+            return None;
+        }
+        if line.starts_with('#') {
+            return None;
+        }
+
+        let values = line.split('\t').collect::<Vec<_>>();
+        let field = |name: &str| {
+            let idx = fields.iter().position(|field| *field == name)?;
+            values.get(idx).copied()
+        };
+
+        Some(Request {
+            date: field("date")?.parse().ok()?,
+            method: field("cs-method")?.into(),
+            path: field("cs-uri-stem")?.into(),
+            status: field("sc-status")?.parse().ok()?,
+        })
+    })
+}
+
+fn fastly_requests(contents: &str) -> impl Iterator<Item = Request> + '_ {
+    #[derive(Deserialize)]
+    struct Line {
+        date_time: String,
+        method: String,
+        url: String,
+        status: u16,
+    }
+
+    contents.lines().filter_map(|line| {
+        let line: Line = serde_json::from_str(line).ok()?;
+        let path = line.url.split('?').next().unwrap_or_default();
+        Some(Request {
+            date: line.date_time.get(..10)?.parse().ok()?,
+            method: line.method,
+            path: path.into(),
+            status: line.status,
+        })
+    })
+}
+
+/// Extracts the crate name and version number from the path of a crate file, e.g.
+/// `/crates/foo/foo-1.0.0.crate`.
+///
+/// Versions that aren't valid semver are rejected, like the paths of files that don't exist.
+pub fn parse_crate_path(path: &str) -> Option<(String, String)> {
+    let path = percent_decode_str(path).decode_utf8().ok()?;
+    let (name, file) = path.strip_prefix("/crates/")?.split_once('/')?;
+    let version = file
+        .strip_prefix(name)?
+        .strip_prefix('-')?
+        .strip_suffix(".crate")?;
+    if name.is_empty() || semver::Version::parse(version).is_err() {
+        return None;
+    }
+    Some((name.into(), version.into()))
+}
+
+fn decompress(contents: Vec<u8>) -> Result<String> {
+    if contents.starts_with(&[0x1f, 0x8b]) {
+        let mut decompressed = String::new();
+        GzDecoder::new(contents.as_slice()).read_to_string(&mut decompressed)?;
+        Ok(decompressed)
+    } else {
+        Ok(String::from_utf8(contents)?)
+    }
+}
+
+/// Adds the downloads of a log file to `version_downloads` and marks the file as processed.
+///
+/// Returns `false` if the file was processed concurrently by another job. Downloads of versions
+/// that don't exist (anymore) are skipped.
+fn save_downloads(conn: &PgConnection, path: &str, downloads: &Downloads) -> QueryResult<bool> {
+    let names = downloads
+        .keys()
+        .map(|(name, _, _)| name.as_str())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+    let version_ids: HashMap<(String, String), i32> = versions::table
+        .inner_join(crates::table)
+        .select((crates::name, versions::num, versions::id))
+        .filter(crates::name.eq_any(names))
+        .load::<(String, String, i32)>(conn)?
+        .into_iter()
+        .map(|(name, num, id)| ((name, num), id))
+        .collect();
+
+    let mut counts: HashMap<(i32, NaiveDate), i32> = HashMap::new();
+    for ((name, version, date), count) in downloads {
+        if let Some(&version_id) = version_ids.get(&(name.clone(), version.clone())) {
+            *counts.entry((version_id, *date)).or_default() += count;
+        }
+    }
+
+    // Sorted like in `DownloadsCounter::persist_shard` to avoid deadlocks with concurrent inserts
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort();
+
+    let total = counts.iter().map(|(_, count)| count).sum::<i32>();
+    let values = counts
+        .iter()
+        .map(|((version_id, date), count)| {
+            (
+                version_downloads::version_id.eq(version_id),
+                version_downloads::date.eq(date),
+                version_downloads::downloads.eq(count),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    conn.transaction(|| {
+        let inserted = diesel::insert_into(processed_cdn_log_files::table)
+            .values((
+                processed_cdn_log_files::path.eq(path),
+                processed_cdn_log_files::downloads.eq(total),
+            ))
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+        if inserted == 0 {
+            return Ok(false);
+        }
+
+        // Rows of past days were already marked as processed by `update_downloads`, so they
+        // have to be reset for the new downloads to be counted.
+        diesel::insert_into(version_downloads::table)
+            .values(&values)
+            .on_conflict((version_downloads::version_id, version_downloads::date))
+            .do_update()
+            .set((
+                version_downloads::downloads
+                    .eq(version_downloads::downloads + excluded(version_downloads::downloads)),
+                version_downloads::processed.eq(false),
+            ))
+            .execute(conn)?;
+
+        Ok(true)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crate_paths() {
+        let parse = |path| parse_crate_path(path);
+        let expected = |name: &str, version: &str| Some((name.into(), version.into()));
+
+        assert_eq!(
+            parse("/crates/foo/foo-1.0.0.crate"),
+            expected("foo", "1.0.0")
+        );
+        assert_eq!(
+            parse("/crates/foo-bar/foo-bar-0.1.0-beta.1.crate"),
+            expected("foo-bar", "0.1.0-beta.1")
+        );
+        assert_eq!(
+            parse("/crates/foo/foo-1.0.0%2Bbuild.crate"),
+            expected("foo", "1.0.0+build")
+        );
+        assert_eq!(parse("/crates/foo/bar-1.0.0.crate"), None);
+        assert_eq!(parse("/crates/foo/foo-1.0.0.tar.gz"), None);
+        assert_eq!(parse("/readmes/foo/foo-1.0.0.html"), None);
+        assert_eq!(parse("/crates/foo/foo-%zz.crate"), None);
+    }
+
+    #[test]
+    fn cloudfront_downloads() {
+        let line = |date, method, path, status| {
+            let fields = [
+                date,
+                "10:00:00",
+                "FRA2",
+                "100",
+                "127.0.0.1",
+                method,
+                "static.crates.io",
+            ];
+            format!("{}\t{path}\t{status}\n", fields.join("\t"))
+        };
+
+        let mut contents = String::from("#Version: 1.0\n");
+        contents.push_str("#Fields: date time x-edge-location sc-bytes c-ip cs-method cs(Host) ");
+        contents.push_str("cs-uri-stem sc-status\n");
+        contents.push_str(&line(
+            "2022-02-14",
+            "GET",
+            "/crates/foo/foo-1.0.0.crate",
+            200,
+        ));
+        contents.push_str(&line(
+            "2022-02-14",
+            "GET",
+            "/crates/foo/foo-1.0.0.crate",
+            200,
+        ));
+        contents.push_str(&line(
+            "2022-02-14",
+            "GET",
+            "/crates/foo/foo-1.0.0.crate",
+            404,
+        ));
+        contents.push_str(&line(
+            "2022-02-14",
+            "HEAD",
+            "/crates/foo/foo-1.0.0.crate",
+            200,
+        ));
+        contents.push_str(&line(
+            "2022-02-15",
+            "GET",
+            "/crates/bar/bar-0.1.0.crate",
+            200,
+        ));
+        contents.push_str("malformed line\n");
+
+        let downloads = LogFormat::CloudFront.count_downloads(&contents);
+        let date = |day| NaiveDate::from_ymd(2022, 2, day);
+        let expected = [
+            (("foo".into(), "1.0.0".into(), date(14)), 2),
+            (("bar".into(), "0.1.0".into(), date(15)), 1),
+        ];
+        assert_eq!(downloads, expected.into_iter().collect());
+    }
+
+    #[test]
+    fn fastly_downloads() {
+        let contents = r#"{"date_time":"2022-02-14T10:00:00Z","method":"GET","url":"/crates/foo/foo-1.0.0.crate","status":200}
+{"date_time":"2022-02-14T10:00:01Z","method":"GET","url":"/crates/foo/foo-1.0.0.crate?x=1","status":200}
+{"date_time":"2022-02-14T10:00:02Z","method":"GET","url":"/crates/foo/foo-1.0.0.crate","status":403}
+{"date_time":"2022-02-14T10:00:03Z","method":"GET","url":"/crates/foo/foo-1.0.0.crate"}
+"#;
+
+        let downloads = LogFormat::Fastly.count_downloads(contents);
+        let date = NaiveDate::from_ymd(2022, 2, 14);
+        let expected = [(("foo".into(), "1.0.0".into(), date), 2)];
+        assert_eq!(downloads, expected.into_iter().collect());
+    }
+}
@@ -0,0 +1,6 @@
+-- The key after which the log files below $1 are listed: the greatest key of the files that
+-- were processed before $2. Compared bytewise like S3 does, instead of by the collation.
+SELECT MAX(path COLLATE "C") AS path
+FROM processed_cdn_log_files
+WHERE starts_with(path, $1)
+    AND processed_at < $2
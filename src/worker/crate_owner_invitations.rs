@@ -0,0 +1,134 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use swirl::PerformError;
+
+use crate::background_jobs::Environment;
+use crate::models::{CrateOwnerInvitation, PendingCrateOwnerInvitation, User};
+use crate::schema::{crate_owner_invitations, crates, pending_crate_owner_invitations};
+
+/// Deletes crate ownership invitations that were not accepted within `expiration_days`, and
+/// lets the users that sent them know. Invitees are reminded `reminder_days` before their
+/// invitation expires.
+///
+/// Expired invitations are already hidden from the invitees, this job cleans up the database.
+#[swirl::background_job]
+pub fn expire_ownership_invitations(
+    conn: &PgConnection,
+    env: &Environment,
+    expiration_days: i64,
+    reminder_days: i64,
+) -> Result<(), PerformError> {
+    let expiration_cutoff = Utc::now().naive_utc() - Duration::days(expiration_days);
+    let reminder_cutoff = expiration_cutoff + Duration::days(reminder_days);
+
+    send_reminders(conn, env, expiration_cutoff, reminder_cutoff)?;
+
+    let expired: Vec<CrateOwnerInvitation> = diesel::delete(
+        crate_owner_invitations::table
+            .filter(crate_owner_invitations::created_at.le(expiration_cutoff)),
+    )
+    .get_results(conn)?;
+    for invitation in &expired {
+        let invitee = User::find(conn, invitation.invited_user_id)?;
+        notify_inviter(
+            conn,
+            env,
+            invitation.invited_by_user_id,
+            &invitee.gh_login,
+            invitation.crate_id,
+        )?;
+    }
+
+    let expired_pending: Vec<PendingCrateOwnerInvitation> = diesel::delete(
+        pending_crate_owner_invitations::table
+            .filter(pending_crate_owner_invitations::created_at.le(expiration_cutoff)),
+    )
+    .get_results(conn)?;
+    for invitation in &expired_pending {
+        notify_inviter(
+            conn,
+            env,
+            invitation.invited_by_user_id,
+            &invitation.invited_login,
+            invitation.crate_id,
+        )?;
+    }
+
+    println!(
+        "Deleted {} expired ownership invitations",
+        expired.len() + expired_pending.len()
+    );
+    Ok(())
+}
+
+/// Reminds the invitees of invitations created between the two cutoffs, unless they were
+/// already reminded.
+fn send_reminders(
+    conn: &PgConnection,
+    env: &Environment,
+    expiration_cutoff: NaiveDateTime,
+    reminder_cutoff: NaiveDateTime,
+) -> QueryResult<()> {
+    let invitations: Vec<(CrateOwnerInvitation, String)> = crate_owner_invitations::table
+        .inner_join(crates::table)
+        .filter(crate_owner_invitations::created_at.gt(expiration_cutoff))
+        .filter(crate_owner_invitations::created_at.le(reminder_cutoff))
+        .filter(crate_owner_invitations::reminder_sent_at.is_null())
+        .select((crate_owner_invitations::all_columns, crates::name))
+        .load(conn)?;
+
+    for (invitation, crate_name) in &invitations {
+        let invitee = User::find(conn, invitation.invited_user_id)?;
+        let inviter = User::find(conn, invitation.invited_by_user_id)?;
+        let days_left = (invitation.created_at - expiration_cutoff)
+            .num_days()
+            .max(1);
+
+        // Invitees without a verified email address can't be reminded, they are skipped
+        // nevertheless to avoid checking them again on every run.
+        if let Some(email) = invitee.notification_email(conn)? {
+            let result = env.emails().send_owner_invite_reminder(
+                conn,
+                &email,
+                &inviter.gh_login,
+                crate_name,
+                &invitation.token,
+                days_left,
+            );
+            if let Err(error) = result {
+                warn!("Failed to send ownership invitation reminder: {error}");
+            }
+        }
+
+        diesel::update(invitation)
+            .set(crate_owner_invitations::reminder_sent_at.eq(diesel::dsl::now))
+            .execute(conn)?;
+    }
+
+    Ok(())
+}
+
+fn notify_inviter(
+    conn: &PgConnection,
+    env: &Environment,
+    inviter_id: i32,
+    invitee_login: &str,
+    crate_id: i32,
+) -> QueryResult<()> {
+    let inviter = User::find(conn, inviter_id)?;
+    let crate_name: String = crates::table
+        .find(crate_id)
+        .select(crates::name)
+        .first(conn)?;
+
+    if let Some(email) = inviter.notification_email(conn)? {
+        let result =
+            env.emails()
+                .send_owner_invite_expired(conn, &email, invitee_login, &crate_name);
+        if let Err(error) = result {
+            warn!("Failed to send ownership invitation expiry notification: {error}");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,206 @@
+//! Computes the scores used to rank crates by momentum and by importance, stored in the
+//! `crate_scores` table.
+//!
+//! - The trending score compares the downloads of the last 7 days with the weekly average of the
+//!   4 weeks before them. The difference is divided by the square root of that average, so that
+//!   a few hundred extra downloads matter for a small crate but not for `serde`. Crates that are
+//!   downloaded less than before get a negative score.
+//! - The popularity score is the PageRank of the crate in the graph of dependencies between the
+//!   latest versions of all crates, scaled so that the average crate scores `1.0`. Crates score
+//!   higher when they are depended on by many crates, and by crates that are popular themselves.
+
+use std::collections::HashMap;
+
+use chrono::Utc;
+use diesel::pg::upsert::excluded;
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{BigInt, Integer};
+use swirl::PerformError;
+
+use crate::schema::{crate_scores, crates};
+
+/// Keeps crates with only a handful of downloads from topping the trending crates.
+const TRENDING_SMOOTHING: f64 = 100.0;
+/// The probability of following a dependency instead of jumping to a random crate.
+const DAMPING_FACTOR: f64 = 0.85;
+const MAX_ITERATIONS: usize = 100;
+/// The iterations stop once the scores of all crates changed by less than this in total.
+const CONVERGENCE_THRESHOLD: f64 = 1e-9;
+/// The rows inserted per statement, keeping the number of bind parameters under the limit.
+const INSERT_BATCH_SIZE: usize = 10_000;
+
+#[swirl::background_job]
+pub fn update_crate_scores(conn: &PgConnection) -> Result<(), PerformError> {
+    let updated = update_scores(conn)?;
+    println!("Updated the scores of {updated} crates");
+    Ok(())
+}
+
+#[derive(QueryableByName)]
+struct CrateDownloads {
+    #[sql_type = "Integer"]
+    crate_id: i32,
+    #[sql_type = "BigInt"]
+    recent: i64,
+    #[sql_type = "BigInt"]
+    previous: i64,
+}
+
+#[derive(QueryableByName)]
+struct DependencyEdge {
+    #[sql_type = "Integer"]
+    dependent_id: i32,
+    #[sql_type = "Integer"]
+    dependency_id: i32,
+}
+
+/// Computes the scores of all crates and stores them, returning the number of crates.
+pub fn update_scores(conn: &PgConnection) -> QueryResult<usize> {
+    let crate_ids: Vec<i32> = crates::table.select(crates::id).load(conn)?;
+
+    let downloads: Vec<CrateDownloads> =
+        sql_query(include_str!("crate_scores_downloads.sql")).load(conn)?;
+    let trending = downloads
+        .into_iter()
+        .map(|row| (row.crate_id, trending_score(row.recent, row.previous)))
+        .collect::<HashMap<_, _>>();
+
+    let edges: Vec<DependencyEdge> =
+        sql_query(include_str!("crate_scores_dependencies.sql")).load(conn)?;
+    let edges = edges
+        .into_iter()
+        .map(|edge| (edge.dependent_id, edge.dependency_id))
+        .collect::<Vec<_>>();
+    let popularity = page_rank(&crate_ids, &edges);
+
+    let now = Utc::now().naive_utc();
+    let rows = crate_ids
+        .iter()
+        .map(|id| {
+            (
+                crate_scores::crate_id.eq(id),
+                crate_scores::trending_score.eq(trending.get(id).copied().unwrap_or(0.0)),
+                crate_scores::popularity_score.eq(popularity.get(id).copied().unwrap_or(0.0)),
+                crate_scores::updated_at.eq(now),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    conn.transaction(|| {
+        for batch in rows.chunks(INSERT_BATCH_SIZE) {
+            diesel::insert_into(crate_scores::table)
+                .values(batch)
+                .on_conflict(crate_scores::crate_id)
+                .do_update()
+                .set((
+                    crate_scores::trending_score.eq(excluded(crate_scores::trending_score)),
+                    crate_scores::popularity_score.eq(excluded(crate_scores::popularity_score)),
+                    crate_scores::updated_at.eq(excluded(crate_scores::updated_at)),
+                ))
+                .execute(conn)?;
+        }
+        Ok(rows.len())
+    })
+}
+
+/// Scores the downloads of the last week against the weekly average of the 4 weeks before.
+fn trending_score(recent: i64, previous: i64) -> f64 {
+    let baseline = previous as f64 / 4.0;
+    (recent as f64 - baseline) / (baseline + TRENDING_SMOOTHING).sqrt()
+}
+
+/// Computes the PageRank of `nodes` in the graph of `(dependent, dependency)` edges, scaled so
+/// that the scores average to `1.0`. Edges between unknown nodes are ignored.
+fn page_rank(nodes: &[i32], edges: &[(i32, i32)]) -> HashMap<i32, f64> {
+    let count = nodes.len();
+    if count == 0 {
+        return HashMap::new();
+    }
+
+    let index = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, id)| (*id, i))
+        .collect::<HashMap<_, _>>();
+    let mut dependencies = vec![Vec::new(); count];
+    for (dependent, dependency) in edges {
+        if let (Some(&from), Some(&to)) = (index.get(dependent), index.get(dependency)) {
+            dependencies[from].push(to);
+        }
+    }
+
+    let n = count as f64;
+    let mut ranks = vec![1.0 / n; count];
+    for _ in 0..MAX_ITERATIONS {
+        // Crates without dependencies spread their rank over all crates
+        let dangling = (0..count)
+            .filter(|&i| dependencies[i].is_empty())
+            .map(|i| ranks[i])
+            .sum::<f64>();
+
+        let base = (1.0 - DAMPING_FACTOR) / n + DAMPING_FACTOR * dangling / n;
+        let mut next = vec![base; count];
+        for (i, targets) in dependencies.iter().enumerate() {
+            if targets.is_empty() {
+                continue;
+            }
+            let share = DAMPING_FACTOR * ranks[i] / targets.len() as f64;
+            for &target in targets {
+                next[target] += share;
+            }
+        }
+
+        let change = ranks
+            .iter()
+            .zip(&next)
+            .map(|(old, new)| (old - new).abs())
+            .sum::<f64>();
+        ranks = next;
+        if change < CONVERGENCE_THRESHOLD {
+            break;
+        }
+    }
+
+    nodes
+        .iter()
+        .zip(ranks)
+        .map(|(id, rank)| (*id, rank * n))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trending_scores() {
+        assert_eq!(trending_score(0, 0), 0.0);
+        // No change in downloads
+        assert_eq!(trending_score(100, 400), 0.0);
+        assert!(trending_score(200, 400) > 0.0);
+        assert!(trending_score(50, 400) < 0.0);
+        // The same growth counts for more on smaller crates
+        assert!(trending_score(200, 400) > trending_score(1_000_100, 4_000_000));
+    }
+
+    #[test]
+    fn page_rank_of_dependencies() {
+        // 1 and 2 depend on 3, which depends on 4. 5 depends on nothing and isn't depended on.
+        let nodes = [1, 2, 3, 4, 5];
+        let edges = [(1, 3), (2, 3), (3, 4), (6, 4)];
+        let ranks = page_rank(&nodes, &edges);
+
+        let sum = ranks.values().sum::<f64>();
+        assert!((sum - 5.0).abs() < 1e-6);
+        assert!(ranks[&4] > ranks[&3]);
+        assert!(ranks[&3] > ranks[&1]);
+        assert!((ranks[&1] - ranks[&2]).abs() < 1e-9);
+        assert!((ranks[&1] - ranks[&5]).abs() < 1e-9);
+    }
+
+    #[test]
+    fn page_rank_without_nodes() {
+        assert!(page_rank(&[], &[(1, 2)]).is_empty());
+    }
+}
@@ -0,0 +1,13 @@
+-- The normal and build dependencies of the latest non-yanked version of each crate
+SELECT DISTINCT
+    latest.crate_id AS dependent_id,
+    dependencies.crate_id AS dependency_id
+FROM (
+    SELECT DISTINCT ON (crate_id) id, crate_id
+    FROM versions
+    WHERE NOT yanked
+    ORDER BY crate_id, to_semver_no_prerelease(num) DESC NULLS LAST
+) latest
+INNER JOIN dependencies ON dependencies.version_id = latest.id
+WHERE dependencies.kind <> 2
+    AND dependencies.crate_id <> latest.crate_id
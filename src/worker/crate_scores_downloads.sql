@@ -0,0 +1,15 @@
+-- The downloads of each crate in the last 7 days, and in the 28 days before them. Today is left
+-- out since its downloads are still being counted.
+SELECT
+    versions.crate_id,
+    COALESCE(SUM(version_downloads.downloads) FILTER (
+        WHERE version_downloads.date >= CURRENT_DATE - 7
+    ), 0)::bigint AS recent,
+    COALESCE(SUM(version_downloads.downloads) FILTER (
+        WHERE version_downloads.date < CURRENT_DATE - 7
+    ), 0)::bigint AS previous
+FROM version_downloads
+INNER JOIN versions ON versions.id = version_downloads.version_id
+WHERE version_downloads.date >= CURRENT_DATE - 35
+    AND version_downloads.date < CURRENT_DATE
+GROUP BY versions.crate_id
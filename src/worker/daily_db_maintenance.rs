@@ -4,9 +4,9 @@
 /// Because the `version_downloads` table includes years of historical data, we can accumulate
 /// a *lot* of garbage before an auto-vacuum is run.
 ///
-/// We only need to keep 90 days of entries in `version_downloads`. Once we have a mechanism to
-/// archive daily download counts and drop historical data, we can drop this task and rely on
-/// auto-vacuum again.
+/// Older entries of `version_downloads` are moved to the uploader by the
+/// `archive_version_downloads` job. Once the table only holds the last 90 days, we can drop this
+/// task and rely on auto-vacuum again.
 use diesel::{sql_query, RunQueryDsl};
 use swirl::PerformError;
 
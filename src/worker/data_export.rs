@@ -0,0 +1,310 @@
+//! Export everything the registry holds about a user, so that they can download it.
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use swirl::PerformError;
+
+use crate::background_jobs::Environment;
+use crate::models::{
+    ApiToken, CrateOwner, DataExport, Email, OwnerKind, User, VersionAction, DATA_EXPORT_TTL_DAYS,
+};
+use crate::schema::{
+    api_tokens, crate_owner_invitations, crate_owners, crates, data_exports, emails, follows,
+    users, version_owner_actions, versions,
+};
+use crate::util::rfc3339;
+use crate::views::{EncodableEmail, OwnedCrate};
+
+/// Builds the archive of a data export and uploads it. The previous exports of the user are
+/// deleted afterwards.
+#[swirl::background_job]
+pub fn export_user_data(
+    conn: &PgConnection,
+    env: &Environment,
+    data_export_id: i32,
+) -> Result<(), PerformError> {
+    let export: DataExport = data_exports::table.find(data_export_id).first(conn)?;
+    let user = User::find(conn, export.user_id)?;
+
+    let data = UserData::load(conn, &user)?;
+    let archive = serde_json::to_vec_pretty(&data)?;
+    env.uploader
+        .upload_data_export(env.http_client(), user.id, &export.token, archive)?;
+
+    diesel::update(&export)
+        .set(data_exports::completed_at.eq(Utc::now().naive_utc()))
+        .execute(conn)?;
+
+    // The export is usable whether or not this works, leftovers are deleted once they expire
+    let previous: Vec<DataExport> = DataExport::belonging_to(&user)
+        .filter(data_exports::id.ne(export.id))
+        .load(conn)?;
+    if let Err(error) = conn.transaction(|| delete_exports(conn, env, &previous)) {
+        warn!(
+            "Failed to delete previous data exports of user {}: {error}",
+            user.id
+        );
+    }
+
+    Ok(())
+}
+
+/// Deletes the data exports that were completed more than `DATA_EXPORT_TTL_DAYS` ago, and the
+/// ones that were requested that long ago but never completed.
+#[swirl::background_job]
+pub fn delete_expired_data_exports(
+    conn: &PgConnection,
+    env: &Environment,
+) -> Result<(), PerformError> {
+    let cutoff = Utc::now().naive_utc() - Duration::days(DATA_EXPORT_TTL_DAYS);
+
+    let expired: Vec<DataExport> = data_exports::table
+        .filter(
+            data_exports::completed_at
+                .lt(cutoff)
+                .or(data_exports::completed_at
+                    .is_null()
+                    .and(data_exports::requested_at.lt(cutoff))),
+        )
+        .load(conn)?;
+    delete_exports(conn, env, &expired)?;
+    println!("Deleted {} expired data exports", expired.len());
+
+    Ok(())
+}
+
+/// Deletes the archives of the data exports of a deleted account. The rows were already
+/// deleted together with the user.
+#[swirl::background_job]
+pub fn delete_data_export_files(
+    env: &Environment,
+    user_id: i32,
+    tokens: Vec<String>,
+) -> Result<(), PerformError> {
+    for token in &tokens {
+        env.uploader
+            .delete_data_export(env.http_client(), user_id, token)?;
+    }
+
+    Ok(())
+}
+
+/// Deletes the archives of data exports, and then their rows. Rows are only deleted once their
+/// archive is gone, so that a failure is retried by the next run.
+fn delete_exports(
+    conn: &PgConnection,
+    env: &Environment,
+    exports: &[DataExport],
+) -> Result<(), PerformError> {
+    for export in exports {
+        env.uploader
+            .delete_data_export(env.http_client(), export.user_id, &export.token)?;
+        diesel::delete(export).execute(conn)?;
+    }
+
+    Ok(())
+}
+
+/// The contents of a data export archive.
+#[derive(Serialize, Debug)]
+pub struct UserData {
+    pub user: ExportedUser,
+    pub emails: Vec<EncodableEmail>,
+    pub api_tokens: Vec<ExportedApiToken>,
+    pub owned_crates: Vec<OwnedCrate>,
+    pub received_invitations: Vec<ExportedInvitation>,
+    pub sent_invitations: Vec<ExportedInvitation>,
+    pub followed_crates: Vec<String>,
+    pub version_actions: Vec<ExportedVersionAction>,
+    pub published_versions: Vec<ExportedVersion>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ExportedUser {
+    pub id: i32,
+    pub login: String,
+    pub name: Option<String>,
+    pub avatar: Option<String>,
+    pub github_id: i32,
+    pub account_lock_reason: Option<String>,
+    #[serde(with = "rfc3339::option")]
+    pub account_lock_until: Option<NaiveDateTime>,
+    pub weekly_digest: bool,
+}
+
+/// Only the metadata of an API token, the token itself is never stored in plain text.
+#[derive(Serialize, Debug)]
+pub struct ExportedApiToken {
+    pub id: i32,
+    pub name: String,
+    #[serde(with = "rfc3339")]
+    pub created_at: NaiveDateTime,
+    #[serde(with = "rfc3339::option")]
+    pub last_used_at: Option<NaiveDateTime>,
+    pub revoked: bool,
+}
+
+/// An ownership invitation, with the login of the other user involved in it.
+#[derive(Serialize, Debug)]
+pub struct ExportedInvitation {
+    pub crate_name: String,
+    pub user: String,
+    #[serde(with = "rfc3339")]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ExportedVersionAction {
+    pub crate_name: String,
+    pub version: String,
+    pub action: String,
+    pub api_token_id: Option<i32>,
+    #[serde(with = "rfc3339")]
+    pub time: NaiveDateTime,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ExportedVersion {
+    pub crate_name: String,
+    pub version: String,
+    #[serde(with = "rfc3339")]
+    pub created_at: NaiveDateTime,
+}
+
+impl UserData {
+    pub fn load(conn: &PgConnection, user: &User) -> QueryResult<UserData> {
+        let emails = Email::belonging_to(user)
+            .order(emails::id)
+            .load::<Email>(conn)?
+            .into_iter()
+            .map(EncodableEmail::from)
+            .collect();
+
+        let api_tokens = ApiToken::belonging_to(user)
+            .order(api_tokens::id)
+            .load::<ApiToken>(conn)?
+            .into_iter()
+            .map(|token| ExportedApiToken {
+                id: token.id,
+                name: token.name,
+                created_at: token.created_at,
+                last_used_at: token.last_used_at,
+                revoked: token.revoked,
+            })
+            .collect();
+
+        let owned_crates = CrateOwner::by_owner_kind(OwnerKind::User)
+            .inner_join(crates::table)
+            .filter(crate_owners::owner_id.eq(user.id))
+            .select((crates::id, crates::name, crate_owners::email_notifications))
+            .order(crates::name)
+            .load(conn)?
+            .into_iter()
+            .map(|(id, name, email_notifications)| OwnedCrate {
+                id,
+                name,
+                email_notifications,
+            })
+            .collect();
+
+        let into_invitations = |rows: Vec<(String, String, NaiveDateTime)>| {
+            rows.into_iter()
+                .map(|(crate_name, user, created_at)| ExportedInvitation {
+                    crate_name,
+                    user,
+                    created_at,
+                })
+                .collect()
+        };
+
+        let received_invitations = crate_owner_invitations::table
+            .inner_join(crates::table)
+            .inner_join(users::table.on(users::id.eq(crate_owner_invitations::invited_by_user_id)))
+            .filter(crate_owner_invitations::invited_user_id.eq(user.id))
+            .select((
+                crates::name,
+                users::gh_login,
+                crate_owner_invitations::created_at,
+            ))
+            .order(crate_owner_invitations::created_at)
+            .load(conn)?;
+
+        let sent_invitations = crate_owner_invitations::table
+            .inner_join(crates::table)
+            .inner_join(users::table.on(users::id.eq(crate_owner_invitations::invited_user_id)))
+            .filter(crate_owner_invitations::invited_by_user_id.eq(user.id))
+            .select((
+                crates::name,
+                users::gh_login,
+                crate_owner_invitations::created_at,
+            ))
+            .order(crate_owner_invitations::created_at)
+            .load(conn)?;
+
+        let followed_crates = follows::table
+            .inner_join(crates::table)
+            .filter(follows::user_id.eq(user.id))
+            .select(crates::name)
+            .order(crates::name)
+            .load(conn)?;
+
+        let version_actions = version_owner_actions::table
+            .inner_join(versions::table.inner_join(crates::table))
+            .filter(version_owner_actions::user_id.eq(user.id))
+            .select((
+                crates::name,
+                versions::num,
+                version_owner_actions::action,
+                version_owner_actions::api_token_id,
+                version_owner_actions::time,
+            ))
+            .order(version_owner_actions::time)
+            .load::<(String, String, VersionAction, Option<i32>, NaiveDateTime)>(conn)?
+            .into_iter()
+            .map(
+                |(crate_name, version, action, api_token_id, time)| ExportedVersionAction {
+                    crate_name,
+                    version,
+                    action: action.into(),
+                    api_token_id,
+                    time,
+                },
+            )
+            .collect();
+
+        let published_versions = versions::table
+            .inner_join(crates::table)
+            .filter(versions::published_by.eq(user.id))
+            .select((crates::name, versions::num, versions::created_at))
+            .order(versions::created_at)
+            .load::<(String, String, NaiveDateTime)>(conn)?
+            .into_iter()
+            .map(|(crate_name, version, created_at)| ExportedVersion {
+                crate_name,
+                version,
+                created_at,
+            })
+            .collect();
+
+        Ok(UserData {
+            user: ExportedUser {
+                id: user.id,
+                login: user.gh_login.clone(),
+                name: user.name.clone(),
+                avatar: user.gh_avatar.clone(),
+                github_id: user.gh_id,
+                account_lock_reason: user.account_lock_reason.clone(),
+                account_lock_until: user.account_lock_until,
+                weekly_digest: user.weekly_digest,
+            },
+            emails,
+            api_tokens,
+            owned_crates,
+            received_invitations: into_invitations(received_invitations),
+            sent_invitations: into_invitations(sent_invitations),
+            followed_crates,
+            version_actions,
+            published_versions,
+        })
+    }
+}
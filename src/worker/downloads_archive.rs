@@ -0,0 +1,137 @@
+//! Moves old rows of `version_downloads` to compressed CSV files in the uploader, one file per
+//! day, so that the table doesn't keep growing.
+//!
+//! The downloads of the archived days are added to the monthly sums in
+//! `version_downloads_monthly`, so that the download statistics endpoints can still show them.
+
+use std::io::Write;
+
+use chrono::{Duration, NaiveDate, Utc};
+use diesel::dsl::{all, any};
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{Array, Date, Integer};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use swirl::PerformError;
+
+use crate::background_jobs::Environment;
+use crate::schema::{
+    crates, version_downloads, version_downloads_archives, version_downloads_by_client, versions,
+};
+
+/// How many days are archived at most per run, so that a backlog of days, e.g. when the job
+/// runs for the first time, is worked off over several runs instead of in one long job.
+pub const MAX_ARCHIVED_DAYS_PER_RUN: i64 = 30;
+
+/// Archives the `version_downloads` rows of up to `MAX_ARCHIVED_DAYS_PER_RUN` days that are
+/// older than `retention_days`, starting with the oldest day.
+///
+/// The rows of a day are only deleted after its archive was uploaded, in the same transaction
+/// that adds them to the monthly sums. Rows that were not counted by `update_downloads` yet are
+/// left alone, as are rows of days that were archived before, since their archive would be
+/// overwritten. Rows of `version_downloads_by_client` older than `retention_days` are deleted
+/// without being archived.
+#[swirl::background_job]
+pub fn archive_version_downloads(
+    conn: &PgConnection,
+    env: &Environment,
+    retention_days: i64,
+) -> Result<(), PerformError> {
+    let cutoff = Utc::today().naive_utc() - Duration::days(retention_days);
+
+    let archived_dates = version_downloads_archives::table.select(version_downloads_archives::date);
+    let dates: Vec<NaiveDate> = version_downloads::table
+        .select(version_downloads::date)
+        .filter(version_downloads::date.lt(cutoff))
+        .filter(version_downloads::processed.eq(true))
+        .filter(version_downloads::date.ne(all(archived_dates)))
+        .distinct()
+        .order(version_downloads::date)
+        .limit(MAX_ARCHIVED_DAYS_PER_RUN)
+        .load(conn)?;
+
+    for date in dates {
+        let rows = load_day(conn, date)?;
+        let archive = encode_archive(date, &rows)?;
+        env.uploader
+            .upload_downloads_archive(env.http_client(), date, archive)?;
+
+        let version_ids = rows.iter().map(|row| row.version_id).collect::<Vec<_>>();
+        let downloads = rows.iter().map(|row| i64::from(row.downloads)).sum::<i64>();
+        conn.transaction(|| {
+            sql_query(include_str!("downloads_archive_monthly.sql"))
+                .bind::<Date, _>(date)
+                .bind::<Array<Integer>, _>(&version_ids)
+                .execute(conn)?;
+
+            // `= ANY` binds a single array, `IN` would need a parameter per row
+            diesel::delete(
+                version_downloads::table
+                    .filter(version_downloads::date.eq(date))
+                    .filter(version_downloads::version_id.eq(any(&version_ids))),
+            )
+            .execute(conn)?;
+
+            diesel::insert_into(version_downloads_archives::table)
+                .values((
+                    version_downloads_archives::date.eq(date),
+                    version_downloads_archives::rows.eq(rows.len() as i32),
+                    version_downloads_archives::downloads.eq(downloads),
+                ))
+                .execute(conn)
+        })?;
+
+        println!("Archived {} version_downloads rows of {date}", rows.len());
+    }
+
+    // The downloads by client are only shown for recent days, so they are not archived
+    let deleted = diesel::delete(
+        version_downloads_by_client::table.filter(version_downloads_by_client::date.lt(cutoff)),
+    )
+    .execute(conn)?;
+    println!("Deleted {deleted} version_downloads_by_client rows");
+
+    Ok(())
+}
+
+/// A row of an archive file.
+#[derive(Queryable, Debug, PartialEq, Eq)]
+pub struct ArchivedDownloads {
+    pub crate_name: String,
+    pub version: String,
+    pub version_id: i32,
+    pub downloads: i32,
+}
+
+/// Loads the counted downloads of a day.
+pub fn load_day(conn: &PgConnection, date: NaiveDate) -> QueryResult<Vec<ArchivedDownloads>> {
+    version_downloads::table
+        .inner_join(versions::table.inner_join(crates::table))
+        .filter(version_downloads::date.eq(date))
+        .filter(version_downloads::processed.eq(true))
+        .select((
+            crates::name,
+            versions::num,
+            version_downloads::version_id,
+            version_downloads::downloads,
+        ))
+        .order(version_downloads::version_id)
+        .load(conn)
+}
+
+/// Encodes the downloads of a day as a gzip compressed CSV file.
+///
+/// Crate names and version numbers can't contain commas or quotes, so no escaping is needed.
+pub fn encode_archive(date: NaiveDate, rows: &[ArchivedDownloads]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    writeln!(encoder, "date,crate,version,version_id,downloads")?;
+    for row in rows {
+        writeln!(
+            encoder,
+            "{date},{},{},{},{}",
+            row.crate_name, row.version, row.version_id, row.downloads
+        )?;
+    }
+    encoder.finish()
+}
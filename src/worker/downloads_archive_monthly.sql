@@ -0,0 +1,8 @@
+-- Adds the downloads of a day to the monthly sums, before the rows of the day are deleted
+INSERT INTO version_downloads_monthly (version_id, month, downloads)
+SELECT version_id, date_trunc('month', date::timestamp)::date, downloads
+FROM version_downloads
+WHERE date = $1
+    AND version_id = ANY($2)
+ON CONFLICT (version_id, month) DO UPDATE
+SET downloads = version_downloads_monthly.downloads + excluded.downloads
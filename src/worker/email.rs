@@ -0,0 +1,15 @@
+use swirl::PerformError;
+
+use crate::background_jobs::Environment;
+use crate::email::EmailMessage;
+
+/// Delivers an email that was rendered and enqueued by `Emails`.
+///
+/// If the delivery fails the job is retried by the background worker, with an exponentially
+/// growing delay between the attempts.
+#[swirl::background_job]
+pub fn send_email(env: &Environment, message: EmailMessage) -> Result<(), PerformError> {
+    env.emails().deliver(&message).map_err(|e| e.to_string())?;
+
+    Ok(())
+}
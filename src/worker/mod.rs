@@ -3,14 +3,36 @@
 //! the daily database maintenance, but also operations like rendering READMEs
 //! and uploading them to S3.
 
+mod api_token_usages;
+pub mod cdn_logs;
+mod crate_owner_invitations;
+pub mod crate_scores;
 mod daily_db_maintenance;
+pub mod data_export;
+pub mod downloads_archive;
 pub mod dump_db;
+mod email;
 mod git;
+mod publish_notifications;
 mod readmes;
+mod sessions;
+mod team_memberships;
 mod update_downloads;
+mod weekly_digest;
 
+pub use api_token_usages::prune_api_token_usages;
+pub use cdn_logs::{ingest_cdn_log_file, ingest_cdn_logs};
+pub use crate_owner_invitations::expire_ownership_invitations;
+pub use crate_scores::update_crate_scores;
 pub use daily_db_maintenance::daily_db_maintenance;
+pub use data_export::{delete_data_export_files, delete_expired_data_exports, export_user_data};
+pub use downloads_archive::archive_version_downloads;
 pub use dump_db::dump_db;
+pub use email::send_email;
 pub use git::{add_crate, squash_index, sync_yanked};
+pub use publish_notifications::send_publish_notifications;
 pub use readmes::render_and_upload_readme;
+pub use sessions::delete_expired_sessions;
+pub use team_memberships::{sync_team_memberships, sync_user_team_memberships};
 pub use update_downloads::update_downloads;
+pub use weekly_digest::send_weekly_digests;
@@ -0,0 +1,67 @@
+use diesel::prelude::*;
+use swirl::PerformError;
+
+use crate::background_jobs::Environment;
+use crate::models::{OwnerKind, User, VersionAction, VersionOwnerAction};
+use crate::schema::{api_tokens, crate_owners, crates, users, version_owner_actions, versions};
+
+/// Emails all owners of a crate that have notifications enabled about a newly published
+/// version, so that publishes with compromised credentials are noticed quickly.
+#[swirl::background_job]
+pub fn send_publish_notifications(
+    conn: &PgConnection,
+    env: &Environment,
+    version_id: i32,
+) -> Result<(), PerformError> {
+    let (num, crate_id, crate_name): (String, i32, String) = versions::table
+        .inner_join(crates::table)
+        .filter(versions::id.eq(version_id))
+        .select((versions::num, crates::id, crates::name))
+        .first(conn)?;
+
+    let (action, publisher): (VersionOwnerAction, User) = version_owner_actions::table
+        .inner_join(users::table)
+        .filter(version_owner_actions::version_id.eq(version_id))
+        .filter(version_owner_actions::action.eq(VersionAction::Publish))
+        .first(conn)?;
+
+    let token_name: Option<String> = match action.api_token_id {
+        Some(api_token_id) => api_tokens::table
+            .find(api_token_id)
+            .select(api_tokens::name)
+            .first(conn)
+            .optional()?,
+        None => None,
+    };
+
+    let owners: Vec<User> = crate_owners::table
+        .inner_join(users::table.on(users::id.eq(crate_owners::owner_id)))
+        .filter(crate_owners::crate_id.eq(crate_id))
+        .filter(crate_owners::owner_kind.eq(OwnerKind::User as i32))
+        .filter(crate_owners::deleted.eq(false))
+        .filter(crate_owners::email_notifications.eq(true))
+        .select(users::all_columns)
+        .load(conn)?;
+
+    for owner in &owners {
+        let email = match owner.notification_email(conn)? {
+            Some(email) => email,
+            None => continue,
+        };
+
+        let result = env.emails().send_version_published(
+            conn,
+            &email,
+            &crate_name,
+            &num,
+            &publisher.gh_login,
+            token_name.as_deref(),
+            action.time,
+        );
+        if let Err(error) = result {
+            warn!("Failed to send publish notification for {crate_name}@{num}: {error}");
+        }
+    }
+
+    Ok(())
+}
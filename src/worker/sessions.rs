@@ -0,0 +1,27 @@
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use swirl::PerformError;
+
+use crate::models::{SESSION_IDLE_TIMEOUT_DAYS, SESSION_MAX_AGE_DAYS};
+use crate::schema::sessions;
+
+/// Deletes the sessions that can no longer be used, since they are older than
+/// `SESSION_MAX_AGE_DAYS` or weren't used for `SESSION_IDLE_TIMEOUT_DAYS`.
+#[swirl::background_job]
+pub fn delete_expired_sessions(conn: &PgConnection) -> Result<(), PerformError> {
+    let now = Utc::now().naive_utc();
+    let created_cutoff = now - Duration::days(SESSION_MAX_AGE_DAYS);
+    let used_cutoff = now - Duration::days(SESSION_IDLE_TIMEOUT_DAYS);
+
+    let deleted = diesel::delete(
+        sessions::table.filter(
+            sessions::created_at
+                .lt(created_cutoff)
+                .or(sessions::last_used_at.lt(used_cutoff)),
+        ),
+    )
+    .execute(conn)?;
+    println!("Deleted {deleted} expired sessions");
+
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use swirl::PerformError;
+
+use crate::background_jobs::Environment;
+use crate::models::{Team, User};
+use crate::schema::{team_memberships, teams, users};
+
+/// Refreshes all cached team memberships of a user
+///
+/// This is enqueued when the user logs in, since they might have joined or left teams in the
+/// meantime, and when an expired cached membership of the user is used.
+#[swirl::background_job]
+pub fn sync_user_team_memberships(
+    conn: &PgConnection,
+    env: &Environment,
+    user_id: i32,
+) -> Result<(), PerformError> {
+    let memberships = team_memberships::table
+        .inner_join(teams::table)
+        .inner_join(users::table)
+        .filter(team_memberships::user_id.eq(user_id))
+        .select((teams::all_columns, users::all_columns))
+        .load(conn)?;
+
+    refresh(conn, env, &memberships);
+    Ok(())
+}
+
+/// Refreshes all cached team memberships that were last checked more than `max_age_secs`
+/// seconds ago
+#[swirl::background_job]
+pub fn sync_team_memberships(
+    conn: &PgConnection,
+    env: &Environment,
+    max_age_secs: i64,
+) -> Result<(), PerformError> {
+    let cutoff = Utc::now().naive_utc() - Duration::seconds(max_age_secs);
+    let memberships = team_memberships::table
+        .inner_join(teams::table)
+        .inner_join(users::table)
+        .filter(team_memberships::checked_at.lt(cutoff))
+        .select((teams::all_columns, users::all_columns))
+        .load(conn)?;
+
+    println!("Refreshing {} team memberships", memberships.len());
+    refresh(conn, env, &memberships);
+    println!("Finished refreshing team memberships");
+    Ok(())
+}
+
+/// Asks GitHub about each of the memberships. Failures are only logged, so that the
+/// previously cached result keeps being used until GitHub answers again.
+fn refresh(conn: &PgConnection, env: &Environment, memberships: &[(Team, User)]) {
+    for (team, user) in memberships {
+        if let Err(error) = team.refresh_membership(env.github(), env.gitlab(), conn, user) {
+            warn!(
+                "Failed to refresh membership of {} in {}: {error}",
+                user.gh_login, team.login
+            );
+        }
+    }
+}
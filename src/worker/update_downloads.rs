@@ -106,6 +106,7 @@ mod test {
             &HashMap::new(),
             None,
             None,
+            None,
             0,
             user_id,
         )
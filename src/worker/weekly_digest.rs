@@ -0,0 +1,123 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::dsl::any;
+use diesel::prelude::*;
+use swirl::PerformError;
+
+use crate::background_jobs::Environment;
+use crate::email::{DigestVersion, WeeklyDigest};
+use crate::models::{Badge, MaintenanceStatus, User, VersionAction};
+use crate::schema::{badges, crates, follows, users, version_owner_actions, versions};
+
+/// Sends the users that subscribed to the weekly digest a summary of the new versions, yanks
+/// and deprecations of the crates they follow.
+///
+/// The digest covers the time since the last digest was sent to the user, but at most a week.
+/// Users are only emailed if something happened to their followed crates.
+#[swirl::background_job]
+pub fn send_weekly_digests(conn: &PgConnection, env: &Environment) -> Result<(), PerformError> {
+    let now = Utc::now().naive_utc();
+    let week_ago = now - Duration::weeks(1);
+
+    let recipients: Vec<User> = users::table
+        .filter(users::weekly_digest.eq(true))
+        .load(conn)?;
+
+    for user in &recipients {
+        let email = match user.notification_email(conn)? {
+            Some(email) => email,
+            None => continue,
+        };
+
+        let since = user
+            .digest_sent_at
+            .map_or(week_ago, |sent| sent.max(week_ago));
+        let digest = load_digest(conn, user.id, since)?;
+        if digest.is_empty() {
+            continue;
+        }
+
+        let token = user.digest_unsubscribe_token(env.email_token_key());
+        let result = env
+            .emails()
+            .send_weekly_digest(conn, &email, &user.gh_login, &digest, &token);
+        if let Err(error) = result {
+            warn!("Failed to send weekly digest to user {}: {error}", user.id);
+            continue;
+        }
+
+        diesel::update(user)
+            .set(users::digest_sent_at.eq(now))
+            .execute(conn)?;
+    }
+
+    Ok(())
+}
+
+fn load_digest(
+    conn: &PgConnection,
+    user_id: i32,
+    since: NaiveDateTime,
+) -> QueryResult<WeeklyDigest> {
+    let followed_crates = || {
+        follows::table
+            .filter(follows::user_id.eq(user_id))
+            .select(follows::crate_id)
+    };
+
+    let new_versions = versions::table
+        .inner_join(crates::table)
+        .filter(crates::id.eq(any(followed_crates())))
+        .filter(versions::created_at.gt(since))
+        .order((crates::name, versions::created_at))
+        .select((crates::name, versions::num))
+        .load::<(String, String)>(conn)?;
+
+    let yanked_versions = version_owner_actions::table
+        .inner_join(versions::table.inner_join(crates::table))
+        .filter(crates::id.eq(any(followed_crates())))
+        .filter(version_owner_actions::action.eq(VersionAction::Yank))
+        .filter(version_owner_actions::time.gt(since))
+        // Versions that were unyanked again are not worth mentioning
+        .filter(versions::yanked.eq(true))
+        .select((crates::name, versions::num))
+        .distinct()
+        .order((crates::name, versions::num))
+        .load::<(String, String)>(conn)?;
+
+    // Badges don't record when they were changed, but they can only be changed by publishing
+    // a new version, which updates the crate.
+    let deprecated_crates = badges::table
+        .inner_join(crates::table)
+        .filter(crates::id.eq(any(followed_crates())))
+        .filter(crates::updated_at.gt(since))
+        .filter(badges::badge_type.eq("maintenance"))
+        .order(crates::name)
+        .select((crates::name, badges::all_columns))
+        .load::<(String, Badge)>(conn)?
+        .into_iter()
+        .filter(|(_, badge)| {
+            matches!(
+                badge,
+                Badge::Maintenance {
+                    status: MaintenanceStatus::Deprecated
+                }
+            )
+        })
+        .map(|(name, _)| name)
+        .collect();
+
+    let into_digest_versions = |rows: Vec<(String, String)>| {
+        rows.into_iter()
+            .map(|(crate_name, version)| DigestVersion {
+                crate_name,
+                version,
+            })
+            .collect()
+    };
+
+    Ok(WeeklyDigest {
+        new_versions: into_digest_versions(new_versions),
+        yanked_versions: into_digest_versions(yanked_versions),
+        deprecated_crates,
+    })
+}